@@ -0,0 +1,261 @@
+//! The `activity` module provides code for tracking how often and how much the price of each
+//! NDC changes, which shows the drugs whose prices are least stable rather than the single
+//! largest price changes.
+
+use crate::aggregate::ndc_key;
+use crate::comparison::ComparisonRow;
+use crate::report::{drugs_label, RecordFormat, Section};
+use crate::statistics::cents;
+use chrono::NaiveDate;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+
+/// The price changes seen for an NDC in a section of the report.
+#[derive(Debug, Clone, Default)]
+struct NdcActivity {
+    /// The description of the drug.
+    description: String,
+
+    /// The NDC of the drug, which may be empty.
+    ndc: String,
+
+    /// The number of price changes.
+    count: u64,
+
+    /// The mean of the price changes, updated with Welford's method.
+    mean: f64,
+
+    /// The sum of the squared distances from the mean, updated with Welford's method.
+    squares: f64,
+
+    /// The distinct effective dates of the price changes.
+    dates: HashSet<NaiveDate>,
+}
+
+impl NdcActivity {
+    /// The sample variance of the price changes, which is 0 for a single change.
+    fn variance(&self) -> f64 {
+        if self.count > 1 {
+            self.squares / (self.count - 1) as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The `Activity` struct keeps the price change activity of each NDC in each section of the
+/// report.
+#[derive(Debug, Default)]
+pub struct Activity {
+    /// The activity of each NDC, by section.
+    sections: BTreeMap<Section, HashMap<String, NdcActivity>>,
+}
+
+impl Activity {
+    /// Add a price change.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The section the change is reported in.
+    /// * `row` - The row of comparison data.
+    pub fn add(&mut self, section: Section, row: &ComparisonRow) {
+        let activity = self
+            .sections
+            .entry(section)
+            .or_default()
+            .entry(ndc_key(row))
+            .or_default();
+        activity.description = row.description.to_string();
+        activity.ndc = row.ndc.to_string();
+        if let Some(effective_date) = row.effective_date {
+            activity.dates.insert(effective_date);
+        }
+
+        let value = (row.new_price - row.old_price).to_f64().unwrap_or_default();
+        activity.count += 1;
+        let delta = value - activity.mean;
+        activity.mean += delta / activity.count as f64;
+        activity.squares += delta * (value - activity.mean);
+    }
+
+    /// Generate the section of the report ranking the most volatile drugs: those with the most
+    /// price changes, and of drugs with as many, those whose changes vary the most.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The section of the report.
+    /// * `count` - The number of drugs requested for the report.
+    /// * `format` - How the drugs are written.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the section.
+    pub fn volatility_report(
+        &self,
+        section: &Section,
+        count: usize,
+        format: &RecordFormat,
+    ) -> String {
+        let drugs = drugs_label(section);
+        let mut report = format!(
+            "Top {} most volatile {}NADAC drugs {}:\n",
+            count, drugs, section.period
+        );
+
+        let mut activities: Vec<&NdcActivity> = self
+            .sections
+            .get(section)
+            .map(|activities| activities.values().collect())
+            .unwrap_or_default();
+        activities.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| b.variance().total_cmp(&a.variance()))
+                .then_with(|| a.description.cmp(&b.description))
+        });
+
+        for activity in activities.into_iter().take(count) {
+            let deviation = Decimal::from_f64(activity.variance().sqrt()).unwrap_or_default();
+            report.push_str(&format!(
+                "{} change(s), {} standard deviation: {}\n",
+                activity.count,
+                cents(deviation),
+                format.describe(&activity.description, &activity.ndc)
+            ));
+        }
+        report
+    }
+
+    /// Generate the section of the report ranking the drugs with price changes on the most
+    /// distinct effective dates. Only the requested number of drugs are held while the ranking
+    /// is worked out.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The section of the report.
+    /// * `count` - The number of drugs requested for the report.
+    /// * `format` - How the drugs are written.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the section.
+    pub fn change_count_report(
+        &self,
+        section: &Section,
+        count: usize,
+        format: &RecordFormat,
+    ) -> String {
+        let drugs = drugs_label(section);
+        let mut report = format!(
+            "Top {} {}NADAC drugs by number of price changes {}:\n",
+            count, drugs, section.period
+        );
+        let Some(activities) = self.sections.get(section) else {
+            return report;
+        };
+
+        // A min-heap of the drugs ranked highest so far, ordered by the number of dates, then
+        // by description and key so that ties are listed alphabetically.
+        let mut top = BinaryHeap::new();
+        for (key, activity) in activities {
+            top.push(Reverse((
+                activity.dates.len(),
+                Reverse(activity.description.as_str()),
+                Reverse(key.as_str()),
+            )));
+            if top.len() > count {
+                top.pop();
+            }
+        }
+
+        for Reverse((dates, _, Reverse(key))) in top.into_sorted_vec() {
+            if let Some(activity) = activities.get(key) {
+                report.push_str(&format!(
+                    "{} price change date(s): {}\n",
+                    dates,
+                    format.describe(&activity.description, &activity.ndc)
+                ));
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dates::Period;
+    use crate::locale::Language;
+    use csv_async::ByteRecord;
+
+    #[test]
+    fn test_volatility_report() {
+        let section = Section {
+            period: Period::Year(2023),
+            classification: None,
+            pricing_unit: None,
+        };
+        let mut activity = Activity::default();
+        for (description, ndc, new_price) in [
+            ("LISINOPRIL 10 MG TABLET", "00000000001", "2"),
+            ("LISINOPRIL 10 MG TABLET", "00000000001", "0"),
+            ("ASPIRIN 81 MG", "00000000002", "2"),
+            ("ASPIRIN 81 MG", "00000000002", "3"),
+            ("HUMIRA PEN", "00000000003", "100"),
+        ] {
+            let record = ByteRecord::from(vec![description, ndc, "1", new_price]);
+            activity.add(section, &ComparisonRow::from_record(&record).unwrap());
+        }
+
+        assert_eq!(
+            activity.volatility_report(
+                &section,
+                2,
+                &RecordFormat {
+                    show_ndc: true,
+                    directory: None,
+                    fields: None,
+                    legacy: false,
+                    percent_places: 2,
+                    chart: false,
+                    language: Language::En,
+                }
+            ),
+            "Top 2 most volatile NADAC drugs of 2023:\n\
+            2 change(s), $1.41 standard deviation: LISINOPRIL 10 MG TABLET (NDC 00000000001)\n\
+            2 change(s), $0.71 standard deviation: ASPIRIN 81 MG (NDC 00000000002)\n"
+        );
+    }
+
+    #[test]
+    fn test_change_count_report() {
+        let section = Section {
+            period: Period::Year(2023),
+            classification: None,
+            pricing_unit: None,
+        };
+        let mut activity = Activity::default();
+        for (description, date) in [
+            ("LISINOPRIL 10 MG TABLET", "01/04/2023"),
+            ("LISINOPRIL 10 MG TABLET", "01/04/2023"),
+            ("ASPIRIN 81 MG", "01/04/2023"),
+            ("ASPIRIN 81 MG", "02/01/2023"),
+            ("HUMIRA PEN", "03/01/2023"),
+            ("ENBREL", "03/01/2023"),
+        ] {
+            let record =
+                ByteRecord::from(vec![description, "", "1", "2", "", "", "", "", "", date]);
+            activity.add(section, &ComparisonRow::from_record(&record).unwrap());
+        }
+
+        assert_eq!(
+            activity.change_count_report(&section, 3, &RecordFormat::default()),
+            "Top 3 NADAC drugs by number of price changes of 2023:\n\
+            2 price change date(s): ASPIRIN 81 MG\n\
+            1 price change date(s): ENBREL\n\
+            1 price change date(s): HUMIRA PEN\n"
+        );
+    }
+}
@@ -0,0 +1,404 @@
+//! The `aggregate` module provides code for combining price changes before they are ranked.
+//! A drug sold in several package sizes or by several labelers has an NDC for each, and an NDC
+//! may have several price changes in a year, either of which would otherwise fill the report
+//! with near duplicates.
+
+use crate::comparison::ComparisonRow;
+use crate::data_store::normalize_description;
+use crate::filters::normalize_ndc;
+use crate::report::Section;
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// Enum describing how the price changes of a drug or NDC are combined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Aggregate {
+    /// The change furthest from zero.
+    #[default]
+    Max,
+
+    /// The average of the changes.
+    Mean,
+
+    /// The change with the latest effective date.
+    Last,
+
+    /// The sum of the changes.
+    Sum,
+
+    /// The spread from the old price of the earliest change to the new price of the latest.
+    #[value(skip)]
+    Endpoints,
+}
+
+/// Enum describing what is ranked in the report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Basis {
+    /// Each price change on its own.
+    #[default]
+    Change,
+
+    /// The net change of each NDC, the sum of its price changes in the period.
+    Net,
+
+    /// The change of each NDC from the old price of its earliest price change in the period to
+    /// the new price of its latest.
+    Endpoints,
+}
+
+impl Basis {
+    /// Get how the price changes are combined before they are ranked.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the grouping and aggregate, or None if the price changes are
+    /// ranked on their own.
+    pub fn aggregation(&self) -> Option<(Grouping, Aggregate)> {
+        match self {
+            Basis::Change => None,
+            Basis::Net => Some((Grouping::Ndc, Aggregate::Sum)),
+            Basis::Endpoints => Some((Grouping::Ndc, Aggregate::Endpoints)),
+        }
+    }
+}
+
+/// Enum describing which price changes are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grouping {
+    /// The changes of all of the NDCs with the same base drug name.
+    Drug,
+
+    /// The changes of each NDC.
+    Ndc,
+}
+
+/// Work out the base name of a drug from its description, leaving out the strength, dosage
+/// form and package that follow it. The base name is the normalized description up to the
+/// first word starting with a digit, e.g. `LISINOPRIL` for `LISINOPRIL 10 MG TABLET`.
+///
+/// # Arguments
+///
+/// * `description` - The description of the drug.
+///
+/// # Returns
+///
+/// The base name, or the whole normalized description if it starts with a digit.
+pub fn base_drug_name(description: &str) -> String {
+    let description = normalize_description(description);
+    let name: Vec<&str> = description
+        .split(' ')
+        .take_while(|word| !word.starts_with(|c: char| c.is_ascii_digit()))
+        .collect();
+
+    if name.is_empty() {
+        description
+    } else {
+        name.join(" ")
+    }
+}
+
+/// Work out the key identifying the NDC of a row: the 11 digit NDC, or the normalized
+/// description for rows without an NDC.
+///
+/// # Arguments
+///
+/// * `row` - The row of comparison data.
+///
+/// # Returns
+///
+/// The key.
+pub fn ndc_key(row: &ComparisonRow) -> String {
+    if row.ndc.trim().is_empty() {
+        normalize_description(&row.description)
+    } else {
+        normalize_ndc(&row.ndc)
+    }
+}
+
+/// The prices of a price change and the date it took effect.
+#[derive(Debug, Clone, Copy)]
+struct DatedChange {
+    /// The date the new price took effect, if known.
+    effective_date: Option<NaiveDate>,
+
+    /// The per unit price before the change.
+    old_price: Decimal,
+
+    /// The per unit price after the change.
+    new_price: Decimal,
+}
+
+/// The price changes seen for a drug or NDC in a section of the report.
+#[derive(Debug, Clone, Default)]
+struct DrugChange {
+    /// The description shown in the report.
+    description: String,
+
+    /// The NDC shown in the report, empty when the changes of several NDCs are combined.
+    ndc: String,
+
+    /// The sum of the changes.
+    total: Decimal,
+
+    /// The number of changes.
+    count: u32,
+
+    /// The change furthest from zero.
+    extreme: Decimal,
+
+    /// The change with the earliest effective date.
+    first: Option<DatedChange>,
+
+    /// The change with the latest effective date.
+    last: Option<DatedChange>,
+}
+
+/// The combined price change of a drug or NDC, ready to be ranked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CombinedChange {
+    /// The section the change is reported in.
+    pub section: Section,
+
+    /// The description shown in the report.
+    pub description: String,
+
+    /// The NDC shown in the report, which may be empty.
+    pub ndc: String,
+
+    /// The combined change in price.
+    pub difference: Decimal,
+}
+
+/// The `DrugChanges` struct collects the price changes for each drug or NDC in each section, so
+/// they can be combined once all of the data has been read.
+#[derive(Debug)]
+pub struct DrugChanges {
+    /// Which changes are combined.
+    grouping: Grouping,
+
+    /// How the changes are combined.
+    aggregate: Aggregate,
+
+    /// The changes for each section and base drug name or NDC.
+    changes: BTreeMap<(Section, String), DrugChange>,
+}
+
+impl DrugChanges {
+    /// Create a new, empty `DrugChanges`.
+    ///
+    /// # Arguments
+    ///
+    /// * `grouping` - Which changes are combined.
+    /// * `aggregate` - How the changes of a drug or NDC are combined.
+    pub fn new(grouping: Grouping, aggregate: Aggregate) -> DrugChanges {
+        DrugChanges {
+            grouping,
+            aggregate,
+            changes: BTreeMap::new(),
+        }
+    }
+
+    /// Add a price change.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The section the change is reported in.
+    /// * `row` - The row of comparison data.
+    pub fn add(&mut self, section: Section, row: &ComparisonRow) {
+        let difference = row.new_price - row.old_price;
+
+        let key = match self.grouping {
+            Grouping::Drug => base_drug_name(&row.description),
+            Grouping::Ndc => ndc_key(row),
+        };
+
+        let change = self.changes.entry((section, key)).or_default();
+        match self.grouping {
+            Grouping::Drug => {
+                if change.description.is_empty() {
+                    change.description = base_drug_name(&row.description);
+                }
+            }
+            Grouping::Ndc => {
+                change.description = row.description.to_string();
+                change.ndc = row.ndc.to_string();
+            }
+        }
+
+        change.total += difference;
+        change.count += 1;
+        if difference.abs() > change.extreme.abs() {
+            change.extreme = difference;
+        }
+        // Of changes on the same date, the first is the one read first and the last is the one
+        // read last.
+        let dated = DatedChange {
+            effective_date: row.effective_date,
+            old_price: row.old_price,
+            new_price: row.new_price,
+        };
+        if change
+            .first
+            .is_none_or(|first| dated.effective_date < first.effective_date)
+        {
+            change.first = Some(dated);
+        }
+        if change
+            .last
+            .is_none_or(|last| dated.effective_date >= last.effective_date)
+        {
+            change.last = Some(dated);
+        }
+    }
+
+    /// Combine the changes of each drug or NDC.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the combined change of each drug or NDC.
+    pub fn into_changes(self) -> impl Iterator<Item = CombinedChange> {
+        let aggregate = self.aggregate;
+        self.changes.into_iter().map(move |((section, _), change)| {
+            let difference = match aggregate {
+                Aggregate::Max => change.extreme,
+                Aggregate::Mean => change.total / Decimal::from(change.count),
+                Aggregate::Last => change
+                    .last
+                    .map_or(Decimal::ZERO, |last| last.new_price - last.old_price),
+                Aggregate::Sum => change.total,
+                Aggregate::Endpoints => change
+                    .first
+                    .zip(change.last)
+                    .map_or(Decimal::ZERO, |(first, last)| {
+                        last.new_price - first.old_price
+                    }),
+            };
+            CombinedChange {
+                section,
+                description: change.description,
+                ndc: change.ndc,
+                difference,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dates::Period;
+    use csv_async::ByteRecord;
+
+    #[test]
+    fn test_base_drug_name() {
+        assert_eq!(base_drug_name("LISINOPRIL 10 MG TABLET"), "LISINOPRIL");
+        assert_eq!(
+            base_drug_name("dexmethylphenidate  ER 40 MG CAP"),
+            "DEXMETHYLPHENIDATE ER"
+        );
+        assert_eq!(
+            base_drug_name("0.9% SODIUM CHLORIDE"),
+            "0.9% SODIUM CHLORIDE"
+        );
+    }
+
+    #[test]
+    fn test_drug_changes() {
+        let section = Section {
+            period: Period::Year(2023),
+            classification: None,
+            pricing_unit: None,
+        };
+        let records = [
+            ByteRecord::from(vec![
+                "LISINOPRIL 10 MG TABLET",
+                "00000000001",
+                "1",
+                "3",
+                "G",
+                "",
+                "",
+                "",
+                "",
+                "03/01/2023",
+            ]),
+            ByteRecord::from(vec![
+                "LISINOPRIL 20 MG TABLET",
+                "00000000002",
+                "5",
+                "1",
+                "G",
+                "",
+                "",
+                "",
+                "",
+                "02/01/2023",
+            ]),
+            ByteRecord::from(vec![
+                "LISINOPRIL 10 MG TABLET",
+                "00000000001",
+                "3",
+                "2",
+                "G",
+                "",
+                "",
+                "",
+                "",
+                "01/01/2023",
+            ]),
+            ByteRecord::from(vec!["ASPIRIN 81 MG", "00000000003", "1", "2"]),
+        ];
+        let changes = |grouping, aggregate| {
+            let mut drug_changes = DrugChanges::new(grouping, aggregate);
+            for record in &records {
+                drug_changes.add(section, &ComparisonRow::from_record(record).unwrap());
+            }
+            drug_changes
+                .into_changes()
+                .map(|change| (change.description, change.ndc, change.difference))
+                .collect::<Vec<_>>()
+        };
+        let change = |description: &str, ndc: &str, difference| {
+            (
+                description.to_string(),
+                ndc.to_string(),
+                Decimal::new(difference, 0),
+            )
+        };
+
+        assert_eq!(
+            changes(Grouping::Drug, Aggregate::Max),
+            vec![change("ASPIRIN", "", 1), change("LISINOPRIL", "", -4)]
+        );
+        assert_eq!(
+            changes(Grouping::Drug, Aggregate::Mean)[1],
+            change("LISINOPRIL", "", -1)
+        );
+        assert_eq!(
+            changes(Grouping::Ndc, Aggregate::Last),
+            vec![
+                change("LISINOPRIL 10 MG TABLET", "00000000001", 2),
+                change("LISINOPRIL 20 MG TABLET", "00000000002", -4),
+                change("ASPIRIN 81 MG", "00000000003", 1),
+            ]
+        );
+        assert_eq!(
+            changes(Grouping::Ndc, Aggregate::Mean)[0],
+            (
+                "LISINOPRIL 10 MG TABLET".to_string(),
+                "00000000001".to_string(),
+                Decimal::new(5, 1)
+            )
+        );
+        assert_eq!(
+            changes(Grouping::Ndc, Aggregate::Sum)[0],
+            change("LISINOPRIL 10 MG TABLET", "00000000001", 1)
+        );
+        assert_eq!(
+            changes(Grouping::Ndc, Aggregate::Endpoints)[0],
+            change("LISINOPRIL 10 MG TABLET", "00000000001", 0)
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! The `alerts` module provides code for picking out every price increase above a threshold,
+//! rather than the largest few, so each one can be reviewed. The alerts are written as CSV as
+//! the data streams past, so nothing but the current row is held.
+
+use crate::comparison::ComparisonRow;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+/// The header line of the alerts CSV.
+pub const ALERT_HEADER: &str =
+    "Effective Date,NDC,NDC Description,Old Price,New Price,Change,Percent Change\n";
+
+/// The `AlertThresholds` struct holds the size of increase that raises an alert. An increase
+/// above either threshold raises one.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AlertThresholds {
+    /// The percent of the old price an increase must be above.
+    pub percent: Option<Decimal>,
+
+    /// The dollars an increase must be above.
+    pub dollars: Option<Decimal>,
+}
+
+impl AlertThresholds {
+    /// Determine if a price change raises an alert.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    ///
+    /// # Returns
+    ///
+    /// True if the price went up by more than either threshold. An increase from an old price
+    /// of zero is above any percent.
+    pub fn matches(&self, row: &ComparisonRow) -> bool {
+        let change = row.new_price - row.old_price;
+        if change <= Decimal::ZERO {
+            return false;
+        }
+
+        let above_dollars = self.dollars.is_some_and(|dollars| change > dollars);
+        let above_percent = self.percent.is_some_and(|percent| {
+            row.old_price <= Decimal::ZERO
+                || change * Decimal::ONE_HUNDRED > percent * row.old_price
+        });
+        above_dollars || above_percent
+    }
+}
+
+/// Quote a CSV field if it has a comma, quote or line break.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Format a price change as a line of the alerts CSV.
+///
+/// # Arguments
+///
+/// * `row` - The row of comparison data.
+/// * `effective_date` - The date the new price took effect.
+///
+/// # Returns
+///
+/// A new String containing the line.
+pub fn alert_line(row: &ComparisonRow, effective_date: NaiveDate) -> String {
+    let change = row.new_price - row.old_price;
+    let percent = if row.old_price.is_zero() {
+        String::new()
+    } else {
+        (change / row.old_price * Decimal::ONE_HUNDRED)
+            .round_dp(2)
+            .to_string()
+    };
+
+    format!(
+        "{},{},{},{},{},{},{}\n",
+        effective_date,
+        csv_field(row.ndc.trim()),
+        csv_field(row.description.trim()),
+        row.old_price,
+        row.new_price,
+        change,
+        percent
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+
+    #[test]
+    fn test_alerts() {
+        let rows = [
+            ByteRecord::from(vec!["ASPIRIN 81 MG", "00001", "1.00", "1.20"]),
+            ByteRecord::from(vec!["HUMIRA, PEN", "00002", "100.00", "105.00"]),
+            ByteRecord::from(vec!["LISINOPRIL", "00003", "2.00", "1.00"]),
+            ByteRecord::from(vec!["NEW DRUG", "00004", "0", "0.50"]),
+        ];
+        let rows: Vec<ComparisonRow> = rows
+            .iter()
+            .map(|record| ComparisonRow::from_record(record).unwrap())
+            .collect();
+
+        let percent = AlertThresholds {
+            percent: Some(Decimal::new(10, 0)),
+            dollars: None,
+        };
+        let matched: Vec<bool> = rows.iter().map(|row| percent.matches(row)).collect();
+        assert_eq!(matched, [true, false, false, true]);
+
+        let either = AlertThresholds {
+            percent: Some(Decimal::new(10, 0)),
+            dollars: Some(Decimal::new(4, 0)),
+        };
+        let matched: Vec<bool> = rows.iter().map(|row| either.matches(row)).collect();
+        assert_eq!(matched, [true, true, false, true]);
+
+        let date = NaiveDate::from_ymd_opt(2023, 1, 4).unwrap();
+        assert_eq!(
+            alert_line(&rows[1], date),
+            "2023-01-04,00002,\"HUMIRA, PEN\",100.00,105.00,5.00,5.00\n"
+        );
+        assert_eq!(
+            alert_line(&rows[3], date),
+            "2023-01-04,00004,NEW DRUG,0,0.50,0.50,\n"
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! The `archive` module provides code for pulling the CSV data out of a zip archive.
+
+use crate::data_source::DataReader;
+use async_zip::base::read::seek::ZipFileReader;
+use futures::io::BufReader;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+/// Determine if a path or URL names a zip archive.
+///
+/// # Arguments
+///
+/// * `name` - The path or URL of the data.
+///
+/// # Returns
+///
+/// Returns true if the name ends with a `.zip` extension.
+pub fn is_zip_name(name: &str) -> bool {
+    let name = name.split(['?', '#']).next().unwrap_or(name);
+    name.to_ascii_lowercase().ends_with(".zip")
+}
+
+/// Open a member of a zip archive as a stream of bytes.
+///
+/// # Arguments
+///
+/// * `file` - The open archive file. Zip archives keep their directory at the end of the file, so
+///   the archive must be in a seekable file rather than a plain stream.
+/// * `member` - The name of the archive entry to read. When `None`, the first entry whose name
+///   ends with `.csv` is used.
+///
+/// # Returns
+///
+/// On success, returns a `DataReader` producing the uncompressed bytes of the entry, on error
+/// returns a std::error::Error in a Box.
+pub async fn open_zip_member(
+    file: tokio::fs::File,
+    member: Option<&str>,
+) -> Result<DataReader, Box<dyn std::error::Error>> {
+    let zip = ZipFileReader::new(BufReader::new(file.compat())).await?;
+
+    let mut index = None;
+    for (i, entry) in zip.file().entries().iter().enumerate() {
+        // Skip entries whose names are not valid UTF-8; NADAC archives do not use them.
+        let name = match entry.filename().as_str() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+
+        let found = match member {
+            Some(member) => name == member,
+            None => !entry.dir()? && name.to_ascii_lowercase().ends_with(".csv"),
+        };
+
+        if found {
+            index = Some(i);
+            break;
+        }
+    }
+
+    let index = match (index, member) {
+        (Some(index), _) => index,
+        (None, Some(member)) => {
+            return Err(format!("The archive does not contain an entry named {}", member).into())
+        }
+        (None, None) => return Err("The archive does not contain a .csv entry".into()),
+    };
+
+    Ok(Box::pin(zip.into_entry(index).await?))
+}
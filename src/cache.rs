@@ -0,0 +1,501 @@
+//! The `cache` module provides code for keeping downloaded CSV data on disk so that repeated
+//! report runs only download the data again when it has changed.
+
+use crate::http::{download, ByteStream, HttpOptions};
+use futures::StreamExt;
+use reqwest::header::{
+    HeaderMap, HeaderValue, CONTENT_ENCODING, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    LAST_MODIFIED,
+};
+use reqwest::{Client, StatusCode};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+
+const DATA_EXTENSION: &str = "data";
+const META_EXTENSION: &str = "meta";
+const PARTIAL_EXTENSION: &str = "part";
+
+/// The information stored alongside each cached download.
+#[derive(Debug, Clone, Default)]
+pub struct CacheEntry {
+    /// The URL the data was downloaded from.
+    pub url: String,
+
+    /// The ETag the server sent with the data.
+    pub etag: Option<String>,
+
+    /// The Last-Modified date the server sent with the data.
+    pub last_modified: Option<String>,
+
+    /// The Content-Type the server sent with the data.
+    pub content_type: Option<String>,
+
+    /// The Content-Encoding the server sent with the data.
+    pub content_encoding: Option<String>,
+
+    /// When the data was downloaded, in seconds since the Unix epoch.
+    pub fetched: u64,
+}
+
+impl CacheEntry {
+    /// Create an entry from the headers of a response.
+    fn from_headers(url: &str, headers: &HeaderMap) -> CacheEntry {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+
+        CacheEntry {
+            url: url.to_string(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            content_type: header(CONTENT_TYPE),
+            content_encoding: header(CONTENT_ENCODING),
+            fetched: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+
+    /// Convert the entry to the `key=value` lines stored in the metadata file.
+    fn to_text(&self) -> String {
+        let mut text = format!("url={}\nfetched={}\n", self.url, self.fetched);
+        let optional = [
+            ("etag", &self.etag),
+            ("last_modified", &self.last_modified),
+            ("content_type", &self.content_type),
+            ("content_encoding", &self.content_encoding),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                text.push_str(&format!("{}={}\n", key, value));
+            }
+        }
+        text
+    }
+
+    /// Parse the `key=value` lines stored in the metadata file.
+    fn from_text(text: &str) -> CacheEntry {
+        let mut entry = CacheEntry::default();
+        for line in text.lines() {
+            if let Some((key, value)) = line.split_once('=') {
+                let value = value.to_string();
+                match key {
+                    "url" => entry.url = value,
+                    "fetched" => entry.fetched = value.parse().unwrap_or(0),
+                    "etag" => entry.etag = Some(value),
+                    "last_modified" => entry.last_modified = Some(value),
+                    "content_type" => entry.content_type = Some(value),
+                    "content_encoding" => entry.content_encoding = Some(value),
+                    _ => {}
+                }
+            }
+        }
+        entry
+    }
+}
+
+/// The data for a URL, either freshly downloaded or from the cache.
+pub struct CachedData {
+    /// The information about the data.
+    pub entry: CacheEntry,
+
+    /// The bytes of the data, exactly as the server sent them.
+    pub body: ByteStream,
+
+    /// True when the bytes are being downloaded, false when they are read from the cache.
+    pub downloaded: bool,
+}
+
+/// A cached download as reported by `DownloadCache::list`.
+#[derive(Debug, Clone)]
+pub struct CacheListing {
+    /// The information about the download.
+    pub entry: CacheEntry,
+
+    /// The size of the cached data in bytes.
+    pub size: u64,
+}
+
+/// The `DownloadCache` stores downloaded data in a directory, keyed by URL.
+#[derive(Debug, Clone)]
+pub struct DownloadCache {
+    /// The directory holding the cached files.
+    dir: PathBuf,
+}
+
+/// Return the default cache directory for the platform.
+pub fn default_cache_dir() -> PathBuf {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    base.join("top10rust")
+}
+
+impl DownloadCache {
+    /// Create a cache that stores its files in `dir`.
+    pub fn new(dir: PathBuf) -> DownloadCache {
+        DownloadCache { dir }
+    }
+
+    /// Return the directory holding the cached files.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Compute the path of a cache file for a URL. Files are named by a hash of the URL so any
+    /// URL maps to a valid file name.
+    fn path_for(&self, url: &str, extension: &str) -> PathBuf {
+        let digest = Sha256::digest(url.as_bytes());
+        let name: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+        self.dir.join(format!("{}.{}", name, extension))
+    }
+
+    /// Look up the cached entry for a URL.
+    ///
+    /// # Returns
+    ///
+    /// Returns the entry if both the data and its metadata are in the cache.
+    pub async fn lookup(&self, url: &str) -> Option<CacheEntry> {
+        if !tokio::fs::try_exists(self.path_for(url, DATA_EXTENSION))
+            .await
+            .unwrap_or(false)
+        {
+            return None;
+        }
+
+        let text = tokio::fs::read_to_string(self.path_for(url, META_EXTENSION))
+            .await
+            .ok()?;
+        Some(CacheEntry::from_text(&text))
+    }
+
+    /// List the downloads in the cache, oldest first.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the list of cached downloads, on error returns a std::error::Error
+    /// in a Box.
+    pub async fn list(&self) -> Result<Vec<CacheListing>, Box<dyn std::error::Error>> {
+        let mut listings = Vec::new();
+
+        // A cache directory that has not been created yet is just an empty cache.
+        if !tokio::fs::try_exists(&self.dir).await? {
+            return Ok(listings);
+        }
+
+        let mut dir = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(item) = dir.next_entry().await? {
+            let path = item.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(META_EXTENSION) {
+                continue;
+            }
+
+            let entry = CacheEntry::from_text(&tokio::fs::read_to_string(&path).await?);
+            if let Ok(metadata) =
+                tokio::fs::metadata(self.path_for(&entry.url, DATA_EXTENSION)).await
+            {
+                listings.push(CacheListing {
+                    entry,
+                    size: metadata.len(),
+                });
+            }
+        }
+
+        listings.sort_by_key(|listing| listing.entry.fetched);
+        Ok(listings)
+    }
+
+    /// Remove downloads from the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `older_than` - When set, only downloads fetched longer ago than this are removed.
+    ///   Otherwise, everything in the cache is removed, including partial downloads.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the number of downloads removed, on error returns a
+    /// std::error::Error in a Box.
+    pub async fn clear(
+        &self,
+        older_than: Option<Duration>,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let mut removed = 0;
+
+        for listing in self.list().await? {
+            let stale = match older_than {
+                Some(age) => listing.entry.fetched.saturating_add(age.as_secs()) < now,
+                None => true,
+            };
+
+            if stale {
+                for extension in [DATA_EXTENSION, META_EXTENSION] {
+                    tokio::fs::remove_file(self.path_for(&listing.entry.url, extension)).await?;
+                }
+                removed += 1;
+            }
+        }
+
+        if older_than.is_none() && tokio::fs::try_exists(&self.dir).await? {
+            let mut dir = tokio::fs::read_dir(&self.dir).await?;
+            while let Some(item) = dir.next_entry().await? {
+                let path = item.path();
+                if path.extension().and_then(|e| e.to_str()) == Some(PARTIAL_EXTENSION) {
+                    tokio::fs::remove_file(path).await?;
+                }
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Fetch the data for a URL from the cache only, without touching the network.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to look up.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `CachedData` for the URL, on error (including when the URL is not
+    /// in the cache) returns a std::error::Error in a Box.
+    pub async fn fetch_offline(&self, url: &str) -> Result<CachedData, Box<dyn std::error::Error>> {
+        match self.lookup(url).await {
+            Some(entry) => self.open_cached(entry).await,
+            None => Err(format!(
+                "{} is not in the download cache at {}; run without --offline to download it",
+                url,
+                self.dir.display()
+            )
+            .into()),
+        }
+    }
+
+    /// Open the cached data for a URL.
+    async fn open_cached(
+        &self,
+        entry: CacheEntry,
+    ) -> Result<CachedData, Box<dyn std::error::Error>> {
+        let file = tokio::fs::File::open(self.path_for(&entry.url, DATA_EXTENSION)).await?;
+        let body = tokio_util::io::ReaderStream::new(file).boxed();
+        Ok(CachedData {
+            entry,
+            body,
+            downloaded: false,
+        })
+    }
+
+    /// Fetch the data for a URL. If the URL is in the cache, a conditional GET is sent with the
+    /// ETag and Last-Modified values of the cached data, and the cached data is reused if the
+    /// server reports that it has not changed. Otherwise, the data is written to the cache as
+    /// it is streamed to the caller.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client used to send the requests.
+    /// * `url` - The URL to fetch.
+    /// * `options` - The options that control the download.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `CachedData` for the URL, on error returns a std::error::Error in
+    /// a Box.
+    pub async fn fetch(
+        &self,
+        client: &Client,
+        url: &str,
+        options: &HttpOptions,
+    ) -> Result<CachedData, Box<dyn std::error::Error>> {
+        let cached = self.lookup(url).await;
+
+        let mut conditional = HeaderMap::new();
+        if let Some(entry) = &cached {
+            if let Some(etag) = &entry.etag {
+                conditional.insert(IF_NONE_MATCH, HeaderValue::from_str(etag)?);
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                conditional.insert(IF_MODIFIED_SINCE, HeaderValue::from_str(last_modified)?);
+            }
+        }
+
+        let response = download(client, url, options, &conditional).await?;
+
+        if response.status == StatusCode::NOT_MODIFIED {
+            if let Some(entry) = cached {
+                return self.open_cached(entry).await;
+            }
+            return Err(format!("{} reported the data as not modified", url).into());
+        }
+
+        let entry = CacheEntry::from_headers(url, &response.headers);
+        let body = self.tee(entry.clone(), response.body).await?;
+        Ok(CachedData {
+            entry,
+            body,
+            downloaded: true,
+        })
+    }
+
+    /// Write a download into the cache while passing its bytes through to the caller. The data
+    /// is written to a partial file that only replaces the cached data once the whole download
+    /// has been read, so an interrupted run never leaves a truncated file in the cache.
+    async fn tee(
+        &self,
+        entry: CacheEntry,
+        body: ByteStream,
+    ) -> Result<ByteStream, Box<dyn std::error::Error>> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        let partial_path = self.path_for(&entry.url, PARTIAL_EXTENSION);
+        let file = tokio::fs::File::create(&partial_path).await?;
+        let cache = self.clone();
+
+        let stream = futures::stream::try_unfold(
+            (body, file, cache, entry, partial_path),
+            |(mut body, mut file, cache, entry, partial_path)| async move {
+                match body.next().await {
+                    Some(Ok(chunk)) => {
+                        file.write_all(&chunk).await?;
+                        Ok(Some((chunk, (body, file, cache, entry, partial_path))))
+                    }
+                    Some(Err(e)) => Err(e),
+                    None => {
+                        // The download is complete, so move it into place and record its
+                        // metadata.
+                        file.flush().await?;
+                        drop(file);
+                        let data_path = cache.path_for(&entry.url, DATA_EXTENSION);
+                        tokio::fs::rename(&partial_path, &data_path).await?;
+                        tokio::fs::write(
+                            cache.path_for(&entry.url, META_EXTENSION),
+                            entry.to_text(),
+                        )
+                        .await?;
+                        Ok(None)
+                    }
+                }
+            },
+        );
+
+        Ok(stream.boxed())
+    }
+}
+
+/// Format a byte count for people, e.g. `1.5 MiB`.
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+
+    let mut value = size as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+/// Generate the text listing of the downloads in the cache.
+///
+/// # Arguments
+///
+/// * `listings` - The cached downloads from `DownloadCache::list`.
+///
+/// # Returns
+///
+/// A new String with one line per download giving its size, when it was fetched and its URL.
+pub fn generate_listing(listings: &[CacheListing]) -> String {
+    if listings.is_empty() {
+        return "The download cache is empty.\n".to_string();
+    }
+
+    let mut listing = String::new();
+    for item in listings {
+        let fetched = chrono::DateTime::from_timestamp(item.entry.fetched as i64, 0)
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        listing.push_str(&format!(
+            "{:>10}  {}  {}\n",
+            format_size(item.size),
+            fetched,
+            item.entry.url
+        ));
+    }
+
+    let total: u64 = listings.iter().map(|item| item.size).sum();
+    listing.push_str(&format!(
+        "{} download(s), {} total\n",
+        listings.len(),
+        format_size(total)
+    ));
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_round_trip() {
+        let entry = CacheEntry {
+            url: "https://download.medicaid.gov/data/nadac.csv?a=b".to_string(),
+            etag: Some("\"abc123\"".to_string()),
+            last_modified: Some("Wed, 17 Apr 2024 10:00:00 GMT".to_string()),
+            content_type: Some("text/csv".to_string()),
+            content_encoding: None,
+            fetched: 1713348000,
+        };
+
+        let parsed = CacheEntry::from_text(&entry.to_text());
+        assert_eq!(parsed.url, entry.url);
+        assert_eq!(parsed.etag, entry.etag);
+        assert_eq!(parsed.last_modified, entry.last_modified);
+        assert_eq!(parsed.content_type, entry.content_type);
+        assert_eq!(parsed.content_encoding, None);
+        assert_eq!(parsed.fetched, entry.fetched);
+    }
+
+    #[tokio::test]
+    async fn test_clear() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = DownloadCache::new(directory.path().to_path_buf());
+        let url = "https://download.medicaid.gov/data/nadac.csv";
+        let entry = CacheEntry {
+            url: url.to_string(),
+            etag: None,
+            last_modified: None,
+            content_type: None,
+            content_encoding: None,
+            fetched: 1713348000,
+        };
+        std::fs::write(cache.path_for(url, DATA_EXTENSION), "data").unwrap();
+        std::fs::write(cache.path_for(url, META_EXTENSION), entry.to_text()).unwrap();
+
+        // An age too large to add to the fetch time keeps everything.
+        assert_eq!(cache.clear(Some(Duration::MAX)).await.unwrap(), 0);
+        assert_eq!(cache.clear(Some(Duration::from_secs(60))).await.unwrap(), 1);
+        assert!(cache.list().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(300 * 1024 * 1024), "300.0 MiB");
+    }
+}
@@ -0,0 +1,324 @@
+//! The `chart` module provides code for drawing the ranked lists of the report, and optionally
+//! the histogram of all of the price changes, as bar charts in an SVG or PNG file given with
+//! `--chart-file`, so the report can be dropped into a slide deck without charting it by hand.
+
+use crate::data_store::DataStore;
+use crate::histogram::Histogram;
+use crate::report::{dollars, drugs_label, ranked_entries, RecordFormat, Section};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::register_font;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use std::path::Path;
+
+/// The font of the charts' text, bundled so the charts can be drawn without system fonts.
+const FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+
+/// The width of the chart, in pixels.
+const CHART_WIDTH: u32 = 1000;
+
+/// The height of each bar of a chart, with the space around it, in pixels.
+const BAR_HEIGHT: u32 = 26;
+
+/// The height of a chart's title and axis, in pixels.
+const PANEL_MARGIN: u32 = 80;
+
+/// The width of the labels of the bars, in pixels.
+const LABEL_WIDTH: u32 = 320;
+
+/// The most characters of a bar's label that are drawn.
+const LABEL_CHARACTERS: usize = 40;
+
+/// The color of the price increases, as in the HTML report.
+const INCREASE: RGBColor = RGBColor(0xb0, 0x3a, 0x2e);
+
+/// The color of the price decreases, as in the HTML report.
+const DECREASE: RGBColor = RGBColor(0x1e, 0x84, 0x49);
+
+/// The color of the histogram's counts.
+const COUNT: RGBColor = RGBColor(0x2e, 0x6d, 0xb0);
+
+/// Enum describing what the bars of a chart measure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartUnit {
+    /// Price changes in dollars, drawn in the color of their direction.
+    Dollars,
+
+    /// Numbers of price changes.
+    Count,
+}
+
+/// The `ChartPanel` struct holds one bar chart of the chart file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartPanel {
+    /// The title of the chart.
+    pub title: String,
+
+    /// The label and value of each bar, from the top of the chart down.
+    pub bars: Vec<(String, f64)>,
+
+    /// What the bars measure.
+    pub unit: ChartUnit,
+}
+
+impl ChartPanel {
+    /// The height of the chart, in pixels.
+    fn height(&self) -> u32 {
+        PANEL_MARGIN + BAR_HEIGHT * self.bars.len() as u32
+    }
+
+    /// Write a value of the chart's axis, e.g. `$100` or `25`.
+    fn axis_label(&self, value: f64) -> String {
+        match self.unit {
+            ChartUnit::Dollars => dollars(&Decimal::from_f64(value).unwrap_or_default()),
+            // The counts are whole numbers, so the ticks between them are not labelled.
+            ChartUnit::Count if value.fract() != 0.0 => String::new(),
+            ChartUnit::Count => format!("{:.0}", value),
+        }
+    }
+
+    /// The color of a bar.
+    fn color(&self, value: f64) -> RGBColor {
+        match self.unit {
+            ChartUnit::Dollars if value < 0.0 => DECREASE,
+            ChartUnit::Dollars => INCREASE,
+            ChartUnit::Count => COUNT,
+        }
+    }
+}
+
+/// Make the charts of the report: one for each ranked list of each section, followed by one of
+/// the histogram when there is one. Lists without price changes are left out.
+///
+/// # Arguments
+///
+/// * `sections` - The sections of the report and their records stores, in date order.
+/// * `format` - How the records' drugs are written.
+/// * `histogram` - When set, the histogram of all of the price changes in the report.
+///
+/// # Returns
+///
+/// A Vec containing the charts, in the order they are drawn.
+pub fn chart_panels(
+    sections: &[(&Section, &DataStore)],
+    format: &RecordFormat,
+    histogram: Option<&Histogram>,
+) -> Vec<ChartPanel> {
+    let mut panels = Vec::new();
+    for (section, data_store) in sections {
+        let period = section.period;
+        let drugs = drugs_label(section);
+        for (kind, count, entries) in ranked_entries(data_store, format.percent_places) {
+            if entries.is_empty() {
+                continue;
+            }
+            panels.push(ChartPanel {
+                title: format!("Top {count} {drugs}NADAC per unit price {kind} {period}"),
+                bars: entries
+                    .iter()
+                    .map(|entry| {
+                        (
+                            format.describe(&entry.description, &entry.ndc),
+                            entry.change.to_f64().unwrap_or_default(),
+                        )
+                    })
+                    .collect(),
+                unit: ChartUnit::Dollars,
+            });
+        }
+    }
+    if let Some(histogram) = histogram {
+        panels.push(ChartPanel {
+            title: "Price change histogram".to_string(),
+            bars: histogram
+                .ranges()
+                .into_iter()
+                .map(|(label, count)| (label, count as f64))
+                .collect(),
+            unit: ChartUnit::Count,
+        });
+    }
+    panels
+}
+
+/// Draw charts into a file, as SVG or PNG depending on the file's extension.
+///
+/// # Arguments
+///
+/// * `path` - The file, ending in `.svg` or `.png`.
+/// * `panels` - The charts, drawn one below the other.
+///
+/// # Returns
+///
+/// On success, returns (), on error returns a std::error::Error in a Box.
+pub fn write_chart(path: &Path, panels: &[ChartPanel]) -> Result<(), Box<dyn std::error::Error>> {
+    register_font("sans-serif", FontStyle::Normal, FONT)
+        .map_err(|_| "The chart font could not be loaded")?;
+    let height = panels.iter().map(ChartPanel::height).sum::<u32>();
+    let size = (CHART_WIDTH, height.max(PANEL_MARGIN));
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("svg") => draw(SVGBackend::new(path, size).into_drawing_area(), panels),
+        Some("png") => draw(BitMapBackend::new(path, size).into_drawing_area(), panels),
+        _ => Err(format!("The chart file {} must end in .svg or .png", path.display()).into()),
+    }
+}
+
+/// Draw charts one below the other on a drawing area.
+fn draw<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    panels: &[ChartPanel],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    let mut area = root.clone();
+    for panel in panels {
+        let (top, rest) = area.split_vertically(panel.height());
+        draw_panel(&top, panel)?;
+        area = rest;
+    }
+    root.present()?;
+    Ok(())
+}
+
+/// Draw a chart of horizontal bars, with the bars of the negative values to the left of zero.
+fn draw_panel<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    panel: &ChartPanel,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let count = panel.bars.len();
+    let low = panel
+        .bars
+        .iter()
+        .map(|(_, value)| *value)
+        .fold(0.0, f64::min);
+    let mut high = panel
+        .bars
+        .iter()
+        .map(|(_, value)| *value)
+        .fold(0.0, f64::max);
+    if low == high {
+        high = low + 1.0;
+    }
+
+    let mut chart = ChartBuilder::on(area)
+        .caption(&panel.title, ("sans-serif", 18))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(LABEL_WIDTH)
+        .build_cartesian_2d(low..high, (0..count.max(1) - 1).into_segmented())?;
+
+    // The first bar is at the top of the chart.
+    let row = |index: usize| count - 1 - index;
+    let label = |value: &SegmentValue<usize>| match value {
+        SegmentValue::CenterOf(position) if *position < count => panel.bars[row(*position)]
+            .0
+            .chars()
+            .take(LABEL_CHARACTERS)
+            .collect(),
+        _ => String::new(),
+    };
+    let axis_label = |value: &f64| panel.axis_label(*value);
+    chart
+        .configure_mesh()
+        .disable_y_mesh()
+        .label_style(("sans-serif", 13))
+        .y_labels(count)
+        .y_label_formatter(&label)
+        .x_label_formatter(&axis_label)
+        .draw()?;
+
+    chart.draw_series(panel.bars.iter().enumerate().map(|(index, (_, value))| {
+        let mut bar = Rectangle::new(
+            [
+                (0.0, SegmentValue::Exact(row(index))),
+                (*value, SegmentValue::Exact(row(index) + 1)),
+            ],
+            panel.color(*value).filled(),
+        );
+        bar.set_margin(3, 3, 0, 0);
+        bar
+    }))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_store::{Direction, Metric};
+    use crate::dates::Period;
+
+    #[test]
+    fn test_chart() {
+        let mut data_store = DataStore::new(2, 2, Metric::Change, Direction::Both).unwrap();
+        let mut histogram = Histogram::new(vec![Decimal::ZERO]).unwrap();
+        for (cents, description) in [(80239, "STELARA"), (32019, "HUMIRA"), (-18314, "HUMALOG")] {
+            let difference = Decimal::new(cents, 2);
+            data_store
+                .insert_change(difference, description, "")
+                .unwrap();
+            histogram.add(difference);
+        }
+        let section = Section {
+            period: Period::Year(2023),
+            classification: None,
+            pricing_unit: None,
+        };
+
+        let panels = chart_panels(
+            &[(&section, &data_store)],
+            &RecordFormat::default(),
+            Some(&histogram),
+        );
+        assert_eq!(
+            panels,
+            [
+                ChartPanel {
+                    title: "Top 2 NADAC per unit price increases of 2023".to_string(),
+                    bars: vec![
+                        ("STELARA".to_string(), 802.39),
+                        ("HUMIRA".to_string(), 320.19)
+                    ],
+                    unit: ChartUnit::Dollars,
+                },
+                ChartPanel {
+                    title: "Top 2 NADAC per unit price decreases of 2023".to_string(),
+                    bars: vec![("HUMALOG".to_string(), -183.14)],
+                    unit: ChartUnit::Dollars,
+                },
+                ChartPanel {
+                    title: "Price change histogram".to_string(),
+                    bars: vec![
+                        ("below $0".to_string(), 1.0),
+                        ("$0 and up".to_string(), 2.0)
+                    ],
+                    unit: ChartUnit::Count,
+                },
+            ]
+        );
+
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("changes.svg");
+        write_chart(&path, &panels).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("STELARA"));
+        assert!(svg.contains("Price change histogram"));
+
+        // The text of a PNG is drawn with the bundled font.
+        let path = directory.path().join("changes.png");
+        write_chart(&path, &panels).unwrap();
+        assert!(std::fs::read(&path).unwrap().starts_with(b"\x89PNG"));
+
+        assert!(write_chart(&directory.path().join("changes.gif"), &panels).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+//! The `classes` module provides code for mapping drugs to therapeutic classes and rolling the
+//! price changes up by class, which shows the kinds of drugs whose prices are rising rather than
+//! single drugs.
+
+use crate::comparison::ComparisonRow;
+use crate::data_store::normalize_description;
+use crate::filters::normalize_ndc;
+use crate::report::{drugs_label, Section};
+use crate::statistics::cents;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+
+/// The headers a class map's first column may have. A first line with one of them is skipped.
+const KEY_HEADERS: [&str; 4] = ["ndc", "name", "description", "drug"];
+
+/// The `ClassMap` struct holds the therapeutic class of drugs, identified by their NDC or
+/// their description.
+#[derive(Debug, Clone, Default)]
+pub struct ClassMap {
+    /// The classes of drugs identified by NDC, keyed by the 11 digit NDC.
+    ndcs: HashMap<String, String>,
+
+    /// The classes of drugs identified by description, keyed by the normalized description.
+    descriptions: HashMap<String, String>,
+}
+
+impl ClassMap {
+    /// Read a class map, with an NDC or description and a class on each line, separated by a
+    /// comma. Text made up of digits and dashes is taken to be an NDC. A header line, blank
+    /// lines and lines starting with `#` are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text of the class map.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `ClassMap`, on error returns a String describing the problem.
+    pub fn parse(text: &str) -> Result<ClassMap, String> {
+        let mut map = ClassMap::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // The descriptions may have commas, but the class names are not expected to.
+            let (key, class) = line
+                .rsplit_once(',')
+                .map(|(key, class)| (key.trim().trim_matches('"'), class.trim().trim_matches('"')))
+                .filter(|(key, class)| !key.is_empty() && !class.is_empty())
+                .ok_or_else(|| {
+                    format!("Invalid class map entry on line {}: {}", number + 1, line)
+                })?;
+
+            if number == 0 && KEY_HEADERS.contains(&key.to_lowercase().as_str()) {
+                continue;
+            }
+
+            if key.chars().all(|c| c.is_ascii_digit() || c == '-') {
+                map.ndcs.insert(normalize_ndc(key), class.to_string());
+            } else {
+                map.descriptions
+                    .insert(normalize_description(key), class.to_string());
+            }
+        }
+        Ok(map)
+    }
+
+    /// Get the therapeutic class of a row's drug. A class given for the NDC is preferred to
+    /// one given for the description.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the class, or None if the map has no class for the drug.
+    pub fn class_of(&self, row: &ComparisonRow) -> Option<&str> {
+        self.ndcs
+            .get(&normalize_ndc(&row.ndc))
+            .or_else(|| {
+                self.descriptions
+                    .get(&normalize_description(&row.description))
+            })
+            .map(String::as_str)
+    }
+}
+
+/// The price changes of a therapeutic class.
+#[derive(Debug, Clone, Copy, Default)]
+struct ClassChanges {
+    /// The number of price changes.
+    count: u64,
+
+    /// The sum of the price changes.
+    total: Decimal,
+}
+
+impl ClassChanges {
+    /// The average price change.
+    fn average(&self) -> Decimal {
+        self.total / Decimal::from(self.count.max(1))
+    }
+}
+
+/// The `ClassRollup` struct sums the price changes of each therapeutic class in each section
+/// of the report.
+#[derive(Debug, Clone, Default)]
+pub struct ClassRollup {
+    /// The price changes of each class, by section.
+    sections: BTreeMap<Section, HashMap<String, ClassChanges>>,
+}
+
+impl ClassRollup {
+    /// Add a price change to the rollup. Changes to drugs without a class are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The section of the report the change is in.
+    /// * `row` - The row of comparison data.
+    /// * `class_map` - The map giving the class of the row's drug.
+    pub fn add(&mut self, section: Section, row: &ComparisonRow, class_map: &ClassMap) {
+        let Some(class) = class_map.class_of(row) else {
+            return;
+        };
+
+        let changes = self
+            .sections
+            .entry(section)
+            .or_default()
+            .entry(class.to_string())
+            .or_default();
+        changes.count += 1;
+        changes.total += row.new_price - row.old_price;
+    }
+
+    /// Generate the section of the report ranking the therapeutic classes by their average
+    /// price change, largest increase first.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The section of the report.
+    /// * `count` - The number of classes requested for the report.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the section.
+    pub fn report(&self, section: &Section, count: usize) -> String {
+        let mut report = format!(
+            "Top {} {}therapeutic classes by average NADAC per unit price change {}:\n",
+            count,
+            drugs_label(section),
+            section.period
+        );
+
+        let mut classes: Vec<(&String, &ClassChanges)> = self
+            .sections
+            .get(section)
+            .map(|classes| classes.iter().collect())
+            .unwrap_or_default();
+        classes.sort_by(|a, b| b.1.average().cmp(&a.1.average()).then_with(|| a.0.cmp(b.0)));
+
+        for (class, changes) in classes.into_iter().take(count) {
+            report.push_str(&format!(
+                "{} average of {} change(s): {}\n",
+                cents(changes.average()),
+                changes.count,
+                class
+            ));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dates::Period;
+    use csv_async::ByteRecord;
+
+    #[test]
+    fn test_class_map() {
+        assert!(ClassMap::parse("HUMIRA PEN\n").is_err());
+
+        let class_map = ClassMap::parse(
+            "NDC,Class\n\
+            # Made up\n\
+            00002-7510-01,Insulins\n\
+            humalog  100 unit/ml vial,Wrong\n\
+            \"HUMIRA(CF) PEN 40 MG/0.4 ML\",Immunosuppressants\n\
+            STELARA 90 MG/ML SYRINGE,Immunosuppressants\n",
+        )
+        .unwrap();
+
+        let section = Section {
+            period: Period::Year(2023),
+            classification: None,
+            pricing_unit: None,
+        };
+        let mut rollup = ClassRollup::default();
+        for (description, ndc, old_price, new_price) in [
+            ("HUMALOG 100 UNIT/ML VIAL", "00002751001", "300", "116.86"),
+            ("HUMIRA(CF) PEN 40 MG/0.4 ML", "00074055402", "3000", "3320"),
+            ("STELARA 90 MG/ML SYRINGE", "57894006103", "13000", "13800"),
+            ("ASPIRIN 81 MG", "00000000001", "1", "2"),
+        ] {
+            let record = ByteRecord::from(vec![description, ndc, old_price, new_price]);
+            let row = ComparisonRow::from_record(&record).unwrap();
+            rollup.add(section, &row, &class_map);
+        }
+
+        assert_eq!(
+            rollup.report(&section, 5),
+            "Top 5 therapeutic classes by average NADAC per unit price change of 2023:\n\
+            $560.00 average of 2 change(s): Immunosuppressants\n\
+            -$183.14 average of 1 change(s): Insulins\n"
+        );
+    }
+}
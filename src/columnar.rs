@@ -0,0 +1,408 @@
+//! The `columnar` module provides code for writing the ranked price changes, or every price
+//! change with `--all`, as Parquet or Arrow IPC files, so they can be loaded into a data lake
+//! without parsing the text report.
+
+use crate::data_store::DataStore;
+use crate::provenance::Provenance;
+use crate::report::{drugs_label, period_label, ranked_entries, OutputFormat, Section};
+use arrow::array::{ArrayRef, Date32Array, Decimal128Array, StringArray, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use chrono::NaiveDate;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+
+/// The number of decimal places of the prices and price changes in the files.
+const CHANGE_SCALE: u32 = 5;
+
+/// The number of rows gathered before they are written as a batch.
+const BATCH_ROWS: usize = 8192;
+
+/// A row of a columnar file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnarRow {
+    /// The period of the row's section, e.g. `2023`, when the rows are ranked in sections.
+    pub period: Option<String>,
+
+    /// The drugs of the row's section, e.g. `brand`, or empty for all of them.
+    pub drugs: Option<String>,
+
+    /// The kind of change of the row's list, e.g. `increases`.
+    pub list: Option<&'static str>,
+
+    /// The rank of the row in its list, starting from 1. Tied rows share their rank.
+    pub rank: u64,
+
+    /// The change in the per unit price.
+    pub change: Decimal,
+
+    /// The change as a percent of the old price, when it is known.
+    pub percent: Option<Decimal>,
+
+    /// The per unit price before the change, when it is known.
+    pub old_price: Option<Decimal>,
+
+    /// The per unit price after the change, when it is known.
+    pub new_price: Option<Decimal>,
+
+    /// The date the new price took effect, when it is known.
+    pub effective_date: Option<NaiveDate>,
+
+    /// The description of the drug.
+    pub description: String,
+
+    /// The NDC of the drug, which may be empty.
+    pub ndc: String,
+}
+
+/// Make a column of decimal numbers.
+///
+/// # Arguments
+///
+/// * `values` - The numbers, or None where they are not known.
+/// * `scale` - The number of decimal places of the column, which the numbers are rounded to.
+///
+/// # Returns
+///
+/// On success, returns the column, on error returns a std::error::Error in a Box.
+fn decimal_array(
+    values: impl Iterator<Item = Option<Decimal>>,
+    scale: u32,
+) -> Result<ArrayRef, Box<dyn std::error::Error>> {
+    let array = values
+        .map(|value| {
+            value.map(|value| {
+                let mut value = value.round_dp(scale);
+                value.rescale(scale);
+                value.mantissa()
+            })
+        })
+        .collect::<Decimal128Array>()
+        .with_precision_and_scale(38, scale as i8)?;
+    Ok(Arc::new(array))
+}
+
+/// The writer of a columnar file.
+enum BatchWriter<W: Write + Send> {
+    /// Writes a Parquet file.
+    Parquet(ArrowWriter<W>),
+
+    /// Writes an Arrow IPC file.
+    Arrow(FileWriter<W>),
+}
+
+/// The `ColumnarWriter` struct writes rows to a Parquet or Arrow IPC file in batches, so any
+/// number of rows can be written without holding them all in memory.
+pub struct ColumnarWriter<W: Write + Send> {
+    /// The columns of the file.
+    schema: SchemaRef,
+
+    /// When true, the file has columns for the section and list of each row.
+    ranked: bool,
+
+    /// The number of decimal places of the percent changes.
+    percent_places: u32,
+
+    /// The rows not yet written.
+    rows: Vec<ColumnarRow>,
+
+    /// Where the batches are written.
+    writer: BatchWriter<W>,
+}
+
+impl<W: Write + Send> ColumnarWriter<W> {
+    /// Create a new `ColumnarWriter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `output_format` - `OutputFormat::Parquet` or `OutputFormat::Arrow`.
+    /// * `ranked` - When true, the file has columns for the section and list of each row.
+    /// * `percent_places` - The number of decimal places of the percent changes.
+    /// * `provenance` - When set, how the report was made, kept as JSON in the `provenance`
+    ///   key of the file's metadata.
+    /// * `out` - Where the file is written.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the new `ColumnarWriter`, on error returns a std::error::Error in a
+    /// Box.
+    pub fn new(
+        output_format: OutputFormat,
+        ranked: bool,
+        percent_places: u32,
+        provenance: Option<&Provenance>,
+        out: W,
+    ) -> Result<ColumnarWriter<W>, Box<dyn std::error::Error>> {
+        let mut fields = Vec::new();
+        if ranked {
+            fields.push(Field::new("period", DataType::Utf8, false));
+            fields.push(Field::new("drugs", DataType::Utf8, true));
+            fields.push(Field::new("list", DataType::Utf8, false));
+        }
+        fields.push(Field::new("rank", DataType::UInt64, false));
+        fields.push(Field::new(
+            "change",
+            DataType::Decimal128(38, CHANGE_SCALE as i8),
+            false,
+        ));
+        fields.push(Field::new(
+            "percent",
+            DataType::Decimal128(38, percent_places as i8),
+            true,
+        ));
+        for name in ["old_price", "new_price"] {
+            fields.push(Field::new(
+                name,
+                DataType::Decimal128(38, CHANGE_SCALE as i8),
+                true,
+            ));
+        }
+        fields.push(Field::new("effective_date", DataType::Date32, true));
+        fields.push(Field::new("description", DataType::Utf8, false));
+        fields.push(Field::new("ndc", DataType::Utf8, true));
+        let mut metadata = HashMap::new();
+        if let Some(provenance) = provenance {
+            metadata.insert("provenance".to_string(), serde_json::to_string(provenance)?);
+        }
+        let schema = Arc::new(Schema::new_with_metadata(fields, metadata));
+
+        let writer = match output_format {
+            OutputFormat::Parquet => {
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::ZSTD(ZstdLevel::default()))
+                    .build();
+                BatchWriter::Parquet(ArrowWriter::try_new(out, schema.clone(), Some(properties))?)
+            }
+            OutputFormat::Arrow => BatchWriter::Arrow(FileWriter::try_new(out, &schema)?),
+            _ => return Err(format!("{} is not a columnar format", output_format.name()).into()),
+        };
+
+        Ok(ColumnarWriter {
+            schema,
+            ranked,
+            percent_places,
+            rows: Vec::new(),
+            writer,
+        })
+    }
+
+    /// Add a row, writing a batch once `BATCH_ROWS` rows have been gathered.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a std::error::Error in a Box.
+    pub fn push(&mut self, row: ColumnarRow) -> Result<(), Box<dyn std::error::Error>> {
+        self.rows.push(row);
+        if self.rows.len() >= BATCH_ROWS {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    /// Write the gathered rows as a batch.
+    fn write_batch(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let rows = std::mem::take(&mut self.rows);
+        let text = |value: fn(&ColumnarRow) -> Option<&str>| -> ArrayRef {
+            Arc::new(rows.iter().map(value).collect::<StringArray>())
+        };
+        let number = |value: fn(&ColumnarRow) -> Option<Decimal>, scale: u32| {
+            decimal_array(rows.iter().map(value), scale)
+        };
+
+        let mut columns: Vec<ArrayRef> = Vec::new();
+        if self.ranked {
+            columns.push(text(|row| row.period.as_deref()));
+            columns.push(text(|row| row.drugs.as_deref().filter(|d| !d.is_empty())));
+            columns.push(text(|row| row.list));
+        }
+        columns.push(Arc::new(
+            rows.iter().map(|row| row.rank).collect::<UInt64Array>(),
+        ));
+        columns.push(number(|row| Some(row.change), CHANGE_SCALE)?);
+        columns.push(number(|row| row.percent, self.percent_places)?);
+        columns.push(number(|row| row.old_price, CHANGE_SCALE)?);
+        columns.push(number(|row| row.new_price, CHANGE_SCALE)?);
+        // Arrow dates are the number of days since 1970-01-01.
+        let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap_or_default();
+        columns.push(Arc::new(
+            rows.iter()
+                .map(|row| {
+                    row.effective_date
+                        .map(|date| (date - epoch).num_days() as i32)
+                })
+                .collect::<Date32Array>(),
+        ));
+        columns.push(text(|row| Some(row.description.as_str())));
+        columns.push(text(|row| {
+            Some(row.ndc.as_str()).filter(|ndc| !ndc.is_empty())
+        }));
+
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        match &mut self.writer {
+            BatchWriter::Parquet(writer) => writer.write(&batch)?,
+            BatchWriter::Arrow(writer) => writer.write(&batch)?,
+        }
+        Ok(())
+    }
+
+    /// Write the rows not yet written and finish the file.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a std::error::Error in a Box.
+    pub fn finish(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.rows.is_empty() {
+            self.write_batch()?;
+        }
+        match self.writer {
+            BatchWriter::Parquet(writer) => {
+                writer.close()?;
+            }
+            BatchWriter::Arrow(mut writer) => writer.finish()?,
+        }
+        Ok(())
+    }
+}
+
+/// Generate the ranked price changes of the report's sections as a columnar file, with a row
+/// for each price change of each list.
+///
+/// # Arguments
+///
+/// * `output_format` - `OutputFormat::Parquet` or `OutputFormat::Arrow`.
+/// * `sections` - The sections of the report and their records stores, in date order.
+/// * `percent_places` - The number of decimal places of the percent changes.
+/// * `provenance` - When set, how the report was made.
+///
+/// # Returns
+///
+/// On success, returns the contents of the file, on error returns a std::error::Error in a
+/// Box.
+pub fn columnar_report(
+    output_format: OutputFormat,
+    sections: &[(&Section, &DataStore)],
+    percent_places: u32,
+    provenance: Option<&Provenance>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut file = Vec::new();
+    let mut writer =
+        ColumnarWriter::new(output_format, true, percent_places, provenance, &mut file)?;
+    for (section, data_store) in sections {
+        let period = period_label(&section.period);
+        let drugs = drugs_label(section).trim().to_string();
+        for (kind, _, entries) in ranked_entries(data_store, percent_places) {
+            for entry in entries {
+                writer.push(ColumnarRow {
+                    period: Some(period.clone()),
+                    drugs: Some(drugs.clone()),
+                    list: Some(kind),
+                    rank: entry.rank as u64,
+                    change: entry.change,
+                    percent: entry.percent,
+                    old_price: entry.old_price,
+                    new_price: entry.new_price,
+                    effective_date: entry.effective_date,
+                    description: entry.description,
+                    ndc: entry.ndc,
+                })?;
+            }
+        }
+    }
+    writer.finish()?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_store::{Direction, Metric, PriceDetails};
+    use crate::dates::Period;
+    use arrow::array::{Array, AsArray};
+    use arrow::datatypes::{Date32Type, Decimal128Type};
+    use arrow::ipc::reader::FileReader;
+    use bytes::Bytes;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_columnar_report() {
+        let mut data_store = DataStore::new(2, 2, Metric::Change, Direction::Both).unwrap();
+        data_store
+            .insert_priced_change(
+                Decimal::new(80239, 2),
+                "STELARA",
+                "57894006103",
+                Some(PriceDetails {
+                    old_price: Decimal::new(2515830, 2),
+                    effective_date: NaiveDate::from_ymd_opt(2023, 1, 11),
+                }),
+            )
+            .unwrap();
+        for (cents, description, ndc) in [(32019, "HUMIRA", ""), (-18314, "HUMALOG", "00002751001")]
+        {
+            data_store
+                .insert_change(Decimal::new(cents, 2), description, ndc)
+                .unwrap();
+        }
+        let section = Section {
+            period: Period::Year(2023),
+            classification: None,
+            pricing_unit: None,
+        };
+        let sections = [(&section, &data_store)];
+
+        let check = |batch: &RecordBatch| {
+            assert_eq!(batch.num_rows(), 3);
+            assert_eq!(batch.num_columns(), 11);
+            let lists = batch.column(2).as_string::<i32>();
+            assert_eq!(lists.value(0), "increases");
+            assert_eq!(lists.value(2), "decreases");
+            let changes = batch.column(4).as_primitive::<Decimal128Type>();
+            assert_eq!(changes.value(0), 80_239_000);
+            assert_eq!(changes.value(2), -18_314_000);
+            let percents = batch.column(5).as_primitive::<Decimal128Type>();
+            assert_eq!(percents.value(0), 319);
+            assert!(percents.is_null(1));
+            let new_prices = batch.column(7).as_primitive::<Decimal128Type>();
+            assert_eq!(new_prices.value(0), 2_596_069_000);
+            assert!(new_prices.is_null(1));
+            let dates = batch.column(8).as_primitive::<Date32Type>();
+            assert_eq!(dates.value(0), 19_368);
+            assert!(dates.is_null(2));
+            assert!(batch.column(10).is_null(1));
+        };
+
+        let parquet = columnar_report(OutputFormat::Parquet, &sections, 2, None).unwrap();
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(parquet))
+            .unwrap()
+            .build()
+            .unwrap();
+        check(&reader.next().unwrap().unwrap());
+
+        // The provenance is kept in the schema's metadata.
+        let provenance = Provenance {
+            sources: vec!["data/sample_comparison.csv".to_string()],
+            sha256: "00ff".to_string(),
+            fetched: "2024-01-02T03:04:05+00:00".to_string(),
+            arguments: vec!["-y".to_string(), "2023".to_string()],
+            version: "1.0.0".to_string(),
+        };
+        let arrow = columnar_report(OutputFormat::Arrow, &sections, 2, Some(&provenance)).unwrap();
+        let mut reader = FileReader::try_new(Cursor::new(arrow), None).unwrap();
+        assert_eq!(
+            reader.schema().metadata().get("provenance"),
+            Some(&serde_json::to_string(&provenance).unwrap())
+        );
+        check(&reader.next().unwrap().unwrap());
+    }
+}
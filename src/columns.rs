@@ -0,0 +1,195 @@
+//! The `columns` module provides code for mapping the columns of price change CSV files that
+//! are not laid out like the NADAC comparison file onto the NADAC comparison columns, so the
+//! rest of the program can process them unchanged.
+
+use csv_async::ByteRecord;
+use std::borrow::Cow;
+
+/// The names of the NADAC comparison columns, in the order of the columns in the CSV file,
+/// followed by the over-the-counter indicator, the pricing unit, the explanation codes and the
+/// prices of the corresponding generic drug, which the comparison file does not have but other
+/// sources do.
+pub const COLUMN_NAMES: [&str; 15] = [
+    "description",
+    "ndc",
+    "old_price",
+    "new_price",
+    "classification",
+    "percent_change",
+    "reason",
+    "start_date",
+    "end_date",
+    "effective_date",
+    "otc",
+    "pricing_unit",
+    "explanation_code",
+    "old_generic_price",
+    "new_generic_price",
+];
+
+/// Put a header of a CSV file in a comparable form, without case, spaces or punctuation. Data
+/// sets publish the same header in several forms, e.g. `Units Reimbursed` and
+/// `units_reimbursed`.
+///
+/// # Arguments
+///
+/// * `header` - The header.
+///
+/// # Returns
+///
+/// A new String containing the comparable form of the header.
+pub fn header_key(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Get a field of a record as text. Records are kept as bytes so that only the fields that are
+/// actually used need to be decoded. Bytes that are not valid UTF-8 are replaced with U+FFFD.
+///
+/// # Arguments
+///
+/// * `record` - The record.
+/// * `index` - The index of the field.
+///
+/// # Returns
+///
+/// An Option which will contain the text of the field, or None if the record does not have the
+/// field. The text borrows from the record unless bytes had to be replaced.
+pub fn decode_field(record: &ByteRecord, index: usize) -> Option<Cow<'_, str>> {
+    record.get(index).map(String::from_utf8_lossy)
+}
+
+/// The `ColumnMap` struct records which column of an input file holds each NADAC comparison
+/// column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnMap {
+    /// For each NADAC comparison column, the index of the input column holding it, or `None`
+    /// if the input does not have it.
+    indexes: [Option<usize>; COLUMN_NAMES.len()],
+}
+
+impl ColumnMap {
+    /// Determine if a column is mapped to an input column.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the column, one of `COLUMN_NAMES`.
+    ///
+    /// # Returns
+    ///
+    /// True if the input has the column.
+    pub fn has(&self, name: &str) -> bool {
+        COLUMN_NAMES
+            .iter()
+            .position(|column| *column == name)
+            .is_some_and(|position| self.indexes[position].is_some())
+    }
+
+    /// Create a `ColumnMap` from `NAME=INDEX` mappings. Columns that are not mapped are left
+    /// empty, so only the columns the input actually has need to be given.
+    ///
+    /// # Arguments
+    ///
+    /// * `mappings` - The mappings, e.g. `description=0` or `old_price=2`. Indexes start at 0.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `ColumnMap`, on error returns a String describing the problem.
+    pub fn from_mappings(mappings: &[String]) -> Result<ColumnMap, String> {
+        let mut indexes: [Option<usize>; COLUMN_NAMES.len()] = [None; COLUMN_NAMES.len()];
+
+        for mapping in mappings {
+            let (name, index) = mapping.split_once('=').ok_or_else(|| {
+                format!("Expected a column like NAME=INDEX but found {}", mapping)
+            })?;
+
+            let position = COLUMN_NAMES
+                .iter()
+                .position(|column| *column == name.trim())
+                .ok_or_else(|| {
+                    format!(
+                        "Unknown column {}, expected one of {}",
+                        name.trim(),
+                        COLUMN_NAMES.join(", ")
+                    )
+                })?;
+
+            let index = index
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("Expected a column index but found {}", index.trim()))?;
+
+            indexes[position] = Some(index);
+        }
+
+        Ok(ColumnMap { indexes })
+    }
+
+    /// Rearrange an input record into the NADAC comparison column order. Columns the input
+    /// record does not have are left empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The input record.
+    ///
+    /// # Returns
+    ///
+    /// The record with the NADAC comparison columns.
+    pub fn apply(&self, record: &ByteRecord) -> ByteRecord {
+        let mut mapped = ByteRecord::with_capacity(record.as_slice().len(), self.indexes.len());
+        for index in self.indexes {
+            mapped.push_field(index.and_then(|i| record.get(i)).unwrap_or(b""));
+        }
+        mapped.set_position(record.position().cloned());
+        mapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_map() {
+        let mappings = vec![
+            "description=3".to_string(),
+            "old_price=1".to_string(),
+            "new_price=2".to_string(),
+            "effective_date=0".to_string(),
+        ];
+        let map = ColumnMap::from_mappings(&mappings).unwrap();
+
+        let record = ByteRecord::from(vec!["01/04/2023", "1.50", "2.25", "ASPIRIN 81 MG"]);
+        let mapped = map.apply(&record);
+
+        assert_eq!(mapped.len(), COLUMN_NAMES.len());
+        assert_eq!(mapped.get(0), Some(&b"ASPIRIN 81 MG"[..]));
+        assert_eq!(mapped.get(2), Some(&b"1.50"[..]));
+        assert_eq!(mapped.get(3), Some(&b"2.25"[..]));
+        assert_eq!(mapped.get(9), Some(&b"01/04/2023"[..]));
+        assert_eq!(mapped.get(5), Some(&b""[..]));
+        assert!(map.has("old_price"));
+        assert!(!map.has("otc"));
+
+        assert!(ColumnMap::from_mappings(&["price=1".to_string()]).is_err());
+        assert!(ColumnMap::from_mappings(&["ndc".to_string()]).is_err());
+        assert!(ColumnMap::from_mappings(&["ndc=x".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_decode_field() {
+        let record = ByteRecord::from(vec![&b"CAF\xc9"[..]]);
+        assert_eq!(decode_field(&record, 0).as_deref(), Some("CAF\u{fffd}"));
+        assert_eq!(decode_field(&record, 1), None);
+    }
+
+    #[test]
+    fn test_header_key() {
+        assert_eq!(header_key("Units Reimbursed"), "unitsreimbursed");
+        assert_eq!(header_key("units_reimbursed"), "unitsreimbursed");
+        assert_eq!(header_key("PRODUCTNDC"), "productndc");
+    }
+}
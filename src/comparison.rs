@@ -0,0 +1,262 @@
+//! The `comparison` module provides the typed form of a row of NADAC comparison data, so the
+//! rest of the program can use named, parsed fields instead of picking columns out of records
+//! by index.
+
+use crate::data_store::parse_price;
+use crate::dates::{parse_date, EFFECTIVE_DATE_FIELD};
+use chrono::NaiveDate;
+use csv_async::ByteRecord;
+use rust_decimal::Decimal;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer};
+use std::borrow::Cow;
+
+/// The index of the description in the NADAC comparison columns.
+const DESCRIPTION_FIELD: usize = 0;
+
+/// The index of the old price in the NADAC comparison columns.
+const OLD_PRICE_FIELD: usize = 2;
+
+/// The index of the new price in the NADAC comparison columns.
+const NEW_PRICE_FIELD: usize = 3;
+
+/// The `FieldError` struct describes why a record cannot be read into a `ComparisonRow`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldError {
+    /// The index of the offending column in the NADAC comparison columns.
+    pub field: usize,
+
+    /// Why the record cannot be used.
+    pub reason: String,
+}
+
+impl FieldError {
+    /// Create a new `FieldError`.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The index of the offending column.
+    /// * `reason` - Why the record cannot be used.
+    fn new(field: usize, reason: impl Into<String>) -> FieldError {
+        FieldError {
+            field,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// The columns of a record in the NADAC comparison column order, as text. Serde reads them
+/// positionally, so the columns the program does not use are still listed, and missing columns
+/// at the end of the record are empty.
+#[derive(Debug, Deserialize)]
+struct RawRow<'a> {
+    #[serde(borrow, default, deserialize_with = "lossy")]
+    description: Cow<'a, str>,
+    #[serde(borrow, default, deserialize_with = "lossy")]
+    ndc: Cow<'a, str>,
+    #[serde(borrow, default, deserialize_with = "lossy")]
+    old_price: Cow<'a, str>,
+    #[serde(borrow, default, deserialize_with = "lossy")]
+    new_price: Cow<'a, str>,
+    #[serde(borrow, default, deserialize_with = "lossy")]
+    classification: Cow<'a, str>,
+    #[serde(default)]
+    _percent_change: IgnoredAny,
+    #[serde(borrow, default, deserialize_with = "lossy")]
+    reason: Cow<'a, str>,
+    #[serde(default)]
+    _start_date: IgnoredAny,
+    #[serde(default)]
+    _end_date: IgnoredAny,
+    #[serde(borrow, default, deserialize_with = "lossy")]
+    effective_date: Cow<'a, str>,
+    #[serde(borrow, default, deserialize_with = "lossy")]
+    otc: Cow<'a, str>,
+    #[serde(borrow, default, deserialize_with = "lossy")]
+    pricing_unit: Cow<'a, str>,
+    #[serde(borrow, default, deserialize_with = "lossy")]
+    explanation_code: Cow<'a, str>,
+    #[serde(borrow, default, deserialize_with = "lossy")]
+    old_generic_price: Cow<'a, str>,
+    #[serde(borrow, default, deserialize_with = "lossy")]
+    new_generic_price: Cow<'a, str>,
+}
+
+/// A row of NADAC comparison data. The text fields borrow from the record they were read from,
+/// so reading a row does not copy it unless bytes that are not valid UTF-8 had to be replaced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparisonRow<'a> {
+    /// The description of the drug.
+    pub description: Cow<'a, str>,
+
+    /// The National Drug Code of the drug. Not every source has NDCs, so this may be empty.
+    pub ndc: Cow<'a, str>,
+
+    /// The per unit price before the change.
+    pub old_price: Decimal,
+
+    /// The per unit price after the change.
+    pub new_price: Decimal,
+
+    /// The classification for rate setting, `B` for brand or `G` for generic.
+    pub classification: Cow<'a, str>,
+
+    /// The primary reason for the change.
+    pub reason: Cow<'a, str>,
+
+    /// The date the new price took effect, or `None` if the row does not have one.
+    pub effective_date: Option<NaiveDate>,
+
+    /// The over-the-counter indicator, `Y` for over-the-counter drugs. The NADAC comparison
+    /// file does not have this column, so it is usually empty.
+    pub otc: Cow<'a, str>,
+
+    /// The unit the prices are per, `EA`, `ML` or `GM`. The NADAC comparison file does not
+    /// have this column, so it is usually empty.
+    pub pricing_unit: Cow<'a, str>,
+
+    /// The NADAC explanation codes, e.g. `1, 5`. The NADAC comparison file does not have this
+    /// column, so it is usually empty.
+    pub explanation_code: Cow<'a, str>,
+
+    /// The price of the corresponding generic drug before the change, which the NADAC
+    /// comparison file does not have. Only brand name drugs with a generic have it.
+    pub old_generic_price: Cow<'a, str>,
+
+    /// The price of the corresponding generic drug after the change, which the NADAC
+    /// comparison file does not have. Only brand name drugs with a generic have it.
+    pub new_generic_price: Cow<'a, str>,
+}
+
+impl<'a> ComparisonRow<'a> {
+    /// Read a row from a record in the NADAC comparison column order.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The record.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `ComparisonRow`, on error returns a `FieldError` with the
+    /// offending column and the reason the record cannot be used.
+    pub fn from_record(record: &'a ByteRecord) -> Result<ComparisonRow<'a>, FieldError> {
+        // Every column is optional and decoded lossily, so this only fails if the record
+        // cannot be read at all.
+        let raw: RawRow = record
+            .deserialize(None)
+            .map_err(|e| FieldError::new(DESCRIPTION_FIELD, e.to_string()))?;
+
+        if raw.description.trim().is_empty() {
+            return Err(FieldError::new(DESCRIPTION_FIELD, "missing description"));
+        }
+        let old_price = price(OLD_PRICE_FIELD, &raw.old_price)?;
+        let new_price = price(NEW_PRICE_FIELD, &raw.new_price)?;
+        let effective_date = optional_date(EFFECTIVE_DATE_FIELD, &raw.effective_date)?;
+
+        Ok(ComparisonRow {
+            description: raw.description,
+            ndc: raw.ndc,
+            old_price,
+            new_price,
+            classification: raw.classification,
+            reason: raw.reason,
+            effective_date,
+            otc: raw.otc,
+            pricing_unit: raw.pricing_unit,
+            explanation_code: raw.explanation_code,
+            old_generic_price: raw.old_generic_price,
+            new_generic_price: raw.new_generic_price,
+        })
+    }
+}
+
+/// Read a column as text, replacing bytes that are not valid UTF-8 with U+FFFD.
+fn lossy<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Cow<'de, str>, D::Error> {
+    let bytes: &'de [u8] = Deserialize::deserialize(deserializer)?;
+    Ok(String::from_utf8_lossy(bytes))
+}
+
+/// Read a price with `parse_price`, which must not be empty.
+fn price(field: usize, text: &str) -> Result<Decimal, FieldError> {
+    if text.trim().is_empty() {
+        return Err(FieldError::new(field, "missing price"));
+    }
+    parse_price(text).map_err(|e| FieldError::new(field, format!("invalid price: {}", e)))
+}
+
+/// Read a date with `parse_date`, where an empty column means there is no date.
+fn optional_date(field: usize, text: &str) -> Result<Option<NaiveDate>, FieldError> {
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    parse_date(text)
+        .map(Some)
+        .map_err(|e| FieldError::new(field, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_from_record() {
+        let mut fields = vec![
+            "ASPIRIN 81 MG",
+            "00001",
+            "1.25",
+            "1.50",
+            "G",
+            "20.00",
+            "Survey Rate",
+            "12/28/2022",
+            "01/03/2023",
+            "01/04/2023",
+        ];
+        let record = ByteRecord::from(fields.clone());
+        let row = ComparisonRow::from_record(&record).unwrap();
+        assert_eq!(row.description, "ASPIRIN 81 MG");
+        assert_eq!(row.old_price, Decimal::from_str("1.25").unwrap());
+        assert_eq!(row.classification, "G");
+        assert_eq!(row.effective_date, NaiveDate::from_ymd_opt(2023, 1, 4));
+        assert_eq!(row.otc, "");
+        assert_eq!(row.pricing_unit, "");
+        assert_eq!(row.explanation_code, "");
+
+        fields[2] = " $1,001.25 ";
+        fields[9] = "";
+        let record = ByteRecord::from(fields.clone());
+        let row = ComparisonRow::from_record(&record).unwrap();
+        assert_eq!(row.old_price, Decimal::from_str("1001.25").unwrap());
+        assert_eq!(row.effective_date, None);
+
+        fields[3] = "N/A";
+        let record = ByteRecord::from(fields.clone());
+        assert_eq!(
+            ComparisonRow::from_record(&record).unwrap_err().field,
+            NEW_PRICE_FIELD
+        );
+
+        fields[9] = "next tuesday";
+        fields[3] = "1.50";
+        let record = ByteRecord::from(fields.clone());
+        assert_eq!(
+            ComparisonRow::from_record(&record),
+            Err(FieldError::new(9, "Unrecognized date next tuesday"))
+        );
+
+        fields[0] = " ";
+        let record = ByteRecord::from(fields.clone());
+        assert_eq!(
+            ComparisonRow::from_record(&record),
+            Err(FieldError::new(0, "missing description"))
+        );
+
+        // Bytes that are not valid UTF-8 are replaced rather than losing the row.
+        let record = ByteRecord::from(vec![&b"CAF\xc9"[..], b"", b"1", b"2"]);
+        assert_eq!(
+            ComparisonRow::from_record(&record).unwrap().description,
+            "CAF\u{fffd}"
+        );
+    }
+}
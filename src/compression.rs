@@ -0,0 +1,181 @@
+//! The `compression` module provides code for transparently decompressing the CSV data before
+//! it reaches the CSV reader.
+
+use crate::data_source::DataReader;
+use async_compression::futures::bufread::{BzDecoder, GzipDecoder, ZstdDecoder};
+use futures::io::{AsyncBufReadExt, BufReader};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+
+/// Enum describing how the bytes from a `DataSource` are compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// The data is plain, uncompressed CSV.
+    None,
+
+    /// The data is gzip compressed.
+    Gzip,
+
+    /// The data is Zstandard compressed.
+    Zstd,
+
+    /// The data is bzip2 compressed.
+    Bzip2,
+}
+
+impl Compression {
+    /// Guess the compression from the file extension at the end of a path or URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The path or URL of the data.
+    ///
+    /// # Returns
+    ///
+    /// The compression implied by the extension, or `Compression::None` if the extension is not
+    /// recognized.
+    pub fn from_name(name: &str) -> Compression {
+        // Ignore any query string or fragment on a URL so that `file.csv.gz?token=abc` is still
+        // recognized.
+        let name = name.split(['?', '#']).next().unwrap_or(name);
+        let name = name.to_ascii_lowercase();
+
+        if name.ends_with(".gz") {
+            Compression::Gzip
+        } else if name.ends_with(".zst") {
+            Compression::Zstd
+        } else if name.ends_with(".bz2") {
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Guess the compression from the value of an HTTP `Content-Encoding` header.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding` - The header value.
+    ///
+    /// # Returns
+    ///
+    /// The compression named by the header, or `Compression::None` for unknown encodings.
+    pub fn from_content_encoding(encoding: &str) -> Compression {
+        match encoding.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Compression::Gzip,
+            "zstd" => Compression::Zstd,
+            "bzip2" | "x-bzip2" => Compression::Bzip2,
+            _ => Compression::None,
+        }
+    }
+
+    /// Guess the compression from the first few bytes of the data.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The leading bytes of the data.
+    ///
+    /// # Returns
+    ///
+    /// The compression whose magic number starts the data, or `Compression::None`.
+    pub fn from_magic(bytes: &[u8]) -> Compression {
+        if bytes.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if bytes.starts_with(BZIP2_MAGIC) {
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Wrap a reader with the decoder for this compression. When this compression is
+    /// `Compression::None` (the name of the data gave no hint), the leading bytes of the data are
+    /// checked for a known magic number instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader producing the compressed bytes.
+    ///
+    /// # Returns
+    ///
+    /// On success, a new `DataReader` that produces the decompressed bytes, on error returns the
+    /// std::io::Error from reading the leading bytes.
+    pub async fn decode(self, reader: DataReader) -> std::io::Result<DataReader> {
+        let mut reader = BufReader::new(reader);
+
+        let compression = match self {
+            // Peek at the buffered bytes without consuming them, so the CSV reader still sees
+            // the whole stream for plain data.
+            Compression::None => Compression::from_magic(reader.fill_buf().await?),
+            compression => compression,
+        };
+
+        Ok(match compression {
+            Compression::None => Box::pin(reader),
+            Compression::Gzip => {
+                let mut decoder = GzipDecoder::new(reader);
+                // Some mirrors concatenate several gzip members into one file, so keep decoding
+                // until the input runs out rather than stopping at the end of the first member.
+                decoder.multiple_members(true);
+                Box::pin(decoder)
+            }
+            Compression::Zstd => {
+                let mut decoder = ZstdDecoder::new(reader);
+                decoder.multiple_members(true);
+                Box::pin(decoder)
+            }
+            Compression::Bzip2 => {
+                let mut decoder = BzDecoder::new(reader);
+                decoder.multiple_members(true);
+                Box::pin(decoder)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(
+            Compression::from_name("data/nadac.csv.gz"),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::from_name("https://example.com/nadac.CSV.GZ?token=abc"),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::from_name("data/nadac.csv.zst"),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::from_name("data/nadac.csv.bz2"),
+            Compression::Bzip2
+        );
+        assert_eq!(Compression::from_name("data/nadac.csv"), Compression::None);
+    }
+
+    #[test]
+    fn test_from_magic() {
+        assert_eq!(
+            Compression::from_magic(&[0x1f, 0x8b, 0x08, 0x00]),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::from_magic(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]),
+            Compression::Zstd
+        );
+        assert_eq!(Compression::from_magic(b"BZh91AY&SY"), Compression::Bzip2);
+        assert_eq!(
+            Compression::from_magic(b"NDC Description,NDC"),
+            Compression::None
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! The `cpi` module provides code for adjusting prices for inflation with the Consumer Price
+//! Index, so price changes from different years can be compared in the same dollars.
+
+use crate::comparison::ComparisonRow;
+use chrono::{Datelike, NaiveDate};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// The annual average CPI for All Urban Consumers (CPI-U, 1982-84=100) published by the Bureau
+/// of Labor Statistics.
+static BUILTIN_CPI: &str = "\
+2013,232.957
+2014,236.736
+2015,237.017
+2016,240.007
+2017,245.120
+2018,251.107
+2019,255.657
+2020,258.811
+2021,270.970
+2022,292.655
+2023,304.702
+2024,313.689
+";
+
+/// The `CpiAdjustment` struct converts prices from the year they took effect into the dollars
+/// of a base year.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CpiAdjustment {
+    /// The CPI of each year.
+    index: BTreeMap<i32, Decimal>,
+
+    /// The year whose dollars the prices are converted to.
+    pub base_year: i32,
+}
+
+impl CpiAdjustment {
+    /// Create a new `CpiAdjustment` from a CPI series.
+    ///
+    /// # Arguments
+    ///
+    /// * `series` - The CPI series, `builtin` for the bundled CPI-U series or the text of a CSV
+    ///   file with a year and CPI on each line. A header line, blank lines and lines starting
+    ///   with `#` are ignored.
+    /// * `base_year` - The year whose dollars the prices are converted to, or None for the last
+    ///   year of the series.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `CpiAdjustment`, on error returns a String describing the problem.
+    pub fn new(series: &str, base_year: Option<i32>) -> Result<CpiAdjustment, String> {
+        let series = if series == "builtin" {
+            BUILTIN_CPI
+        } else {
+            series
+        };
+
+        let mut index = BTreeMap::new();
+        for (number, line) in series.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || format!("Invalid CPI on line {}: {}", number + 1, line);
+            let (year, cpi) = line.split_once(',').ok_or_else(invalid)?;
+            let year = match year.trim().parse::<i32>() {
+                Ok(year) => year,
+                // The first line may be a header.
+                Err(_) if number == 0 => continue,
+                Err(_) => return Err(invalid()),
+            };
+            let cpi = Decimal::from_str(cpi.trim()).map_err(|_| invalid())?;
+            if cpi <= Decimal::ZERO {
+                return Err(invalid());
+            }
+            index.insert(year, cpi);
+        }
+
+        let base_year = match base_year.or_else(|| index.keys().last().copied()) {
+            Some(base_year) => base_year,
+            None => return Err("The CPI series is empty".to_string()),
+        };
+        if !index.contains_key(&base_year) {
+            return Err(format!("The CPI series has no value for {}", base_year));
+        }
+
+        Ok(CpiAdjustment { index, base_year })
+    }
+
+    /// Convert the prices of a row to the dollars of the base year.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    /// * `effective_date` - The date the row's new price took effect.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error, when the series has no CPI for the year of the
+    /// effective date, returns a String describing the problem.
+    pub fn adjust(&self, row: &mut ComparisonRow, effective_date: NaiveDate) -> Result<(), String> {
+        let year = effective_date.year();
+        let (Some(cpi), Some(base)) = (self.index.get(&year), self.index.get(&self.base_year))
+        else {
+            return Err(format!("no CPI value for {}", year));
+        };
+
+        let factor = base / cpi;
+        row.old_price *= factor;
+        row.new_price *= factor;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+
+    #[test]
+    fn test_cpi_adjustment() {
+        let builtin = CpiAdjustment::new("builtin", None).unwrap();
+        assert_eq!(builtin.base_year, 2024);
+        assert!(CpiAdjustment::new("builtin", Some(1990)).is_err());
+        assert!(CpiAdjustment::new("2023,abc", None).is_err());
+        assert!(CpiAdjustment::new("Year,CPI\n", None).is_err());
+
+        let adjustment =
+            CpiAdjustment::new("Year,CPI\n# Made up\n2022,100\n2023,125\n", None).unwrap();
+        let record = ByteRecord::from(vec!["LISINOPRIL 10 MG TABLET", "", "1.00", "2.00"]);
+        let mut row = ComparisonRow::from_record(&record).unwrap();
+
+        adjustment
+            .adjust(&mut row, NaiveDate::from_ymd_opt(2022, 6, 1).unwrap())
+            .unwrap();
+        assert_eq!(row.new_price - row.old_price, Decimal::new(125, 2));
+
+        assert_eq!(
+            adjustment.adjust(&mut row, NaiveDate::from_ymd_opt(2021, 6, 1).unwrap()),
+            Err("no CPI value for 2021".to_string())
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! The `csv` module provides code for writing the ranked price changes as a CSV file, with a
+//! row for each price change and the columns chosen with `--fields`.
+
+use crate::data_store::DataStore;
+use crate::report::{drugs_label, period_label, ranked_entries, ReportField, Section};
+
+/// Quote a CSV field when it holds a comma, quote or line break.
+///
+/// # Arguments
+///
+/// * `field` - The text of the field.
+///
+/// # Returns
+///
+/// A new String containing the field as it is written to the file.
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Generate the ranked price changes of the report's sections as a CSV file. Each row has the
+/// period, drugs and kind of list of its price change, its rank and whether it is tied, then
+/// the chosen fields.
+///
+/// # Arguments
+///
+/// * `sections` - The sections of the report and their records stores, in date order.
+/// * `fields` - The fields of each price change, in order.
+/// * `percent_places` - The number of decimal places of the percent changes.
+///
+/// # Returns
+///
+/// A new String containing the CSV file.
+pub fn csv_report(
+    sections: &[(&Section, &DataStore)],
+    fields: &[ReportField],
+    percent_places: u32,
+) -> String {
+    let mut header = vec!["period", "drugs", "list", "rank", "tied"];
+    header.extend(fields.iter().map(ReportField::name));
+    let mut csv = header.join(",");
+    csv.push('\n');
+
+    for (section, data_store) in sections {
+        let period = period_label(&section.period);
+        let drugs = drugs_label(section);
+        for (kind, _, entries) in ranked_entries(data_store, percent_places) {
+            for entry in entries {
+                let mut row = vec![
+                    quote(&period),
+                    quote(drugs.trim()),
+                    quote(kind),
+                    entry.rank.to_string(),
+                    entry.tied.to_string(),
+                ];
+                row.extend(fields.iter().map(|field| quote(&entry.value(*field))));
+                csv.push_str(&row.join(","));
+                csv.push('\n');
+            }
+        }
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_store::{Direction, Metric, PriceDetails};
+    use crate::dates::Period;
+    use crate::filters::Classification;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_csv_report() {
+        let mut data_store = DataStore::new(1, 1, Metric::Change, Direction::Both).unwrap();
+        data_store
+            .insert_priced_change(
+                Decimal::new(80239, 2),
+                "STELARA 45 MG/0.5 ML, SYRINGE",
+                "57894006003",
+                Some(PriceDetails {
+                    old_price: Decimal::new(2406995, 2),
+                    effective_date: NaiveDate::from_ymd_opt(2023, 5, 17),
+                }),
+            )
+            .unwrap();
+        data_store
+            .insert_change(Decimal::new(-18314, 2), "HUMALOG \"KWIKPEN\"", "")
+            .unwrap();
+        let section = Section {
+            period: Period::Year(2023),
+            classification: Some(Classification::Brand),
+            pricing_unit: None,
+        };
+
+        assert_eq!(
+            csv_report(
+                &[(&section, &data_store)],
+                &crate::report::ALL_FIELDS,
+                crate::report::DEFAULT_PERCENT_PLACES
+            ),
+            "period,drugs,list,rank,tied,change,percent,old_price,new_price,ndc,description,\
+            effective_date\n\
+            2023,brand,increases,1,false,802.39,3.33,24069.95,24872.34,57894006003,\
+            \"STELARA 45 MG/0.5 ML, SYRINGE\",2023-05-17\n\
+            2023,brand,decreases,1,false,-183.14,,,,,\"HUMALOG \"\"KWIKPEN\"\"\",\n"
+        );
+        assert_eq!(
+            csv_report(
+                &[(&section, &data_store)],
+                &[
+                    ReportField::Description,
+                    ReportField::Change,
+                    ReportField::Percent
+                ],
+                0
+            ),
+            "period,drugs,list,rank,tied,description,change,percent\n\
+            2023,brand,increases,1,false,\"STELARA 45 MG/0.5 ML, SYRINGE\",802.39,3\n\
+            2023,brand,decreases,1,false,\"HUMALOG \"\"KWIKPEN\"\"\",-183.14,\n"
+        );
+    }
+}
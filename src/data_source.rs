@@ -0,0 +1,651 @@
+//! The `data_source` module provides code for opening the places the NADAC comparison CSV data
+//! can come from and turning them into a single kind of byte stream for the CSV reader.
+
+use crate::archive::{is_zip_name, open_zip_member};
+use crate::cache::DownloadCache;
+use crate::columns::ColumnMap;
+use crate::compression::Compression;
+use crate::dialect::Delimiter;
+use crate::encoding::decode;
+use crate::http::{download, into_reader, HttpOptions};
+use crate::medicaid_api::{self, ApiQuery};
+use crate::run_summary::RunCounters;
+use crate::sampling::Sampling;
+use crate::sftp::{open_sftp_file, split_sftp_location};
+use aws_config::BehaviorVersion;
+use csv_async::ByteRecord;
+use encoding_rs::Encoding;
+use futures::io::AsyncRead;
+use futures::stream::LocalBoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::azure::MicrosoftAzureBuilder;
+use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::path::Path as ObjectPath;
+use object_store::{Attribute, ObjectStore};
+use reqwest::header::{HeaderMap, CONTENT_ENCODING, CONTENT_TYPE};
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::AsyncSeekExt;
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+/// The byte stream handed to `csv_async`. Every source is converted to this type so the rest
+/// of the program does not need to know where the data came from.
+pub type DataReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// The stream of comparison records fed to the `DataStore`. The records always have the columns
+/// of the NADAC comparison CSV file, whichever input they came from. They are kept as bytes so
+/// that only the fields that are used get decoded, see `decode_field`.
+pub type RecordStream<'a> = LocalBoxStream<'a, Result<ByteRecord, Box<dyn std::error::Error>>>;
+
+/// The `DataSource` enum describes where the CSV data for the report lives.
+#[derive(Debug, Clone)]
+pub enum DataSource {
+    /// The data is downloaded from an HTTP(S) URL.
+    Url(String),
+
+    /// The data is read from a file on the local disk.
+    File(PathBuf),
+
+    /// The data is piped into the program on standard input.
+    Stdin,
+
+    /// The data is an object in an AWS S3 bucket.
+    S3 { bucket: String, key: String },
+
+    /// The data is an object in a Google Cloud Storage bucket.
+    Gcs { bucket: String, key: String },
+
+    /// The data is a blob in an Azure Blob Storage container. When the account is `None`, it
+    /// comes from the AZURE_STORAGE_ACCOUNT_NAME environment variable.
+    Azure {
+        account: Option<String>,
+        container: String,
+        blob: String,
+    },
+
+    /// The data is a file on an SFTP server. The destination is `[user@]host[:port]`.
+    Sftp { destination: String, path: String },
+}
+
+/// The `Input` enum describes how the comparison records for the report are obtained.
+#[derive(Debug, Clone)]
+pub enum Input {
+    /// The records are rows of a CSV file read from a `DataSource`.
+    Csv(DataSource),
+
+    /// The records are paged out of the data.medicaid.gov datastore JSON API.
+    Api(ApiQuery),
+
+    /// The records are rows of a CSV file read from the first of several mirrors that can be
+    /// opened. The mirrors are tried in order.
+    Mirrored(Vec<DataSource>),
+}
+
+/// The records of an opened `Input`.
+pub struct OpenedInput {
+    /// A description of where the records are actually read from. For a mirrored input this
+    /// is the mirror that was opened.
+    pub source: String,
+
+    /// The records.
+    pub records: RecordStream<'static>,
+}
+
+/// Options that control how a `DataSource` is opened.
+#[derive(Debug, Clone, Default)]
+pub struct SourceOptions {
+    /// The name of the entry to read when the data is a zip archive. When `None`, the first
+    /// `.csv` entry in the archive is used.
+    pub archive_member: Option<String>,
+
+    /// The options that control downloads over HTTP(S).
+    pub http: HttpOptions,
+
+    /// The cache for data downloaded over HTTP(S). When `None`, the data is always downloaded.
+    pub cache: Option<DownloadCache>,
+
+    /// When true, the network is never used. URLs are only read from the cache, and sources
+    /// that can only be read over the network fail.
+    pub offline: bool,
+
+    /// The mapping of the CSV columns onto the NADAC comparison columns. When `None`, the CSV
+    /// data is laid out like the NADAC comparison file.
+    pub columns: Option<ColumnMap>,
+
+    /// The text encoding of the CSV data. When `None`, the encoding is detected from the data.
+    pub encoding: Option<&'static Encoding>,
+
+    /// How the fields of the CSV data are separated.
+    pub delimiter: Delimiter,
+
+    /// When true, the CSV data has no header row, so its first row is data.
+    pub no_header: bool,
+
+    /// How much of each input is read.
+    pub sampling: Sampling,
+
+    /// The counts of the rows read from the inputs and the bytes downloaded for them. Data read
+    /// from the download cache is not counted as downloaded.
+    pub counters: RunCounters,
+}
+
+impl DataSource {
+    /// Create a `DataSource` from a location given on the command line. The location may be an
+    /// HTTP(S) URL, an `s3://bucket/key` URL, a `gs://bucket/key` URL, an `az://container/blob`
+    /// or `https://<account>.blob.core.windows.net/container/blob` URL, an
+    /// `sftp://[user@]host[:port]/path` URL, or `-` for stdin.
+    ///
+    /// # Arguments
+    ///
+    /// * `location` - The location string.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `DataSource`, on error returns a String describing the problem.
+    pub fn from_location(location: &str) -> Result<DataSource, String> {
+        if location == "-" {
+            return Ok(DataSource::Stdin);
+        }
+
+        if let Some(path) = location.strip_prefix("s3://") {
+            let (bucket, key) = split_bucket_path(path)
+                .ok_or_else(|| format!("Expected s3://bucket/key but found {}", location))?;
+            return Ok(DataSource::S3 { bucket, key });
+        }
+
+        if let Some(path) = location.strip_prefix("gs://") {
+            let (bucket, key) = split_bucket_path(path)
+                .ok_or_else(|| format!("Expected gs://bucket/key but found {}", location))?;
+            return Ok(DataSource::Gcs { bucket, key });
+        }
+
+        if let Some(path) = location.strip_prefix("az://") {
+            let (container, blob) = split_bucket_path(path)
+                .ok_or_else(|| format!("Expected az://container/blob but found {}", location))?;
+            return Ok(DataSource::Azure {
+                account: None,
+                container,
+                blob,
+            });
+        }
+
+        if let Some(rest) = location.strip_prefix("sftp://") {
+            let (destination, path) = split_sftp_location(rest).ok_or_else(|| {
+                format!(
+                    "Expected sftp://[user@]host[:port]/path but found {}",
+                    location
+                )
+            })?;
+            return Ok(DataSource::Sftp { destination, path });
+        }
+
+        // Blob URLs with a query string carry a SAS token, so they can be downloaded as plain
+        // HTTPS. Otherwise, go through Azure so the standard credentials are used.
+        if let Some(rest) = location.strip_prefix("https://") {
+            if let Some((host, path)) = rest.split_once('/') {
+                if let Some(account) = host.strip_suffix(".blob.core.windows.net") {
+                    if !path.contains('?') {
+                        if let Some((container, blob)) = split_bucket_path(path) {
+                            return Ok(DataSource::Azure {
+                                account: Some(account.to_string()),
+                                container,
+                                blob,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(DataSource::Url(location.to_string()))
+    }
+
+    /// Open the source and return a stream of the bytes in the CSV data. Compressed data is
+    /// decompressed on the fly and zip archives are opened to find the CSV entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The options that control how the source is opened.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `DataReader` for the source, on error returns a std::error::Error
+    /// in a Box.
+    pub async fn open(
+        &self,
+        options: &SourceOptions,
+    ) -> Result<DataReader, Box<dyn std::error::Error>> {
+        match self {
+            DataSource::S3 { .. }
+            | DataSource::Gcs { .. }
+            | DataSource::Azure { .. }
+            | DataSource::Sftp { .. }
+                if options.offline =>
+            {
+                Err(format!("{} cannot be read in offline mode", self).into())
+            }
+            DataSource::Url(url) => {
+                let client = options.http.client()?;
+
+                if let Some(cache) = &options.cache {
+                    let data = if options.offline {
+                        cache.fetch_offline(url).await?
+                    } else {
+                        cache.fetch(&client, url, &options.http).await?
+                    };
+                    let entry = data.entry;
+                    let is_zip =
+                        is_zip_name(url) || is_zip_content_type(entry.content_type.as_deref());
+                    let encoding = entry.content_encoding.as_deref();
+                    let mut reader = into_reader(data.body);
+                    if data.downloaded {
+                        reader = counted(reader, options);
+                    }
+                    return unpack_remote(reader, url, encoding, is_zip, options).await;
+                }
+
+                let response = download(&client, url, &options.http, &HeaderMap::new()).await?;
+
+                let header = |name| {
+                    response
+                        .headers
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                };
+                let is_zip = is_zip_name(url) || is_zip_content_type(header(CONTENT_TYPE));
+                let encoding = header(CONTENT_ENCODING).map(|e| e.to_string());
+                let reader = counted(into_reader(response.body), options);
+
+                unpack_remote(reader, url, encoding.as_deref(), is_zip, options).await
+            }
+            DataSource::File(path) => {
+                // Tokio files implement the tokio flavor of AsyncRead, so use the compat layer
+                // to get the futures flavor.
+                let file = tokio::fs::File::open(path).await?;
+                if is_zip_name(&path.to_string_lossy()) {
+                    return open_zip_member(file, options.archive_member.as_deref()).await;
+                }
+
+                let compression = Compression::from_name(&path.to_string_lossy());
+                Ok(compression.decode(Box::pin(file.compat())).await?)
+            }
+            DataSource::Stdin => Ok(Compression::None
+                .decode(Box::pin(tokio::io::stdin().compat()))
+                .await?),
+            DataSource::S3 { bucket, key } => {
+                // Credentials and region come from the standard AWS provider chain (environment,
+                // shared config files, instance metadata and so on).
+                let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+                let client = aws_sdk_s3::Client::new(&config);
+                let object = client.get_object().bucket(bucket).key(key).send().await?;
+
+                let is_zip = is_zip_name(key) || is_zip_content_type(object.content_type());
+                let encoding = object.content_encoding().map(|e| e.to_string());
+                let reader = counted(Box::pin(object.body.into_async_read().compat()), options);
+
+                unpack_remote(reader, key, encoding.as_deref(), is_zip, options).await
+            }
+            DataSource::Gcs { bucket, key } => {
+                // Credentials come from the standard Google environment variables, such as
+                // GOOGLE_APPLICATION_CREDENTIALS.
+                let store = GoogleCloudStorageBuilder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()?;
+                open_object(&store, key, options).await
+            }
+            DataSource::Azure {
+                account,
+                container,
+                blob,
+            } => {
+                // Credentials come from the standard Azure environment variables, such as
+                // AZURE_STORAGE_ACCOUNT_KEY or the AZURE_CLIENT_* service principal settings.
+                let mut builder = MicrosoftAzureBuilder::from_env().with_container_name(container);
+                if let Some(account) = account {
+                    builder = builder.with_account(account);
+                }
+                let store = builder.build()?;
+                open_object(&store, blob, options).await
+            }
+            DataSource::Sftp { destination, path } => {
+                let reader = counted(open_sftp_file(destination, path).await?, options);
+                unpack_remote(reader, path, None, is_zip_name(path), options).await
+            }
+        }
+    }
+}
+
+/// Stream an object out of an `ObjectStore` without buffering the whole object in memory.
+///
+/// # Arguments
+///
+/// * `store` - The object store holding the object.
+/// * `key` - The path of the object in the store.
+/// * `options` - The options that control how the source is opened.
+///
+/// # Returns
+///
+/// On success, returns the `DataReader` producing the CSV bytes, on error returns a
+/// std::error::Error in a Box.
+async fn open_object(
+    store: &dyn ObjectStore,
+    key: &str,
+    options: &SourceOptions,
+) -> Result<DataReader, Box<dyn std::error::Error>> {
+    let result = store.get(&ObjectPath::from(key)).await?;
+
+    let content_type = result.attributes.get(&Attribute::ContentType);
+    let is_zip = is_zip_name(key) || is_zip_content_type(content_type.map(|value| value.as_ref()));
+    let encoding = result
+        .attributes
+        .get(&Attribute::ContentEncoding)
+        .map(|value| value.to_string());
+
+    let reader = result
+        .into_stream()
+        .map_err(std::io::Error::other)
+        .into_async_read();
+
+    let reader = counted(Box::pin(reader), options);
+    unpack_remote(reader, key, encoding.as_deref(), is_zip, options).await
+}
+
+/// A `DataReader` that counts the bytes read through it as downloaded.
+struct CountingReader {
+    /// The reader of the downloaded bytes.
+    inner: DataReader,
+
+    /// Where the bytes are counted.
+    counters: RunCounters,
+}
+
+impl AsyncRead for CountingReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let poll = self.inner.as_mut().poll_read(cx, buf);
+        if let Poll::Ready(Ok(read)) = &poll {
+            self.counters.add_downloaded(*read as u64);
+        }
+        poll
+    }
+}
+
+/// Count the bytes of a download in the options' `counters` as they are read.
+fn counted(reader: DataReader, options: &SourceOptions) -> DataReader {
+    Box::pin(CountingReader {
+        inner: reader,
+        counters: options.counters.clone(),
+    })
+}
+
+/// Split `bucket/path/to/object` into the bucket and the object path.
+fn split_bucket_path(path: &str) -> Option<(String, String)> {
+    match path.split_once('/') {
+        Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => {
+            Some((bucket.to_string(), key.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Determine if a `Content-Type` value describes a zip archive.
+fn is_zip_content_type(content_type: Option<&str>) -> bool {
+    content_type
+        .map(|value| value.contains("zip") && !value.contains("gzip"))
+        .unwrap_or(false)
+}
+
+/// Turn the raw bytes of a remote object into the CSV bytes by opening zip archives and
+/// decompressing compressed data.
+///
+/// # Arguments
+///
+/// * `reader` - The raw bytes of the object.
+/// * `name` - The URL or key of the object, used to guess the compression.
+/// * `encoding` - The content encoding reported by the server, if any. It wins over the name.
+/// * `is_zip` - True if the object is a zip archive.
+/// * `options` - The options that control how the source is opened.
+///
+/// # Returns
+///
+/// On success, returns the `DataReader` producing the CSV bytes, on error returns a
+/// std::error::Error in a Box.
+async fn unpack_remote(
+    reader: DataReader,
+    name: &str,
+    encoding: Option<&str>,
+    is_zip: bool,
+    options: &SourceOptions,
+) -> Result<DataReader, Box<dyn std::error::Error>> {
+    if is_zip {
+        // The zip directory lives at the end of the archive, so the download has to be spooled
+        // to a temporary file before the CSV entry can be located.
+        let mut file = tokio::fs::File::from_std(tempfile::tempfile()?);
+        futures::io::copy(reader, &mut (&mut file).compat_write()).await?;
+        file.rewind().await?;
+        return open_zip_member(file, options.archive_member.as_deref()).await;
+    }
+
+    let compression = match encoding {
+        Some(encoding) => Compression::from_content_encoding(encoding),
+        None => Compression::from_name(name),
+    };
+
+    Ok(compression.decode(reader).await?)
+}
+
+impl Input {
+    /// Open the input and return a stream of its comparison records, limited and sampled as
+    /// the options' `sampling` asks.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The options that control how the input is opened.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `OpenedInput` for the input, on error returns a
+    /// std::error::Error in a Box.
+    pub async fn records(
+        &self,
+        options: &SourceOptions,
+    ) -> Result<OpenedInput, Box<dyn std::error::Error>> {
+        let opened = self.open(options).await?;
+        let counters = options.counters.clone();
+        let sampled = options.counters.clone();
+        let records = opened
+            .records
+            .inspect(move |_| counters.add_row())
+            .boxed_local();
+        let records = options
+            .sampling
+            .apply(records)
+            .inspect(move |_| sampled.add_sampled_row())
+            .boxed_local();
+        Ok(OpenedInput {
+            source: opened.source,
+            records,
+        })
+    }
+
+    /// Open the input and return a stream of all of its comparison records.
+    async fn open(
+        &self,
+        options: &SourceOptions,
+    ) -> Result<OpenedInput, Box<dyn std::error::Error>> {
+        match self {
+            Input::Csv(source) => Ok(OpenedInput {
+                source: source.to_string(),
+                records: csv_records(source, options).await?,
+            }),
+            Input::Api(_) if options.offline => {
+                Err(format!("{} cannot be read in offline mode", self).into())
+            }
+            Input::Api(query) => Ok(OpenedInput {
+                source: self.to_string(),
+                records: medicaid_api::records(
+                    options.http.client()?,
+                    query.clone(),
+                    options.http.clone(),
+                ),
+            }),
+            Input::Mirrored(sources) => {
+                let mut last_error: Box<dyn std::error::Error> = "No mirrors were given".into();
+
+                for source in sources {
+                    match csv_records(source, options).await {
+                        Ok(records) => {
+                            return Ok(OpenedInput {
+                                source: source.to_string(),
+                                records,
+                            })
+                        }
+                        Err(e) => {
+                            eprintln!("Could not read {}: {}", source, e);
+                            last_error = e;
+                        }
+                    }
+                }
+
+                Err(last_error)
+            }
+        }
+    }
+}
+
+/// Open a `DataSource` and read it as CSV.
+///
+/// # Arguments
+///
+/// * `source` - The source to open.
+/// * `options` - The options that control how the source is opened.
+///
+/// # Returns
+///
+/// On success, returns the `RecordStream` for the source, on error returns a std::error::Error
+/// in a Box.
+async fn csv_records(
+    source: &DataSource,
+    options: &SourceOptions,
+) -> Result<RecordStream<'static>, Box<dyn std::error::Error>> {
+    let reader = decode(source.open(options).await?, options.encoding).await?;
+    let (reader, delimiter) = options.delimiter.resolve(reader).await?;
+    let records = csv_async::AsyncReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(!options.no_header)
+        .create_reader(reader)
+        .into_byte_records()
+        .map_err(|e| e.into());
+
+    match options.columns.clone() {
+        Some(columns) => Ok(records
+            .map_ok(move |record| columns.apply(&record))
+            .boxed_local()),
+        None => Ok(records.boxed_local()),
+    }
+}
+
+impl Display for Input {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Input::Csv(source) => write!(f, "{}", source),
+            Input::Api(query) => write!(f, "data.medicaid.gov dataset {}", query.dataset_id),
+            Input::Mirrored(sources) => {
+                let sources: Vec<String> = sources.iter().map(|s| s.to_string()).collect();
+                write!(f, "{}", sources.join(" or "))
+            }
+        }
+    }
+}
+
+impl Display for DataSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataSource::Url(url) => write!(f, "{}", url),
+            DataSource::File(path) => write!(f, "{}", path.display()),
+            DataSource::Stdin => write!(f, "<stdin>"),
+            DataSource::S3 { bucket, key } => write!(f, "s3://{}/{}", bucket, key),
+            DataSource::Gcs { bucket, key } => write!(f, "gs://{}/{}", bucket, key),
+            DataSource::Azure {
+                account: Some(account),
+                container,
+                blob,
+            } => write!(
+                f,
+                "https://{}.blob.core.windows.net/{}/{}",
+                account, container, blob
+            ),
+            DataSource::Azure {
+                account: None,
+                container,
+                blob,
+            } => write!(f, "az://{}/{}", container, blob),
+            DataSource::Sftp { destination, path } => match path.strip_prefix('/') {
+                Some(absolute) => write!(f, "sftp://{}/{}", destination, absolute),
+                None => write!(f, "sftp://{}/~/{}", destination, path),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_location() {
+        match DataSource::from_location("s3://nadac-archive/2024/comparison.csv.gz").unwrap() {
+            DataSource::S3 { bucket, key } => {
+                assert_eq!(bucket, "nadac-archive");
+                assert_eq!(key, "2024/comparison.csv.gz");
+            }
+            other => panic!("Unexpected source {:?}", other),
+        }
+
+        assert!(matches!(
+            DataSource::from_location("gs://nadac-archive/comparison.csv").unwrap(),
+            DataSource::Gcs { .. }
+        ));
+        match DataSource::from_location("https://nadac.blob.core.windows.net/data/2024/c.csv")
+            .unwrap()
+        {
+            DataSource::Azure {
+                account,
+                container,
+                blob,
+            } => {
+                assert_eq!(account.as_deref(), Some("nadac"));
+                assert_eq!(container, "data");
+                assert_eq!(blob, "2024/c.csv");
+            }
+            other => panic!("Unexpected source {:?}", other),
+        }
+        assert!(matches!(
+            DataSource::from_location("az://data/comparison.csv").unwrap(),
+            DataSource::Azure { account: None, .. }
+        ));
+        assert!(matches!(
+            DataSource::from_location("https://nadac.blob.core.windows.net/data/c.csv?sv=1&sig=x")
+                .unwrap(),
+            DataSource::Url(_)
+        ));
+        assert!(matches!(
+            DataSource::from_location("-").unwrap(),
+            DataSource::Stdin
+        ));
+        assert!(matches!(
+            DataSource::from_location("https://download.medicaid.gov/data/x.csv").unwrap(),
+            DataSource::Url(_)
+        ));
+        assert!(matches!(
+            DataSource::from_location("sftp://nadac@drop.example.gov/outgoing/c.csv").unwrap(),
+            DataSource::Sftp { .. }
+        ));
+        assert!(DataSource::from_location("s3://bucket-only").is_err());
+    }
+}
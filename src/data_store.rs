@@ -1,16 +1,106 @@
 //! The `DataStore` module provides code for efficiently caching records from the CSV file.
 
+use crate::aggregate::{Aggregate, DrugChanges, Grouping};
+use crate::comparison::ComparisonRow;
+use crate::explain::Explainer;
 use crate::record_pool::{PoolType, RecordPool};
+use crate::report::{dollars, Section};
 use bimap::BiMap;
-use csv_async::StringRecord;
+use chrono::NaiveDate;
+use clap::ValueEnum;
 use rust_decimal::Decimal;
-use std::collections::HashMap;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
 use std::str::FromStr;
 
-const START_PRICE_INDEX: usize = 2;
-const END_PRICE_INDEX: usize = 3;
-const DESCRIPTION_INDEX: usize = 0;
+/// Parse a price, tolerating the `$1,234.56` style formatting found in some exports. Dollar
+/// signs, thousands separators and whitespace are removed before the conversion.
+///
+/// # Arguments
+///
+/// * `text` - The price text.
+///
+/// # Returns
+///
+/// On success, returns the price, on error returns a rust_decimal::Error.
+pub fn parse_price(text: &str) -> Result<Decimal, rust_decimal::Error> {
+    // Most prices are plain numbers, which need no copy.
+    if let Ok(price) = Decimal::from_str(text) {
+        return Ok(price);
+    }
+
+    let cleaned: String = text
+        .chars()
+        .filter(|c| *c != '$' && *c != ',' && !c.is_whitespace())
+        .collect();
+    Decimal::from_str(&cleaned)
+}
+
+/// Normalize a description so that logically identical descriptions compare equal. Leading and
+/// trailing whitespace is removed, runs of whitespace become a single space and the text is
+/// upper cased, the convention of the NADAC data.
+///
+/// # Arguments
+///
+/// * `description` - The description to normalize.
+///
+/// # Returns
+///
+/// The normalized description.
+pub fn normalize_description(description: &str) -> String {
+    description
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_uppercase()
+}
+
+/// The `Drug` struct identifies the drug a record is about.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Drug {
+    /// The description of the drug.
+    pub description: String,
+
+    /// The National Drug Code of the drug.
+    pub ndc: String,
+}
+
+/// The `PriceDetails` struct holds the prices behind a single price change, which the report
+/// can show next to the change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriceDetails {
+    /// The per unit price before the change.
+    pub old_price: Decimal,
+
+    /// The date the new price took effect, if the row has one.
+    pub effective_date: Option<NaiveDate>,
+}
+
+/// Enum describing how the price changes are ranked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Metric {
+    /// The largest increases and the largest decreases are ranked separately.
+    #[default]
+    Change,
+
+    /// The largest changes in either direction are ranked together, by their magnitude.
+    Magnitude,
+}
+
+/// Enum describing which directions of price change are ranked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Direction {
+    /// Only the largest increases are ranked.
+    Increases,
+
+    /// Only the largest decreases are ranked.
+    Decreases,
+
+    /// The largest increases and the largest decreases are ranked.
+    #[default]
+    Both,
+}
 
 /// The `DataStore` provides a place to store records according to the criteria
 /// of the assignment:
@@ -23,15 +113,17 @@ const DESCRIPTION_INDEX: usize = 0;
 /// - The store is time efficient.
 #[derive(Debug)]
 pub struct DataStore {
-    /// The pool of records that hold the largest positive price changes.
-    pub top: RecordPool,
+    /// The pool of records that hold the largest positive price changes, unless only the
+    /// decreases are ranked.
+    pub top: Option<RecordPool>,
 
-    /// The pool of records that holds the largest decrease in price changes.
-    pub bottom: RecordPool,
+    /// The pool of records that holds the largest decrease in price changes, unless only the
+    /// increases are ranked or the changes are ranked by their magnitude.
+    pub bottom: Option<RecordPool>,
 
     /// A map that efficiently stores just one copy of the record descriptions
-    /// for the records in `top` and `bottom`.
-    pub descriptions: BiMap<String, usize>,
+    /// and NDCs for the records in `top` and `bottom`.
+    pub descriptions: BiMap<Drug, usize>,
 
     /// A small secondary map that helps manage the codes used to map the
     /// records.
@@ -39,109 +131,345 @@ pub struct DataStore {
 
     /// The next code value to use when mapping a unique record description.
     pub next_code: usize,
+
+    /// The drugs that have been given a code. A drug whose code was let go is given a new one
+    /// when it is kept again, so the codes overcount the drugs.
+    pub drugs: HashSet<Drug>,
+
+    /// The number of price changes inserted, whether or not they were kept in a pool.
+    pub inserted: usize,
+
+    /// The prices behind the records in `top` and `bottom` that are single price changes, by
+    /// their difference and description code.
+    pub details: HashMap<(Decimal, usize), PriceDetails>,
+
+    /// When true, descriptions are normalized with `normalize_description` before they are
+    /// stored, so descriptions differing only in case or whitespace share one code.
+    pub normalize_descriptions: bool,
+
+    /// How the price changes are ranked. With `Metric::Magnitude`, all of the changes are kept
+    /// in `top` and there is no `bottom`.
+    pub metric: Metric,
+
+    /// When set, what happens to the price changes of the drug it follows is logged.
+    pub explainer: Option<Explainer>,
 }
 
 impl DataStore {
-    /// Create a new `DataStore` that will track the top and bottom N price changes
-    /// in the CSV data.
-    pub fn new(size: usize) -> Result<DataStore, Box<dyn std::error::Error>> {
+    /// Create a new `DataStore` that will track the N price changes ranked highest by a metric.
+    ///
+    /// # Arguments
+    ///
+    /// * `top_size` - The number of price changes to track in the top pool, the increases for
+    ///   `Metric::Change` or the changes in either direction for `Metric::Magnitude`.
+    /// * `bottom_size` - The number of decreases to track in the bottom pool.
+    /// * `metric` - How the price changes are ranked.
+    /// * `direction` - Which directions of change are tracked for `Metric::Change`. Only the
+    ///   pools for those directions are created.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `DataStore`, on error returns a std::error::Error in a Box.
+    pub fn new(
+        top_size: usize,
+        bottom_size: usize,
+        metric: Metric,
+        direction: Direction,
+    ) -> Result<DataStore, Box<dyn std::error::Error>> {
+        let (top, bottom) = match (metric, direction) {
+            (Metric::Magnitude, _) => (Some(PoolType::Magnitude), None),
+            (Metric::Change, Direction::Increases) => (Some(PoolType::Most), None),
+            (Metric::Change, Direction::Decreases) => (None, Some(PoolType::Least)),
+            (Metric::Change, Direction::Both) => (Some(PoolType::Most), Some(PoolType::Least)),
+        };
+
         Ok(DataStore {
-            top: RecordPool::new(size, PoolType::Most)?,
-            bottom: RecordPool::new(size, PoolType::Least)?,
+            top: top
+                .map(|pool_type| RecordPool::new(top_size, pool_type))
+                .transpose()?,
+            bottom: bottom
+                .map(|pool_type| RecordPool::new(bottom_size, pool_type))
+                .transpose()?,
             descriptions: BiMap::new(),
             code_use: HashMap::new(),
             next_code: 0,
+            drugs: HashSet::new(),
+            inserted: 0,
+            details: HashMap::new(),
+            normalize_descriptions: false,
+            metric,
+            explainer: None,
         })
     }
 
-    /// Insert a record into the data store.
+    /// Insert a row into the data store.
     ///
     /// # Arguments
     ///
-    /// * `record` - The CSV record from csv_async.
+    /// * `row` - The row of comparison data.
     ///
     /// # Returns
     ///
     /// On success, returns (), on error returns a std::error::Error in a Box.
-    pub fn insert(&mut self, record: &StringRecord) -> Result<(), Box<dyn std::error::Error>> {
-        // Get the start and end prices. Convert them to Decimals
-
-        let start_price = match record.get(START_PRICE_INDEX) {
-            Some(price) => Decimal::from_str(price)?,
-            None => return Err("Failed to get start price".into()),
-        };
+    pub fn insert(&mut self, row: &ComparisonRow) -> Result<(), Box<dyn std::error::Error>> {
+        // Let the rust_decimal crate handle the floating point calculations.
+        self.insert_priced_change(
+            row.new_price - row.old_price,
+            &row.description,
+            &row.ndc,
+            Some(PriceDetails {
+                old_price: row.old_price,
+                effective_date: row.effective_date,
+            }),
+        )
+    }
 
-        let new_price = match record.get(END_PRICE_INDEX) {
-            Some(price) => Decimal::from_str(price)?,
-            None => return Err("Failed to get new price".into()),
-        };
+    /// Insert a price change into the data store.
+    ///
+    /// # Arguments
+    ///
+    /// * `difference` - The change in price.
+    /// * `description` - The description of the drug.
+    /// * `ndc` - The NDC of the drug, which may be empty.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a std::error::Error in a Box.
+    pub fn insert_change(
+        &mut self,
+        difference: Decimal,
+        description: &str,
+        ndc: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.insert_priced_change(difference, description, ndc, None)
+    }
 
-        let description = match record.get(DESCRIPTION_INDEX) {
-            Some(code) => code,
-            None => return Err("Failed to get description code".into()),
-        };
+    /// Insert a price change into the data store, with the prices behind it.
+    ///
+    /// # Arguments
+    ///
+    /// * `difference` - The change in price.
+    /// * `description` - The description of the drug.
+    /// * `ndc` - The NDC of the drug, which may be empty.
+    /// * `details` - The prices behind the change, unless it combines several changes.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a std::error::Error in a Box.
+    pub fn insert_priced_change(
+        &mut self,
+        difference: Decimal,
+        description: &str,
+        ndc: &str,
+        details: Option<PriceDetails>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.inserted += 1;
 
-        // Let the rust_decimal crate handle the floating point calculations.
-        let difference = new_price - start_price;
+        // A single pool for one direction of change only takes changes in that direction, even
+        // while it has room for more.
+        if self.metric == Metric::Change {
+            let wrong_direction = match (&self.top, &self.bottom) {
+                (Some(_), None) => difference < Decimal::ZERO,
+                (None, Some(_)) => difference >= Decimal::ZERO,
+                _ => false,
+            };
+            if wrong_direction {
+                self.explain(
+                    difference,
+                    description,
+                    ndc,
+                    "not kept: in the direction that is not ranked",
+                );
+                return Ok(());
+            }
+        }
 
         // Check to see if the difference for this record will 'fit' in the top record pool. Here,
         // fit means that either the pool has fewer records than its max capacity or that this
         // difference value is in the range [lowest, highest] (inclusive) for the values already
         // in the pool.
-        if self.top.fits(&difference) {
+        // With `Metric::Magnitude` changes in both directions share the top pool, and there is
+        // no bottom pool for a change it kicks out to move to.
+        if self.top.as_ref().is_some_and(|top| top.fits(&difference)) {
             // The difference should be recorded.  Now either retrieve the record description code
             // or generate a new code (by storing the new description).
-            let code = self.code_for_description(description);
+            let code = self.code_for_description(description, ndc);
+            self.keep_details(difference, code, details);
 
             // Now insert the difference and the description code into the top pool. The top pool
             // might return a value (as a Some()) for any value that it kicks out of the pool
             // as a result of the insert operation.
-            if let Some((replaced_diff, replaced_code)) = self.top.insert(difference, code) {
+            let replaced = self
+                .top
+                .as_mut()
+                .and_then(|top| top.insert(difference, code));
+            let mut decision = "inserted into the top pool";
+            if let Some((replaced_diff, replaced_code)) = replaced {
                 // The top pool kicked out a value, we need to check to see if the value can
-                // fit in the bottom pool.
-                if self.bottom.fits(&replaced_diff) {
-                    self.bottom.insert(replaced_diff, replaced_code);
+                // fit in the bottom pool. A value tied at the cutoff that loses the tie-break is
+                // kicked out as soon as it goes in, so it was never kept.
+                let rejected = (replaced_diff, replaced_code) == (difference, code);
+                if !rejected {
+                    self.explain_eviction(
+                        replaced_diff,
+                        replaced_code,
+                        "top",
+                        difference,
+                        description,
+                    );
+                }
+                if let Some(bottom) = self
+                    .bottom
+                    .as_mut()
+                    .filter(|bottom| bottom.fits(&replaced_diff))
+                {
+                    bottom.insert(replaced_diff, replaced_code);
+                    if rejected {
+                        decision = "inserted into the bottom pool";
+                    } else {
+                        self.explain_code(replaced_diff, replaced_code, "moved to the bottom pool");
+                    }
                 } else {
                     // The value didn't fit in the bottom pool so clean up the description codes/
                     // stored descriptions. We removed a value from a pool and depending on whether
                     // the description is duplicated between several records, we may need to delete
                     // the description string.
+                    self.details.remove(&(replaced_diff, replaced_code));
                     self.cleanup_descriptions(replaced_code);
+                    if rejected {
+                        decision = "not kept: below the pool cutoff";
+                    }
                 }
             }
+            self.explain(difference, description, ndc, decision);
 
         // The difference didn't fit in the top pool, see if it will go in the bottom.
-        } else if self.bottom.fits(&difference) {
+        } else if self
+            .bottom
+            .as_ref()
+            .is_some_and(|bottom| bottom.fits(&difference))
+        {
             // Similarly to the top case, get the code for the description (maybe adding a new code).
-            let code = self.code_for_description(description);
+            let code = self.code_for_description(description, ndc);
+            self.keep_details(difference, code, details);
 
             // Check to see if the insertion returns a record.
-            if let Some((replaced_diff, replaced_code)) = self.bottom.insert(difference, code) {
+            let replaced = self
+                .bottom
+                .as_mut()
+                .and_then(|bottom| bottom.insert(difference, code));
+            let mut decision = "inserted into the bottom pool";
+            if let Some((replaced_diff, replaced_code)) = replaced {
+                // As with the top pool, the value kicked out may be the one just inserted.
+                let rejected = (replaced_diff, replaced_code) == (difference, code);
+                if !rejected {
+                    self.explain_eviction(
+                        replaced_diff,
+                        replaced_code,
+                        "bottom",
+                        difference,
+                        description,
+                    );
+                }
                 // The insert returned a record, see if it would fit in the top. It shouldn't fit,
                 // but check anyway.
-                if self.top.fits(&replaced_diff) {
-                    self.top.insert(replaced_diff, replaced_code);
+                if let Some(top) = self.top.as_mut().filter(|top| top.fits(&replaced_diff)) {
+                    top.insert(replaced_diff, replaced_code);
+                    if rejected {
+                        decision = "inserted into the top pool";
+                    }
                 } else {
                     // Cleanup the description and code if it is unused.
+                    self.details.remove(&(replaced_diff, replaced_code));
                     self.cleanup_descriptions(replaced_code);
+                    if rejected {
+                        decision = "not kept: below the pool cutoff";
+                    }
                 }
             }
+            self.explain(difference, description, ndc, decision);
+        } else {
+            self.explain(
+                difference,
+                description,
+                ndc,
+                "not large enough to fit in the pools",
+            );
         }
 
         Ok(())
     }
 
-    /// Return a reference to the top pool
-    pub fn get_top(&self) -> &RecordPool {
-        &self.top
+    /// Keep the prices behind a price change going into a pool. A record already in the pool
+    /// with the same difference and description code keeps the prices it has.
+    fn keep_details(&mut self, difference: Decimal, code: usize, details: Option<PriceDetails>) {
+        if let Some(details) = details {
+            self.details.entry((difference, code)).or_insert(details);
+        }
+    }
+
+    /// Log what happened to a price change, if it is followed by the explainer.
+    fn explain(&self, difference: Decimal, description: &str, ndc: &str, decision: &str) {
+        if let Some(explainer) = &self.explainer {
+            explainer.log(description, ndc, Some(difference), decision);
+        }
+    }
+
+    /// Log what happened to a price change already in a pool, if it is followed by the
+    /// explainer.
+    fn explain_code(&self, difference: Decimal, code: usize, decision: &str) {
+        if let Some(drug) = self.get_drug_for_code(code) {
+            self.explain(difference, &drug.description, &drug.ndc, decision);
+        }
+    }
+
+    /// Log that a price change was pushed out of a pool by a new one, if it is followed by the
+    /// explainer.
+    fn explain_eviction(
+        &self,
+        difference: Decimal,
+        code: usize,
+        pool: &str,
+        by_difference: Decimal,
+        by_description: &str,
+    ) {
+        if self.explainer.is_some() {
+            let decision = format!(
+                "evicted from the {} pool by {}: {}",
+                pool,
+                dollars(&by_difference),
+                by_description.trim()
+            );
+            self.explain_code(difference, code, &decision);
+        }
+    }
+
+    /// Return a reference to the top pool, if there is one.
+    pub fn get_top(&self) -> Option<&RecordPool> {
+        self.top.as_ref()
     }
 
-    /// Return a reference to the bottom pool.
-    pub fn get_bottom(&self) -> &RecordPool {
-        &self.bottom
+    /// Return a reference to the bottom pool, if there is one.
+    pub fn get_bottom(&self) -> Option<&RecordPool> {
+        self.bottom.as_ref()
     }
 
-    /// Look up the description string for a code value.
+    /// Look up the prices behind a record in the pools.
+    ///
+    /// # Arguments
+    ///
+    /// * `difference` - The record's difference value.
+    /// * `code` - The record's description code.
+    ///
+    /// # Returns
+    ///
+    /// Return an Option that may contain the prices, which are not known for records that
+    /// combine several price changes.
+    pub fn get_details(&self, difference: Decimal, code: usize) -> Option<&PriceDetails> {
+        self.details.get(&(difference, code))
+    }
+
+    /// Look up the drug (description and NDC) for a code value.
     ///
     /// # Arguments
     ///
@@ -149,28 +477,35 @@ impl DataStore {
     ///
     /// # Returns
     ///
-    /// Return an Option that may contain the description string.
-    pub fn get_description_for_code(&self, code: usize) -> Option<String> {
-        if let Some(description) = self.descriptions.get_by_right(&code) {
-            Some(description.clone())
-        } else {
-            None
-        }
+    /// Return an Option that may contain the drug.
+    pub fn get_drug_for_code(&self, code: usize) -> Option<&Drug> {
+        self.descriptions.get_by_right(&code)
     }
 
-    /// Either retrieve an existing code for the description string or create a new one.
-    /// If the function creates a new code, insert the description in the map.
+    /// Either retrieve an existing code for the description string and NDC or create a new one.
+    /// If the function creates a new code, insert the description and NDC in the map.
     ///
     /// # Arguments
     ///
     /// * `description` - The description string to convert to a code.
+    /// * `ndc` - The NDC of the record.
     ///
     /// # Returns
     ///
     /// The existing code or newly assigned code.
-    fn code_for_description(&mut self, description: &str) -> usize {
+    fn code_for_description(&mut self, description: &str, ndc: &str) -> usize {
+        let description = if self.normalize_descriptions {
+            normalize_description(description)
+        } else {
+            description.to_string()
+        };
+        let drug = Drug {
+            description,
+            ndc: ndc.to_string(),
+        };
+
         // See if we already have the value in the map.
-        if let Some(code) = self.descriptions.get_by_left(description) {
+        if let Some(code) = self.descriptions.get_by_left(&drug) {
             // The value is in the map, increase the count value for code
             // so we track how many records reference the description.
             if let Some(count) = self.code_use.get_mut(code) {
@@ -181,7 +516,8 @@ impl DataStore {
             // The map does not have this description, so insert it.
             let new_code = self.next_code;
             self.next_code += 1;
-            self.descriptions.insert(description.to_string(), new_code);
+            self.drugs.insert(drug.clone());
+            self.descriptions.insert(drug, new_code);
             self.code_use.insert(new_code, 1);
             new_code
         }
@@ -203,3 +539,320 @@ impl DataStore {
         }
     }
 }
+
+/// The `SectionDataStores` struct keeps a `DataStore` for each section of the report, so the
+/// report can have a section for each month or quarter, or for each classification.
+#[derive(Debug)]
+pub struct SectionDataStores {
+    /// The number of price changes each data store tracks in its top pool.
+    top_size: usize,
+
+    /// The number of price changes each data store tracks in its bottom pool.
+    bottom_size: usize,
+
+    /// When true, the data stores normalize descriptions.
+    normalize_descriptions: bool,
+
+    /// How the data stores rank the price changes.
+    metric: Metric,
+
+    /// Which directions of change the data stores track.
+    direction: Direction,
+
+    /// When set, the data stores log what happens to the price changes of the drug it follows.
+    explainer: Option<Explainer>,
+
+    /// The data store for each section, in date order.
+    stores: BTreeMap<Section, DataStore>,
+
+    /// When set, the price changes are collected here and combined for each drug or NDC before
+    /// they go into the data stores.
+    drug_changes: Option<DrugChanges>,
+}
+
+impl SectionDataStores {
+    /// Create a new, empty `SectionDataStores`.
+    ///
+    /// # Arguments
+    ///
+    /// * `top_size` - The number of price changes each data store tracks in its top pool.
+    /// * `bottom_size` - The number of price changes each data store tracks in its bottom
+    ///   pool.
+    /// * `normalize_descriptions` - When true, the data stores normalize descriptions.
+    /// * `metric` - How the data stores rank the price changes.
+    /// * `direction` - Which directions of change the data stores track.
+    /// * `explainer` - When set, the data stores log what happens to the price changes of the
+    ///   drug it follows.
+    /// * `aggregation` - When set, the price changes of each drug or NDC are combined this way
+    ///   and ranked in place of the individual changes.
+    pub fn new(
+        top_size: usize,
+        bottom_size: usize,
+        normalize_descriptions: bool,
+        metric: Metric,
+        direction: Direction,
+        explainer: Option<Explainer>,
+        aggregation: Option<(Grouping, Aggregate)>,
+    ) -> SectionDataStores {
+        SectionDataStores {
+            top_size,
+            bottom_size,
+            normalize_descriptions,
+            metric,
+            direction,
+            explainer,
+            stores: BTreeMap::new(),
+            drug_changes: aggregation
+                .map(|(grouping, aggregate)| DrugChanges::new(grouping, aggregate)),
+        }
+    }
+
+    /// Insert a row into the data store for a section.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The section the row is reported in.
+    /// * `row` - The row of comparison data.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a std::error::Error in a Box.
+    pub fn insert(
+        &mut self,
+        section: Section,
+        row: &ComparisonRow,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match &mut self.drug_changes {
+            Some(drug_changes) => {
+                drug_changes.add(section, row);
+                if let Some(explainer) = &self.explainer {
+                    explainer.log_row(row, "combined with the other changes of its drug or NDC");
+                }
+                Ok(())
+            }
+            None => self.get_mut(section)?.insert(row),
+        }
+    }
+
+    /// Put the combined price change of each drug or NDC into the data stores, once all of the
+    /// rows have been inserted. Does nothing unless the changes are combined.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a std::error::Error in a Box.
+    pub fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(drug_changes) = self.drug_changes.take() {
+            for change in drug_changes.into_changes() {
+                self.get_mut(change.section)?.insert_change(
+                    change.difference,
+                    &change.description,
+                    &change.ndc,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the data store for a section, creating it if there is not one yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The section.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the data store, on error returns a std::error::Error in a Box.
+    pub fn get_mut(
+        &mut self,
+        section: Section,
+    ) -> Result<&mut DataStore, Box<dyn std::error::Error>> {
+        match self.stores.entry(section) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let mut data_store =
+                    DataStore::new(self.top_size, self.bottom_size, self.metric, self.direction)?;
+                data_store.normalize_descriptions = self.normalize_descriptions;
+                data_store.explainer = self.explainer.clone();
+                Ok(entry.insert(data_store))
+            }
+        }
+    }
+
+    /// Get the number of price changes inserted into the data stores.
+    pub fn inserted(&self) -> usize {
+        self.stores
+            .values()
+            .map(|data_store| data_store.inserted)
+            .sum()
+    }
+
+    /// Get the number of distinct drugs the data stores have given a code.
+    pub fn interned(&self) -> usize {
+        self.stores
+            .values()
+            .flat_map(|data_store| data_store.drugs.iter())
+            .collect::<HashSet<&Drug>>()
+            .len()
+    }
+
+    /// Iterate over the sections and their data stores in date order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Section, &DataStore)> {
+        self.stores.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_parse_price() {
+        let expected = Decimal::from_str("1234.56").unwrap();
+
+        assert_eq!(parse_price("1234.56"), Ok(expected));
+        assert_eq!(parse_price("$1,234.56"), Ok(expected));
+        assert_eq!(parse_price(" $ 1,234.56 "), Ok(expected));
+        assert_eq!(parse_price("-$1,234.56"), Ok(-expected));
+        assert!(parse_price("$").is_err());
+        assert!(parse_price("N/A").is_err());
+    }
+
+    #[test]
+    fn test_normalize_descriptions() {
+        assert_eq!(
+            normalize_description(" lisinopril  10mg\ttab "),
+            "LISINOPRIL 10MG TAB"
+        );
+
+        let records = [
+            ByteRecord::from(vec!["LISINOPRIL 10MG TAB", "00001", "1.00", "2.00"]),
+            ByteRecord::from(vec!["lisinopril 10mg  tab ", "00001", "1.00", "3.00"]),
+        ];
+
+        for (normalize, expected) in [(true, 1), (false, 2)] {
+            let mut data_store = DataStore::new(10, 10, Metric::Change, Direction::Both).unwrap();
+            data_store.normalize_descriptions = normalize;
+            for record in &records {
+                let row = ComparisonRow::from_record(record).unwrap();
+                data_store.insert(&row).unwrap();
+            }
+            assert_eq!(data_store.descriptions.len(), expected);
+        }
+    }
+
+    #[test]
+    fn test_explain() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let explainer = Explainer::new("drug", log.clone()).unwrap();
+
+        let mut data_store = DataStore::new(2, 1, Metric::Change, Direction::Both).unwrap();
+        data_store.explainer = Some(explainer.clone());
+        // DRUG D ties DRUG A at the cutoff of the top pool and loses the tie-break as the newer
+        // change, so it is never kept.
+        for (description, change) in [
+            ("DRUG A", 5),
+            ("DRUG B", 3),
+            ("DRUG C", 7),
+            ("DRUG D", 5),
+            ("DRUG E", -1),
+            ("DRUG F", 4),
+        ] {
+            data_store
+                .insert_change(Decimal::new(change * 100, 2), description, "")
+                .unwrap();
+        }
+
+        let mut increases = DataStore::new(2, 2, Metric::Change, Direction::Increases).unwrap();
+        increases.explainer = Some(explainer);
+        increases
+            .insert_change(Decimal::new(-200, 2), "DRUG G", "")
+            .unwrap();
+
+        let expected = "Explain: DRUG A, change $5.00: inserted into the top pool\n\
+            Explain: DRUG B, change $3.00: inserted into the top pool\n\
+            Explain: DRUG B, change $3.00: evicted from the top pool by $7.00: DRUG C\n\
+            Explain: DRUG B, change $3.00: moved to the bottom pool\n\
+            Explain: DRUG C, change $7.00: inserted into the top pool\n\
+            Explain: DRUG D, change $5.00: not kept: below the pool cutoff\n\
+            Explain: DRUG B, change $3.00: evicted from the bottom pool by -$1.00: DRUG E\n\
+            Explain: DRUG E, change -$1.00: inserted into the bottom pool\n\
+            Explain: DRUG F, change $4.00: not large enough to fit in the pools\n\
+            Explain: DRUG G, change -$2.00: not kept: in the direction that is not ranked\n";
+        assert_eq!(String::from_utf8(log.borrow().clone()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_direction() {
+        // A single pool with room for every change still only takes those in its direction.
+        let changes = [3, -2, 5, -7, 1];
+        for (direction, top_size, bottom_size, top, bottom) in [
+            (Direction::Both, 2, 2, Some(vec![5, 3]), Some(vec![-7, -2])),
+            (Direction::Both, 2, 1, Some(vec![5, 3]), Some(vec![-7])),
+            (Direction::Increases, 2, 2, Some(vec![5, 3]), None),
+            (Direction::Decreases, 2, 2, None, Some(vec![-7, -2])),
+            (Direction::Increases, 10, 10, Some(vec![5, 3, 1]), None),
+            (Direction::Decreases, 10, 10, None, Some(vec![-7, -2])),
+        ] {
+            let mut data_store =
+                DataStore::new(top_size, bottom_size, Metric::Change, direction).unwrap();
+            for change in changes {
+                data_store
+                    .insert_change(Decimal::new(change, 0), &change.to_string(), "")
+                    .unwrap();
+            }
+
+            let differences = |pool: &RecordPool| {
+                let mut differences: Vec<i64> = pool
+                    .iter()
+                    .map(|(difference, _)| difference.mantissa() as i64)
+                    .collect();
+                differences.sort_by_key(|difference| std::cmp::Reverse(difference.abs()));
+                differences
+            };
+            assert_eq!(data_store.get_top().map(differences), top);
+            assert_eq!(data_store.get_bottom().map(differences), bottom);
+            let expected = top.iter().chain(&bottom).flatten().count();
+            assert_eq!(data_store.descriptions.len(), expected);
+        }
+    }
+
+    #[test]
+    fn test_details() {
+        let mut data_store = DataStore::new(1, 1, Metric::Change, Direction::Both).unwrap();
+        let details = |old_price: i64| PriceDetails {
+            old_price: Decimal::new(old_price, 0),
+            effective_date: NaiveDate::from_ymd_opt(2023, 1, 1),
+        };
+        for (change, description, old_price) in [(3, "A", 10), (5, "B", 20), (-2, "C", 30)] {
+            data_store
+                .insert_priced_change(
+                    Decimal::new(change, 0),
+                    description,
+                    "",
+                    Some(details(old_price)),
+                )
+                .unwrap();
+        }
+        data_store
+            .insert_change(Decimal::new(-4, 0), "D", "")
+            .unwrap();
+
+        // A was pushed out by B and C by D, so only B's prices are kept.
+        assert_eq!(data_store.details.len(), 1);
+        let code = *data_store
+            .descriptions
+            .get_by_left(&Drug {
+                description: "B".to_string(),
+                ndc: String::new(),
+            })
+            .unwrap();
+        assert_eq!(
+            data_store.get_details(Decimal::new(5, 0), code),
+            Some(&details(20))
+        );
+    }
+}
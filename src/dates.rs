@@ -0,0 +1,302 @@
+//! The `dates` module provides code for parsing the dates in the price change data, which come
+//! in different formats depending on where the data was obtained.
+
+use chrono::{Datelike, Month, NaiveDate};
+use clap::ValueEnum;
+use std::fmt;
+
+/// The index of the effective date in the NADAC comparison columns.
+pub const EFFECTIVE_DATE_FIELD: usize = 9;
+
+/// The date formats accepted in the data, tried in order. The NADAC comparison file uses
+/// `%m/%d/%Y`. Two digit years come first because `%Y` would read `23` as the year 23, while
+/// `%y` does not match four digit years.
+const DATE_FORMATS: [&str; 8] = [
+    "%m/%d/%y",
+    "%m/%d/%Y",
+    "%Y-%m-%d",
+    "%Y/%m/%d",
+    "%m-%d-%Y",
+    "%d-%b-%Y",
+    "%b %d, %Y",
+    "%Y%m%d",
+];
+
+/// Parse a date in any of the accepted formats. A time after the date, as in
+/// `2023-01-04T00:00:00` or `01/04/2023 00:00:00`, is ignored.
+///
+/// # Arguments
+///
+/// * `text` - The date text.
+///
+/// # Returns
+///
+/// On success, returns the date, on error returns a String describing the problem.
+pub fn parse_date(text: &str) -> Result<NaiveDate, String> {
+    let text = text.trim();
+    let date = text
+        .split_once(['T', ' '])
+        .filter(|(_, time)| time.contains(':'))
+        .map(|(date, _)| date)
+        .unwrap_or(text);
+
+    // The NADAC comparison file's own format is checked by hand first, since there is a date on
+    // every row and chrono's format parsing is comparatively slow.
+    if let Some(date) = parse_month_day_year(date) {
+        return Ok(date);
+    }
+
+    DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(date, format).ok())
+        .ok_or_else(|| format!("Unrecognized date {}", text))
+}
+
+/// Parse a date in the `MM/DD/YYYY` format of the NADAC comparison file.
+///
+/// # Arguments
+///
+/// * `text` - The date text.
+///
+/// # Returns
+///
+/// An Option which will contain the date, or None if the text is not in the format.
+fn parse_month_day_year(text: &str) -> Option<NaiveDate> {
+    let mut parts = text.split('/');
+    let (month, day, year) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || year.len() != 4 {
+        return None;
+    }
+
+    let number = |part: &str| {
+        if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        part.parse::<u32>().ok()
+    };
+    NaiveDate::from_ymd_opt(number(year)? as i32, number(month)?, number(day)?)
+}
+
+/// Find the most recent complete year of data with effective dates from `first` to `last`. A
+/// year is complete when the data runs to its last day or beyond.
+///
+/// # Arguments
+///
+/// * `first` - The earliest effective date in the data.
+/// * `last` - The latest effective date in the data.
+///
+/// # Returns
+///
+/// An Option which will contain the year, or None if the data does not cover the end of any
+/// year it starts in.
+pub fn latest_complete_year(first: NaiveDate, last: NaiveDate) -> Option<i32> {
+    let year = last.succ_opt().map_or(last.year(), |next| next.year()) - 1;
+    (year >= first.year()).then_some(year)
+}
+
+/// Enum describing the span of effective dates the report covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Period {
+    /// The price changes that took effect in a calendar year.
+    Year(i32),
+
+    /// The price changes that took effect in a month, numbered from 1.
+    Month { year: i32, month: u32 },
+
+    /// The price changes that took effect in a quarter, numbered from 1.
+    Quarter { year: i32, quarter: u32 },
+
+    /// The price changes that took effect between two dates, inclusive. A missing bound leaves
+    /// that end of the range open.
+    Range {
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    },
+}
+
+impl Period {
+    /// Determine if a date falls within the period.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The date to check.
+    ///
+    /// # Returns
+    ///
+    /// True if the date is in the period.
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        match self {
+            Period::Year(year) => date.year() == *year,
+            Period::Month { .. } => GroupBy::Month.period_of(date) == *self,
+            Period::Quarter { .. } => GroupBy::Quarter.period_of(date) == *self,
+            Period::Range { from, to } => {
+                from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+            }
+        }
+    }
+}
+
+/// Display the period as it reads in the report headers, e.g. `of 2023` or
+/// `from 2023-03-01 to 2023-09-30`.
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Period::Year(year) => write!(f, "of {}", year),
+            Period::Month { year, month } => match Month::try_from(*month as u8) {
+                Ok(month) => write!(f, "of {} {}", month.name(), year),
+                Err(_) => write!(f, "of {}-{:02}", year, month),
+            },
+            Period::Quarter { year, quarter } => write!(f, "of Q{} {}", quarter, year),
+            Period::Range {
+                from: Some(from),
+                to: Some(to),
+            } => write!(f, "from {} to {}", from, to),
+            Period::Range {
+                from: Some(from),
+                to: None,
+            } => write!(f, "from {}", from),
+            Period::Range {
+                from: None,
+                to: Some(to),
+            } => write!(f, "up to {}", to),
+            Period::Range {
+                from: None,
+                to: None,
+            } => write!(f, "of all dates"),
+        }
+    }
+}
+
+/// Enum describing how the price changes are split into sections of the report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    /// A section for each month.
+    Month,
+
+    /// A section for each quarter.
+    Quarter,
+}
+
+impl GroupBy {
+    /// Find the period of this kind that a date falls in.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The date.
+    ///
+    /// # Returns
+    ///
+    /// The month or quarter containing the date.
+    pub fn period_of(&self, date: NaiveDate) -> Period {
+        match self {
+            GroupBy::Month => Period::Month {
+                year: date.year(),
+                month: date.month(),
+            },
+            GroupBy::Quarter => Period::Quarter {
+                year: date.year(),
+                quarter: (date.month() - 1) / 3 + 1,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date() {
+        let expected = NaiveDate::from_ymd_opt(2023, 1, 4).unwrap();
+
+        assert_eq!(parse_date("01/04/2023"), Ok(expected));
+        assert_eq!(parse_date("1/4/2023"), Ok(expected));
+        assert_eq!(parse_date("2023-01-04"), Ok(expected));
+        assert_eq!(parse_date("2023-01-04T00:00:00"), Ok(expected));
+        assert_eq!(parse_date("01/04/2023 00:00:00"), Ok(expected));
+        assert_eq!(parse_date("01/04/23"), Ok(expected));
+        assert_eq!(parse_date("04-Jan-2023"), Ok(expected));
+        assert_eq!(parse_date("Jan 04, 2023"), Ok(expected));
+        assert_eq!(parse_date("20230104"), Ok(expected));
+        assert!(parse_date("next tuesday").is_err());
+        assert!(parse_date("13/45/2023").is_err());
+    }
+
+    #[test]
+    fn test_latest_complete_year() {
+        let date = |year, month, day| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+
+        assert_eq!(
+            latest_complete_year(date(2022, 12, 7), date(2024, 1, 3)),
+            Some(2023)
+        );
+        assert_eq!(
+            latest_complete_year(date(2023, 1, 4), date(2023, 12, 31)),
+            Some(2023)
+        );
+        assert_eq!(
+            latest_complete_year(date(2022, 1, 5), date(2023, 12, 27)),
+            Some(2022)
+        );
+        assert_eq!(
+            latest_complete_year(date(2023, 1, 4), date(2023, 12, 27)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_period() {
+        let date = |month, day| NaiveDate::from_ymd_opt(2023, month, day).unwrap();
+
+        let year = Period::Year(2023);
+        assert!(year.contains(date(1, 1)));
+        assert!(!year.contains(NaiveDate::from_ymd_opt(2022, 12, 31).unwrap()));
+        assert_eq!(year.to_string(), "of 2023");
+
+        let range = Period::Range {
+            from: Some(date(3, 1)),
+            to: Some(date(9, 30)),
+        };
+        assert!(range.contains(date(3, 1)));
+        assert!(range.contains(date(9, 30)));
+        assert!(!range.contains(date(2, 28)));
+        assert!(!range.contains(date(10, 1)));
+        assert_eq!(range.to_string(), "from 2023-03-01 to 2023-09-30");
+
+        let open = Period::Range {
+            from: None,
+            to: Some(date(9, 30)),
+        };
+        assert!(open.contains(NaiveDate::from_ymd_opt(1999, 1, 1).unwrap()));
+        assert_eq!(open.to_string(), "up to 2023-09-30");
+    }
+
+    #[test]
+    fn test_group_by() {
+        let date = NaiveDate::from_ymd_opt(2023, 8, 9).unwrap();
+
+        let month = GroupBy::Month.period_of(date);
+        assert_eq!(
+            month,
+            Period::Month {
+                year: 2023,
+                month: 8
+            }
+        );
+        assert!(month.contains(date));
+        assert!(!month.contains(NaiveDate::from_ymd_opt(2023, 9, 1).unwrap()));
+        assert_eq!(month.to_string(), "of August 2023");
+
+        let quarter = GroupBy::Quarter.period_of(date);
+        assert_eq!(
+            quarter,
+            Period::Quarter {
+                year: 2023,
+                quarter: 3
+            }
+        );
+        assert!(quarter.contains(NaiveDate::from_ymd_opt(2023, 7, 1).unwrap()));
+        assert!(!quarter.contains(NaiveDate::from_ymd_opt(2022, 8, 9).unwrap()));
+        assert_eq!(quarter.to_string(), "of Q3 2023");
+    }
+}
@@ -0,0 +1,155 @@
+//! The `dedup` module provides code for skipping rows of the price change data that repeat an
+//! earlier row, which some snapshots contain and which would otherwise take up two places in
+//! the report.
+
+use crate::comparison::ComparisonRow;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+
+/// The `RowKey` struct holds the fields that identify a row: the description, NDC, old price,
+/// new price and effective date.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RowKey {
+    description: String,
+    ndc: String,
+    old_price: Decimal,
+    new_price: Decimal,
+    effective_date: Option<NaiveDate>,
+}
+
+impl RowKey {
+    /// Create a new `RowKey` for a row.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    fn new(row: &ComparisonRow) -> RowKey {
+        RowKey {
+            description: row.description.trim().to_string(),
+            ndc: row.ndc.trim().to_string(),
+            old_price: row.old_price,
+            new_price: row.new_price,
+            effective_date: row.effective_date,
+        }
+    }
+}
+
+/// The `RowDeduplicator` struct remembers the rows it has seen so repeats can be skipped. Only
+/// the identifying fields of the most recently seen rows are kept, so the memory used is
+/// bounded however large the data is.
+#[derive(Debug)]
+pub struct RowDeduplicator {
+    /// The number of rows remembered.
+    capacity: usize,
+
+    /// Each remembered row and when it was last seen. The whole key is compared, so rows whose
+    /// hashes collide are not taken for repeats.
+    last_seen: HashMap<RowKey, u64>,
+
+    /// The remembered rows by when they were last seen, oldest first.
+    by_age: BTreeMap<u64, RowKey>,
+
+    /// The number of rows checked so far, used to order the rows by when they were seen.
+    tick: u64,
+
+    /// The number of repeated rows found.
+    duplicates: usize,
+}
+
+impl RowDeduplicator {
+    /// Create a new `RowDeduplicator`.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of rows to remember. Repeats of rows seen longer ago than this
+    ///   are not found.
+    pub fn new(capacity: usize) -> RowDeduplicator {
+        RowDeduplicator {
+            capacity: capacity.max(1),
+            last_seen: HashMap::new(),
+            by_age: BTreeMap::new(),
+            tick: 0,
+            duplicates: 0,
+        }
+    }
+
+    /// Check if a row repeats a remembered row, and remember it.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    ///
+    /// # Returns
+    ///
+    /// True if the row is a repeat and should be skipped.
+    pub fn is_duplicate(&mut self, row: &ComparisonRow) -> bool {
+        let key = RowKey::new(row);
+        self.tick += 1;
+
+        let previous = self.last_seen.insert(key.clone(), self.tick);
+        self.by_age.insert(self.tick, key);
+
+        if let Some(previous) = previous {
+            self.by_age.remove(&previous);
+            self.duplicates += 1;
+            return true;
+        }
+
+        // Forget the row seen longest ago once there are too many.
+        if self.last_seen.len() > self.capacity {
+            if let Some((_, oldest)) = self.by_age.pop_first() {
+                self.last_seen.remove(&oldest);
+            }
+        }
+
+        false
+    }
+
+    /// Get the number of repeated rows found.
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+
+    fn is_duplicate(dedup: &mut RowDeduplicator, ndc: &str, new_price: &str) -> bool {
+        let record = ByteRecord::from(vec![
+            "ASPIRIN 81 MG",
+            ndc,
+            "1.00",
+            new_price,
+            "G",
+            "",
+            "Survey Rate",
+            "",
+            "",
+            "01/04/2023",
+        ]);
+        dedup.is_duplicate(&ComparisonRow::from_record(&record).unwrap())
+    }
+
+    #[test]
+    fn test_is_duplicate() {
+        let mut dedup = RowDeduplicator::new(2);
+
+        assert!(!is_duplicate(&mut dedup, "00001", "2.00"));
+        assert!(is_duplicate(&mut dedup, "00001", "2.00"));
+        assert!(!is_duplicate(&mut dedup, "00001", "3.00"));
+        assert!(is_duplicate(&mut dedup, "00001", "2.00"));
+
+        // 00001 at 2.00 was seen more recently than 00001 at 3.00, so 00001 at 3.00 is
+        // forgotten to make room for 00002.
+        assert!(!is_duplicate(&mut dedup, "00002", "2.00"));
+        assert!(!is_duplicate(&mut dedup, "00001", "3.00"));
+        assert!(is_duplicate(&mut dedup, "00002", "2.00"));
+
+        // The fields are compared without their surrounding spaces.
+        assert!(is_duplicate(&mut dedup, " 00002 ", "2.00"));
+        assert_eq!(dedup.duplicates(), 4);
+    }
+}
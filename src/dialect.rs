@@ -0,0 +1,188 @@
+//! The `dialect` module provides code for handling the variants of the CSV format the price
+//! change data may come in, such as tab or semicolon separated files.
+
+use crate::data_source::DataReader;
+use futures::io::{AsyncBufReadExt, BufReader};
+
+/// The delimiters considered when detecting the delimiter, in order of preference.
+const CANDIDATE_DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+/// The number of leading bytes examined to detect the delimiter.
+const SAMPLE_SIZE: usize = 16 * 1024;
+
+/// The number of lines examined to detect the delimiter.
+const SAMPLE_LINES: usize = 20;
+
+/// Enum describing how the fields of the CSV data are separated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delimiter {
+    /// The fields are separated by this byte.
+    Byte(u8),
+
+    /// The delimiter is detected from the leading bytes of the data.
+    Auto,
+}
+
+impl Default for Delimiter {
+    fn default() -> Self {
+        Delimiter::Byte(b',')
+    }
+}
+
+impl Delimiter {
+    /// Work out the delimiter byte for the data read by a reader. For `Delimiter::Auto`, the
+    /// leading bytes of the data are examined without consuming them.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader producing the CSV data.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns a `DataReader` that produces the whole of the data and the delimiter
+    /// byte, on error returns the std::io::Error from reading the leading bytes.
+    pub async fn resolve(self, reader: DataReader) -> std::io::Result<(DataReader, u8)> {
+        match self {
+            Delimiter::Byte(delimiter) => Ok((reader, delimiter)),
+            Delimiter::Auto => {
+                let mut reader = BufReader::with_capacity(SAMPLE_SIZE, reader);
+                let delimiter = detect_delimiter(reader.fill_buf().await?);
+                Ok((Box::pin(reader), delimiter))
+            }
+        }
+    }
+}
+
+/// Parse a delimiter given on the command line.
+///
+/// # Arguments
+///
+/// * `text` - The delimiter: a single ASCII character, or `tab`, `\t`, `comma`, `semicolon`,
+///   `pipe` or `auto`.
+///
+/// # Returns
+///
+/// On success, returns the `Delimiter`, on error returns a String describing the problem.
+pub fn parse_delimiter(text: &str) -> Result<Delimiter, String> {
+    match text.to_ascii_lowercase().as_str() {
+        "auto" => Ok(Delimiter::Auto),
+        "tab" | "\\t" => Ok(Delimiter::Byte(b'\t')),
+        "comma" => Ok(Delimiter::Byte(b',')),
+        "semicolon" => Ok(Delimiter::Byte(b';')),
+        "pipe" => Ok(Delimiter::Byte(b'|')),
+        _ => match text.as_bytes() {
+            [byte] if byte.is_ascii() && *byte != b'"' && *byte != b'\n' && *byte != b'\r' => {
+                Ok(Delimiter::Byte(*byte))
+            }
+            _ => Err(format!(
+                "Expected a single character delimiter, tab, comma, semicolon, pipe or auto but \
+                found {}",
+                text
+            )),
+        },
+    }
+}
+
+/// Detect the delimiter of CSV data from its leading bytes. A delimiter that appears the same
+/// number of times on every line is preferred, otherwise the delimiter that appears most often
+/// is used. Delimiters inside quoted fields are not counted.
+///
+/// # Arguments
+///
+/// * `sample` - The leading bytes of the data.
+///
+/// # Returns
+///
+/// The delimiter, which is a comma when the sample gives no hint.
+pub fn detect_delimiter(sample: &[u8]) -> u8 {
+    let mut lines: Vec<&[u8]> = sample
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.iter().all(|byte| byte.is_ascii_whitespace()))
+        .collect();
+    // The last line of the sample is probably cut short.
+    if lines.len() > 1 && !sample.ends_with(b"\n") {
+        lines.pop();
+    }
+    lines.truncate(SAMPLE_LINES);
+
+    let counts: Vec<Vec<usize>> = CANDIDATE_DELIMITERS
+        .iter()
+        .map(|delimiter| {
+            lines
+                .iter()
+                .map(|line| count_unquoted(line, *delimiter))
+                .collect()
+        })
+        .collect();
+
+    let consistent = CANDIDATE_DELIMITERS
+        .iter()
+        .zip(&counts)
+        .filter(|(_, counts)| counts.first().is_some_and(|first| *first > 0))
+        .filter(|(_, counts)| counts.iter().all(|count| *count == counts[0]))
+        .max_by_key(|(delimiter, counts)| (counts[0], preference(**delimiter)));
+    if let Some((delimiter, _)) = consistent {
+        return *delimiter;
+    }
+
+    CANDIDATE_DELIMITERS
+        .iter()
+        .zip(&counts)
+        .map(|(delimiter, counts)| (*delimiter, counts.iter().sum::<usize>()))
+        .filter(|(_, total)| *total > 0)
+        .max_by_key(|(delimiter, total)| (*total, preference(*delimiter)))
+        .map_or(b',', |(delimiter, _)| delimiter)
+}
+
+/// Rank a candidate delimiter so that earlier candidates win ties.
+fn preference(delimiter: u8) -> usize {
+    CANDIDATE_DELIMITERS.len()
+        - CANDIDATE_DELIMITERS
+            .iter()
+            .position(|candidate| *candidate == delimiter)
+            .unwrap_or(CANDIDATE_DELIMITERS.len())
+}
+
+/// Count the times a delimiter appears in a line outside of quoted fields.
+fn count_unquoted(line: &[u8], delimiter: u8) -> usize {
+    let mut quoted = false;
+    let mut count = 0;
+    for byte in line {
+        if *byte == b'"' {
+            quoted = !quoted;
+        } else if *byte == delimiter && !quoted {
+            count += 1;
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_delimiter() {
+        assert_eq!(parse_delimiter(","), Ok(Delimiter::Byte(b',')));
+        assert_eq!(parse_delimiter("tab"), Ok(Delimiter::Byte(b'\t')));
+        assert_eq!(parse_delimiter("\\t"), Ok(Delimiter::Byte(b'\t')));
+        assert_eq!(parse_delimiter("\t"), Ok(Delimiter::Byte(b'\t')));
+        assert_eq!(parse_delimiter("Semicolon"), Ok(Delimiter::Byte(b';')));
+        assert_eq!(parse_delimiter("|"), Ok(Delimiter::Byte(b'|')));
+        assert_eq!(parse_delimiter("auto"), Ok(Delimiter::Auto));
+        assert!(parse_delimiter("\"").is_err());
+        assert!(parse_delimiter(";;").is_err());
+        assert!(parse_delimiter("é").is_err());
+    }
+
+    #[test]
+    fn test_detect_delimiter() {
+        assert_eq!(detect_delimiter(b"a,b,c\n1,2,3\n"), b',');
+        assert_eq!(detect_delimiter(b"a\tb\tc\n1\t2\t3\n4\t5"), b'\t');
+        assert_eq!(detect_delimiter(b"a;b;c\n\"1,5\";2;3\n"), b';');
+        assert_eq!(detect_delimiter(b"a|b,c|d\n1|2,5|3\n"), b'|');
+        assert_eq!(detect_delimiter(b"a;b\n1;2;3,4\n"), b';');
+        assert_eq!(detect_delimiter(b"description\n"), b',');
+        assert_eq!(detect_delimiter(b""), b',');
+    }
+}
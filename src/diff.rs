@@ -0,0 +1,410 @@
+//! The `diff` module provides code for the `diff` subcommand, which compares two reports saved
+//! with `--format json`, e.g. last month's and this month's, showing for each ranked list the
+//! drugs that entered and left it, how the ranks of the others moved and how their changes
+//! differ. The lists are matched by the drugs and kind of change they cover rather than their
+//! period, so reports on different periods can be compared.
+
+use crate::json::JSON_REPORT_VERSION;
+use crate::report::dollars;
+use comfy_table::{presets, CellAlignment, Table};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// What identifies a ranked list in both reports: the drugs and kind of change it covers, and
+/// how many lists of the same drugs and kind come before it in the report, e.g. the second
+/// list of increases of a report on two years.
+type ListKey<'a> = (Option<&'a str>, &'a str, usize);
+
+/// What identifies a price change of a ranked list in both reports: the drug's key and how
+/// many changes of the same drug come before it in the list, since a drug may have several.
+type EntryKey<'a> = (Option<&'a str>, usize);
+
+/// A saved JSON report.
+#[derive(Debug, Deserialize)]
+struct SavedReport {
+    /// The version of the layout of the report.
+    version: u32,
+
+    /// The sections of the report.
+    sections: Vec<SavedSection>,
+}
+
+impl SavedReport {
+    /// Get the ranked lists of the report, each with its key and the period it covers.
+    fn keyed_lists(&self) -> Vec<(ListKey<'_>, &str, &SavedList)> {
+        let mut seen: HashMap<(Option<&str>, &str), usize> = HashMap::new();
+        let mut lists = Vec::new();
+        for section in &self.sections {
+            for list in &section.lists {
+                let count = seen
+                    .entry((section.drugs.as_deref(), list.kind.as_str()))
+                    .or_default();
+                let key = (section.drugs.as_deref(), list.kind.as_str(), *count);
+                *count += 1;
+                lists.push((key, section.period.as_str(), list));
+            }
+        }
+        lists
+    }
+}
+
+/// A section of a saved JSON report.
+#[derive(Debug, Deserialize)]
+struct SavedSection {
+    /// The period of the section, e.g. `2023`.
+    period: String,
+
+    /// The drugs of the section, e.g. `brand`, or None for all of them.
+    drugs: Option<String>,
+
+    /// The ranked lists of the section.
+    lists: Vec<SavedList>,
+}
+
+/// A ranked list of a saved JSON report.
+#[derive(Debug, Deserialize)]
+struct SavedList {
+    /// The kind of change of the list, e.g. `increases`.
+    kind: String,
+
+    /// The price changes of the list, in rank order.
+    entries: Vec<SavedEntry>,
+}
+
+impl SavedList {
+    /// Get the key of each price change of the list, in rank order.
+    fn entry_keys(&self) -> Vec<EntryKey<'_>> {
+        let mut seen: HashMap<Option<&str>, usize> = HashMap::new();
+        self.entries
+            .iter()
+            .map(|entry| {
+                let count = seen.entry(entry.key()).or_default();
+                let key = (entry.key(), *count);
+                *count += 1;
+                key
+            })
+            .collect()
+    }
+}
+
+/// A price change of a saved JSON report. The report may have been saved with only some of
+/// the fields, chosen with `--fields`.
+#[derive(Debug, Deserialize)]
+struct SavedEntry {
+    /// The rank of the change in its list.
+    rank: usize,
+
+    /// The change in the per unit price.
+    change: Option<Decimal>,
+
+    /// The NDC of the drug, which may be empty.
+    ndc: Option<String>,
+
+    /// The description of the drug.
+    description: Option<String>,
+}
+
+impl SavedEntry {
+    /// Get what identifies the drug in both reports: its NDC when there is one, otherwise its
+    /// description.
+    fn key(&self) -> Option<&str> {
+        self.ndc
+            .as_deref()
+            .filter(|ndc| !ndc.is_empty())
+            .or(self.description.as_deref())
+    }
+
+    /// Describe the drug for the comparison.
+    fn drug(&self) -> String {
+        match (self.description.as_deref(), self.ndc.as_deref()) {
+            (Some(description), Some(ndc)) if !ndc.is_empty() => {
+                format!("{} (NDC {})", description, ndc)
+            }
+            (Some(description), _) => description.to_string(),
+            (None, ndc) => format!("NDC {}", ndc.unwrap_or_default()),
+        }
+    }
+}
+
+/// Read a saved JSON report.
+///
+/// # Arguments
+///
+/// * `name` - The name of the report, used in error messages.
+/// * `text` - The text of the report.
+///
+/// # Returns
+///
+/// On success, returns the `SavedReport`, on error returns a String describing the problem.
+fn read_report(name: &str, text: &str) -> Result<SavedReport, String> {
+    let report: SavedReport = serde_json::from_str(text)
+        .map_err(|e| format!("{} is not a report saved with --format json: {}", name, e))?;
+    if report.version != JSON_REPORT_VERSION {
+        return Err(format!(
+            "{} is a version {} report, only version {} reports can be compared",
+            name, report.version, JSON_REPORT_VERSION
+        ));
+    }
+    for entry in report
+        .sections
+        .iter()
+        .flat_map(|section| &section.lists)
+        .flat_map(|list| &list.entries)
+    {
+        if entry.key().is_none() {
+            return Err(format!(
+                "{} was saved without the description and ndc fields, which identify the drugs",
+                name
+            ));
+        }
+    }
+    Ok(report)
+}
+
+/// Describe the movement of a drug between two ranks, e.g. `up 2`.
+fn movement(old_rank: usize, new_rank: usize) -> String {
+    match new_rank.cmp(&old_rank) {
+        std::cmp::Ordering::Less => format!("up {}", old_rank - new_rank),
+        std::cmp::Ordering::Greater => format!("down {}", new_rank - old_rank),
+        std::cmp::Ordering::Equal => "same".to_string(),
+    }
+}
+
+/// Write a change of a saved report, or nothing when it was not saved.
+fn change_text(change: Option<Decimal>) -> String {
+    change.map(|change| dollars(&change)).unwrap_or_default()
+}
+
+/// Compare a ranked list of the two reports.
+///
+/// # Arguments
+///
+/// * `old` - The list in the old report, or None when it has none.
+/// * `new` - The list in the new report, or None when it has none.
+///
+/// # Returns
+///
+/// A Vec containing the lines of the comparison, without the line breaks: an aligned table
+/// with a row for each price change of the new list, in rank order, followed by a row for each
+/// that left the list. A drug with several changes in a list has its first change matched with
+/// its first change in the other list, its second with its second, and so on.
+fn compare_list(old: Option<&SavedList>, new: Option<&SavedList>) -> Vec<String> {
+    let old_keys = old.map(SavedList::entry_keys).unwrap_or_default();
+    let new_keys = new.map(SavedList::entry_keys).unwrap_or_default();
+    let old = old.map(|list| list.entries.as_slice()).unwrap_or_default();
+    let new = new.map(|list| list.entries.as_slice()).unwrap_or_default();
+
+    let mut table = Table::new();
+    table.load_preset(presets::NOTHING);
+    table.set_header([
+        "Status",
+        "Old rank",
+        "New rank",
+        "Old change",
+        "New change",
+        "Difference",
+        "Drug",
+    ]);
+
+    for (entry, key) in new.iter().zip(&new_keys) {
+        let earlier = old_keys
+            .iter()
+            .position(|earlier| earlier == key)
+            .map(|index| &old[index]);
+        let row = match earlier {
+            Some(earlier) => {
+                let difference = match (earlier.change, entry.change) {
+                    (Some(earlier), Some(later)) => {
+                        let difference = later - earlier;
+                        let sign = if difference.is_sign_positive() || difference.is_zero() {
+                            "+"
+                        } else {
+                            ""
+                        };
+                        format!("{}{}", sign, dollars(&difference))
+                    }
+                    _ => String::new(),
+                };
+                [
+                    movement(earlier.rank, entry.rank),
+                    earlier.rank.to_string(),
+                    entry.rank.to_string(),
+                    change_text(earlier.change),
+                    change_text(entry.change),
+                    difference,
+                    entry.drug(),
+                ]
+            }
+            None => [
+                "entered".to_string(),
+                String::new(),
+                entry.rank.to_string(),
+                String::new(),
+                change_text(entry.change),
+                String::new(),
+                entry.drug(),
+            ],
+        };
+        table.add_row(row);
+    }
+    for (entry, key) in old.iter().zip(&old_keys) {
+        if !new_keys.contains(key) {
+            table.add_row([
+                "left".to_string(),
+                entry.rank.to_string(),
+                String::new(),
+                change_text(entry.change),
+                String::new(),
+                String::new(),
+                entry.drug(),
+            ]);
+        }
+    }
+
+    // The ranks and amounts are aligned to the right and the columns are separated by two
+    // spaces.
+    for (index, column) in table.column_iter_mut().enumerate() {
+        if (1..=5).contains(&index) {
+            column.set_cell_alignment(CellAlignment::Right);
+        }
+        column.set_padding((0, 2));
+    }
+    table
+        .lines()
+        .map(|line| line.trim_end().to_string())
+        .collect()
+}
+
+/// Compare two reports saved with `--format json`. Each ranked list of the new report is
+/// compared with the list of the same drugs and kind of change in the old report, whatever
+/// their periods, and the drugs are matched by their NDC, or their description when they have
+/// none. The lists only in the old report follow.
+///
+/// # Arguments
+///
+/// * `old_name` - The name of the old report, used in error messages.
+/// * `old` - The text of the old report.
+/// * `new_name` - The name of the new report, used in error messages.
+/// * `new` - The text of the new report.
+///
+/// # Returns
+///
+/// On success, returns a new String containing the comparison, on error returns a String
+/// describing why a report could not be read.
+pub fn diff_reports(
+    old_name: &str,
+    old: &str,
+    new_name: &str,
+    new: &str,
+) -> Result<String, String> {
+    let old = read_report(old_name, old)?;
+    let new = read_report(new_name, new)?;
+
+    let old_lists = old.keyed_lists();
+    let new_lists = new.keyed_lists();
+
+    // Each comparison is named by the list, e.g. `brand increases of 2023`, and the period of
+    // the old list when it differs.
+    let name = |(drugs, kind, _): &ListKey, period: &str| match drugs {
+        Some(drugs) => format!("{} {} of {}", drugs, kind, period),
+        None => format!("{} of {}", kind, period),
+    };
+    let mut comparisons = Vec::new();
+    for (key, period, list) in &new_lists {
+        let earlier = old_lists.iter().find(|(earlier, _, _)| earlier == key);
+        let mut heading = name(key, period);
+        if let Some((_, earlier_period, _)) = earlier.filter(|(_, earlier, _)| earlier != period) {
+            heading.push_str(&format!(", compared with {}", earlier_period));
+        }
+        let earlier = earlier.map(|(_, _, earlier)| *earlier);
+        comparisons.push((heading, compare_list(earlier, Some(list))));
+    }
+    for (key, period, list) in &old_lists {
+        if !new_lists.iter().any(|(later, _, _)| later == key) {
+            comparisons.push((name(key, period), compare_list(Some(list), None)));
+        }
+    }
+
+    Ok(comparisons
+        .into_iter()
+        .map(|(name, lines)| {
+            let mut comparison = format!("Changes in the {}:\n", name);
+            for line in lines {
+                comparison.push_str(&line);
+                comparison.push('\n');
+            }
+            comparison
+        })
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_store::{DataStore, Direction, Metric};
+    use crate::dates::Period;
+    use crate::json::json_report;
+    use crate::report::{Section, ALL_FIELDS};
+
+    /// Save a report of increases in a year as JSON.
+    fn saved(year: i32, changes: &[(i64, &str)]) -> String {
+        let mut data_store = DataStore::new(3, 3, Metric::Change, Direction::Increases).unwrap();
+        for (cents, description) in changes {
+            data_store
+                .insert_change(Decimal::new(*cents, 2), description, "")
+                .unwrap();
+        }
+        let section = Section {
+            period: Period::Year(year),
+            classification: None,
+            pricing_unit: None,
+        };
+        json_report(&[(&section, &data_store)], &ALL_FIELDS, 2, &[], None).unwrap()
+    }
+
+    #[test]
+    fn test_diff_reports() {
+        let old = saved(
+            2023,
+            &[(80239, "STELARA"), (32019, "HUMIRA"), (7595, "ENBREL")],
+        );
+        let new = saved(
+            2023,
+            &[(32019, "HUMIRA"), (30000, "STELARA"), (5000, "OZEMPIC")],
+        );
+
+        assert_eq!(
+            diff_reports("old.json", &old, "new.json", &new).unwrap(),
+            "Changes in the increases of 2023:\n\
+            Status   Old rank  New rank  Old change  New change  Difference  Drug\n\
+            up 1            2         1     $320.19     $320.19      +$0.00  HUMIRA\n\
+            down 1          1         2     $802.39     $300.00    -$502.39  STELARA\n\
+            entered                   3                  $50.00              OZEMPIC\n\
+            left            3                $75.95                          ENBREL\n"
+        );
+
+        // The lists of reports on different periods are compared, and a drug's changes are
+        // matched in turn.
+        let later = saved(2024, &[(60000, "STELARA"), (5000, "OZEMPIC")]);
+        let earlier = saved(2023, &[(80239, "STELARA"), (7595, "STELARA")]);
+        assert_eq!(
+            diff_reports("old.json", &earlier, "new.json", &later).unwrap(),
+            "Changes in the increases of 2024, compared with 2023:\n\
+            Status   Old rank  New rank  Old change  New change  Difference  Drug\n\
+            same            1         1     $802.39     $600.00    -$202.39  STELARA\n\
+            entered                   2                  $50.00              OZEMPIC\n\
+            left            2                $75.95                          STELARA\n"
+        );
+
+        assert!(diff_reports("old.json", "{}", "new.json", &new)
+            .unwrap_err()
+            .starts_with("old.json is not a report saved with --format json"));
+        let future = new.replacen("\"version\": 1", "\"version\": 2", 1);
+        assert_eq!(
+            diff_reports("old.json", &old, "new.json", &future).unwrap_err(),
+            "new.json is a version 2 report, only version 1 reports can be compared"
+        );
+    }
+}
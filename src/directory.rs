@@ -0,0 +1,255 @@
+//! The `directory` module provides code for reading the FDA National Drug Code Directory, which
+//! names the labeler (the manufacturer or distributor) behind each NDC. The NADAC data only has
+//! the NDCs, so the directory is needed to see which companies are raising prices.
+
+use crate::columns::header_key;
+use crate::comparison::ComparisonRow;
+use crate::data_source::{DataSource, SourceOptions};
+use crate::dialect::Delimiter;
+use crate::encoding::decode;
+use crate::filters::normalize_ndc;
+use futures::StreamExt;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// The headers of the directory's NDC column, in order of preference. The product file has a
+/// `PRODUCTNDC` column and the package file a `NDCPACKAGECODE` column.
+const NDC_HEADERS: [&str; 3] = ["productndc", "ndcpackagecode", "ndc"];
+
+/// The headers of the directory's labeler column, in order of preference.
+const LABELER_HEADERS: [&str; 3] = ["labelername", "labeler", "manufacturer"];
+
+/// Get the 5 digit labeler code of an NDC. The directory writes NDCs with dashes and 4 or 5
+/// digit labeler codes, e.g. `0002-1433`, while the NADAC data uses the 11 digit form, whose
+/// first 5 digits are the labeler code.
+///
+/// # Arguments
+///
+/// * `ndc` - The NDC.
+///
+/// # Returns
+///
+/// An Option which will contain the labeler code, or None if the NDC has none.
+fn labeler_code(ndc: &str) -> Option<String> {
+    let ndc = ndc.trim();
+    let code = if ndc.contains('-') {
+        let segment = ndc.split('-').next().unwrap_or_default();
+        format!("{:0>5}", segment)
+    } else {
+        normalize_ndc(ndc).chars().take(5).collect()
+    };
+
+    (code.len() == 5 && code.chars().all(|c| c.is_ascii_digit())).then_some(code)
+}
+
+/// The `NdcDirectory` struct holds the name of the labeler of each labeler code.
+#[derive(Debug, Clone, Default)]
+pub struct NdcDirectory {
+    /// The labeler names, keyed by their 5 digit labeler code.
+    labelers: HashMap<String, String>,
+}
+
+impl NdcDirectory {
+    /// Read the NDC Directory, either the product or the package file, in tab or comma
+    /// delimited form.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Where the directory is.
+    /// * `options` - The options used to open the source. Only the download and encoding
+    ///   options are used, since the directory has its own layout.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `NdcDirectory`, on error returns a std::error::Error in a Box.
+    pub async fn load(
+        source: &DataSource,
+        options: &SourceOptions,
+    ) -> Result<NdcDirectory, Box<dyn std::error::Error>> {
+        let reader = decode(source.open(options).await?, options.encoding).await?;
+        let (reader, delimiter) = Delimiter::Auto.resolve(reader).await?;
+        let mut csv_reader = csv_async::AsyncReaderBuilder::new()
+            .delimiter(delimiter)
+            .flexible(true)
+            .create_reader(reader);
+
+        let headers: Vec<String> = csv_reader.headers().await?.iter().map(header_key).collect();
+        let column = |names: &[&str], name: &str| {
+            names
+                .iter()
+                .find_map(|key| headers.iter().position(|header| header == key))
+                .ok_or_else(|| format!("The NDC directory from {} has no {} column", source, name))
+        };
+        let ndc_column = column(&NDC_HEADERS, "NDC")?;
+        let labeler_column = column(&LABELER_HEADERS, "labeler name")?;
+
+        let mut directory = NdcDirectory::default();
+        let mut records = csv_reader.byte_records();
+        while let Some(record) = records.next().await {
+            let record = record?;
+            let field = |index: usize| {
+                record
+                    .get(index)
+                    .map(String::from_utf8_lossy)
+                    .unwrap_or_default()
+            };
+
+            let labeler = field(labeler_column);
+            let Some(code) = labeler_code(&field(ndc_column)) else {
+                continue;
+            };
+            if !labeler.trim().is_empty() {
+                directory
+                    .labelers
+                    .entry(code)
+                    .or_insert_with(|| labeler.trim().to_string());
+            }
+        }
+
+        Ok(directory)
+    }
+
+    /// Get the manufacturer of an NDC.
+    ///
+    /// # Arguments
+    ///
+    /// * `ndc` - The NDC.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the name of the NDC's labeler, or None if the directory
+    /// does not have its labeler code.
+    pub fn manufacturer(&self, ndc: &str) -> Option<&str> {
+        self.labelers.get(&labeler_code(ndc)?).map(String::as_str)
+    }
+}
+
+/// The `LargeIncreases` struct counts each manufacturer's price increases of at least a
+/// percent of the old price.
+#[derive(Debug, Clone)]
+pub struct LargeIncreases {
+    /// The smallest increase counted, as a percent of the old price.
+    percent: Decimal,
+
+    /// The number of increases of each manufacturer.
+    counts: HashMap<String, u64>,
+}
+
+impl LargeIncreases {
+    /// Create a new, empty `LargeIncreases`.
+    ///
+    /// # Arguments
+    ///
+    /// * `percent` - The smallest increase counted, as a percent of the old price.
+    pub fn new(percent: Decimal) -> LargeIncreases {
+        LargeIncreases {
+            percent,
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Count a price change if it is a large enough increase and its manufacturer is known.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    /// * `directory` - The directory naming the row's manufacturer.
+    pub fn add(&mut self, row: &ComparisonRow, directory: &NdcDirectory) {
+        if row.old_price <= Decimal::ZERO {
+            return;
+        }
+        let percent = (row.new_price - row.old_price) / row.old_price * Decimal::ONE_HUNDRED;
+        if percent <= Decimal::ZERO || percent < self.percent {
+            return;
+        }
+        if let Some(manufacturer) = directory.manufacturer(&row.ndc) {
+            *self.counts.entry(manufacturer.to_string()).or_default() += 1;
+        }
+    }
+
+    /// Generate the section of the report ranking the manufacturers by their number of large
+    /// increases.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - The number of manufacturers requested for the report.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the section.
+    pub fn report(&self, count: usize) -> String {
+        let mut report = format!(
+            "Top {} manufacturers by number of price increases of at least {}%:\n",
+            count, self.percent
+        );
+
+        let mut counts: Vec<(&String, &u64)> = self.counts.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        if counts.is_empty() {
+            report.push_str("No increases found\n");
+        }
+        for (manufacturer, increases) in counts.into_iter().take(count) {
+            report.push_str(&format!("{} increase(s): {}\n", increases, manufacturer));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn test_ndc_directory() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            "PRODUCTID\tPRODUCTNDC\tPROPRIETARYNAME\tLABELERNAME\n\
+            0002-1433_a1\t0002-1433\tTrulicity\tEli Lilly and Company\n\
+            57894-061_b2\t57894-061\tStelara\tJanssen Biotech, Inc.\n\
+            00000-000_c3\t\tUnknown\tNobody\n"
+        )
+        .unwrap();
+
+        let directory = NdcDirectory::load(
+            &DataSource::File(file.path().to_path_buf()),
+            &SourceOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            directory.manufacturer("00002751001"),
+            Some("Eli Lilly and Company")
+        );
+        assert_eq!(
+            directory.manufacturer("57894-0061-03"),
+            Some("Janssen Biotech, Inc.")
+        );
+        assert_eq!(directory.manufacturer("00074055402"), None);
+
+        let mut increases = LargeIncreases::new(Decimal::new(10, 0));
+        for (ndc, old_price, new_price) in [
+            ("00002751001", "1.00", "1.20"),
+            ("00002143380", "1.00", "1.05"),
+            ("57894006103", "100.00", "150.00"),
+            ("57894006103", "100.00", "125.00"),
+            ("00074055402", "1.00", "2.00"),
+        ] {
+            let record = ByteRecord::from(vec!["DRUG", ndc, old_price, new_price]);
+            increases.add(&ComparisonRow::from_record(&record).unwrap(), &directory);
+        }
+
+        assert_eq!(
+            increases.report(5),
+            "Top 5 manufacturers by number of price increases of at least 10%:\n\
+            2 increase(s): Janssen Biotech, Inc.\n\
+            1 increase(s): Eli Lilly and Company\n"
+        );
+        assert!(LargeIncreases::new(Decimal::ONE)
+            .report(5)
+            .ends_with("No increases found\n"));
+    }
+}
@@ -0,0 +1,204 @@
+//! The `discovery` module provides code for finding the URL and identifier of the current NADAC
+//! comparison dataset from the data.medicaid.gov metadata API, so the program does not depend on
+//! a hard-coded snapshot URL.
+
+use crate::http::{send_with_retries, HttpOptions};
+use reqwest::header::HeaderMap;
+use reqwest::Client;
+use serde::Deserialize;
+
+/// The data.medicaid.gov (DKAN) metastore endpoint listing every dataset.
+static DATASET_METASTORE_URL: &str =
+    "https://data.medicaid.gov/api/1/metastore/schemas/dataset/items";
+
+/// The text that identifies the NADAC comparison dataset in the dataset titles.
+const COMPARISON_TITLE: &str = "nadac comparison";
+
+/// The parts of a metastore dataset record needed to find the CSV download.
+#[derive(Debug, Deserialize)]
+struct Dataset {
+    #[serde(default)]
+    identifier: String,
+
+    #[serde(default)]
+    title: String,
+
+    #[serde(default)]
+    modified: String,
+
+    #[serde(default)]
+    distribution: Vec<Distribution>,
+}
+
+/// The parts of a metastore distribution record needed to find the CSV download.
+#[derive(Debug, Deserialize)]
+struct Distribution {
+    #[serde(rename = "downloadURL")]
+    download_url: Option<String>,
+
+    #[serde(rename = "mediaType")]
+    media_type: Option<String>,
+}
+
+/// Pick the CSV download URL of the most recently modified NADAC comparison dataset.
+///
+/// # Arguments
+///
+/// * `datasets` - The dataset records from the metastore.
+///
+/// # Returns
+///
+/// An Option which will contain the URL if a NADAC comparison dataset with a CSV download was
+/// found.
+fn select_comparison_url(datasets: &[Dataset]) -> Option<String> {
+    datasets
+        .iter()
+        .filter(|dataset| dataset.title.to_lowercase().contains(COMPARISON_TITLE))
+        .filter_map(|dataset| {
+            dataset
+                .distribution
+                .iter()
+                .filter_map(|distribution| {
+                    let url = distribution.download_url.as_ref()?;
+                    let is_csv = distribution.media_type.as_deref() == Some("text/csv")
+                        || url.to_lowercase().ends_with(".csv");
+                    is_csv.then(|| url.clone())
+                })
+                .next()
+                .map(|url| (&dataset.modified, url))
+        })
+        // The modified dates are ISO 8601, so they sort correctly as strings.
+        .max_by(|a, b| a.0.cmp(b.0))
+        .map(|(_, url)| url)
+}
+
+/// Pick the identifier of the most recently modified NADAC comparison dataset.
+///
+/// # Arguments
+///
+/// * `datasets` - The dataset records from the metastore.
+///
+/// # Returns
+///
+/// An Option which will contain the identifier if a NADAC comparison dataset was found.
+fn select_comparison_dataset_id(datasets: &[Dataset]) -> Option<String> {
+    datasets
+        .iter()
+        .filter(|dataset| dataset.title.to_lowercase().contains(COMPARISON_TITLE))
+        .filter(|dataset| !dataset.identifier.is_empty())
+        .max_by(|a, b| a.modified.cmp(&b.modified))
+        .map(|dataset| dataset.identifier.clone())
+}
+
+/// Fetch the dataset records from the data.medicaid.gov metadata API.
+///
+/// # Arguments
+///
+/// * `client` - The client used to send the request.
+/// * `options` - The retry configuration. Extra headers meant for data mirrors are not sent.
+///
+/// # Returns
+///
+/// On success, returns the dataset records, on error returns a std::error::Error in a Box.
+async fn fetch_datasets(
+    client: &Client,
+    options: &HttpOptions,
+) -> Result<Vec<Dataset>, Box<dyn std::error::Error>> {
+    let options = HttpOptions {
+        headers: HeaderMap::new(),
+        ..options.clone()
+    };
+
+    Ok(
+        send_with_retries(client, DATASET_METASTORE_URL, &options, &HeaderMap::new())
+            .await?
+            .json()
+            .await?,
+    )
+}
+
+/// Ask the data.medicaid.gov metadata API for the URL of the current NADAC comparison CSV.
+///
+/// # Arguments
+///
+/// * `client` - The client used to send the request.
+/// * `options` - The retry configuration. Extra headers meant for data mirrors are not sent.
+///
+/// # Returns
+///
+/// On success, returns the URL, on error returns a std::error::Error in a Box.
+pub async fn latest_comparison_url(
+    client: &Client,
+    options: &HttpOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let datasets = fetch_datasets(client, options).await?;
+
+    select_comparison_url(&datasets).ok_or_else(|| {
+        "Could not find a NADAC comparison dataset in the data.medicaid.gov metadata".into()
+    })
+}
+
+/// Ask the data.medicaid.gov metadata API for the identifier of the current NADAC comparison
+/// dataset, as used by the datastore query API.
+///
+/// # Arguments
+///
+/// * `client` - The client used to send the request.
+/// * `options` - The retry configuration. Extra headers meant for data mirrors are not sent.
+///
+/// # Returns
+///
+/// On success, returns the identifier, on error returns a std::error::Error in a Box.
+pub async fn latest_comparison_dataset_id(
+    client: &Client,
+    options: &HttpOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let datasets = fetch_datasets(client, options).await?;
+
+    select_comparison_dataset_id(&datasets).ok_or_else(|| {
+        "Could not find a NADAC comparison dataset in the data.medicaid.gov metadata".into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_comparison_url() {
+        let json = r#"[
+            {
+                "title": "NADAC (National Average Drug Acquisition Cost) 2024",
+                "modified": "2024-10-01",
+                "distribution": [{"downloadURL": "https://example.com/nadac-2024.csv", "mediaType": "text/csv"}]
+            },
+            {
+                "identifier": "a217613c-12bc-5137-8b3a-ada0e4dad1ff",
+                "title": "NADAC Comparison",
+                "modified": "2024-04-17",
+                "distribution": [{"downloadURL": "https://example.com/nadac-comparison-04-17-2024.csv", "mediaType": "text/csv"}]
+            },
+            {
+                "identifier": "fbb83258-11c7-47f5-8b18-5f8e79f7e704",
+                "title": "NADAC Comparison",
+                "modified": "2024-09-25",
+                "distribution": [
+                    {"downloadURL": "https://example.com/dictionary.pdf", "mediaType": "application/pdf"},
+                    {"downloadURL": "https://example.com/nadac-comparison-09-25-2024.csv"}
+                ]
+            },
+            {"title": "Drug Products in the Medicaid Drug Rebate Program"}
+        ]"#;
+
+        let datasets: Vec<Dataset> = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            select_comparison_url(&datasets).as_deref(),
+            Some("https://example.com/nadac-comparison-09-25-2024.csv")
+        );
+        assert_eq!(select_comparison_url(&datasets[..1]), None);
+        assert_eq!(
+            select_comparison_dataset_id(&datasets).as_deref(),
+            Some("fbb83258-11c7-47f5-8b18-5f8e79f7e704")
+        );
+    }
+}
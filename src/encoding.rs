@@ -0,0 +1,168 @@
+//! The `encoding` module provides code for transcoding the CSV data to UTF-8 before it reaches
+//! the CSV reader. Older NADAC snapshots are Windows-1252 text with a byte order mark, which
+//! otherwise breaks header matching and garbles the descriptions.
+
+use crate::data_source::DataReader;
+use encoding_rs::{Decoder, Encoding, UTF_8, WINDOWS_1252};
+use futures::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use futures::TryStreamExt;
+
+/// The number of leading bytes examined to detect the encoding.
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// The number of bytes decoded at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Look up an encoding from a name given on the command line.
+///
+/// # Arguments
+///
+/// * `label` - The name of the encoding, e.g. `utf-8`, `windows-1252` or `latin1`.
+///
+/// # Returns
+///
+/// On success, returns the encoding, on error returns a String describing the problem.
+pub fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| format!("Unknown encoding {}", label))
+}
+
+/// Guess the encoding of the data from its leading bytes. A byte order mark decides the
+/// encoding, otherwise data that is valid UTF-8 is taken to be UTF-8 and anything else to be
+/// Windows-1252, the encoding of the older NADAC snapshots.
+///
+/// # Arguments
+///
+/// * `bytes` - The leading bytes of the data.
+///
+/// # Returns
+///
+/// The encoding of the data.
+pub fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(_) => UTF_8,
+        // The sample may end part way through a character.
+        Err(e) if e.error_len().is_none() => UTF_8,
+        Err(_) => WINDOWS_1252,
+    }
+}
+
+/// Wrap a reader so that it produces UTF-8 without a byte order mark.
+///
+/// # Arguments
+///
+/// * `reader` - The reader producing the encoded bytes.
+/// * `encoding` - The encoding of the data, or `None` to detect it from the leading bytes. A
+///   byte order mark for another encoding takes precedence over the encoding given.
+///
+/// # Returns
+///
+/// On success, a new `DataReader` that produces UTF-8, on error returns the std::io::Error from
+/// reading the leading bytes.
+pub async fn decode(
+    reader: DataReader,
+    encoding: Option<&'static Encoding>,
+) -> std::io::Result<DataReader> {
+    let mut reader = BufReader::with_capacity(SAMPLE_SIZE, reader);
+    let sample = reader.fill_buf().await?;
+
+    let encoding = encoding.unwrap_or_else(|| detect_encoding(sample));
+    let bom = Encoding::for_bom(sample);
+
+    // UTF-8 data only needs its byte order mark removed, so skip the transcoding.
+    match bom {
+        None if encoding == UTF_8 => return Ok(Box::pin(reader)),
+        Some((bom_encoding, length)) if bom_encoding == UTF_8 => {
+            reader.consume_unpin(length);
+            return Ok(Box::pin(reader));
+        }
+        _ => {}
+    }
+
+    let decoder = encoding.new_decoder();
+    let chunks = futures::stream::try_unfold(
+        (reader, decoder, false),
+        |(mut reader, mut decoder, finished)| async move {
+            if finished {
+                return Ok(None);
+            }
+
+            let mut bytes = vec![0; CHUNK_SIZE];
+            let length = reader.read(&mut bytes).await?;
+            let last = length == 0;
+            let text = decode_chunk(&mut decoder, &bytes[..length], last)?;
+            Ok(Some((text.into_bytes(), (reader, decoder, last))))
+        },
+    );
+
+    Ok(Box::pin(Box::pin(chunks).into_async_read()))
+}
+
+/// Decode one chunk of the data.
+///
+/// # Arguments
+///
+/// * `decoder` - The decoder, which keeps any character split across chunks.
+/// * `bytes` - The bytes of the chunk.
+/// * `last` - True for the chunk at the end of the data.
+///
+/// # Returns
+///
+/// On success, returns the decoded text, on error returns a std::io::Error.
+fn decode_chunk(decoder: &mut Decoder, bytes: &[u8], last: bool) -> std::io::Result<String> {
+    let capacity = decoder
+        .max_utf8_buffer_length(bytes.len())
+        .ok_or_else(|| std::io::Error::other("The data is too large to decode"))?;
+    let mut text = String::with_capacity(capacity);
+
+    // The text has room for the whole chunk, so the decoder reads all of it.
+    let (_, read, _) = decoder.decode_to_string(bytes, &mut text, last);
+    debug_assert_eq!(read, bytes.len());
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn decode_all(bytes: &[u8], encoding: Option<&'static Encoding>) -> String {
+        let reader: DataReader = Box::pin(futures::io::Cursor::new(bytes.to_vec()));
+        let mut text = String::new();
+        decode(reader, encoding)
+            .await
+            .unwrap()
+            .read_to_string(&mut text)
+            .await
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        assert_eq!(detect_encoding(b"NDC Description,NDC"), UTF_8);
+        assert_eq!(detect_encoding(b"\xef\xbb\xbfNDC"), UTF_8);
+        assert_eq!(detect_encoding(b"\xff\xfeN\x00"), encoding_rs::UTF_16LE);
+        assert_eq!(detect_encoding(b"CAF\xc3"), UTF_8);
+        assert_eq!(detect_encoding(b"CAF\xc9 AU LAIT,1"), WINDOWS_1252);
+    }
+
+    #[tokio::test]
+    async fn test_decode() {
+        assert_eq!(decode_all(b"\xef\xbb\xbfNDC,1\n", None).await, "NDC,1\n");
+        assert_eq!(
+            decode_all(b"CAF\xc9 \x96 1 MG,1\n", None).await,
+            "CAF\u{c9} \u{2013} 1 MG,1\n"
+        );
+        assert_eq!(decode_all(b"\xff\xfeN\x00D\x00C\x00", None).await, "NDC");
+        assert_eq!(
+            decode_all(b"caf\xe9", Some(parse_encoding("latin1").unwrap())).await,
+            "caf\u{e9}"
+        );
+        assert!(parse_encoding("klingon").is_err());
+    }
+}
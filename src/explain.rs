@@ -0,0 +1,186 @@
+//! The `explain` module provides code for following the rows of one drug through the report,
+//! logging whether each of its price changes went into a pool, which pool, what pushed it out
+//! again, or why it was left out.
+
+use crate::comparison::ComparisonRow;
+use crate::filters::normalize_ndc;
+use crate::report::dollars;
+use rust_decimal::Decimal;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::Write;
+use std::rc::Rc;
+
+/// The `Explainer` struct picks out the rows whose handling is logged, by NDC or by part of
+/// their description.
+#[derive(Clone)]
+pub struct Explainer {
+    /// The digits of the NDC to follow, when the target is an NDC.
+    ndc: Option<String>,
+
+    /// The upper case text to look for in the descriptions, when the target is not an NDC.
+    description: String,
+
+    /// Where the lines are logged, shared by the clones of the `Explainer`.
+    sink: Rc<RefCell<dyn Write>>,
+}
+
+impl fmt::Debug for Explainer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Explainer")
+            .field("ndc", &self.ndc)
+            .field("description", &self.description)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Explainer {
+    /// Create a new `Explainer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - An NDC, made only of digits and dashes, or text to look for in the
+    ///   descriptions, ignoring case.
+    /// * `sink` - Where the lines are logged, usually stderr.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `Explainer`, on error returns a String describing the problem.
+    pub fn new(target: &str, sink: Rc<RefCell<dyn Write>>) -> Result<Explainer, String> {
+        let target = target.trim();
+        if target.is_empty() {
+            return Err("--explain needs an NDC or part of a description".to_string());
+        }
+
+        let is_ndc = target.chars().all(|c| c.is_ascii_digit() || c == '-')
+            && target.chars().any(|c| c.is_ascii_digit());
+        Ok(Explainer {
+            ndc: is_ndc.then(|| normalize_ndc(target)),
+            description: target.to_uppercase(),
+            sink,
+        })
+    }
+
+    /// Determine if a drug's rows are logged.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - The description of the drug.
+    /// * `ndc` - The NDC of the drug, which may be empty.
+    ///
+    /// # Returns
+    ///
+    /// True if the drug is the one followed.
+    pub fn matches(&self, description: &str, ndc: &str) -> bool {
+        match &self.ndc {
+            Some(target) => !ndc.trim().is_empty() && normalize_ndc(ndc) == *target,
+            None => description.to_uppercase().contains(&self.description),
+        }
+    }
+
+    /// Log what happened to a row, if it is one of the rows followed.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    /// * `decision` - What happened to the row.
+    pub fn log_row(&self, row: &ComparisonRow, decision: &str) {
+        self.log(
+            &row.description,
+            &row.ndc,
+            Some(row.new_price - row.old_price),
+            decision,
+        );
+    }
+
+    /// Log what happened to a price change, if it is one of the changes followed.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - The description of the drug.
+    /// * `ndc` - The NDC of the drug, which may be empty.
+    /// * `difference` - The change in price, when it is known.
+    /// * `decision` - What happened to the change.
+    pub fn log(&self, description: &str, ndc: &str, difference: Option<Decimal>, decision: &str) {
+        if self.matches(description, ndc) {
+            // Like eprintln!, the logging carries on if the sink cannot be written to.
+            let _ = writeln!(
+                self.sink.borrow_mut(),
+                "{}",
+                explain_line(description, ndc, difference, decision)
+            );
+        }
+    }
+}
+
+/// Format the line logged for a price change.
+///
+/// # Arguments
+///
+/// * `description` - The description of the drug.
+/// * `ndc` - The NDC of the drug, which may be empty.
+/// * `difference` - The change in price, when it is known.
+/// * `decision` - What happened to the change.
+///
+/// # Returns
+///
+/// A new String containing the line.
+fn explain_line(
+    description: &str,
+    ndc: &str,
+    difference: Option<Decimal>,
+    decision: &str,
+) -> String {
+    let mut line = format!("Explain: {}", description.trim());
+    if !ndc.trim().is_empty() {
+        line.push_str(&format!(" (NDC {})", ndc.trim()));
+    }
+    if let Some(difference) = difference {
+        line.push_str(&format!(", change {}", dollars(&difference)));
+    }
+    format!("{}: {}", line, decision)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create an `Explainer` logging into a buffer.
+    ///
+    /// # Returns
+    ///
+    /// The `Explainer` and the buffer it logs into.
+    fn buffered(target: &str) -> (Explainer, Rc<RefCell<Vec<u8>>>) {
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        (Explainer::new(target, buffer.clone()).unwrap(), buffer)
+    }
+
+    #[test]
+    fn test_explainer() {
+        let (humira, log) = buffered(" humira ");
+        assert!(humira.matches("HUMIRA PEN 40 MG/0.4 ML", "00074-0554-02"));
+        assert!(!humira.matches("ENBREL 50 MG/ML", "58406-0435-04"));
+        humira.log("ENBREL 50 MG/ML", "", None, "left out");
+        humira.log("HUMIRA PEN", "", None, "left out");
+        assert_eq!(log.borrow().as_slice(), b"Explain: HUMIRA PEN: left out\n");
+
+        let (ndc, _) = buffered("74-554-2");
+        assert!(ndc.matches("ANYTHING", "00074055402"));
+        assert!(!ndc.matches("HUMIRA", ""));
+        assert!(Explainer::new("  ", Rc::new(RefCell::new(Vec::new()))).is_err());
+
+        assert_eq!(
+            explain_line(
+                "HUMIRA",
+                "00074055402",
+                Some(Decimal::new(-1250, 2)),
+                "inserted"
+            ),
+            "Explain: HUMIRA (NDC 00074055402), change -$12.50: inserted"
+        );
+        assert_eq!(
+            explain_line("HUMIRA", "", None, "left out"),
+            "Explain: HUMIRA: left out"
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! The `explanation` module provides code for working with the NADAC explanation codes, which
+//! say how each price was arrived at, e.g. from the monthly survey or from a change in the
+//! published price. A row may have several codes, written like `1, 5`.
+
+use crate::comparison::ComparisonRow;
+use std::collections::BTreeMap;
+
+/// Split the explanation codes of a row.
+///
+/// # Arguments
+///
+/// * `text` - The explanation code field, e.g. `1, 5`.
+///
+/// # Returns
+///
+/// An iterator over the codes, trimmed, leaving out empty codes.
+pub fn explanation_codes(text: &str) -> impl Iterator<Item = &str> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|code| !code.is_empty())
+}
+
+/// The `ExplanationCodes` struct counts the price changes with each explanation code.
+#[derive(Debug, Clone, Default)]
+pub struct ExplanationCodes {
+    /// The number of price changes with each code.
+    counts: BTreeMap<String, u64>,
+
+    /// The number of price changes without a code.
+    uncoded: u64,
+}
+
+impl ExplanationCodes {
+    /// Count a price change under each of its explanation codes.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    pub fn add(&mut self, row: &ComparisonRow) {
+        let mut coded = false;
+        for code in explanation_codes(&row.explanation_code) {
+            *self.counts.entry(code.to_string()).or_default() += 1;
+            coded = true;
+        }
+        if !coded {
+            self.uncoded += 1;
+        }
+    }
+
+    /// Generate the section of the report with the number of price changes with each
+    /// explanation code. A change with several codes is counted under each of them.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the section.
+    pub fn report(&self) -> String {
+        let mut report = String::from("Price changes by explanation code:\n");
+
+        let mut counts: Vec<(&String, &u64)> = self.counts.iter().collect();
+        // Numeric codes are put in numeric order.
+        counts.sort_by_key(|(code, _)| (code.parse::<u64>().unwrap_or(u64::MAX), *code));
+        for (code, count) in counts {
+            report.push_str(&format!("Code {}: {}\n", code, count));
+        }
+        if self.uncoded > 0 {
+            report.push_str(&format!("No code: {}\n", self.uncoded));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+
+    #[test]
+    fn test_explanation_codes() {
+        let mut codes = ExplanationCodes::default();
+        for explanation_code in ["1, 5", "5", "10", "", "2,5"] {
+            let mut fields = vec!["ASPIRIN", "", "1", "2", "", "", "", "", "", "", "", ""];
+            fields.push(explanation_code);
+            let record = ByteRecord::from(fields);
+            codes.add(&ComparisonRow::from_record(&record).unwrap());
+        }
+
+        assert_eq!(
+            codes.report(),
+            "Price changes by explanation code:\n\
+            Code 1: 1\n\
+            Code 2: 1\n\
+            Code 5: 3\n\
+            Code 10: 1\n\
+            No code: 1\n"
+        );
+    }
+}
@@ -0,0 +1,536 @@
+//! The `expression` module provides a small language for filtering the price change records,
+//! e.g. `change > 1.50 && classification == 'G' && description =~ 'INSULIN'`, so conditions
+//! that have no flag of their own can be combined freely.
+//!
+//! An expression compares fields of a record with numbers, or with text in single or double
+//! quotes, and combines the comparisons with `&&`, `||`, `!` and parentheses. The numeric
+//! fields are `change`, `abs_change`, `percent_change`, `old_price` and `new_price`, the text
+//! fields are `description`, `ndc`, `classification`, `reason`, `otc`, `pricing_unit` and
+//! `explanation_code`, and `effective_date` is compared with a date in quotes. Text is
+//! compared without regard to case, and `=~` and `!~` test whether text contains a value.
+
+use crate::comparison::ComparisonRow;
+use crate::dates::parse_date;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// Enum describing how a field is compared with a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// `==`
+    Equal,
+
+    /// `!=`
+    NotEqual,
+
+    /// `<`
+    Less,
+
+    /// `<=`
+    LessOrEqual,
+
+    /// `>`
+    Greater,
+
+    /// `>=`
+    GreaterOrEqual,
+
+    /// `=~`, text containing the value.
+    Contains,
+
+    /// `!~`, text not containing the value.
+    NotContains,
+}
+
+impl Operator {
+    /// The operators and how they are written, longest first so `<=` is not read as `<`.
+    const SYMBOLS: [(&'static str, Operator); 8] = [
+        ("==", Operator::Equal),
+        ("!=", Operator::NotEqual),
+        ("<=", Operator::LessOrEqual),
+        (">=", Operator::GreaterOrEqual),
+        ("=~", Operator::Contains),
+        ("!~", Operator::NotContains),
+        ("<", Operator::Less),
+        (">", Operator::Greater),
+    ];
+
+    /// How the operator is written.
+    fn symbol(&self) -> &'static str {
+        Operator::SYMBOLS
+            .iter()
+            .find(|(_, operator)| operator == self)
+            .map_or("", |(symbol, _)| symbol)
+    }
+
+    /// Determine if the ordering of a field's value against the compared value satisfies the
+    /// operator. `Contains` and `NotContains` are not orderings and never match.
+    fn matches(&self, ordering: Ordering) -> bool {
+        match self {
+            Operator::Equal => ordering == Ordering::Equal,
+            Operator::NotEqual => ordering != Ordering::Equal,
+            Operator::Less => ordering == Ordering::Less,
+            Operator::LessOrEqual => ordering != Ordering::Greater,
+            Operator::Greater => ordering == Ordering::Greater,
+            Operator::GreaterOrEqual => ordering != Ordering::Less,
+            Operator::Contains | Operator::NotContains => false,
+        }
+    }
+}
+
+/// Enum describing the numeric fields of a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberField {
+    /// The new price less the old price.
+    Change,
+
+    /// The size of the change in either direction.
+    AbsChange,
+
+    /// The change as a percent of the old price, which an old price of zero does not have.
+    PercentChange,
+
+    /// The old per unit price.
+    OldPrice,
+
+    /// The new per unit price.
+    NewPrice,
+}
+
+impl NumberField {
+    /// Get the field's value for a row, or None if the row does not have one.
+    fn value(&self, row: &ComparisonRow) -> Option<Decimal> {
+        let change = row.new_price - row.old_price;
+        match self {
+            NumberField::Change => Some(change),
+            NumberField::AbsChange => Some(change.abs()),
+            NumberField::PercentChange => (!row.old_price.is_zero())
+                .then(|| change / row.old_price.abs() * Decimal::ONE_HUNDRED),
+            NumberField::OldPrice => Some(row.old_price),
+            NumberField::NewPrice => Some(row.new_price),
+        }
+    }
+}
+
+/// Enum describing the text fields of a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextField {
+    /// The description of the drug.
+    Description,
+
+    /// The NDC of the drug.
+    Ndc,
+
+    /// The classification for rate setting, `B` or `G`.
+    Classification,
+
+    /// The primary reason for the change.
+    Reason,
+
+    /// The over-the-counter indicator, `Y` or `N`.
+    Otc,
+
+    /// The pricing unit, e.g. `EA`.
+    PricingUnit,
+
+    /// The explanation codes, e.g. `1, 5`.
+    ExplanationCode,
+}
+
+impl TextField {
+    /// Get the field's text for a row.
+    fn value<'r>(&self, row: &'r ComparisonRow) -> &'r str {
+        match self {
+            TextField::Description => &row.description,
+            TextField::Ndc => &row.ndc,
+            TextField::Classification => &row.classification,
+            TextField::Reason => &row.reason,
+            TextField::Otc => &row.otc,
+            TextField::PricingUnit => &row.pricing_unit,
+            TextField::ExplanationCode => &row.explanation_code,
+        }
+    }
+}
+
+/// Enum describing a parsed filter expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    /// Both expressions match.
+    And(Box<Expression>, Box<Expression>),
+
+    /// Either expression matches.
+    Or(Box<Expression>, Box<Expression>),
+
+    /// The expression does not match.
+    Not(Box<Expression>),
+
+    /// A numeric field compared with a number.
+    Number(NumberField, Operator, Decimal),
+
+    /// A text field compared with text, stored in upper case.
+    Text(TextField, Operator, String),
+
+    /// The effective date compared with a date.
+    Date(Operator, NaiveDate),
+}
+
+impl Expression {
+    /// Parse an expression.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The expression text.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `Expression`, on error returns a String describing the problem.
+    pub fn parse(text: &str) -> Result<Expression, String> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let expression = parser.or()?;
+        match parser.next() {
+            None => Ok(expression),
+            Some(token) => Err(format!("Unexpected {} after the expression", token)),
+        }
+    }
+
+    /// Determine if a row matches the expression. A comparison with a field the row does not
+    /// have, such as a missing effective date, does not match.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    ///
+    /// # Returns
+    ///
+    /// True if the row matches.
+    pub fn matches(&self, row: &ComparisonRow) -> bool {
+        match self {
+            Expression::And(left, right) => left.matches(row) && right.matches(row),
+            Expression::Or(left, right) => left.matches(row) || right.matches(row),
+            Expression::Not(expression) => !expression.matches(row),
+            Expression::Number(field, operator, value) => field
+                .value(row)
+                .is_some_and(|field| operator.matches(field.cmp(value))),
+            Expression::Text(field, operator, value) => {
+                let field = field.value(row).trim().to_uppercase();
+                match operator {
+                    Operator::Contains => field.contains(value.as_str()),
+                    Operator::NotContains => !field.contains(value.as_str()),
+                    _ => operator.matches(field.as_str().cmp(value.as_str())),
+                }
+            }
+            Expression::Date(operator, value) => row
+                .effective_date
+                .is_some_and(|date| operator.matches(date.cmp(value))),
+        }
+    }
+}
+
+/// A piece of an expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A field name.
+    Name(String),
+
+    /// A number.
+    Number(Decimal),
+
+    /// Text in quotes.
+    Text(String),
+
+    /// A comparison operator.
+    Operator(Operator),
+
+    /// `&&`
+    And,
+
+    /// `||`
+    Or,
+
+    /// `!`
+    Not,
+
+    /// `(`
+    Open,
+
+    /// `)`
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Text(text) => write!(f, "'{}'", text),
+            Token::Operator(operator) => write!(f, "{}", operator.symbol()),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+/// Split an expression into tokens.
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let (token, length) = if let Some((symbol, operator)) = Operator::SYMBOLS
+            .iter()
+            .find(|(symbol, _)| rest.starts_with(symbol))
+        {
+            (Token::Operator(*operator), symbol.len())
+        } else if rest.starts_with("&&") {
+            (Token::And, 2)
+        } else if rest.starts_with("||") {
+            (Token::Or, 2)
+        } else if c == '!' {
+            (Token::Not, 1)
+        } else if c == '(' {
+            (Token::Open, 1)
+        } else if c == ')' {
+            (Token::Close, 1)
+        } else if c == '\'' || c == '"' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| format!("Missing closing quote for {}", rest))?;
+            (Token::Text(rest[1..=end].to_string()), end + 2)
+        } else if c.is_ascii_digit() || c == '.' || c == '-' {
+            let length = rest[1..]
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .map_or(rest.len(), |end| end + 1);
+            let number = Decimal::from_str(&rest[..length])
+                .map_err(|_| format!("Invalid number {}", &rest[..length]))?;
+            (Token::Number(number), length)
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let length = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            (Token::Name(rest[..length].to_lowercase()), length)
+        } else {
+            return Err(format!("Unexpected character {}", c));
+        };
+
+        tokens.push(token);
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser over the tokens of an expression. `||` binds more loosely than
+/// `&&`, which binds more loosely than `!`.
+struct Parser {
+    /// The tokens.
+    tokens: Vec<Token>,
+
+    /// The index of the next token.
+    position: usize,
+}
+
+impl Parser {
+    /// Take the next token.
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Take the next token if it is the one given.
+    fn next_if(&mut self, token: &Token) -> bool {
+        let found = self.tokens.get(self.position) == Some(token);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    /// Parse expressions joined by `||`.
+    fn or(&mut self) -> Result<Expression, String> {
+        let mut expression = self.and()?;
+        while self.next_if(&Token::Or) {
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    /// Parse expressions joined by `&&`.
+    fn and(&mut self) -> Result<Expression, String> {
+        let mut expression = self.unary()?;
+        while self.next_if(&Token::And) {
+            expression = Expression::And(Box::new(expression), Box::new(self.unary()?));
+        }
+        Ok(expression)
+    }
+
+    /// Parse a negated expression, an expression in parentheses or a comparison.
+    fn unary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expression::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expression = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expression),
+                    Some(token) => Err(format!("Expected ) but found {}", token)),
+                    None => Err("Expected ) but found the end of the expression".to_string()),
+                }
+            }
+            Some(Token::Name(name)) => self.comparison(&name),
+            Some(token) => Err(format!("Expected a field name but found {}", token)),
+            None => Err("Expected a field name but found the end of the expression".to_string()),
+        }
+    }
+
+    /// Parse the operator and value of a comparison with a field.
+    fn comparison(&mut self, name: &str) -> Result<Expression, String> {
+        let operator = match self.next() {
+            Some(Token::Operator(operator)) => operator,
+            Some(token) => {
+                return Err(format!(
+                    "Expected an operator after {} but found {}",
+                    name, token
+                ))
+            }
+            None => return Err(format!("Expected an operator after {}", name)),
+        };
+        let value = self
+            .next()
+            .ok_or_else(|| format!("Expected a value after {} {}", name, operator.symbol()))?;
+        let ordering_only = |operator: Operator| {
+            if matches!(operator, Operator::Contains | Operator::NotContains) {
+                Err(format!(
+                    "{} only applies to text fields, not {}",
+                    operator.symbol(),
+                    name
+                ))
+            } else {
+                Ok(operator)
+            }
+        };
+
+        let number_field = match name {
+            "change" => Some(NumberField::Change),
+            "abs_change" => Some(NumberField::AbsChange),
+            "percent_change" => Some(NumberField::PercentChange),
+            "old_price" => Some(NumberField::OldPrice),
+            "new_price" => Some(NumberField::NewPrice),
+            _ => None,
+        };
+        if let Some(field) = number_field {
+            return match value {
+                Token::Number(number) => {
+                    Ok(Expression::Number(field, ordering_only(operator)?, number))
+                }
+                token => Err(format!(
+                    "Expected a number to compare {} with but found {}",
+                    name, token
+                )),
+            };
+        }
+
+        if name == "effective_date" {
+            return match value {
+                Token::Text(text) => Ok(Expression::Date(
+                    ordering_only(operator)?,
+                    parse_date(&text)?,
+                )),
+                token => Err(format!(
+                    "Expected a date in quotes to compare {} with but found {}",
+                    name, token
+                )),
+            };
+        }
+
+        let field = match name {
+            "description" => TextField::Description,
+            "ndc" => TextField::Ndc,
+            "classification" => TextField::Classification,
+            "reason" => TextField::Reason,
+            "otc" => TextField::Otc,
+            "pricing_unit" => TextField::PricingUnit,
+            "explanation_code" => TextField::ExplanationCode,
+            _ => return Err(format!("Unknown field {}", name)),
+        };
+        match value {
+            Token::Text(text) => Ok(Expression::Text(
+                field,
+                operator,
+                text.trim().to_uppercase(),
+            )),
+            token => Err(format!(
+                "Expected text in quotes to compare {} with but found {}",
+                name, token
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+
+    #[test]
+    fn test_expression() {
+        let records = [
+            ByteRecord::from(vec!["INSULIN LISPRO", "00002", "10", "12", "G"]),
+            ByteRecord::from(vec!["HUMALOG INSULIN", "00003", "10", "11", "B"]),
+            ByteRecord::from(vec!["METFORMIN", "00004", "1", "0.5", " g "]),
+        ];
+        let rows: Vec<ComparisonRow> = records
+            .iter()
+            .map(|record| ComparisonRow::from_record(record).unwrap())
+            .collect();
+        let matching = |text: &str| {
+            let expression = Expression::parse(text).unwrap();
+            rows.iter()
+                .map(|row| expression.matches(row))
+                .collect::<Vec<bool>>()
+        };
+
+        assert_eq!(
+            matching("change > 1.50 && classification == 'G' && description =~ 'insulin'"),
+            [true, false, false]
+        );
+        assert_eq!(
+            matching("classification == \"g\" || !(percent_change >= 10)"),
+            [true, false, true]
+        );
+        assert_eq!(
+            matching("abs_change>=0.5&&description!~'LISPRO'"),
+            [false, true, true]
+        );
+        assert_eq!(matching("change < -0.25"), [false, false, true]);
+        assert_eq!(
+            matching("effective_date >= '2023-01-01'"),
+            [false, false, false]
+        );
+
+        for (text, error) in [
+            ("price > 1", "Unknown field price"),
+            (
+                "change > 'G'",
+                "Expected a number to compare change with but found 'G'",
+            ),
+            ("change =~ 1", "=~ only applies to text fields, not change"),
+            (
+                "(change > 1",
+                "Expected ) but found the end of the expression",
+            ),
+            (
+                "change > 1 change",
+                "Unexpected change after the expression",
+            ),
+            ("description == 'X", "Missing closing quote for 'X"),
+        ] {
+            assert_eq!(Expression::parse(text), Err(error.to_string()), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! The `external_sort` module provides code for sorting every price change, rather than
+//! keeping the largest few in a `RecordPool`, without holding them all in memory. The changes
+//! are gathered into runs that are sorted and spilled to temporary files, and the runs are
+//! merged as the sorted changes are written.
+
+use crate::data_store::Metric;
+use crate::report::{dollars, RecordFormat};
+use rust_decimal::Decimal;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Seek, SeekFrom, Write};
+use std::str::FromStr;
+
+/// A price change to sort.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// The change in the per unit price.
+    pub difference: Decimal,
+
+    /// The description of the drug.
+    pub description: String,
+
+    /// The NDC of the drug, which may be empty.
+    pub ndc: String,
+}
+
+impl Change {
+    /// Create a new `Change`. Tabs and line breaks in the description and NDC are replaced by
+    /// spaces, since they separate the changes in a spilled run.
+    ///
+    /// # Arguments
+    ///
+    /// * `difference` - The change in the per unit price.
+    /// * `description` - The description of the drug.
+    /// * `ndc` - The NDC of the drug.
+    ///
+    /// # Returns
+    ///
+    /// The new `Change`.
+    pub fn new(difference: Decimal, description: &str, ndc: &str) -> Change {
+        let clean = |text: &str| text.trim().replace(['\t', '\n', '\r'], " ");
+        Change {
+            difference,
+            description: clean(description),
+            ndc: clean(ndc),
+        }
+    }
+
+    /// Parse a change from a line of a spilled run.
+    fn from_line(line: &str) -> Result<Change, String> {
+        let mut fields = line.splitn(3, '\t');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(difference), Some(ndc), Some(description)) => Ok(Change {
+                difference: Decimal::from_str(difference)
+                    .map_err(|e| format!("Invalid price change in a sorted run: {}", e))?,
+                description: description.to_string(),
+                ndc: ndc.to_string(),
+            }),
+            _ => Err(format!("Invalid line in a sorted run: {}", line)),
+        }
+    }
+
+    /// Format the change as a line of a spilled run.
+    fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\n", self.difference, self.ndc, self.description)
+    }
+}
+
+/// Compare two changes in the order they are written: by the metric's ranking, largest
+/// first, then by description and NDC.
+fn compare(metric: Metric, a: &Change, b: &Change) -> Ordering {
+    let rank = |change: &Change| match metric {
+        Metric::Change => change.difference,
+        Metric::Magnitude => change.difference.abs(),
+    };
+    rank(b)
+        .cmp(&rank(a))
+        .then_with(|| a.description.cmp(&b.description))
+        .then_with(|| a.ndc.cmp(&b.ndc))
+}
+
+/// The next change of a spilled run while the runs are merged.
+struct Head {
+    /// The change.
+    change: Change,
+
+    /// The index of the run the change came from.
+    run: usize,
+
+    /// How the changes are ranked.
+    metric: Metric,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    // BinaryHeap pops the greatest item, so the change written first is the greatest.
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(self.metric, &other.change, &self.change).then_with(|| other.run.cmp(&self.run))
+    }
+}
+
+/// The `ExternalSort` struct sorts any number of price changes, holding at most `run_size`
+/// of them in memory.
+#[derive(Debug)]
+pub struct ExternalSort {
+    /// How the changes are ranked.
+    metric: Metric,
+
+    /// The number of changes gathered before they are sorted and spilled.
+    run_size: usize,
+
+    /// The changes not yet spilled.
+    buffer: Vec<Change>,
+
+    /// The temporary files holding the sorted runs, which are deleted when closed.
+    runs: Vec<File>,
+}
+
+impl ExternalSort {
+    /// Create a new `ExternalSort`.
+    ///
+    /// # Arguments
+    ///
+    /// * `metric` - How the changes are ranked.
+    /// * `run_size` - The number of changes held in memory before they are spilled.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the new `ExternalSort`, on error returns a String describing the
+    /// error.
+    pub fn new(metric: Metric, run_size: usize) -> Result<ExternalSort, String> {
+        if run_size == 0 {
+            return Err("The number of changes held in memory must be at least 1".to_string());
+        }
+
+        Ok(ExternalSort {
+            metric,
+            run_size,
+            buffer: Vec::new(),
+            runs: Vec::new(),
+        })
+    }
+
+    /// Add a change, spilling the changes held in memory to a temporary file once there are
+    /// `run_size` of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `change` - The change.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a std::error::Error in a Box.
+    pub fn push(&mut self, change: Change) -> Result<(), Box<dyn std::error::Error>> {
+        self.buffer.push(change);
+        if self.buffer.len() >= self.run_size {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Sort the changes held in memory and write them to a new temporary file.
+    fn spill(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let metric = self.metric;
+        self.buffer.sort_by(|a, b| compare(metric, a, b));
+
+        let mut writer = BufWriter::new(tempfile::tempfile()?);
+        for change in self.buffer.drain(..) {
+            writer.write_all(change.to_line().as_bytes())?;
+        }
+        let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        self.runs.push(file);
+        Ok(())
+    }
+
+    /// Write the sorted changes, one per line, merging the spilled runs.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - How the changes' drugs are written.
+    /// * `out` - Where the changes are written.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the number of changes written, on error returns a
+    /// std::error::Error in a Box.
+    pub fn write(
+        self,
+        format: &RecordFormat,
+        out: &mut dyn Write,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        self.for_each(|change| {
+            out.write_all(
+                format!(
+                    "{}: {}\n",
+                    dollars(&change.difference),
+                    format.describe(&change.description, &change.ndc)
+                )
+                .as_bytes(),
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Pass the sorted changes to a function, in order, merging the spilled runs.
+    ///
+    /// # Arguments
+    ///
+    /// * `each` - The function called with each change.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the number of changes, on error returns a std::error::Error in a
+    /// Box.
+    pub fn for_each(
+        mut self,
+        mut each: impl FnMut(&Change) -> Result<(), Box<dyn std::error::Error>>,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        // When nothing was spilled the changes are sorted in memory.
+        if self.runs.is_empty() {
+            let metric = self.metric;
+            self.buffer.sort_by(|a, b| compare(metric, a, b));
+            for change in &self.buffer {
+                each(change)?;
+            }
+            return Ok(self.buffer.len());
+        }
+
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+
+        let mut readers: Vec<Lines<BufReader<File>>> = self
+            .runs
+            .drain(..)
+            .map(|file| BufReader::new(file).lines())
+            .collect();
+        let mut heads = BinaryHeap::new();
+        for (run, reader) in readers.iter_mut().enumerate() {
+            if let Some(line) = reader.next() {
+                let change = Change::from_line(&line?)?;
+                heads.push(Head {
+                    change,
+                    run,
+                    metric: self.metric,
+                });
+            }
+        }
+
+        let mut written = 0;
+        while let Some(head) = heads.pop() {
+            each(&head.change)?;
+            written += 1;
+            if let Some(line) = readers[head.run].next() {
+                let change = Change::from_line(&line?)?;
+                heads.push(Head { change, ..head });
+            }
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(metric: Metric, run_size: usize, changes: &[(i64, &str)]) -> (String, usize) {
+        let mut sort = ExternalSort::new(metric, run_size).unwrap();
+        for (cents, description) in changes {
+            sort.push(Change::new(Decimal::new(*cents, 2), description, ""))
+                .unwrap();
+        }
+        let runs = sort.runs.len();
+
+        let mut out = Vec::new();
+        let written = sort.write(&RecordFormat::default(), &mut out).unwrap();
+        assert_eq!(written, changes.len());
+        (String::from_utf8(out).unwrap(), runs)
+    }
+
+    #[test]
+    fn test_external_sort() {
+        let changes = [
+            (-250, "LISINOPRIL"),
+            (1000, "HUMIRA"),
+            (5, "ASPIRIN"),
+            (-1500, "HUMALOG"),
+            (1000, "ENBREL\tPEN"),
+            (300, "METFORMIN"),
+            (0, "ATORVASTATIN"),
+        ];
+
+        let in_memory = sorted(Metric::Change, 100, &changes);
+        assert_eq!(
+            in_memory,
+            (
+                "$10.00: ENBREL PEN\n\
+                $10.00: HUMIRA\n\
+                $3.00: METFORMIN\n\
+                $0.05: ASPIRIN\n\
+                $0.00: ATORVASTATIN\n\
+                -$2.50: LISINOPRIL\n\
+                -$15.00: HUMALOG\n"
+                    .to_string(),
+                0
+            )
+        );
+
+        let spilled = sorted(Metric::Change, 2, &changes);
+        assert_eq!(spilled.0, in_memory.0);
+        assert_eq!(spilled.1, 3);
+
+        let (magnitude, _) = sorted(Metric::Magnitude, 3, &changes);
+        assert!(magnitude.starts_with("-$15.00: HUMALOG\n$10.00: ENBREL PEN\n"));
+        assert!(ExternalSort::new(Metric::Change, 0).is_err());
+    }
+}
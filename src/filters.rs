@@ -0,0 +1,416 @@
+//! The `filters` module provides code for deciding which price change records go into the
+//! report, so the report can be narrowed to the drugs of interest.
+
+use crate::comparison::ComparisonRow;
+use crate::explanation::explanation_codes;
+use crate::expression::Expression;
+use clap::ValueEnum;
+use rust_decimal::Decimal;
+use std::collections::HashSet;
+
+/// The classification for rate setting of a drug.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Classification {
+    /// Brand name drugs.
+    #[value(name = "B", alias = "b", alias = "brand")]
+    Brand,
+
+    /// Generic drugs.
+    #[value(name = "G", alias = "g", alias = "generic")]
+    Generic,
+}
+
+impl Classification {
+    /// Look up the classification for a code used in the NADAC data.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The code, `B` or `G` in either case, with any surrounding whitespace.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the classification, or None for any other code.
+    pub fn from_code(code: &str) -> Option<Classification> {
+        match code.trim() {
+            "B" | "b" => Some(Classification::Brand),
+            "G" | "g" => Some(Classification::Generic),
+            _ => None,
+        }
+    }
+}
+
+/// The unit a drug's NADAC price is per.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum PricingUnit {
+    /// Each tablet, capsule or other unit.
+    #[value(name = "EA", alias = "ea")]
+    Each,
+
+    /// Milliliters.
+    #[value(name = "ML", alias = "ml")]
+    Milliliter,
+
+    /// Grams.
+    #[value(name = "GM", alias = "gm")]
+    Gram,
+}
+
+impl PricingUnit {
+    /// Look up the pricing unit for a code used in the NADAC data.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The code, `EA`, `ML` or `GM` in any case, with any surrounding whitespace.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the pricing unit, or None for any other code.
+    pub fn from_code(code: &str) -> Option<PricingUnit> {
+        match code.trim().to_uppercase().as_str() {
+            "EA" => Some(PricingUnit::Each),
+            "ML" => Some(PricingUnit::Milliliter),
+            "GM" => Some(PricingUnit::Gram),
+            _ => None,
+        }
+    }
+
+    /// The code of the pricing unit used in the NADAC data and the report.
+    pub fn code(&self) -> &'static str {
+        match self {
+            PricingUnit::Each => "EA",
+            PricingUnit::Milliliter => "ML",
+            PricingUnit::Gram => "GM",
+        }
+    }
+}
+
+/// How over-the-counter drugs are treated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OtcFilter {
+    /// Report on over-the-counter and prescription drugs.
+    #[default]
+    Include,
+
+    /// Leave out over-the-counter drugs.
+    Exclude,
+
+    /// Only report on over-the-counter drugs.
+    Only,
+}
+
+/// Put an NDC in the 11 digit form used by the NADAC data. Dashed NDCs in the 4-4-2, 5-3-2 and
+/// 5-4-1 layouts are padded to 5-4-2 before the dashes are removed.
+///
+/// # Arguments
+///
+/// * `ndc` - The NDC.
+///
+/// # Returns
+///
+/// The digits of the NDC.
+pub fn normalize_ndc(ndc: &str) -> String {
+    let parts: Vec<&str> = ndc.trim().split('-').collect();
+    match parts.as_slice() {
+        [labeler, product, package] => {
+            format!("{:0>5}{:0>4}{:0>2}", labeler, product, package)
+        }
+        _ => ndc.chars().filter(|c| c.is_ascii_digit()).collect(),
+    }
+}
+
+/// Read a list of NDCs, one per line. Blank lines and lines starting with `#` are ignored.
+///
+/// # Arguments
+///
+/// * `text` - The text of the list.
+///
+/// # Returns
+///
+/// The set of NDCs, normalized with `normalize_ndc`.
+pub fn parse_ndc_list(text: &str) -> HashSet<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(normalize_ndc)
+        .collect()
+}
+
+/// The `RecordFilter` struct holds the conditions a record must meet to go into the report.
+/// Conditions that are not set match every record.
+#[derive(Debug, Clone, Default)]
+pub struct RecordFilter {
+    /// Only records with this classification for rate setting match.
+    pub classification: Option<Classification>,
+
+    /// How records for over-the-counter drugs are treated. Records without an
+    /// over-the-counter indicator are treated as prescription drugs.
+    pub otc: OtcFilter,
+
+    /// Only records priced per this unit match. Records without a pricing unit do not match.
+    pub pricing_unit: Option<PricingUnit>,
+
+    /// Only records with this explanation code, among any others, match.
+    pub explanation_code: Option<String>,
+
+    /// Only records with an old price of at least this much match. Leaving out very cheap
+    /// drugs keeps tiny absolute changes from crowding the report.
+    pub min_old_price: Option<Decimal>,
+
+    /// Only records whose price changed by at least this many dollars, in either direction,
+    /// match.
+    pub min_change: Option<Decimal>,
+
+    /// Only records whose price changed by at least this percent of the old price, in either
+    /// direction, match. A change from an old price of zero counts as an unlimited percent.
+    pub min_change_percent: Option<Decimal>,
+
+    /// Only records for these NDCs, normalized with `normalize_ndc`, match. Records without an
+    /// NDC do not match.
+    pub ndcs: Option<HashSet<String>>,
+
+    /// Only records matching this `--where` expression match.
+    pub expression: Option<Expression>,
+}
+
+impl RecordFilter {
+    /// Determine if a row meets the conditions of the filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    ///
+    /// # Returns
+    ///
+    /// True if the row should go into the report.
+    pub fn matches(&self, row: &ComparisonRow) -> bool {
+        self.rejection(row).is_none()
+    }
+
+    /// Find the first condition of the filter a row does not meet.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain a description of the condition, or None if the row meets
+    /// all of them.
+    pub fn rejection(&self, row: &ComparisonRow) -> Option<&'static str> {
+        if self.classification.is_some()
+            && Classification::from_code(&row.classification) != self.classification
+        {
+            return Some("not the --classification");
+        }
+
+        if self.pricing_unit.is_some()
+            && PricingUnit::from_code(&row.pricing_unit) != self.pricing_unit
+        {
+            return Some("not the --pricing-unit");
+        }
+
+        if let Some(explanation_code) = &self.explanation_code {
+            if !explanation_codes(&row.explanation_code).any(|code| code == explanation_code) {
+                return Some("not the --explanation-code");
+            }
+        }
+
+        if self
+            .min_old_price
+            .is_some_and(|min_old_price| row.old_price < min_old_price)
+        {
+            return Some("old price below --min-old-price");
+        }
+
+        let change = (row.new_price - row.old_price).abs();
+        if self
+            .min_change
+            .is_some_and(|min_change| change < min_change)
+        {
+            return Some("change below --min-change");
+        }
+        if let Some(min_change_percent) = self.min_change_percent {
+            if row.old_price.is_zero() {
+                if change.is_zero() {
+                    return Some("change below --min-change-pct");
+                }
+            } else if change * Decimal::ONE_HUNDRED < min_change_percent * row.old_price.abs() {
+                return Some("change below --min-change-pct");
+            }
+        }
+
+        if let Some(ndcs) = &self.ndcs {
+            if !ndcs.contains(&normalize_ndc(&row.ndc)) {
+                return Some("NDC not in --ndc-file");
+            }
+        }
+
+        if self
+            .expression
+            .as_ref()
+            .is_some_and(|expression| !expression.matches(row))
+        {
+            return Some("not matched by --where");
+        }
+
+        let otc = row.otc.trim().eq_ignore_ascii_case("Y");
+        let matched = match self.otc {
+            OtcFilter::Include => true,
+            OtcFilter::Exclude => !otc,
+            OtcFilter::Only => otc,
+        };
+        (!matched).then_some("left out by --otc")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+
+    fn row(record: &ByteRecord) -> ComparisonRow<'_> {
+        ComparisonRow::from_record(record).unwrap()
+    }
+
+    #[test]
+    fn test_classification_filter() {
+        let brand = ByteRecord::from(vec!["HUMIRA", "", "1", "2", "B"]);
+        let generic = ByteRecord::from(vec!["METFORMIN", "", "1", "2", " g "]);
+        let unknown = ByteRecord::from(vec!["ASPIRIN", "", "1", "2"]);
+
+        let filter = RecordFilter::default();
+        assert!(filter.matches(&row(&brand)));
+        assert!(filter.matches(&row(&unknown)));
+
+        let filter = RecordFilter {
+            classification: Some(Classification::Generic),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&row(&brand)));
+        assert!(filter.matches(&row(&generic)));
+        assert!(!filter.matches(&row(&unknown)));
+    }
+
+    #[test]
+    fn test_otc_filter() {
+        let mut fields = vec!["ASPIRIN", "", "1", "2", "G", "", "", "", "", "", "Y"];
+        let otc = ByteRecord::from(fields.clone());
+        fields[10] = "N";
+        let prescription = ByteRecord::from(fields.clone());
+        let unknown = ByteRecord::from(fields[..10].to_vec());
+
+        let filter = RecordFilter {
+            otc: OtcFilter::Exclude,
+            ..Default::default()
+        };
+        assert!(!filter.matches(&row(&otc)));
+        assert!(filter.matches(&row(&prescription)));
+        assert!(filter.matches(&row(&unknown)));
+
+        let filter = RecordFilter {
+            otc: OtcFilter::Only,
+            ..Default::default()
+        };
+        assert!(filter.matches(&row(&otc)));
+        assert!(!filter.matches(&row(&prescription)));
+        assert!(!filter.matches(&row(&unknown)));
+    }
+
+    #[test]
+    fn test_pricing_unit_filter() {
+        let mut fields = vec!["ASPIRIN", "", "1", "2", "G", "", "", "", "", "", "", "ea"];
+        let each = ByteRecord::from(fields.clone());
+        fields[11] = "ML";
+        let milliliter = ByteRecord::from(fields.clone());
+        let unknown = ByteRecord::from(fields[..10].to_vec());
+
+        let filter = RecordFilter {
+            pricing_unit: Some(PricingUnit::Each),
+            ..Default::default()
+        };
+        assert!(filter.matches(&row(&each)));
+        assert!(!filter.matches(&row(&milliliter)));
+        assert!(!filter.matches(&row(&unknown)));
+        assert!(RecordFilter::default().matches(&row(&unknown)));
+    }
+
+    #[test]
+    fn test_explanation_code_filter() {
+        let mut fields = vec![
+            "ASPIRIN", "", "1", "2", "", "", "", "", "", "", "", "", "1, 5",
+        ];
+        let coded = ByteRecord::from(fields.clone());
+        fields[12] = "15";
+        let other = ByteRecord::from(fields.clone());
+
+        let filter = RecordFilter {
+            explanation_code: Some("5".to_string()),
+            ..Default::default()
+        };
+        assert!(filter.matches(&row(&coded)));
+        assert!(!filter.matches(&row(&other)));
+    }
+
+    #[test]
+    fn test_min_old_price_filter() {
+        let cheap = ByteRecord::from(vec!["ASPIRIN", "", "0.02", "0.03"]);
+        let at_minimum = ByteRecord::from(vec!["METFORMIN", "", "1.00", "0.90"]);
+
+        let filter = RecordFilter {
+            min_old_price: Some(Decimal::ONE),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&row(&cheap)));
+        assert!(filter.matches(&row(&at_minimum)));
+    }
+
+    #[test]
+    fn test_ndc_filter() {
+        assert_eq!(normalize_ndc("00002143380"), "00002143380");
+        assert_eq!(normalize_ndc("0002-1433-80"), "00002143380");
+        assert_eq!(normalize_ndc(" 50090-123-4 "), "50090012304");
+
+        let ndcs = parse_ndc_list("# Formulary\n00002-1433-80\n\n 00378-0208-01\n");
+        assert_eq!(ndcs.len(), 2);
+
+        let listed = ByteRecord::from(vec!["TRULICITY", "00002143380", "1", "2"]);
+        let unlisted = ByteRecord::from(vec!["ASPIRIN", "12345678901", "1", "2"]);
+        let missing = ByteRecord::from(vec!["ASPIRIN", "", "1", "2"]);
+
+        let filter = RecordFilter {
+            ndcs: Some(ndcs),
+            ..Default::default()
+        };
+        assert!(filter.matches(&row(&listed)));
+        assert!(!filter.matches(&row(&unlisted)));
+        assert!(!filter.matches(&row(&missing)));
+    }
+
+    #[test]
+    fn test_min_change_filter() {
+        let small = ByteRecord::from(vec!["ASPIRIN", "", "0.02", "0.03"]);
+        let large = ByteRecord::from(vec!["HUMIRA", "", "3206.71", "3100.00"]);
+        let from_zero = ByteRecord::from(vec!["SALINE", "", "0", "0.01"]);
+
+        let filter = RecordFilter {
+            min_change: Some(Decimal::new(5, 2)),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&row(&small)));
+        assert!(filter.matches(&row(&large)));
+        assert_eq!(
+            filter.rejection(&row(&small)),
+            Some("change below --min-change")
+        );
+        assert_eq!(filter.rejection(&row(&large)), None);
+
+        // ASPIRIN rose 50% and HUMIRA fell by about 3.3%.
+        let filter = RecordFilter {
+            min_change_percent: Some(Decimal::new(5, 0)),
+            ..Default::default()
+        };
+        assert!(filter.matches(&row(&small)));
+        assert!(!filter.matches(&row(&large)));
+        assert!(filter.matches(&row(&from_zero)));
+    }
+}
@@ -0,0 +1,185 @@
+//! The `gaps` module provides code for following the gap between the price of a brand name
+//! drug and the price of its corresponding generic, which shows the brands whose premium over
+//! the generic grew or shrank over the report's period.
+
+use crate::aggregate::ndc_key;
+use crate::comparison::ComparisonRow;
+use crate::data_store::parse_price;
+use crate::filters::Classification;
+use crate::report::{dollars, drugs_label, RecordFormat, Section};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+
+/// The gap between a brand's price and its generic's price, and the date it applied from.
+type DatedGap = (NaiveDate, Decimal);
+
+/// The gaps of a brand at the start and end of the price changes seen.
+#[derive(Debug, Clone)]
+struct BrandGap {
+    /// The description of the brand.
+    description: String,
+
+    /// The NDC of the brand, which may be empty.
+    ndc: String,
+
+    /// The gap before the earliest price change.
+    first: DatedGap,
+
+    /// The gap after the latest price change.
+    last: DatedGap,
+}
+
+impl BrandGap {
+    /// How much the gap grew, or shrank when negative.
+    fn change(&self) -> Decimal {
+        self.last.1 - self.first.1
+    }
+}
+
+/// The `GenericGaps` struct keeps the gap between each brand and its corresponding generic in
+/// each section of the report.
+#[derive(Debug, Clone, Default)]
+pub struct GenericGaps {
+    /// The gaps of each brand, by section.
+    sections: BTreeMap<Section, HashMap<String, BrandGap>>,
+}
+
+impl GenericGaps {
+    /// Add a price change. Changes to drugs that are not brands, or without the prices of
+    /// their corresponding generic, are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The section the change is reported in.
+    /// * `row` - The row of comparison data.
+    pub fn add(&mut self, section: Section, row: &ComparisonRow) {
+        if Classification::from_code(&row.classification) != Some(Classification::Brand) {
+            return;
+        }
+        let (Some(effective_date), Ok(old_generic), Ok(new_generic)) = (
+            row.effective_date,
+            parse_price(&row.old_generic_price),
+            parse_price(&row.new_generic_price),
+        ) else {
+            return;
+        };
+
+        // The old price applied from before the change, so it is dated a day earlier to sort
+        // ahead of a new price from the same date.
+        let before = effective_date.pred_opt().unwrap_or(effective_date);
+        let old_gap = (before, row.old_price - old_generic);
+        let new_gap = (effective_date, row.new_price - new_generic);
+
+        let gaps = self.sections.entry(section).or_default();
+        let gap = gaps.entry(ndc_key(row)).or_insert_with(|| BrandGap {
+            description: row.description.to_string(),
+            ndc: row.ndc.to_string(),
+            first: old_gap,
+            last: new_gap,
+        });
+        if old_gap.0 < gap.first.0 {
+            gap.first = old_gap;
+        }
+        if new_gap.0 >= gap.last.0 {
+            gap.last = new_gap;
+        }
+    }
+
+    /// Generate the section of the report ranking the brands whose gap over their generic
+    /// widened the most, followed by those whose gap narrowed the most.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The section of the report.
+    /// * `count` - The number of brands requested for each list.
+    /// * `format` - How the brands are written.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the section.
+    pub fn report(&self, section: &Section, count: usize, format: &RecordFormat) -> String {
+        let drugs = drugs_label(section);
+        let gaps: Vec<&BrandGap> = self
+            .sections
+            .get(section)
+            .map(|gaps| gaps.values().collect())
+            .unwrap_or_default();
+
+        let list = |kind: &str, widening: bool| {
+            let mut report = format!(
+                "Top {} {}NADAC brand to generic price gaps {} {}:\n",
+                count, drugs, kind, section.period
+            );
+            let mut ranked: Vec<&&BrandGap> = gaps
+                .iter()
+                .filter(|gap| (gap.change() > Decimal::ZERO) == widening && !gap.change().is_zero())
+                .collect();
+            ranked.sort_by(|a, b| {
+                let order = if widening {
+                    b.change().cmp(&a.change())
+                } else {
+                    a.change().cmp(&b.change())
+                };
+                order.then_with(|| a.description.cmp(&b.description))
+            });
+            for gap in ranked.into_iter().take(count) {
+                report.push_str(&format!(
+                    "{}: {} (gap {} to {})\n",
+                    dollars(&gap.change()),
+                    format.describe(&gap.description, &gap.ndc),
+                    dollars(&gap.first.1),
+                    dollars(&gap.last.1)
+                ));
+            }
+            report
+        };
+
+        format!("{}\n{}", list("widening", true), list("narrowing", false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dates::Period;
+    use csv_async::ByteRecord;
+
+    #[test]
+    fn test_generic_gaps() {
+        let section = Section {
+            period: Period::Year(2023),
+            classification: None,
+            pricing_unit: None,
+        };
+        let mut gaps = GenericGaps::default();
+        for (description, classification, old_price, new_price, generic, date) in [
+            ("LIPITOR 40 MG", "B", "10", "12", ("1", "1"), "03/01/2023"),
+            ("LIPITOR 40 MG", "B", "8", "10", ("1", "0.5"), "01/04/2023"),
+            ("NEXIUM 40 MG", "B", "9", "8", ("2", "3"), "02/01/2023"),
+            ("PLAVIX 75 MG", "B", "5", "6", ("", ""), "02/01/2023"),
+            (
+                "ATORVASTATIN 40 MG",
+                "G",
+                "1",
+                "2",
+                ("1", "2"),
+                "02/01/2023",
+            ),
+        ] {
+            let mut fields = vec![description, "", old_price, new_price, classification];
+            fields.extend(["", "", "", "", date, "", "", "", generic.0, generic.1]);
+            let record = ByteRecord::from(fields);
+            gaps.add(section, &ComparisonRow::from_record(&record).unwrap());
+        }
+
+        assert_eq!(
+            gaps.report(&section, 5, &RecordFormat::default()),
+            "Top 5 NADAC brand to generic price gaps widening of 2023:\n\
+            $4: LIPITOR 40 MG (gap $7 to $11)\n\
+            \n\
+            Top 5 NADAC brand to generic price gaps narrowing of 2023:\n\
+            -$2: NEXIUM 40 MG (gap $7 to $5)\n"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! The `histogram` module provides code for counting the price changes in ranges, which shows
+//! how exceptional the largest changes in the report are.
+
+use crate::report::dollars;
+use clap::ValueEnum;
+use rust_decimal::Decimal;
+
+/// The widest bar in the ASCII histogram, in characters.
+const BAR_WIDTH: usize = 50;
+
+/// Enum describing how the histogram is written in the report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum HistogramFormat {
+    /// A bar chart drawn with text.
+    #[default]
+    Ascii,
+
+    /// A JSON array of the ranges and their counts.
+    Json,
+}
+
+/// The default edges of the histogram's ranges, in dollars.
+pub fn default_edges() -> Vec<Decimal> {
+    [-1000, -100, -10, -1, 0, 1, 10, 100, 1000]
+        .iter()
+        .map(|edge| Decimal::new(*edge, 1).normalize())
+        .collect()
+}
+
+/// The `Histogram` struct counts the price changes falling in each range. The edges divide the
+/// changes into ranges that include their lower edge, plus a range below the first edge and a
+/// range from the last edge up.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    /// The edges of the ranges, in increasing order.
+    edges: Vec<Decimal>,
+
+    /// The number of changes in each range, one more than there are edges.
+    counts: Vec<u64>,
+}
+
+impl Histogram {
+    /// Create a new, empty `Histogram`.
+    ///
+    /// # Arguments
+    ///
+    /// * `edges` - The edges of the ranges.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `Histogram`, on error returns a String describing the problem.
+    pub fn new(edges: Vec<Decimal>) -> Result<Histogram, String> {
+        if edges.is_empty() {
+            return Err("The histogram needs at least one bucket edge".to_string());
+        }
+        if edges.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("The histogram bucket edges must be in increasing order".to_string());
+        }
+
+        Ok(Histogram {
+            counts: vec![0; edges.len() + 1],
+            edges,
+        })
+    }
+
+    /// Count a price change.
+    ///
+    /// # Arguments
+    ///
+    /// * `difference` - The change in the per unit price.
+    pub fn add(&mut self, difference: Decimal) {
+        let range = self.edges.partition_point(|edge| *edge <= difference);
+        self.counts[range] += 1;
+    }
+
+    /// Describe a range of the histogram.
+    fn label(&self, range: usize) -> String {
+        if range == 0 {
+            format!("below {}", dollars(&self.edges[0]))
+        } else if range == self.edges.len() {
+            format!("{} and up", dollars(&self.edges[range - 1]))
+        } else {
+            format!(
+                "{} to {}",
+                dollars(&self.edges[range - 1]),
+                dollars(&self.edges[range])
+            )
+        }
+    }
+
+    /// Get the ranges of the histogram and the number of changes in each.
+    ///
+    /// # Returns
+    ///
+    /// A Vec containing the description and count of each range, from the lowest up.
+    pub fn ranges(&self) -> Vec<(String, u64)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(range, count)| (self.label(range), *count))
+            .collect()
+    }
+
+    /// Generate the histogram section of the report.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - How the histogram is written.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the section.
+    pub fn report(&self, format: HistogramFormat) -> String {
+        let mut report = String::from("Price change histogram:\n");
+        match format {
+            HistogramFormat::Ascii => {
+                let labels: Vec<String> = (0..self.counts.len()).map(|i| self.label(i)).collect();
+                let label_width = labels.iter().map(String::len).max().unwrap_or(0);
+                let count_width = self.counts.iter().max().unwrap_or(&0).to_string().len();
+                let most = *self.counts.iter().max().unwrap_or(&0);
+
+                for (label, count) in labels.iter().zip(&self.counts) {
+                    // Round the bars up, so every non-empty range shows.
+                    let bar = match most {
+                        0 => 0,
+                        most => (*count as usize * BAR_WIDTH).div_ceil(most as usize),
+                    };
+                    let line = format!(
+                        "{:<label_width$}  {:>count_width$} {}",
+                        label,
+                        count,
+                        "#".repeat(bar)
+                    );
+                    report.push_str(line.trim_end());
+                    report.push('\n');
+                }
+            }
+            HistogramFormat::Json => {
+                // The edges are written as strings so they keep their exact decimal values.
+                let ranges: Vec<serde_json::Value> = self
+                    .counts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, count)| {
+                        serde_json::json!({
+                            "from": i.checked_sub(1).map(|i| self.edges[i].to_string()),
+                            "to": self.edges.get(i).map(|edge| edge.to_string()),
+                            "count": count,
+                        })
+                    })
+                    .collect();
+                let json = serde_json::to_string_pretty(&ranges).unwrap_or_default();
+                report.push_str(&json);
+                report.push('\n');
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        assert!(Histogram::new(vec![]).is_err());
+        assert!(Histogram::new(vec![Decimal::ONE, Decimal::ZERO]).is_err());
+
+        let mut histogram = Histogram::new(vec![Decimal::new(-1, 0), Decimal::ZERO]).unwrap();
+        for cents in [-250, -100, -1, 0, 0, 75] {
+            histogram.add(Decimal::new(cents, 2));
+        }
+
+        assert_eq!(
+            histogram.report(HistogramFormat::Ascii),
+            format!(
+                "Price change histogram:\n\
+                below -$1  1 {}\n\
+                -$1 to $0  2 {}\n\
+                $0 and up  3 {}\n",
+                "#".repeat(17),
+                "#".repeat(34),
+                "#".repeat(50)
+            )
+        );
+
+        let json = histogram.report(HistogramFormat::Json);
+        let ranges: serde_json::Value =
+            serde_json::from_str(json.trim_start_matches("Price change histogram:\n")).unwrap();
+        assert_eq!(
+            ranges[0],
+            serde_json::json!({"from": null, "to": "-1", "count": 1})
+        );
+        assert_eq!(
+            ranges[2],
+            serde_json::json!({"from": "0", "to": null, "count": 3})
+        );
+    }
+}
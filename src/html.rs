@@ -0,0 +1,266 @@
+//! The `html` module provides code for writing the report as a single HTML file, with styled
+//! tables and a bar chart of each section's changes drawn as inline SVG, so it can be emailed
+//! and opened in any browser without other files or scripts.
+
+use crate::data_store::DataStore;
+use crate::report::{dollars, drugs_label, ranked_entries, RecordFormat, ReportField, Section};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+
+/// The start of an HTML report, up to the title.
+pub const HTML_HEADER: &str = "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<title>NADAC per unit price changes</title>
+<style>
+body { font-family: Helvetica, Arial, sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.6em; }
+h2 { font-size: 1.2em; margin-top: 1.5em; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.8em; }
+th { background: #f0f0f0; }
+td.number { text-align: right; font-variant-numeric: tabular-nums; }
+.increase { color: #b03a2e; }
+.decrease { color: #1e8449; }
+pre { background: #f8f8f8; padding: 0.8em; }
+</style>
+</head>
+<body>
+<h1>NADAC per unit price changes</h1>
+";
+
+/// The end of an HTML report.
+pub const HTML_FOOTER: &str = "</body>\n</html>\n";
+
+/// The width of the bar chart, in pixels.
+const CHART_WIDTH: usize = 720;
+
+/// The width of the chart's drug labels, in pixels.
+const LABEL_WIDTH: usize = 320;
+
+/// The height of each bar of the chart, with the space below it, in pixels.
+const BAR_HEIGHT: usize = 22;
+
+/// Escape the characters of text that HTML would otherwise read as markup.
+///
+/// # Arguments
+///
+/// * `text` - The text.
+///
+/// # Returns
+///
+/// A new String containing the escaped text.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Draw a bar chart of price changes, with the increases to the right of the zero line and the
+/// decreases to the left.
+///
+/// # Arguments
+///
+/// * `records` - The price changes and their drugs, in the order they are drawn.
+///
+/// # Returns
+///
+/// A new String containing the SVG of the chart, or an empty String when there are no changes.
+fn bar_chart(records: &[(Decimal, String)]) -> String {
+    if records.is_empty() {
+        return String::new();
+    }
+
+    let largest = records
+        .iter()
+        .filter_map(|(difference, _)| difference.abs().to_f64())
+        .fold(0.0, f64::max);
+    let half = (CHART_WIDTH - LABEL_WIDTH) as f64 / 2.0;
+    let zero = LABEL_WIDTH as f64 + half;
+    let height = records.len() * BAR_HEIGHT;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" role=\"img\">\n",
+        CHART_WIDTH, height
+    );
+    for (index, (difference, drug)) in records.iter().enumerate() {
+        let y = index * BAR_HEIGHT;
+        let value = difference.to_f64().unwrap_or(0.0);
+        // The bars of the largest changes reach the edges of the chart.
+        let length = if largest > 0.0 {
+            value.abs() / largest * (half - 4.0)
+        } else {
+            0.0
+        };
+        let (x, class) = if value < 0.0 {
+            (zero - length, "decrease")
+        } else {
+            (zero, "increase")
+        };
+        let label: String = drug.chars().take(40).collect();
+        svg.push_str(&format!(
+            "<text x=\"0\" y=\"{}\" font-size=\"12\">{}</text>\n",
+            y + 15,
+            escape(&label)
+        ));
+        svg.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" class=\"{}\" \
+            fill=\"currentColor\"><title>{}</title></rect>\n",
+            x,
+            y + 3,
+            length,
+            BAR_HEIGHT - 6,
+            class,
+            escape(&dollars(difference))
+        ));
+    }
+    svg.push_str(&format!(
+        "<line x1=\"{0:.1}\" y1=\"0\" x2=\"{0:.1}\" y2=\"{1}\" stroke=\"#888\"/>\n</svg>\n",
+        zero, height
+    ));
+    svg
+}
+
+/// Generate a section of the report as a heading and table for each ranked list, followed by a
+/// bar chart of all of the section's changes.
+///
+/// # Arguments
+///
+/// * `data_store` - The records store.
+/// * `section` - The section of the report.
+/// * `format` - How the records' drugs are written.
+///
+/// # Returns
+///
+/// A new String containing the HTML for the section.
+pub fn html_lists(data_store: &DataStore, section: &Section, format: &RecordFormat) -> String {
+    let period = section.period;
+    let drugs = drugs_label(section);
+
+    let mut html = String::new();
+    let mut records = Vec::new();
+    for (kind, count, entries) in ranked_entries(data_store, format.percent_places) {
+        html.push_str(&format!(
+            "<h2>Top {count} {drugs}NADAC per unit price {kind} {period}</h2>\n"
+        ));
+        html.push_str(
+            "<table>\n<tr><th>Rank</th><th>Change</th><th>% Change</th><th>Old price</th>\
+            <th>New price</th><th>Effective</th><th>Drug</th></tr>\n",
+        );
+        for entry in &entries {
+            let class = if entry.change.is_sign_negative() && !entry.change.is_zero() {
+                "decrease"
+            } else {
+                "increase"
+            };
+            let drug = format.describe(&entry.description, &entry.ndc);
+            html.push_str(&format!(
+                "<tr><td class=\"number\">{}</td><td class=\"number {}\">{}</td>",
+                entry.rank_label(),
+                class,
+                escape(&dollars(&entry.change))
+            ));
+            for field in [
+                ReportField::Percent,
+                ReportField::OldPrice,
+                ReportField::NewPrice,
+                ReportField::EffectiveDate,
+            ] {
+                html.push_str(&format!(
+                    "<td class=\"number\">{}</td>",
+                    escape(&entry.field(field))
+                ));
+            }
+            html.push_str(&format!("<td>{}</td></tr>\n", escape(&drug)));
+            records.push((entry.change, drug));
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str(&bar_chart(&records));
+    html
+}
+
+/// Turn a section of the plain text report into HTML. The section may hold several lists
+/// separated by blank lines: the first line of each, without the trailing colon, becomes a
+/// heading and the rest is kept as it is in a `pre` block.
+///
+/// # Arguments
+///
+/// * `text` - The plain text section.
+///
+/// # Returns
+///
+/// A new String containing the HTML for the section.
+pub fn html_section(text: &str) -> String {
+    text.split("\n\n")
+        .filter(|list| !list.trim().is_empty())
+        .map(|list| {
+            let (heading, body) = list.split_once('\n').unwrap_or((list, ""));
+            let mut html = format!(
+                "<h2>{}</h2>\n",
+                escape(heading.trim_end().trim_end_matches(':'))
+            );
+            if !body.trim().is_empty() {
+                html.push_str(&format!("<pre>{}</pre>\n", escape(body.trim_end())));
+            }
+            html
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_store::{Direction, Metric, PriceDetails};
+    use crate::dates::Period;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_html() {
+        let mut data_store = DataStore::new(1, 1, Metric::Change, Direction::Both).unwrap();
+        data_store
+            .insert_priced_change(
+                Decimal::new(80239, 2),
+                "STELARA <90 MG>",
+                "",
+                Some(PriceDetails {
+                    old_price: Decimal::new(2515830, 2),
+                    effective_date: NaiveDate::from_ymd_opt(2023, 1, 11),
+                }),
+            )
+            .unwrap();
+        data_store
+            .insert_change(Decimal::new(-18314, 2), "HUMALOG & CO", "")
+            .unwrap();
+        let section = Section {
+            period: Period::Year(2023),
+            classification: None,
+            pricing_unit: None,
+        };
+
+        let html = html_lists(&data_store, &section, &RecordFormat::default());
+        assert!(html.starts_with(
+            "<h2>Top 1 NADAC per unit price increases of 2023</h2>\n\
+            <table>\n\
+            <tr><th>Rank</th><th>Change</th><th>% Change</th><th>Old price</th>\
+            <th>New price</th><th>Effective</th><th>Drug</th></tr>\n\
+            <tr><td class=\"number\">1</td><td class=\"number increase\">$802.39</td>\
+            <td class=\"number\">3.19%</td><td class=\"number\">$25158.30</td><td class=\"number\">$25960.69</td>\
+            <td class=\"number\">2023-01-11</td><td>STELARA &lt;90 MG&gt;</td></tr>\n\
+            </table>\n"
+        ));
+        assert!(html.contains("<td>HUMALOG &amp; CO</td>"));
+        // The largest change reaches the edge of the chart, the smaller one is in proportion.
+        assert!(html.contains("<rect x=\"520.0\" y=\"3\" width=\"196.0\""));
+        assert!(html.contains("<rect x=\"475.3\" y=\"25\" width=\"44.7\""));
+        assert!(html.ends_with("</svg>\n"));
+
+        assert_eq!(
+            html_section("Summary statistics:\nMean: <$1>\n"),
+            "<h2>Summary statistics</h2>\n<pre>Mean: &lt;$1&gt;</pre>\n"
+        );
+    }
+}
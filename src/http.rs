@@ -0,0 +1,619 @@
+//! The `http` module provides code for downloading the CSV data over HTTP(S) with retries for
+//! transient failures and resumption of interrupted downloads.
+
+use crate::data_source::DataReader;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, ETAG, IF_RANGE, LAST_MODIFIED, RANGE,
+};
+use reqwest::{Certificate, Client, Response, StatusCode};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The User-Agent sent when none is given, so servers can identify the program and version.
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// A stream of the bytes in a response body.
+pub type ByteStream = BoxStream<'static, std::io::Result<Bytes>>;
+
+/// Options that control how data is downloaded over HTTP(S).
+#[derive(Debug, Clone)]
+pub struct HttpOptions {
+    /// The number of times to retry a request after a transient failure.
+    pub retries: u32,
+
+    /// The delay before the first retry. The delay doubles for each following retry.
+    pub retry_backoff: Duration,
+
+    /// Extra headers sent with every request, such as authentication for private mirrors.
+    pub headers: HeaderMap,
+
+    /// The maximum number of bytes per second to download. When `None`, downloads are not
+    /// throttled.
+    pub max_download_rate: Option<u64>,
+
+    /// The longest time to wait for a connection to the server. When `None`, there is no limit.
+    pub connect_timeout: Option<Duration>,
+
+    /// The longest time to wait for each read from the server, so a server that stops sending
+    /// data part way through a download does not stall the program. When `None`, there is no
+    /// limit.
+    pub read_timeout: Option<Duration>,
+
+    /// Files of extra root certificates (PEM or DER) to trust, such as the certificate of a TLS
+    /// intercepting proxy.
+    pub ca_certificates: Vec<PathBuf>,
+
+    /// When true, TLS certificates are not verified at all.
+    pub insecure: bool,
+
+    /// The User-Agent header sent with every request.
+    pub user_agent: String,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            retries: 3,
+            retry_backoff: Duration::from_millis(500),
+            headers: HeaderMap::new(),
+            max_download_rate: None,
+            connect_timeout: Some(Duration::from_secs(30)),
+            read_timeout: Some(Duration::from_secs(60)),
+            ca_certificates: Vec::new(),
+            insecure: false,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+        }
+    }
+}
+
+impl HttpOptions {
+    /// Add a header to send with every request.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The header in `KEY:VALUE` form.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a String describing the problem.
+    pub fn add_header(&mut self, header: &str) -> Result<(), String> {
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| format!("Expected a header in KEY:VALUE form but found {}", header))?;
+
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|e| format!("Invalid header name in {}: {}", header, e))?;
+        let value = HeaderValue::from_str(value.trim())
+            .map_err(|e| format!("Invalid header value in {}: {}", header, e))?;
+
+        self.headers.append(name, value);
+        Ok(())
+    }
+
+    /// Send a bearer token in the `Authorization` header of every request.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The bearer token.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a String describing the problem.
+    pub fn set_bearer_token(&mut self, token: &str) -> Result<(), String> {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| "The bearer token contains invalid characters".to_string())?;
+        // Keep the token out of debug output.
+        value.set_sensitive(true);
+
+        self.headers.insert(AUTHORIZATION, value);
+        Ok(())
+    }
+
+    /// Build the `Client` used to send requests with these options.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `Client`, on error returns a std::error::Error in a Box.
+    pub fn client(&self) -> Result<Client, Box<dyn std::error::Error>> {
+        let mut builder = Client::builder().user_agent(&self.user_agent);
+
+        for path in &self.ca_certificates {
+            for certificate in load_certificates(path)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        if self.insecure {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+
+        Ok(builder.build()?)
+    }
+
+    /// Compute how long to wait before a retry. The delay grows exponentially with the attempt
+    /// number and is jittered to somewhere between half and all of that value so that many
+    /// clients retrying at once do not hit the server in lock step.
+    ///
+    /// # Arguments
+    ///
+    /// * `attempt` - The number of the retry, starting at 0.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .retry_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.min(16)));
+        delay / 2 + delay.mul_f64(fastrand::f64() / 2.0)
+    }
+}
+
+/// Load the certificates in a PEM bundle or a DER certificate file.
+///
+/// # Arguments
+///
+/// * `path` - The path of the certificate file.
+///
+/// # Returns
+///
+/// On success, returns the certificates, on error returns a String describing the problem.
+fn load_certificates(path: &Path) -> Result<Vec<Certificate>, String> {
+    let data = std::fs::read(path)
+        .map_err(|e| format!("Could not read the certificate {}: {}", path.display(), e))?;
+
+    let certificates = if data.starts_with(b"-----BEGIN") || data.starts_with(b"\n-----BEGIN") {
+        Certificate::from_pem_bundle(&data)
+    } else {
+        Certificate::from_der(&data).map(|certificate| vec![certificate])
+    };
+
+    certificates.map_err(|e| format!("Invalid certificate in {}: {}", path.display(), e))
+}
+
+/// Parse a download rate such as `2MiB/s`, `500KB/s` or `1000000`. The `/s` suffix is optional,
+/// the units are B, KB, MB and GB (powers of 1000) and KiB, MiB and GiB (powers of 1024), and a
+/// number without a unit is in bytes.
+///
+/// # Arguments
+///
+/// * `rate` - The rate text.
+///
+/// # Returns
+///
+/// On success, returns the rate in bytes per second, on error returns a String describing the
+/// problem.
+pub fn parse_rate(rate: &str) -> Result<u64, String> {
+    let text = rate.trim();
+    let text = text.strip_suffix("/s").unwrap_or(text).trim();
+
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" | "k" => 1000,
+        "mb" | "m" => 1000 * 1000,
+        "gb" | "g" => 1000 * 1000 * 1000,
+        "kib" => 1024,
+        "mib" => 1024 * 1024,
+        "gib" => 1024 * 1024 * 1024,
+        _ => return Err(format!("Unknown unit in download rate {}", rate)),
+    };
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Expected a download rate like 2MiB/s but found {}", rate))?;
+
+    let bytes = (number * multiplier as f64) as u64;
+    if bytes == 0 {
+        return Err(format!("The download rate {} is too small", rate));
+    }
+
+    Ok(bytes)
+}
+
+/// Limit the rate at which a byte stream produces bytes. After each chunk the stream sleeps
+/// for as long as it takes for the average rate since the start to fall back to the limit.
+///
+/// # Arguments
+///
+/// * `body` - The stream to throttle.
+/// * `rate` - The maximum number of bytes per second.
+///
+/// # Returns
+///
+/// The throttled `ByteStream`.
+fn throttle(body: ByteStream, rate: u64) -> ByteStream {
+    let start = Instant::now();
+    let mut sent: u64 = 0;
+
+    body.then(move |chunk| {
+        if let Ok(bytes) = &chunk {
+            sent += bytes.len() as u64;
+        }
+        let due = start + Duration::from_secs_f64(sent as f64 / rate as f64);
+
+        async move {
+            tokio::time::sleep_until(due.into()).await;
+            chunk
+        }
+    })
+    .boxed()
+}
+
+/// The parts of a successful response from `download`.
+pub struct HttpResponse {
+    /// The status of the response.
+    pub status: StatusCode,
+
+    /// The headers of the response.
+    pub headers: HeaderMap,
+
+    /// The body of the response.
+    pub body: ByteStream,
+}
+
+/// Convert a response body into the byte stream handed to `csv_async`.
+pub fn into_reader(body: ByteStream) -> DataReader {
+    Box::pin(body.into_async_read())
+}
+
+/// The state of a download that restarts itself when the connection fails part way through.
+struct Download {
+    /// The client used to (re)issue the request.
+    client: Client,
+
+    /// The URL being downloaded.
+    url: String,
+
+    /// The retry configuration.
+    options: HttpOptions,
+
+    /// The body of the current response.
+    body: BoxStream<'static, reqwest::Result<Bytes>>,
+
+    /// The number of bytes already handed to the reader.
+    received: u64,
+
+    /// The number of bytes to throw away from the start of a restarted response because the
+    /// reader has already seen them.
+    skip: u64,
+
+    /// The validator (ETag or Last-Modified) of the original response. Sent with range requests
+    /// so the server only resumes the download if the file has not changed in the meantime.
+    validator: Option<HeaderValue>,
+}
+
+/// Get the validator of a response: its ETag, or its Last-Modified date when it has no ETag or
+/// a weak one, which cannot be used with If-Range.
+fn response_validator(headers: &HeaderMap) -> Option<HeaderValue> {
+    match headers.get(ETAG) {
+        Some(etag) if !etag.as_bytes().starts_with(b"W/") => Some(etag.clone()),
+        _ => headers.get(LAST_MODIFIED).cloned(),
+    }
+}
+
+/// Determine if a failed response is worth retrying.
+fn is_transient_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Send a GET request, retrying connection failures, server errors and rate limiting with
+/// exponential backoff.
+///
+/// # Arguments
+///
+/// * `client` - The client used to send the request.
+/// * `url` - The URL to fetch.
+/// * `options` - The retry configuration.
+/// * `extra_headers` - Headers to send with this request in addition to those in `options`.
+///
+/// # Returns
+///
+/// On success, returns the successful response, on error returns the reqwest::Error from the
+/// last attempt.
+pub async fn send_with_retries(
+    client: &Client,
+    url: &str,
+    options: &HttpOptions,
+    extra_headers: &HeaderMap,
+) -> reqwest::Result<Response> {
+    let mut attempt = 0;
+    loop {
+        let result = client
+            .get(url)
+            .headers(options.headers.clone())
+            .headers(extra_headers.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        match result {
+            Ok(response) => return Ok(response),
+            Err(e) => {
+                let transient = match e.status() {
+                    Some(status) => is_transient_status(status),
+                    None => e.is_connect() || e.is_timeout() || e.is_request() || e.is_body(),
+                };
+
+                if !transient || attempt >= options.retries {
+                    return Err(e);
+                }
+            }
+        }
+
+        tokio::time::sleep(options.backoff(attempt)).await;
+        attempt += 1;
+    }
+}
+
+/// Download a URL as a stream of bytes. Transient failures are retried, both when making the
+/// request and part way through reading the body. When the body fails part way through, the
+/// request is reissued with a `Range` header so the download continues from the last byte the
+/// reader saw. Servers that do not support ranges send the whole file again, in which case the
+/// bytes the reader has already seen are skipped, but only when the file has the same ETag or
+/// Last-Modified date as before. Either way the reader sees one continuous stream. A download
+/// whose file has changed, or whose first response had neither header, is not resumed and
+/// fails instead.
+///
+/// # Arguments
+///
+/// * `client` - The client used to send the requests.
+/// * `url` - The URL to download.
+/// * `options` - The retry configuration.
+/// * `extra_headers` - Headers to send with the first request in addition to those in
+///   `options`, such as the headers for a conditional GET.
+///
+/// # Returns
+///
+/// On success, returns the `HttpResponse`, on error returns a std::error::Error in a Box.
+pub async fn download(
+    client: &Client,
+    url: &str,
+    options: &HttpOptions,
+    extra_headers: &HeaderMap,
+) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+    let response = send_with_retries(client, url, options, extra_headers).await?;
+    let status = response.status();
+    let headers = response.headers().clone();
+
+    let validator = response_validator(&headers);
+
+    let download = Download {
+        client: client.clone(),
+        url: url.to_string(),
+        options: options.clone(),
+        body: response.bytes_stream().boxed(),
+        received: 0,
+        skip: 0,
+        validator,
+    };
+
+    // Returning an error or None from the closure ends the stream.
+    let stream = futures::stream::try_unfold(download, |mut download| async move {
+        let mut attempt = 0;
+        loop {
+            match download.body.next().await {
+                Some(Ok(mut chunk)) => {
+                    // Drop the part of a restarted response the reader has already seen.
+                    if download.skip > 0 {
+                        let skipped = download.skip.min(chunk.len() as u64);
+                        download.skip -= skipped;
+                        chunk = chunk.slice(skipped as usize..);
+                        if chunk.is_empty() {
+                            continue;
+                        }
+                    }
+
+                    download.received += chunk.len() as u64;
+                    return Ok(Some((chunk, download)));
+                }
+                None => return Ok(None),
+                Some(Err(e)) => {
+                    // Without a validator there is no telling whether a new response is the
+                    // same file, so the download is not resumed.
+                    if attempt >= download.options.retries || download.validator.is_none() {
+                        return Err(std::io::Error::other(e));
+                    }
+
+                    tokio::time::sleep(download.options.backoff(attempt)).await;
+                    attempt += 1;
+
+                    let mut resume = HeaderMap::new();
+                    resume.insert(
+                        RANGE,
+                        HeaderValue::from_str(&format!("bytes={}-", download.received))
+                            .map_err(std::io::Error::other)?,
+                    );
+                    if let Some(validator) = &download.validator {
+                        resume.insert(IF_RANGE, validator.clone());
+                    }
+
+                    let response = send_with_retries(
+                        &download.client,
+                        &download.url,
+                        &download.options,
+                        &resume,
+                    )
+                    .await
+                    .map_err(std::io::Error::other)?;
+
+                    // A 206 response starts where the reader left off. Anything else is the
+                    // whole file again, which may have changed if the If-Range validator did
+                    // not match.
+                    download.skip = if response.status() == StatusCode::PARTIAL_CONTENT {
+                        0
+                    } else if response_validator(response.headers()) == download.validator {
+                        download.received
+                    } else {
+                        return Err(std::io::Error::other(format!(
+                            "{} changed while it was being downloaded",
+                            download.url
+                        )));
+                    };
+                    download.body = response.bytes_stream().boxed();
+                }
+            }
+        }
+    });
+
+    let body = match options.max_download_rate {
+        Some(rate) => throttle(stream.boxed(), rate),
+        None => stream.boxed(),
+    };
+
+    Ok(HttpResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// Serve each response on a connection of its own, in turn.
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the URL to request and a handle giving the requests received.
+    async fn serve(responses: Vec<String>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/data.csv", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = socket.read(&mut buffer).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                requests.push(String::from_utf8_lossy(&request).to_ascii_lowercase());
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    /// Write a response with a body, which is cut off when it is shorter than `length`.
+    fn response(etag: Option<&str>, length: usize, body: &str) -> String {
+        let etag = etag
+            .map(|etag| format!("ETag: \"{}\"\r\n", etag))
+            .unwrap_or_default();
+        format!(
+            "HTTP/1.1 200 OK\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            etag, length, body
+        )
+    }
+
+    /// Download a URL, reading the whole body.
+    async fn download_body(url: &str) -> std::io::Result<Vec<u8>> {
+        let options = HttpOptions {
+            retries: 1,
+            retry_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let client = options.client().unwrap();
+        let mut body = download(&client, url, &options, &HeaderMap::new())
+            .await
+            .unwrap()
+            .body;
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.next().await {
+            bytes.extend_from_slice(&chunk?);
+        }
+        Ok(bytes)
+    }
+
+    #[tokio::test]
+    async fn test_download_resume() {
+        // A server that ignores the range of the same file sends it all again, and the part
+        // already read is skipped.
+        let (url, server) = serve(vec![
+            response(Some("a"), 10, "01234"),
+            response(Some("a"), 10, "0123456789"),
+        ])
+        .await;
+        assert_eq!(download_body(&url).await.unwrap(), b"0123456789");
+        let requests = server.await.unwrap();
+        assert!(requests[1].contains("range: bytes=5-"));
+        assert!(requests[1].contains("if-range: \"a\""));
+
+        // A file that changed is not joined onto the start of the old one.
+        let (url, server) = serve(vec![
+            response(Some("a"), 10, "01234"),
+            response(Some("b"), 10, "abcdefghij"),
+        ])
+        .await;
+        let error = download_body(&url).await.unwrap_err();
+        assert!(error
+            .to_string()
+            .ends_with("data.csv changed while it was being downloaded"));
+        server.await.unwrap();
+
+        // Without a validator the download is not resumed at all.
+        let (url, server) = serve(vec![response(None, 10, "01234")]).await;
+        assert!(download_body(&url).await.is_err());
+        assert_eq!(server.await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let options = HttpOptions {
+            retries: 5,
+            retry_backoff: Duration::from_millis(100),
+            ..Default::default()
+        };
+
+        for attempt in 0..5 {
+            let full = Duration::from_millis(100 * 2u64.pow(attempt));
+            let delay = options.backoff(attempt);
+            assert!(delay >= full / 2);
+            assert!(delay <= full);
+        }
+    }
+
+    #[test]
+    fn test_add_header() {
+        let mut options = HttpOptions::default();
+        options.add_header("X-Api-Key: abc123").unwrap();
+        options.set_bearer_token("secret").unwrap();
+
+        assert_eq!(options.headers.get("x-api-key").unwrap(), "abc123");
+        assert_eq!(options.headers.get(AUTHORIZATION).unwrap(), "Bearer secret");
+        assert!(options.add_header("no-separator").is_err());
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("2MiB/s").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_rate("500KB/s").unwrap(), 500_000);
+        assert_eq!(parse_rate("1.5 MB").unwrap(), 1_500_000);
+        assert_eq!(parse_rate("1000000").unwrap(), 1_000_000);
+        assert!(parse_rate("fast").is_err());
+        assert!(parse_rate("2 furlongs/s").is_err());
+        assert!(parse_rate("0").is_err());
+    }
+}
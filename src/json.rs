@@ -0,0 +1,306 @@
+//! The `json` module provides code for writing the report as a JSON document, with the ranked
+//! lists of each section and the rank and fields of their price changes, chosen with
+//! `--fields`. The document records the version of its layout, so saved reports can be read
+//! back, e.g. by the `diff` subcommand, and the `schema` subcommand prints its JSON Schema.
+
+use crate::data_store::DataStore;
+use crate::provenance::Provenance;
+use crate::report::{drugs_label, period_label, ranked_entries, ReportEntry, ReportField, Section};
+use schemars::{json_schema, schema_for, JsonSchema, Schema, SchemaGenerator};
+use serde::Serialize;
+use serde_json::Value;
+
+/// The version of the layout of the JSON report. It changes only when a field is removed or
+/// its meaning changes, so reports saved by earlier runs can still be read.
+pub const JSON_REPORT_VERSION: u32 = 1;
+
+/// A JSON report.
+#[derive(Debug, Serialize, JsonSchema)]
+struct JsonReport {
+    /// The version of the layout of the report, `JSON_REPORT_VERSION`.
+    version: u32,
+
+    /// The notes on how the data was read and adjusted.
+    notes: Vec<String>,
+
+    /// How the report was made, when it was asked for with `--provenance`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provenance: Option<Provenance>,
+
+    /// The sections of the report, in date order.
+    sections: Vec<JsonSection>,
+}
+
+/// A section of a JSON report.
+#[derive(Debug, Serialize, JsonSchema)]
+struct JsonSection {
+    /// The period of the section, e.g. `2023`.
+    period: String,
+
+    /// The drugs of the section, e.g. `brand`, or null for all of them.
+    drugs: Option<String>,
+
+    /// The ranked lists of the section.
+    lists: Vec<JsonList>,
+}
+
+/// A ranked list of a JSON report.
+#[derive(Debug, Serialize, JsonSchema)]
+struct JsonList {
+    /// The kind of change of the list, e.g. `increases`.
+    kind: &'static str,
+
+    /// The number of price changes requested for the list.
+    count: usize,
+
+    /// The chosen fields of each price change of the list, in rank order.
+    #[schemars(schema_with = "entries_schema")]
+    entries: Vec<Value>,
+}
+
+/// Describe the entries of a ranked list: `ReportEntry` objects, of which only the rank and
+/// whether it is tied are always written, the other fields being those chosen with `--fields`.
+fn entries_schema(generator: &mut SchemaGenerator) -> Schema {
+    let mut entry = ReportEntry::json_schema(generator);
+    entry.insert("required".to_string(), serde_json::json!(["rank", "tied"]));
+    json_schema!({
+        "type": "array",
+        "items": entry,
+    })
+}
+
+/// Generate the JSON Schema of the JSON report, so the reports can be validated and code can
+/// be generated to read them.
+///
+/// # Returns
+///
+/// On success, returns a new String containing the schema, on error returns a
+/// std::error::Error in a Box.
+pub fn json_report_schema() -> Result<String, Box<dyn std::error::Error>> {
+    let mut schema = serde_json::to_string_pretty(&schema_for!(JsonReport))?;
+    schema.push('\n');
+    Ok(schema)
+}
+
+/// Generate the report as a JSON document.
+///
+/// # Arguments
+///
+/// * `sections` - The sections of the report and their records stores, in date order.
+/// * `fields` - The fields of each price change.
+/// * `percent_places` - The number of decimal places of the percent changes.
+/// * `notes` - The notes on how the data was read and adjusted.
+/// * `provenance` - When set, how the report was made.
+///
+/// # Returns
+///
+/// On success, returns a new String containing the JSON document, on error returns a
+/// std::error::Error in a Box.
+pub fn json_report(
+    sections: &[(&Section, &DataStore)],
+    fields: &[ReportField],
+    percent_places: u32,
+    notes: &[String],
+    provenance: Option<&Provenance>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut json_sections = Vec::new();
+    for (section, data_store) in sections {
+        let mut lists = Vec::new();
+        for (kind, count, entries) in ranked_entries(data_store, percent_places) {
+            let mut values = Vec::new();
+            for entry in entries {
+                let mut value = serde_json::to_value(&entry)?;
+                if let Value::Object(object) = &mut value {
+                    // The rank is always kept, so the entries can be referred to.
+                    object.retain(|key, _| {
+                        key == "rank"
+                            || key == "tied"
+                            || fields.iter().any(|field| field.name() == key)
+                    });
+                }
+                values.push(value);
+            }
+            lists.push(JsonList {
+                kind,
+                count,
+                entries: values,
+            });
+        }
+        let drugs = drugs_label(section);
+        json_sections.push(JsonSection {
+            period: period_label(&section.period),
+            drugs: Some(drugs.trim().to_string()).filter(|drugs| !drugs.is_empty()),
+            lists,
+        });
+    }
+
+    let report = JsonReport {
+        version: JSON_REPORT_VERSION,
+        notes: notes.to_vec(),
+        provenance: provenance.cloned(),
+        sections: json_sections,
+    };
+    let mut json = serde_json::to_string_pretty(&report)?;
+    json.push('\n');
+    Ok(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_store::{Direction, Metric, PriceDetails};
+    use crate::dates::Period;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_json_report() {
+        let mut data_store = DataStore::new(1, 1, Metric::Change, Direction::Both).unwrap();
+        data_store
+            .insert_priced_change(
+                Decimal::new(80239, 2),
+                "STELARA",
+                "57894006003",
+                Some(PriceDetails {
+                    old_price: Decimal::new(2406995, 2),
+                    effective_date: NaiveDate::from_ymd_opt(2023, 5, 17),
+                }),
+            )
+            .unwrap();
+        data_store
+            .insert_change(Decimal::new(-18314, 2), "HUMALOG", "")
+            .unwrap();
+        let section = Section {
+            period: Period::Year(2023),
+            classification: None,
+            pricing_unit: None,
+        };
+
+        let json = json_report(
+            &[(&section, &data_store)],
+            &crate::report::ALL_FIELDS,
+            3,
+            &["Partial report".to_string()],
+            None,
+        )
+        .unwrap();
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "version": 1,
+                "notes": ["Partial report"],
+                "sections": [{
+                    "period": "2023",
+                    "drugs": null,
+                    "lists": [
+                        {
+                            "kind": "increases",
+                            "count": 1,
+                            "entries": [{
+                                "rank": 1,
+                                "tied": false,
+                                "change": "802.39",
+                                "percent": "3.334",
+                                "old_price": "24069.95",
+                                "new_price": "24872.34",
+                                "ndc": "57894006003",
+                                "description": "STELARA",
+                                "effective_date": "2023-05-17"
+                            }]
+                        },
+                        {
+                            "kind": "decreases",
+                            "count": 1,
+                            "entries": [{
+                                "rank": 1,
+                                "tied": false,
+                                "change": "-183.14",
+                                "percent": null,
+                                "old_price": null,
+                                "new_price": null,
+                                "ndc": "",
+                                "description": "HUMALOG",
+                                "effective_date": null
+                            }]
+                        }
+                    ]
+                }]
+            })
+        );
+
+        let provenance = Provenance {
+            sources: vec!["data/sample_comparison.csv".to_string()],
+            sha256: "00ff".to_string(),
+            fetched: "2024-01-02T03:04:05+00:00".to_string(),
+            arguments: vec!["-y".to_string(), "2023".to_string()],
+            version: "1.0.0".to_string(),
+        };
+        let json = json_report(
+            &[(&section, &data_store)],
+            &[ReportField::Change],
+            2,
+            &[],
+            Some(&provenance),
+        )
+        .unwrap();
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value["sections"][0]["lists"][1]["entries"][0],
+            serde_json::json!({"rank": 1, "tied": false, "change": "-183.14"})
+        );
+        assert_eq!(
+            value["provenance"],
+            serde_json::json!({
+                "sources": ["data/sample_comparison.csv"],
+                "sha256": "00ff",
+                "fetched": "2024-01-02T03:04:05+00:00",
+                "arguments": ["-y", "2023"],
+                "version": "1.0.0"
+            })
+        );
+
+        // Tied changes share their rank.
+        let mut data_store = DataStore::new(3, 1, Metric::Change, Direction::Increases).unwrap();
+        for (cents, description) in [(500, "ASPIRIN"), (500, "IBUPROFEN"), (300, "NAPROXEN")] {
+            data_store
+                .insert_change(Decimal::new(cents, 2), description, "")
+                .unwrap();
+        }
+        let json = json_report(&[(&section, &data_store)], &[], 2, &[], None).unwrap();
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value["sections"][0]["lists"][0]["entries"],
+            serde_json::json!([
+                {"rank": 1, "tied": true},
+                {"rank": 1, "tied": true},
+                {"rank": 3, "tied": false}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_json_report_schema() {
+        let schema: Value = serde_json::from_str(&json_report_schema().unwrap()).unwrap();
+        assert_eq!(schema["title"], "JsonReport");
+        assert_eq!(
+            schema["required"],
+            serde_json::json!(["version", "notes", "sections"])
+        );
+
+        // The entries have the fields of a `ReportEntry`, all but the rank chosen with --fields.
+        let entries = &schema["$defs"]["JsonList"]["properties"]["entries"];
+        assert_eq!(entries["type"], "array");
+        assert_eq!(
+            entries["items"]["required"],
+            serde_json::json!(["rank", "tied"])
+        );
+        for field in crate::report::ALL_FIELDS {
+            assert!(entries["items"]["properties"][field.name()].is_object());
+        }
+        assert_eq!(
+            entries["items"]["properties"]["effective_date"]["format"],
+            "date"
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! The `latest_year` module provides code for `--year latest`, which reports on the most recent
+//! year the data runs to the end of. The year is only known once every row has been read, so
+//! the rows are spooled to temporary files until then, keeping memory use bounded, and the
+//! inputs are read only once: standard input can only be read once, and remote data would
+//! otherwise be downloaded twice.
+
+use crate::data_source::RecordStream;
+use crate::dates::{latest_complete_year, parse_date};
+use chrono::NaiveDate;
+use csv_async::{AsyncReaderBuilder, AsyncWriter, ByteRecord, Position};
+use futures::{StreamExt, TryStreamExt};
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use std::rc::Rc;
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+/// The `DateSpan` struct tracks the earliest and latest effective dates of the rows read. Its
+/// clones share the dates.
+#[derive(Debug, Clone)]
+struct DateSpan {
+    /// The index of the effective date in the rows.
+    field: usize,
+
+    /// The earliest and latest effective dates read so far.
+    dates: Rc<Cell<Option<(NaiveDate, NaiveDate)>>>,
+}
+
+impl DateSpan {
+    /// Widen the span to cover a row's effective date. Rows without a valid effective date are
+    /// left for the report to record as skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The row.
+    fn add(&self, record: &ByteRecord) {
+        let date = record
+            .get(self.field)
+            .and_then(|text| std::str::from_utf8(text).ok())
+            .and_then(|text| parse_date(text).ok());
+        if let Some(date) = date {
+            self.dates.set(Some(match self.dates.get() {
+                Some((first, last)) => (first.min(date), last.max(date)),
+                None => (date, date),
+            }));
+        }
+    }
+}
+
+/// The `LatestYear` struct finds the latest complete year of the data as it is read, spooling
+/// the rows until the year is known.
+#[derive(Debug)]
+pub struct LatestYear {
+    /// The effective dates read so far.
+    span: DateSpan,
+
+    /// The sources of the spooled rows, in the order they were read, and the files their rows
+    /// are spooled to.
+    spools: Vec<(String, File)>,
+}
+
+impl LatestYear {
+    /// Create a new `LatestYear`.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The index of the effective date in the rows.
+    ///
+    /// # Returns
+    ///
+    /// The new `LatestYear`.
+    pub fn new(field: usize) -> LatestYear {
+        LatestYear {
+            span: DateSpan {
+                field,
+                dates: Rc::new(Cell::new(None)),
+            },
+            spools: Vec::new(),
+        }
+    }
+
+    /// Track the effective dates of the rows of a `RecordStream` as they are read, without
+    /// spooling them. Weekly files are read this way, since their prices are collected until
+    /// every file has been read anyway.
+    ///
+    /// # Arguments
+    ///
+    /// * `records` - The records to track.
+    ///
+    /// # Returns
+    ///
+    /// A new `RecordStream` giving the same records.
+    pub fn track(&self, records: RecordStream<'static>) -> RecordStream<'static> {
+        let span = self.span.clone();
+        records
+            .inspect(move |record| {
+                if let Ok(record) = record {
+                    span.add(record);
+                }
+            })
+            .boxed_local()
+    }
+
+    /// Read the rows of a `RecordStream`, spooling every one of them to a temporary file along
+    /// with its line number, so the rows outside the report's years and those without a valid
+    /// effective date are accounted for when the rows are added to the report.
+    ///
+    /// # Arguments
+    ///
+    /// * `records` - The records to read.
+    /// * `source` - Where the records come from.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a std::error::Error in a Box.
+    pub async fn spool(
+        &mut self,
+        records: &mut RecordStream<'_>,
+        source: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let file = tempfile::tempfile()?;
+        let mut writer =
+            AsyncWriter::from_writer(tokio::fs::File::from_std(file.try_clone()?).compat_write());
+        while let Some(record) = records.next().await {
+            let record = record?;
+            self.span.add(&record);
+            let line = record
+                .position()
+                .map(|position| position.line().to_string())
+                .unwrap_or_default();
+            writer
+                .write_record(std::iter::once(line.as_bytes()).chain(record.iter()))
+                .await?;
+        }
+        writer.flush().await?;
+        self.spools.push((source.to_string(), file));
+        Ok(())
+    }
+
+    /// Find the latest complete year of the rows read.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the year, on error returns a String describing why there is none.
+    pub fn year(&self) -> Result<i32, String> {
+        let (first, last) = self
+            .span
+            .dates
+            .get()
+            .ok_or("--year latest found no effective dates in the data")?;
+        latest_complete_year(first, last).ok_or_else(|| {
+            format!(
+                "--year latest found no complete year in the data, whose effective dates run \
+                from {} to {}",
+                first, last
+            )
+        })
+    }
+
+    /// Read back the spooled rows, with the line numbers they were read from.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns a Vec containing each source, in the order they were read, and the
+    /// `RecordStream` of its rows, on error returns a std::error::Error in a Box.
+    pub fn into_records(
+        self,
+    ) -> Result<Vec<(String, RecordStream<'static>)>, Box<dyn std::error::Error>> {
+        let mut sources = Vec::new();
+        for (source, mut file) in self.spools {
+            file.seek(SeekFrom::Start(0))?;
+            let records = AsyncReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .create_reader(tokio::fs::File::from_std(file).compat())
+                .into_byte_records()
+                .map_err(|e| e.into())
+                .map_ok(|spooled| {
+                    let mut record: ByteRecord = spooled.iter().skip(1).collect();
+                    let line = std::str::from_utf8(&spooled[0])
+                        .ok()
+                        .and_then(|line| line.parse().ok());
+                    record.set_position(line.map(|line| {
+                        let mut position = Position::new();
+                        position.set_line(line);
+                        position
+                    }));
+                    record
+                })
+                .boxed_local();
+            sources.push((source, records));
+        }
+        Ok(sources)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+
+    /// Make a stream of rows with effective dates in the last field, read from lines 2 on.
+    fn rows(dates: &[&str]) -> RecordStream<'static> {
+        let records: Vec<Result<ByteRecord, Box<dyn std::error::Error>>> = dates
+            .iter()
+            .zip(2..)
+            .map(|(date, line)| {
+                let mut record = ByteRecord::from(vec!["DRUG", *date]);
+                let mut position = Position::new();
+                position.set_line(line);
+                record.set_position(Some(position));
+                Ok(record)
+            })
+            .collect();
+        stream::iter(records).boxed_local()
+    }
+
+    /// Read the lines and dates of spooled rows.
+    async fn lines(records: RecordStream<'static>) -> Vec<(u64, String)> {
+        records
+            .map(|record| {
+                let record = record.unwrap();
+                (
+                    record.position().unwrap().line(),
+                    String::from_utf8_lossy(&record[1]).to_string(),
+                )
+            })
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_latest_year() {
+        let mut latest_year = LatestYear::new(1);
+        latest_year
+            .spool(
+                &mut rows(&["06/01/2019", "06/01/2020", "01/04/2021", "", "06/01/2021"]),
+                "first.csv",
+            )
+            .await
+            .unwrap();
+        latest_year
+            .spool(&mut rows(&["12/31/2022", "bad"]), "second.csv")
+            .await
+            .unwrap();
+        assert_eq!(latest_year.year(), Ok(2022));
+
+        // Every row is read back, with its line number.
+        let mut spooled = latest_year.into_records().unwrap().into_iter();
+        let (source, records) = spooled.next().unwrap();
+        assert_eq!(source, "first.csv");
+        assert_eq!(
+            lines(records).await,
+            [
+                (2, "06/01/2019".to_string()),
+                (3, "06/01/2020".to_string()),
+                (4, "01/04/2021".to_string()),
+                (5, "".to_string()),
+                (6, "06/01/2021".to_string())
+            ]
+        );
+        let (source, records) = spooled.next().unwrap();
+        assert_eq!(source, "second.csv");
+        assert_eq!(
+            lines(records).await,
+            [(2, "12/31/2022".to_string()), (3, "bad".to_string())]
+        );
+
+        // Weekly files are only tracked.
+        let latest_year = LatestYear::new(1);
+        let mut records = latest_year.track(rows(&["01/04/2023", "12/30/2023"]));
+        while records.next().await.is_some() {}
+        assert_eq!(
+            latest_year.year(),
+            Err(
+                "--year latest found no complete year in the data, whose effective dates run \
+                from 2023-01-04 to 2023-12-30"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            LatestYear::new(1).year(),
+            Err("--year latest found no effective dates in the data".to_string())
+        );
+    }
+}
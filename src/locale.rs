@@ -0,0 +1,368 @@
+//! The `locale` module provides the words of the text report in each language it can be written
+//! in, chosen with `--lang`, so the report can be handed out as it is where English is not the
+//! language of its readers.
+
+use crate::dates::Period;
+use crate::filters::Classification;
+use crate::report::{ReportField, Section};
+use clap::ValueEnum;
+
+/// Enum describing the language the text report is written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Language {
+    /// English.
+    #[default]
+    En,
+
+    /// Spanish.
+    Es,
+}
+
+/// The words of the text report in a language. The headings are templates, with the values
+/// they are given written in braces, e.g. `{count}`.
+struct Words {
+    /// The heading of a ranked list, given its `count`, `drugs`, `kind` and `period`.
+    list_heading: &'static str,
+
+    /// The period of the ranked lists whose years are side by side.
+    by_year: &'static str,
+
+    /// The kind of change of the lists of the largest increases.
+    increases: &'static str,
+
+    /// The kind of change of the lists of the largest decreases.
+    decreases: &'static str,
+
+    /// The kind of change of the lists of the largest changes in either direction.
+    swings: &'static str,
+
+    /// The words for brand drugs.
+    brand: &'static str,
+
+    /// The words for generic drugs.
+    generic: &'static str,
+
+    /// The words for drugs priced per a `unit`.
+    priced: &'static str,
+
+    /// The heading of the rank column.
+    rank: &'static str,
+
+    /// The headings of the columns of the change, percent, old price, new price, NDC,
+    /// description and effective date fields.
+    headings: [&'static str; 7],
+
+    /// The names of the months, from January.
+    months: [&'static str; 12],
+
+    /// A year, given its `year`.
+    year: &'static str,
+
+    /// A month, given its `month` and `year`.
+    month: &'static str,
+
+    /// A quarter, given its `quarter` and `year`.
+    quarter: &'static str,
+
+    /// The dates between two dates, given the dates `from` and `to`.
+    range: &'static str,
+
+    /// The dates from a date, given the date `from`.
+    from: &'static str,
+
+    /// The dates up to a date, given the date `to`.
+    to: &'static str,
+
+    /// Every date.
+    all_dates: &'static str,
+}
+
+/// The words of the report in English.
+const ENGLISH: Words = Words {
+    list_heading: "Top {count} {drugs}NADAC per unit price {kind} {period}",
+    by_year: "by year",
+    increases: "increases",
+    decreases: "decreases",
+    swings: "swings in either direction",
+    brand: "brand",
+    generic: "generic",
+    priced: "{unit}-priced",
+    rank: "Rank",
+    headings: [
+        "Change",
+        "% Change",
+        "Old price",
+        "New price",
+        "NDC",
+        "Drug",
+        "Effective",
+    ],
+    months: [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ],
+    year: "of {year}",
+    month: "of {month} {year}",
+    quarter: "of Q{quarter} {year}",
+    range: "from {from} to {to}",
+    from: "from {from}",
+    to: "up to {to}",
+    all_dates: "of all dates",
+};
+
+/// The words of the report in Spanish.
+const SPANISH: Words = Words {
+    list_heading: "Los {count} mayores {kind} {drugs}del precio NADAC por unidad {period}",
+    by_year: "por año",
+    increases: "aumentos",
+    decreases: "descensos",
+    swings: "cambios en cualquier dirección",
+    brand: "de marca",
+    generic: "genéricos",
+    priced: "con precio por {unit}",
+    rank: "Posición",
+    headings: [
+        "Cambio",
+        "% Cambio",
+        "Precio anterior",
+        "Precio nuevo",
+        "NDC",
+        "Medicamento",
+        "Vigencia",
+    ],
+    months: [
+        "enero",
+        "febrero",
+        "marzo",
+        "abril",
+        "mayo",
+        "junio",
+        "julio",
+        "agosto",
+        "septiembre",
+        "octubre",
+        "noviembre",
+        "diciembre",
+    ],
+    year: "de {year}",
+    month: "de {month} de {year}",
+    quarter: "del T{quarter} de {year}",
+    range: "del {from} al {to}",
+    from: "desde el {from}",
+    to: "hasta el {to}",
+    all_dates: "de todas las fechas",
+};
+
+/// Fill in the values of a template, e.g. `{year}`.
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut text = template.to_string();
+    for (name, value) in values {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+impl Language {
+    /// The words of the report in the language.
+    fn words(&self) -> &'static Words {
+        match self {
+            Language::En => &ENGLISH,
+            Language::Es => &SPANISH,
+        }
+    }
+
+    /// Write the heading of a ranked list, without the trailing colon.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - The number of price changes requested for the list.
+    /// * `section` - The section of the report the list is in.
+    /// * `kind` - The kind of change of the list, as `ranked_entries` gives it, e.g.
+    ///   `increases`.
+    /// * `period` - The period of the list, or None for lists whose years are side by side.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the heading.
+    pub fn list_heading(
+        &self,
+        count: usize,
+        section: &Section,
+        kind: &str,
+        period: Option<&Period>,
+    ) -> String {
+        let words = self.words();
+        let period = match period {
+            Some(period) => self.period(period),
+            None => words.by_year.to_string(),
+        };
+        fill(
+            words.list_heading,
+            &[
+                ("count", &count.to_string()),
+                ("drugs", &self.drugs(section)),
+                ("kind", self.kind(kind)),
+                ("period", &period),
+            ],
+        )
+    }
+
+    /// Translate the kind of change of a ranked list, e.g. `increases`.
+    fn kind(&self, kind: &str) -> &'static str {
+        let words = self.words();
+        match kind {
+            "increases" => words.increases,
+            "decreases" => words.decreases,
+            _ => words.swings,
+        }
+    }
+
+    /// Write the words for the drugs of a section used in the report headers, e.g.
+    /// `brand EA-priced `, with a trailing space, or nothing when the section covers every
+    /// classification and pricing unit.
+    pub fn drugs(&self, section: &Section) -> String {
+        let words = self.words();
+        let mut label = String::new();
+        if let Some(classification) = section.classification {
+            let name = match classification {
+                Classification::Brand => words.brand,
+                Classification::Generic => words.generic,
+            };
+            label.push_str(&format!("{} ", name));
+        }
+        if let Some(pricing_unit) = section.pricing_unit {
+            label.push_str(&fill(words.priced, &[("unit", pricing_unit.code())]));
+            label.push(' ');
+        }
+        label
+    }
+
+    /// Write a period as it reads in the report headers, e.g. `of 2023`.
+    pub fn period(&self, period: &Period) -> String {
+        let words = self.words();
+        match period {
+            Period::Year(year) => fill(words.year, &[("year", &year.to_string())]),
+            Period::Month { year, month } => {
+                let name = match words.months.get((*month as usize).wrapping_sub(1)) {
+                    Some(name) => name.to_string(),
+                    None => format!("{:02}", month),
+                };
+                fill(
+                    words.month,
+                    &[("month", &name), ("year", &year.to_string())],
+                )
+            }
+            Period::Quarter { year, quarter } => fill(
+                words.quarter,
+                &[
+                    ("quarter", &quarter.to_string()),
+                    ("year", &year.to_string()),
+                ],
+            ),
+            Period::Range {
+                from: Some(from),
+                to: Some(to),
+            } => fill(
+                words.range,
+                &[("from", &from.to_string()), ("to", &to.to_string())],
+            ),
+            Period::Range {
+                from: Some(from),
+                to: None,
+            } => fill(words.from, &[("from", &from.to_string())]),
+            Period::Range {
+                from: None,
+                to: Some(to),
+            } => fill(words.to, &[("to", &to.to_string())]),
+            Period::Range {
+                from: None,
+                to: None,
+            } => words.all_dates.to_string(),
+        }
+    }
+
+    /// The heading of the rank column of the text report.
+    pub fn rank(&self) -> &'static str {
+        self.words().rank
+    }
+
+    /// The heading of a field's column in the text report.
+    pub fn heading(&self, field: ReportField) -> &'static str {
+        let index = match field {
+            ReportField::Change => 0,
+            ReportField::Percent => 1,
+            ReportField::OldPrice => 2,
+            ReportField::NewPrice => 3,
+            ReportField::Ndc => 4,
+            ReportField::Description => 5,
+            ReportField::EffectiveDate => 6,
+        };
+        self.words().headings[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::PricingUnit;
+    use crate::report::ALL_FIELDS;
+
+    #[test]
+    fn test_language() {
+        let section = Section {
+            period: Period::Month {
+                year: 2023,
+                month: 3,
+            },
+            classification: Some(Classification::Brand),
+            pricing_unit: Some(PricingUnit::Each),
+        };
+
+        // The English words are those of the report's headings and columns.
+        for field in ALL_FIELDS {
+            assert_eq!(Language::En.heading(field), field.heading());
+        }
+        for period in [
+            Period::Year(2023),
+            section.period,
+            Period::Quarter {
+                year: 2023,
+                quarter: 2,
+            },
+            Period::Range {
+                from: None,
+                to: None,
+            },
+        ] {
+            assert_eq!(Language::En.period(&period), period.to_string());
+        }
+        assert_eq!(
+            Language::En.list_heading(10, &section, "increases", Some(&section.period)),
+            "Top 10 brand EA-priced NADAC per unit price increases of March 2023"
+        );
+
+        assert_eq!(
+            Language::Es.list_heading(10, &section, "decreases", Some(&section.period)),
+            "Los 10 mayores descensos de marca con precio por EA del precio NADAC por unidad de \
+            marzo de 2023"
+        );
+        assert_eq!(
+            Language::Es.list_heading(5, &section, "increases", None),
+            "Los 5 mayores aumentos de marca con precio por EA del precio NADAC por unidad por año"
+        );
+        assert_eq!(
+            Language::Es.heading(ReportField::OldPrice),
+            "Precio anterior"
+        );
+    }
+}
@@ -1,10 +1,127 @@
+mod activity;
+mod aggregate;
+mod alerts;
+mod archive;
+mod cache;
+mod chart;
+mod classes;
+mod columnar;
+mod columns;
+mod comparison;
+mod compression;
+mod cpi;
+mod csv;
+mod data_source;
 mod data_store;
+mod dates;
+mod dedup;
+mod dialect;
+mod diff;
+mod directory;
+mod discovery;
+mod encoding;
+mod explain;
+mod explanation;
+mod expression;
+mod external_sort;
+mod filters;
+mod gaps;
+mod histogram;
+mod html;
+mod http;
+mod json;
+mod latest_year;
+mod locale;
+mod markdown;
+mod medicaid_api;
+mod outliers;
+mod output;
+mod pdf;
+mod provenance;
 mod record_pool;
 mod report;
+mod row_errors;
+mod run_summary;
+mod sampling;
+mod seasonality;
+mod sftp;
+mod snapshots;
+mod statistics;
+mod strength;
+mod template;
+mod thresholds;
+mod trend;
+mod utilization;
+mod validate;
+mod weekly;
+mod xlsx;
 
-use crate::report::generate_report;
-use clap::Parser;
-use futures::{StreamExt, TryStreamExt};
+use crate::activity::Activity;
+use crate::aggregate::{Aggregate, Basis, Grouping};
+use crate::alerts::{alert_line, AlertThresholds, ALERT_HEADER};
+use crate::cache::{default_cache_dir, generate_listing, DownloadCache};
+use crate::chart::{chart_panels, write_chart};
+use crate::classes::{ClassMap, ClassRollup};
+use crate::columnar::{columnar_report, ColumnarRow, ColumnarWriter};
+use crate::columns::ColumnMap;
+use crate::comparison::{ComparisonRow, FieldError};
+use crate::cpi::CpiAdjustment;
+use crate::csv::csv_report;
+use crate::data_source::{DataSource, Input, RecordStream, SourceOptions};
+use crate::data_store::{DataStore, Direction, Metric, SectionDataStores};
+use crate::dates::{parse_date, GroupBy, Period, EFFECTIVE_DATE_FIELD};
+use crate::dedup::RowDeduplicator;
+use crate::dialect::parse_delimiter;
+use crate::diff::diff_reports;
+use crate::directory::{LargeIncreases, NdcDirectory};
+use crate::discovery::{latest_comparison_dataset_id, latest_comparison_url};
+use crate::encoding::parse_encoding;
+use crate::explain::Explainer;
+use crate::explanation::ExplanationCodes;
+use crate::expression::Expression;
+use crate::external_sort::{Change, ExternalSort};
+use crate::filters::{parse_ndc_list, Classification, OtcFilter, PricingUnit, RecordFilter};
+use crate::gaps::GenericGaps;
+use crate::histogram::{default_edges, Histogram, HistogramFormat};
+use crate::html::{html_lists, html_section, HTML_FOOTER, HTML_HEADER};
+use crate::http::{parse_rate, HttpOptions};
+use crate::json::{json_report, json_report_schema};
+use crate::latest_year::LatestYear;
+use crate::locale::Language;
+use crate::markdown::{markdown_lists, markdown_section, MARKDOWN_TITLE};
+use crate::medicaid_api::{ApiCondition, ApiQuery};
+use crate::outliers::{OutlierRule, Outliers};
+use crate::output::ReportOutput;
+use crate::pdf::pdf_report;
+use crate::provenance::{DatasetHash, Provenance};
+use crate::report::{
+    generate_report, generate_side_by_side_report, OutputFormat, RecordFormat, ReportField,
+    ReportKind, Section, ALL_FIELDS, DEFAULT_PERCENT_PLACES, MAX_PERCENT_PLACES,
+};
+use crate::row_errors::RowErrors;
+use crate::run_summary::RunSummary;
+use crate::sampling::Sampling;
+use crate::seasonality::Seasonality;
+use crate::snapshots::{discontinued_report, new_drugs_report, Snapshot};
+use crate::statistics::Statistics;
+use crate::strength::{parse_strength, Normalization};
+use crate::template::ReportTemplate;
+use crate::thresholds::{ExitThresholds, PriceExtremes, Threshold, THRESHOLD_EXIT_CODE};
+use crate::trend::Trend;
+use crate::utilization::Utilization;
+use crate::validate::{generate_summary, validate_source};
+use crate::weekly::{WeeklyPrices, EFFECTIVE_DATE_FIELD as WEEKLY_EFFECTIVE_DATE_FIELD};
+use crate::xlsx::xlsx_report;
+use chrono::Local;
+use clap::{Parser, Subcommand};
+use futures::StreamExt;
+use rust_decimal::Decimal;
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::time::Duration;
 
 static NADAC_COMPARISON_URL: &str =
     "https://download.medicaid.gov/data/nadac-comparison-04-17-2024.csv";
@@ -12,83 +129,2890 @@ static NADAC_COMPARISON_URL: &str =
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    // Price change data URL
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    // Price change data URL (http, https, s3://, gs://, az://container/blob or sftp://), use - for stdin
+    // and latest (the default) to look up the current NADAC comparison dataset (repeatable, the
+    // data from every URL goes into one report)
+    #[arg(short, long = "url", value_name = "URL", global = true)]
+    urls: Vec<String>,
+
+    // Local NADAC comparison CSV file to read instead of downloading the data (repeatable, the
+    // data from every file goes into one report)
+    #[arg(short, long = "input-file", value_name = "INPUT_FILE", global = true)]
+    input_files: Vec<PathBuf>,
+
+    // Mirror URL to download the data from when the --url download fails (repeatable, the
+    // mirrors are tried in order)
+    #[arg(long = "fallback-url", value_name = "URL", conflicts_with_all = ["input_files", "stdin"])]
+    fallback_urls: Vec<String>,
+
+    // Read the price change data from stdin
+    #[arg(long, global = true, conflicts_with_all = ["urls", "input_files"])]
+    stdin: bool,
+
+    // Column of the CSV data holding one of the NADAC comparison columns, as NAME=INDEX with
+    // indexes starting at 0, for price change files laid out differently (repeatable). The
+    // names are description, ndc, old_price, new_price, classification, percent_change, reason,
+    // start_date, end_date, effective_date, otc, pricing_unit, explanation_code,
+    // old_generic_price and new_generic_price
+    #[arg(long = "column", value_name = "NAME=INDEX")]
+    columns: Vec<String>,
+
+    // Text encoding of the CSV data, e.g. utf-8 or windows-1252 (detected from the data when
+    // not given)
+    #[arg(long)]
+    encoding: Option<String>,
+
+    // The CSV data has no header row, so its first row is data. Combine with --column when the
+    // columns are not in the NADAC comparison order
+    #[arg(long)]
+    no_header: bool,
+
+    // Character separating the fields of the CSV data: a single character, or tab, comma,
+    // semicolon or pipe, or auto to detect it from the data (defaults to comma)
+    #[arg(long)]
+    delimiter: Option<String>,
+
+    // Read the data from the data.medicaid.gov datastore JSON API instead of a CSV file, using
+    // the dataset with this identifier (latest looks up the current NADAC comparison dataset)
+    #[arg(long, value_name = "ID", conflicts_with_all = ["urls", "input_files", "stdin", "columns"])]
+    api_dataset: Option<String>,
+
+    // Only pull the API records matching a condition, as PROPERTY<OP>VALUE where OP is one of
+    // = != < <= > >= (repeatable, e.g. effective_date>=2023-01-01)
+    #[arg(
+        long = "api-condition",
+        value_name = "CONDITION",
+        requires = "api_dataset"
+    )]
+    api_conditions: Vec<String>,
+
+    // Number of records to request per page from the datastore API
+    #[arg(long, default_value_t = 500, requires = "api_dataset")]
+    api_page_size: usize,
+
+    // Name of the CSV entry to read when the data is a zip archive (defaults to the first .csv)
+    #[arg(long)]
+    archive_member: Option<String>,
+
+    // Number of times to retry a failed download
+    #[arg(long, default_value_t = 3)]
+    retries: u32,
+
+    // Delay in milliseconds before the first retry, doubled for each following retry
+    #[arg(long, default_value_t = 500)]
+    retry_backoff_ms: u64,
+
+    // Seconds to wait for a connection to the server before giving up (0 waits forever)
+    #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+    connect_timeout: u64,
+
+    // Seconds to wait for more data from the server before giving up (0 waits forever)
+    #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+    read_timeout: u64,
+
+    // Extra root certificate (PEM or DER) to trust when downloading over HTTPS, e.g. the
+    // certificate of a TLS intercepting proxy (repeatable)
+    #[arg(long = "ca-cert", value_name = "PATH")]
+    ca_certs: Vec<PathBuf>,
+
+    // Do not verify TLS certificates at all. Only use this on a network you trust; it cannot be
+    // combined with --bearer-token or --header so credentials are never sent unverified
+    #[arg(long, conflicts_with_all = ["bearer_token", "headers"])]
+    insecure: bool,
+
+    // User-Agent header to send when downloading the data (defaults to top10rust/<version>)
+    #[arg(long)]
+    user_agent: Option<String>,
+
+    // Maximum download speed, e.g. 2MiB/s or 500KB/s
+    #[arg(long, value_name = "RATE")]
+    max_download_rate: Option<String>,
+
+    // Extra HTTP header to send when downloading the data, as KEY:VALUE (repeatable)
+    #[arg(long = "header", value_name = "KEY:VALUE")]
+    headers: Vec<String>,
+
+    // Bearer token to send in the Authorization header when downloading the data
+    #[arg(long)]
+    bearer_token: Option<String>,
+
+    // Keep downloaded data in the download cache and reuse it while it is unchanged
+    #[arg(long)]
+    cache: bool,
+
+    // Directory for the download cache (implies --cache)
+    #[arg(long, global = true)]
+    cache_dir: Option<PathBuf>,
+
+    // Never use the network, only read downloads from the download cache (implies --cache)
+    #[arg(long)]
+    offline: bool,
+
+    // Write the rows that were skipped because of missing or invalid data to this CSV file,
+    // with their line numbers and the reason they were skipped
+    #[arg(long, value_name = "PATH")]
+    errors_file: Option<PathBuf>,
+
+    // Only read the first N rows of each input, for a quick partial run
+    #[arg(long, value_name = "N")]
+    max_rows: Option<usize>,
+
+    // Only read a random fraction of the rows of each input, e.g. 0.01, for a quick partial run
+    #[arg(long, value_name = "FRACTION")]
+    sample: Option<Decimal>,
+
+    // Read weekly NADAC files (one price per NDC per effective date) and compute the price
+    // changes between consecutive effective dates, instead of reading NADAC comparison files
+    #[arg(long, conflicts_with_all = ["api_dataset", "columns"])]
+    weekly: bool,
+
+    // Only report on drugs with this classification for rate setting: B (brand) or G (generic)
+    #[arg(long, value_enum)]
+    classification: Option<Classification>,
+
+    // Include, exclude or only report on over-the-counter drugs. The NADAC comparison file does
+    // not say which drugs are over-the-counter, so this needs --weekly or --column otc=INDEX
+    #[arg(long, value_enum, default_value_t = OtcFilter::Include)]
+    otc: OtcFilter,
+
+    // Only report on drugs priced per this unit: EA (each), ML or GM. The NADAC comparison file
+    // does not have the pricing unit, so this needs --weekly or --column pricing_unit=INDEX
+    #[arg(long, value_enum, conflicts_with = "by_pricing_unit")]
+    pricing_unit: Option<PricingUnit>,
+
+    // Only report on price changes with this NADAC explanation code, e.g. 5. The NADAC
+    // comparison file does not have the codes, so this needs --weekly or
+    // --column explanation_code=INDEX
+    #[arg(long, value_name = "CODE")]
+    explanation_code: Option<String>,
+
+    // Add a section with the number of price changes with each NADAC explanation code, which
+    // needs --weekly or --column explanation_code=INDEX
+    #[arg(long)]
+    explanation_codes: bool,
+
+    // Add a section with the number and sum of the price increases and decreases in each
+    // calendar month, whatever the year, showing patterns like repricing each January
+    #[arg(long)]
+    seasonality: bool,
+
+    // Add sections ranking the brands by how much the gap between their price and the price
+    // of their corresponding generic widened or narrowed, which needs --weekly or
+    // --column old_generic_price=INDEX --column new_generic_price=INDEX
+    #[arg(long)]
+    generic_gap: bool,
+
+    // Treat descriptions that differ only in case or whitespace as the same drug, and show
+    // them trimmed and upper cased
+    #[arg(long)]
+    normalize_descriptions: bool,
+
+    // Skip rows that repeat an earlier row (same description, NDC, prices and effective date)
+    #[arg(long)]
+    dedup: bool,
+
+    // Number of recent rows remembered by --dedup, bounding the memory it uses
+    #[arg(
+        long,
+        value_name = "ROWS",
+        default_value_t = 100_000,
+        requires = "dedup"
+    )]
+    dedup_window: usize,
+
+    // Log to stderr what happens to each price change of a drug, given by NDC or by part of its
+    // description: the pool it went into, the change that pushed it out, or why it was left out
+    #[arg(long, value_name = "NDC_OR_TEXT")]
+    explain: Option<String>,
+
+    // Add a section summarizing all of the price changes in the report: their count, mean,
+    // median, standard deviation, minimum and maximum
+    #[arg(long)]
+    summary: bool,
+
+    // End the report with a summary of the run: the rows read, the rows skipped for missing or
+    // invalid data and the rows left out, by reason, the changes inserted, the descriptions
+    // stored, the time taken and the bytes downloaded
+    #[arg(long)]
+    run_summary: bool,
+
+    // Start the report with where the data came from, a hash of the rows read, when they were
+    // read, the arguments of the run without the values of --bearer-token and --header and the
+    // program's version, so it can be made again. CSV has no place for it, so it cannot be used
+    // with --format csv
+    #[arg(long)]
+    provenance: bool,
+
+    // Add a histogram of all of the price changes in the report
+    #[arg(long)]
+    histogram: bool,
+
+    // Edges of the histogram's ranges in dollars, in increasing order, e.g. -1,0,1
+    #[arg(
+        long,
+        value_name = "EDGES",
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        requires = "histogram"
+    )]
+    histogram_buckets: Vec<Decimal>,
+
+    // How the histogram is written: a text bar chart (ascii) or json
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = HistogramFormat::Ascii,
+        requires = "histogram"
+    )]
+    histogram_format: HistogramFormat,
+
+    // Add a section listing the price changes more than this many standard deviations from the
+    // mean of all of the price changes in the report
+    #[arg(long, value_name = "Z", conflicts_with = "outlier_iqr")]
+    outlier_z: Option<Decimal>,
+
+    // Add a section listing the price changes more than this many interquartile ranges below
+    // the first quartile or above the third, e.g. 1.5
+    #[arg(long, value_name = "MULTIPLE")]
+    outlier_iqr: Option<Decimal>,
+
+    // The most outliers listed in each direction by --outlier-z or --outlier-iqr
+    #[arg(long, value_name = "N", default_value_t = 50)]
+    outlier_count: usize,
+
+    // Adjust the prices for inflation to the dollars of --cpi-base-year before they are ranked,
+    // with the bundled CPI-U series (builtin) or a CSV file of years and CPI values
+    #[arg(long, value_name = "SOURCE")]
+    adjust_cpi: Option<String>,
+
+    // The year whose dollars --adjust-cpi converts the prices to, by default the last year of
+    // the CPI series
+    #[arg(long, requires = "adjust_cpi")]
+    cpi_base_year: Option<i32>,
+
+    // Rank the drugs by estimated spend impact, the per unit price change times the units
+    // Medicaid reimbursed in this State Drug Utilization Data file, leaving out NDCs it lacks
+    #[arg(long, value_name = "SDUD_FILE")]
+    utilization: Option<PathBuf>,
+
+    // Normalize the per unit prices before they are ranked, so different strengths of a drug
+    // can be compared: per-mg divides them by the milligrams in a unit read from the
+    // description, leaving out drugs without a strength in milligrams
+    #[arg(long, value_enum)]
+    normalize: Option<Normalization>,
+
+    // Show each drug's manufacturer after its description, from this FDA NDC Directory product
+    // or package file
+    #[arg(long, value_name = "PATH")]
+    ndc_directory: Option<PathBuf>,
+
+    // Add a section ranking the manufacturers by their number of price increases of at least
+    // this percent of the old price, e.g. 10
+    #[arg(long, value_name = "PERCENT", requires = "ndc_directory")]
+    top_manufacturers: Option<Decimal>,
+
+    // Add sections ranking the therapeutic classes by their average price change, with the
+    // classes from this CSV file of NDCs or descriptions and their class
+    #[arg(long, value_name = "CSV")]
+    class_map: Option<PathBuf>,
+
+    // Leave out price changes whose old and new prices are the same, so they do not take
+    // places among the smallest changes or fill the --all list (the default)
+    #[arg(long, overrides_with = "no_exclude_zero")]
+    exclude_zero: bool,
+
+    // Rank price changes whose old and new prices are the same
+    #[arg(long, overrides_with = "exclude_zero")]
+    no_exclude_zero: bool,
+
+    // Show each drug's NDC after its description in the report
+    #[arg(long)]
+    show_ndc: bool,
+
+    // End each row of the text report with a bar as long as its change is large, compared to
+    // the largest change of its list; increases are drawn with # and decreases with =
+    #[arg(long)]
+    chart: bool,
+
+    // Draw the ranked lists, and the histogram with --histogram, as bar charts in this SVG or
+    // PNG file, depending on its extension
+    #[arg(long, value_name = "PATH")]
+    chart_file: Option<PathBuf>,
+
+    // The language of the headings of the text report: English (en) or Spanish (es)
+    #[arg(long, value_enum, value_name = "LANG", default_value_t = Language::En)]
+    lang: Language,
+
+    // How the report is written: plain text with the ranked lists as aligned tables, the plain
+    // text lines of earlier versions (legacy), GitHub-flavored Markdown with the ranked lists as
+    // tables, a single HTML file with styled tables and bar charts, an Excel workbook (xlsx)
+    // with a sheet for each list and one of summary statistics, or a Parquet or Arrow IPC file
+    // of the ranked changes, or of every change with --all, a PDF laid out from the Markdown
+    // report, or CSV or JSON of the ranked changes. Workbooks, Parquet and Arrow files and PDFs
+    // should be redirected to a file
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    // The fields of each ranked price change, in order, for the text, CSV and JSON output,
+    // e.g. change,percent,old_price,new_price,ndc,description,effective_date. The text report
+    // shows the change, percent, old and new prices, effective date and description without
+    // it, CSV and JSON show every field. The rank of each change, e.g. 3= when it is tied, is
+    // always shown
+    #[arg(long, value_enum, value_delimiter = ',')]
+    fields: Option<Vec<ReportField>>,
+
+    // Number of decimal places of the percent change shown next to each price change, in
+    // every output format
+    #[arg(long, value_name = "PLACES", default_value_t = DEFAULT_PERCENT_PLACES)]
+    percent_places: u32,
+
+    // Write the report with this Tera template instead of the built-in wording, see
+    // data/report.tmpl for an example and the values the template is given
+    #[arg(long, value_name = "FILE")]
+    template: Option<PathBuf>,
+
+    // Write the report to this file instead of stdout, use - for stdout. The file is replaced
+    // only once the report is complete, so a failed run leaves the previous report in place
+    #[arg(short, long, value_name = "PATH", global = true)]
+    output: Option<PathBuf>,
+
+    // Add the report to the end of the --output file instead of replacing it
+    #[arg(long, global = true, requires = "output")]
+    append: bool,
+
+    // Exit with status 3 after writing the report when the largest price increase in it is
+    // above this percent of the old price, e.g. 50%, or this many dollars per unit, e.g. 25
+    #[arg(long, value_name = "PCT_OR_DOLLARS")]
+    fail_if_max_increase_above: Option<Threshold>,
+
+    // Exit with status 3 after writing the report when the largest price decrease in it is
+    // above this percent of the old price, e.g. 50%, or this many dollars per unit, e.g. 25
+    #[arg(long, value_name = "PCT_OR_DOLLARS")]
+    fail_if_max_decrease_above: Option<Threshold>,
+
+    // Leave out drugs whose old per-unit price is below this, e.g. 1.00, since a fraction of a
+    // cent is a large change for very cheap drugs
+    #[arg(long, value_name = "PRICE")]
+    min_old_price: Option<Decimal>,
+
+    // Leave out price changes smaller than this many dollars per unit, in either direction,
+    // e.g. 0.05
+    #[arg(long, value_name = "DOLLARS")]
+    min_change: Option<Decimal>,
+
+    // Leave out price changes smaller than this percent of the old price, in either direction,
+    // e.g. 5
+    #[arg(long = "min-change-pct", value_name = "PERCENT")]
+    min_change_percent: Option<Decimal>,
+
+    // Only report on the NDCs listed in this file, one per line, such as a formulary
+    #[arg(long, value_name = "PATH")]
+    ndc_file: Option<PathBuf>,
+
+    // Only report on the price changes matching an expression, e.g.
+    // "change > 1.50 && classification == 'G' && description =~ 'INSULIN'". Compare change,
+    // abs_change, percent_change, old_price or new_price with numbers, description, ndc,
+    // classification, reason, otc, pricing_unit or explanation_code with quoted text (=~ tests
+    // if the text contains the value), or effective_date with a quoted date, and combine the
+    // comparisons with &&, ||, ! and parentheses
+    #[arg(long = "where", value_name = "EXPRESSION")]
+    where_expression: Option<String>,
+
+    // Number of top per-unit price increases and decreases
+    #[arg(short, long, default_value_t = 10)]
+    count: usize,
+
+    // Number of per-unit price increases to rank, or changes in either direction for
+    // --metric magnitude, instead of --count
+    #[arg(long, value_name = "COUNT")]
+    top_count: Option<usize>,
+
+    // Number of per-unit price decreases to rank instead of --count
+    #[arg(long, value_name = "COUNT")]
+    bottom_count: Option<usize>,
+
+    // Write every price change in the periods, ranked like --metric, instead of the top
+    // --count. The changes are sorted in temporary files, so any number of them fit in memory
+    #[arg(
+        long,
+        conflicts_with_all = ["group_by", "group_by_drug", "per_ndc", "basis", "report", "direction"]
+    )]
+    all: bool,
+
+    // Number of price changes --all holds in memory before sorting them into a temporary file
+    #[arg(
+        long,
+        value_name = "CHANGES",
+        default_value_t = 100_000,
+        requires = "all"
+    )]
+    sort_buffer: usize,
+
+    // Drug price change year to report on (repeatable, to compare years side by side), or
+    // latest for the most recent year the data runs to the end of
     #[arg(
         short,
         long,
-        default_value = NADAC_COMPARISON_URL
+        default_values_t = ["2023".to_string()],
+        conflicts_with_all = ["from", "to", "years"]
     )]
-    url: String,
+    year: Vec<String>,
+
+    // Range of years to compare side by side, e.g. 2021..2023 (both years included)
+    #[arg(long, value_name = "FIRST..LAST", conflicts_with_all = ["from", "to"])]
+    years: Option<String>,
+
+    // Report on price changes effective on or after this date instead of a whole year, e.g.
+    // 2023-03-01
+    #[arg(long, value_name = "DATE")]
+    from: Option<String>,
+
+    // Report on price changes effective on or before this date instead of a whole year, e.g.
+    // 2023-09-30
+    #[arg(long, value_name = "DATE")]
+    to: Option<String>,
+
+    // Split the report into a section for each month or quarter
+    #[arg(long, value_enum)]
+    group_by: Option<GroupBy>,
+
+    // Combine the price changes of the NDCs of each drug, found from the description without
+    // its strength and form, and rank the drugs instead of the NDCs
+    #[arg(long)]
+    group_by_drug: bool,
+
+    // How --group-by-drug combines the price changes of a drug: the change furthest from zero
+    // (max), the average change (mean), the latest change (last) or the sum of the changes (sum)
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Aggregate::Max,
+        requires = "group_by_drug"
+    )]
+    drug_aggregate: Aggregate,
+
+    // Rank one change for each NDC with several in the period: the change furthest from zero
+    // (max), the average change (mean), the latest change (last) or the sum of the changes (sum)
+    #[arg(long, value_enum, conflicts_with = "group_by_drug")]
+    per_ndc: Option<Aggregate>,
+
+    // Rank each price change (change), the net change of each NDC over the period, the sum of
+    // its price changes (net), or the change of each NDC from the old price of its earliest
+    // price change to the new price of its latest (endpoints)
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Basis::Change,
+        conflicts_with_all = ["group_by_drug", "per_ndc"]
+    )]
+    basis: Basis,
+
+    // Report on brand and generic drugs in separate sections, leaving out drugs with any other
+    // classification for rate setting
+    #[arg(long, conflicts_with = "classification")]
+    by_classification: bool,
+
+    // Report on drugs priced per each, per milliliter and per gram in separate sections, since
+    // their per unit prices are not comparable, leaving out drugs without a pricing unit
+    #[arg(long)]
+    by_pricing_unit: bool,
+
+    // What the report ranks: the largest price changes (changes), the drugs with the most
+    // price changes, the most varied first among drugs with as many (volatility), or the drugs
+    // with price changes on the most distinct effective dates (change-counts)
+    #[arg(long, value_enum, default_value_t = ReportKind::Changes)]
+    report: ReportKind,
+
+    // Rank the price changes as separate increases and decreases (change), or together by
+    // their size in either direction (magnitude)
+    #[arg(long, value_enum, default_value_t = Metric::Change)]
+    metric: Metric,
+
+    // Only rank the price increases (increases) or the decreases (decreases), skipping the
+    // work of ranking the other direction, or rank both (both)
+    #[arg(long, value_enum, default_value_t = Direction::Both)]
+    direction: Direction,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    // Manage the download cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
+
+    // Check that the price change data has the expected headers, column counts, dates and
+    // numbers without building a report
+    Validate,
+
+    // Show every price change of a drug in the years being reported on that the row filters,
+    // e.g. --min-change, match, in date order
+    Trend {
+        // The description of the drug, or its NDC
+        drug: String,
+    },
+
+    // List the NDCs in the second input that are not in the first, for two snapshots of the
+    // comparison or weekly NADAC files, showing newly priced products
+    NewDrugs,
+
+    // List the NDCs in the first input that are not in the second, with their last known
+    // prices, for two snapshots of the comparison or weekly NADAC files, showing discontinued
+    // products
+    Discontinued,
+
+    // Write every price increase above a threshold in the years being reported on as CSV,
+    // as the data is read, instead of the largest price changes
+    #[command(group = clap::ArgGroup::new("threshold").required(true).multiple(true))]
+    Alerts {
+        // Alert on increases of more than this percent of the old price
+        #[arg(long, value_name = "PERCENT", group = "threshold")]
+        pct: Option<Decimal>,
+
+        // Alert on increases of more than this many dollars per unit
+        #[arg(long, value_name = "DOLLARS", group = "threshold")]
+        dollars: Option<Decimal>,
+    },
+
+    // Compare two reports saved with --format json, e.g. last month's and this month's, showing
+    // the drugs that entered and left each ranked list, how their ranks moved and how their
+    // changes differ (the lists are matched by their drugs and kind of change, whatever their
+    // periods)
+    Diff {
+        // The earlier report
+        old: PathBuf,
+
+        // The later report
+        new: PathBuf,
+    },
+
+    // Print the JSON Schema of the report written with --format json
+    Schema,
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    // List the cached downloads with their sizes and fetch times
+    List,
+
+    // Remove cached downloads
+    Clear {
+        // Only remove downloads fetched more than this many days ago
+        #[arg(long)]
+        older_than_days: Option<u64>,
+    },
+
+    // Print the location of the download cache
+    Path,
+}
+
+/// Options that control what goes into the report.
+#[derive(Debug, Clone)]
+struct ReportOptions {
+    /// The spans of effective dates of the price changes to report on, in date order. When
+    /// there are several, their sections are shown side by side.
+    periods: Vec<Period>,
+
+    /// When true, the most recent complete year in the data is added to `periods` once the
+    /// data has been read.
+    latest_year: bool,
+
+    /// When set, the report has a section for each month or quarter of the periods.
+    group_by: Option<GroupBy>,
+
+    /// When true, brand and generic drugs are reported in separate sections.
+    by_classification: bool,
+
+    /// When true, the report has a section for each pricing unit.
+    by_pricing_unit: bool,
+
+    /// What the report ranks.
+    report: ReportKind,
+
+    /// How the price changes are ranked.
+    metric: Metric,
+
+    /// Which directions of price change are ranked.
+    direction: Direction,
+
+    /// When set, the price changes of each drug or NDC are combined this way and the combined
+    /// changes are ranked.
+    aggregation: Option<(Grouping, Aggregate)>,
+
+    /// The number of price increases and decreases to report.
+    count: usize,
+
+    /// When set, the number of price increases to report instead of `count`.
+    top_count: Option<usize>,
+
+    /// When set, the number of price decreases to report instead of `count`.
+    bottom_count: Option<usize>,
+
+    /// When true, price changes of zero are not ranked.
+    exclude_zero: bool,
+
+    /// When true, the inputs are weekly NADAC files and the price changes are computed from
+    /// them.
+    weekly: bool,
+
+    /// When true, each drug's NDC is shown after its description.
+    show_ndc: bool,
+
+    /// When true, each row of the text report ends with a bar showing the size of its change.
+    chart: bool,
+
+    /// When set, the ranked lists, and the histogram when there is one, are drawn as bar
+    /// charts in this file.
+    chart_file: Option<PathBuf>,
+
+    /// The language of the headings of the text report.
+    language: Language,
+
+    /// How the report is written.
+    output_format: OutputFormat,
+
+    /// When set, the report is written with this template.
+    template: Option<ReportTemplate>,
+
+    /// When set, the fields of each ranked price change, in order, for the text, CSV and JSON
+    /// output.
+    fields: Option<Vec<ReportField>>,
+
+    /// The number of decimal places of the percent changes.
+    percent_places: u32,
+
+    /// When true, the report has a section with the number of price changes with each
+    /// explanation code.
+    explanation_codes: bool,
+
+    /// When true, the report has a section with the price increases and decreases in each
+    /// calendar month.
+    seasonality: bool,
+
+    /// When true, the report has sections ranking the brands by the change in their gap over
+    /// their corresponding generic.
+    generic_gap: bool,
+
+    /// When true, the report ends with summary statistics of the price changes.
+    summary: bool,
+
+    /// When true, the report ends with a summary of the run that produced it.
+    run_summary: bool,
+
+    /// When true, the report starts with a record of how it was made.
+    provenance: bool,
+
+    /// The arguments of the run, without the program's name, for the record of how the report
+    /// was made.
+    arguments: Vec<String>,
+
+    /// When set, the report ends with this histogram of the price changes, which starts empty.
+    histogram: Option<Histogram>,
+
+    /// How the histogram is written.
+    histogram_format: HistogramFormat,
+
+    /// When set, the report ends with the price changes that are outliers by this rule.
+    outliers: Option<OutlierRule>,
+
+    /// The most outliers listed in each direction.
+    outlier_count: usize,
+
+    /// When set, the prices are adjusted for inflation before they are ranked.
+    cpi: Option<CpiAdjustment>,
+
+    /// When set, the price changes are multiplied by the units reimbursed for their NDC before
+    /// they are ranked.
+    utilization: Option<Utilization>,
+
+    /// When set, the prices are normalized this way before they are ranked.
+    normalization: Option<Normalization>,
+
+    /// When set, each drug's manufacturer from this directory is shown after its description.
+    directory: Option<NdcDirectory>,
+
+    /// When set, the report ends with the manufacturers with the most price increases of at
+    /// least this percent.
+    top_manufacturers: Option<Decimal>,
+
+    /// When set, the report has sections ranking the therapeutic classes in this map by their
+    /// average price change.
+    class_map: Option<ClassMap>,
+
+    /// When true, descriptions that differ only in case or whitespace are treated as the same.
+    normalize_descriptions: bool,
+
+    /// When set, rows repeating one of this many recent rows are skipped.
+    dedup_window: Option<usize>,
+
+    /// When set, what happens to the price changes of the drug it follows is logged.
+    explainer: Option<Explainer>,
+
+    /// The conditions a price change must meet to go into the report.
+    filter: RecordFilter,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        ReportOptions {
+            periods: vec![Period::Year(2023)],
+            latest_year: false,
+            group_by: None,
+            by_classification: false,
+            by_pricing_unit: false,
+            report: ReportKind::Changes,
+            metric: Metric::Change,
+            direction: Direction::Both,
+            aggregation: None,
+            count: 10,
+            top_count: None,
+            bottom_count: None,
+            weekly: false,
+            show_ndc: false,
+            chart: false,
+            chart_file: None,
+            language: Language::En,
+            output_format: OutputFormat::Text,
+            template: None,
+            fields: None,
+            percent_places: DEFAULT_PERCENT_PLACES,
+            explanation_codes: false,
+            seasonality: false,
+            generic_gap: false,
+            exclude_zero: true,
+            summary: false,
+            run_summary: false,
+            provenance: false,
+            arguments: Vec::new(),
+            histogram: None,
+            histogram_format: HistogramFormat::Ascii,
+            outliers: None,
+            outlier_count: 50,
+            cpi: None,
+            utilization: None,
+            normalization: None,
+            directory: None,
+            top_manufacturers: None,
+            class_map: None,
+            normalize_descriptions: false,
+            dedup_window: None,
+            explainer: None,
+            filter: RecordFilter::default(),
+        }
+    }
+}
+
+impl Args {
+    /// Work out which `Input`s the command line arguments refer to. When no URL or file is
+    /// given, or a URL is `latest`, the current NADAC comparison dataset is looked up on
+    /// data.medicaid.gov.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The options used for the lookup.
+    async fn inputs(
+        &self,
+        options: &SourceOptions,
+    ) -> Result<Vec<Input>, Box<dyn std::error::Error>> {
+        if let Some(dataset_id) = &self.api_dataset {
+            return Ok(vec![Input::Api(self.api_query(dataset_id, options).await?)]);
+        }
+
+        Ok(self
+            .data_sources(options)
+            .await?
+            .into_iter()
+            .map(Input::Csv)
+            .collect())
+    }
+
+    /// Add the `--fallback-url` mirrors to the `Input`s.
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - The inputs without the mirrors.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the inputs with the mirrors, on error returns a String describing the
+    /// problem.
+    fn with_fallbacks(&self, inputs: Vec<Input>) -> Result<Vec<Input>, String> {
+        if self.fallback_urls.is_empty() {
+            return Ok(inputs);
+        }
+
+        let primary = match <[Input; 1]>::try_from(inputs) {
+            Ok([Input::Csv(source)]) => source,
+            _ => return Err("--fallback-url can only be used with a single --url".to_string()),
+        };
+
+        let mut sources = vec![primary];
+        for url in &self.fallback_urls {
+            sources.push(DataSource::from_location(url)?);
+        }
+
+        Ok(vec![Input::Mirrored(sources)])
+    }
+
+    /// Build the datastore API query from the command line arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `dataset_id` - The dataset identifier, or `latest`.
+    /// * `options` - The options used to look up the latest dataset.
+    async fn api_query(
+        &self,
+        dataset_id: &str,
+        options: &SourceOptions,
+    ) -> Result<ApiQuery, Box<dyn std::error::Error>> {
+        if self.api_page_size == 0 {
+            return Err("--api-page-size must be greater than 0".into());
+        }
+
+        let conditions = self
+            .api_conditions
+            .iter()
+            .map(|condition| ApiCondition::parse(condition))
+            .collect::<Result<Vec<ApiCondition>, String>>()?;
+
+        let dataset_id = match dataset_id {
+            "latest" if options.offline => {
+                return Err("--api-dataset cannot be used with --offline".into());
+            }
+            "latest" => {
+                let client = options.http.client()?;
+                latest_comparison_dataset_id(&client, &options.http).await?
+            }
+            id => id.to_string(),
+        };
+
+        Ok(ApiQuery {
+            dataset_id,
+            conditions,
+            page_size: self.api_page_size,
+        })
+    }
+
+    /// Work out which `DataSource`s the command line arguments refer to. The URLs come first,
+    /// followed by the input files.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The options used for the lookup.
+    async fn data_sources(
+        &self,
+        options: &SourceOptions,
+    ) -> Result<Vec<DataSource>, Box<dyn std::error::Error>> {
+        if self.stdin {
+            return Ok(vec![DataSource::Stdin]);
+        }
+
+        if self.urls.is_empty() && self.input_files.is_empty() {
+            // Without the network, fall back to the built in snapshot so a cached copy of it
+            // can still be used.
+            if options.offline {
+                return Ok(vec![DataSource::Url(NADAC_COMPARISON_URL.to_string())]);
+            }
+
+            return Ok(vec![self.url_source("latest", options).await?]);
+        }
+
+        let mut sources = Vec::new();
+        for url in &self.urls {
+            sources.push(self.url_source(url, options).await?);
+        }
+
+        sources.extend(self.input_files.iter().cloned().map(DataSource::File));
+
+        if sources
+            .iter()
+            .filter(|source| matches!(source, DataSource::Stdin))
+            .count()
+            > 1
+        {
+            return Err("stdin can only be read once".into());
+        }
+
+        Ok(sources)
+    }
+
+    /// Work out which `DataSource` a `--url` refers to.
+    ///
+    /// # Arguments
+    ///
+    /// * `location` - The URL given on the command line.
+    /// * `options` - The options used for the lookup.
+    async fn url_source(
+        &self,
+        location: &str,
+        options: &SourceOptions,
+    ) -> Result<DataSource, Box<dyn std::error::Error>> {
+        match location {
+            "latest" if options.offline => {
+                Err("--url latest needs the network and cannot be used with --offline".into())
+            }
+            "latest" => {
+                let client = options.http.client()?;
+                let url = latest_comparison_url(&client, &options.http).await?;
+                Ok(DataSource::Url(url))
+            }
+            location => Ok(DataSource::from_location(location)?),
+        }
+    }
+
+    /// Collect the options that control how the `DataSource` is opened.
+    fn source_options(&self) -> Result<SourceOptions, String> {
+        let mut http = HttpOptions {
+            retries: self.retries,
+            retry_backoff: Duration::from_millis(self.retry_backoff_ms),
+            connect_timeout: seconds(self.connect_timeout),
+            read_timeout: seconds(self.read_timeout),
+            ca_certificates: self.ca_certs.clone(),
+            insecure: self.insecure,
+            ..Default::default()
+        };
+
+        if self.insecure {
+            eprintln!("Warning: --insecure is set, TLS certificates will not be verified");
+        }
+
+        if let Some(user_agent) = &self.user_agent {
+            http.user_agent = user_agent.clone();
+        }
+
+        if let Some(rate) = &self.max_download_rate {
+            http.max_download_rate = Some(parse_rate(rate)?);
+        }
+
+        for header in &self.headers {
+            http.add_header(header)?;
+        }
+
+        if let Some(token) = &self.bearer_token {
+            http.set_bearer_token(token)?;
+        }
+
+        let cache = if self.cache || self.cache_dir.is_some() || self.offline {
+            Some(self.download_cache())
+        } else {
+            None
+        };
+
+        let columns = if self.columns.is_empty() {
+            None
+        } else {
+            Some(ColumnMap::from_mappings(&self.columns)?)
+        };
+
+        Ok(SourceOptions {
+            archive_member: self.archive_member.clone(),
+            http,
+            cache,
+            offline: self.offline,
+            columns,
+            encoding: self.encoding.as_deref().map(parse_encoding).transpose()?,
+            delimiter: self
+                .delimiter
+                .as_deref()
+                .map(parse_delimiter)
+                .transpose()?
+                .unwrap_or_default(),
+            no_header: self.no_header,
+            sampling: Sampling::new(self.max_rows, self.sample)?,
+            counters: Default::default(),
+        })
+    }
+
+    /// Collect the thresholds of the largest price changes that fail the run.
+    fn exit_thresholds(&self) -> ExitThresholds {
+        ExitThresholds {
+            max_increase: self.fail_if_max_increase_above,
+            max_decrease: self.fail_if_max_decrease_above,
+        }
+    }
+
+    /// Collect the options that control what goes into the report.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `ReportOptions`, on error returns a String describing the
+    /// problem.
+    fn report_options(&self) -> Result<ReportOptions, String> {
+        // The API's NADAC comparison dataset only has the columns of the comparison file.
+        if self.api_dataset.is_some()
+            && (self.otc != OtcFilter::Include
+                || self.pricing_unit.is_some()
+                || self.by_pricing_unit
+                || self.explanation_code.is_some()
+                || self.explanation_codes
+                || self.generic_gap)
+        {
+            return Err(
+                "--api-dataset records have no over-the-counter indicator, pricing unit, \
+                explanation codes or generic prices, so it cannot be used with --otc, \
+                --pricing-unit, --by-pricing-unit, --explanation-code, --explanation-codes or \
+                --generic-gap"
+                    .to_string(),
+            );
+        }
+        let has_column = |name: &str| {
+            self.weekly || ColumnMap::from_mappings(&self.columns).is_ok_and(|map| map.has(name))
+        };
+        if self.otc != OtcFilter::Include && !has_column("otc") {
+            return Err(
+                "--otc needs data with an over-the-counter indicator, use --weekly or \
+                map the indicator with --column otc=INDEX"
+                    .to_string(),
+            );
+        }
+        if (self.pricing_unit.is_some() || self.by_pricing_unit) && !has_column("pricing_unit") {
+            return Err(
+                "--pricing-unit and --by-pricing-unit need data with a pricing unit, use \
+                --weekly or map the pricing unit with --column pricing_unit=INDEX"
+                    .to_string(),
+            );
+        }
+        if (self.explanation_code.is_some() || self.explanation_codes)
+            && !has_column("explanation_code")
+        {
+            return Err(
+                "--explanation-code and --explanation-codes need data with explanation codes, \
+                use --weekly or map the codes with --column explanation_code=INDEX"
+                    .to_string(),
+            );
+        }
+
+        if self.generic_gap && !(has_column("old_generic_price") && has_column("new_generic_price"))
+        {
+            return Err(
+                "--generic-gap needs data with the prices of the corresponding generics, use \
+                --weekly or map them with --column old_generic_price=INDEX and \
+                --column new_generic_price=INDEX"
+                    .to_string(),
+            );
+        }
+
+        if self.append
+            && matches!(
+                self.format,
+                OutputFormat::Xlsx
+                    | OutputFormat::Parquet
+                    | OutputFormat::Arrow
+                    | OutputFormat::Pdf
+                    | OutputFormat::Json
+                    | OutputFormat::Csv
+            )
+        {
+            // A second JSON document or CSV header after the first leaves a file that no
+            // longer parses.
+            return Err(format!(
+                "--append adds text to the end of a file, a {} file cannot be appended to",
+                self.format.name()
+            ));
+        }
+
+        if self.metric == Metric::Magnitude && self.direction != Direction::Both {
+            return Err(
+                "--direction only applies to --metric change, magnitude ranks both directions \
+                together"
+                    .to_string(),
+            );
+        }
+
+        let columnar = matches!(self.format, OutputFormat::Parquet | OutputFormat::Arrow);
+        let lists_only = matches!(
+            self.format,
+            OutputFormat::Xlsx | OutputFormat::Csv | OutputFormat::Json
+        );
+        if (columnar || lists_only) && self.report != ReportKind::Changes {
+            return Err(format!(
+                "--format {} writes the price change lists, it cannot be used with --report",
+                self.format.name()
+            ));
+        }
+        if self.all
+            && !columnar
+            && !matches!(self.format, OutputFormat::Text | OutputFormat::Legacy)
+        {
+            return Err(format!(
+                "--all writes text, Parquet or Arrow, not --format {}",
+                self.format.name()
+            ));
+        }
+        if self.fields.is_some()
+            && (self.all
+                || self.report != ReportKind::Changes
+                || !matches!(
+                    self.format,
+                    OutputFormat::Text | OutputFormat::Csv | OutputFormat::Json
+                ))
+        {
+            return Err(
+                "--fields chooses the fields of the ranked price changes in the text, CSV and \
+                JSON output, it cannot be used with --all, --report or other formats"
+                    .to_string(),
+            );
+        }
+        if self.template.is_some()
+            && (self.all || self.format != OutputFormat::Text || self.report != ReportKind::Changes)
+        {
+            return Err(
+                "--template writes the text report of the price change lists, it cannot be used \
+                with --all, --format or --report"
+                    .to_string(),
+            );
+        }
+
+        if self.chart
+            && (self.all
+                || self.format != OutputFormat::Text
+                || self.report != ReportKind::Changes
+                || self.template.is_some())
+        {
+            return Err(
+                "--chart draws bars in the text report of the price change lists, it cannot be \
+                used with --all, --format, --report or --template"
+                    .to_string(),
+            );
+        }
+
+        let thresholds = self.exit_thresholds();
+        if thresholds != ExitThresholds::default()
+            && (self.all || self.command.is_some() || self.report != ReportKind::Changes)
+        {
+            return Err(
+                "--fail-if-max-increase-above and --fail-if-max-decrease-above check the price \
+                change report, they cannot be used with --all, --report or subcommands"
+                    .to_string(),
+            );
+        }
+
+        if self.lang != Language::En
+            && (self.all
+                || !matches!(self.format, OutputFormat::Text | OutputFormat::Legacy)
+                || self.report != ReportKind::Changes
+                || self.template.is_some())
+        {
+            return Err(
+                "--lang translates the headings of the text report of the price change lists, it \
+                cannot be used with --all, --format, --report or --template"
+                    .to_string(),
+            );
+        }
+
+        if self.chart_file.is_some() && (self.all || self.report != ReportKind::Changes) {
+            return Err(
+                "--chart-file draws the price change lists, it cannot be used with --all or \
+                --report"
+                    .to_string(),
+            );
+        }
+
+        if self.run_summary
+            && (self.all
+                || !matches!(
+                    self.format,
+                    OutputFormat::Text
+                        | OutputFormat::Legacy
+                        | OutputFormat::Markdown
+                        | OutputFormat::Html
+                        | OutputFormat::Pdf
+                ))
+        {
+            return Err(format!(
+                "--run-summary ends a text report, it cannot be used with --all or --format {}",
+                self.format.name()
+            ));
+        }
+
+        if self.provenance
+            && (self.all || self.command.is_some() || self.format == OutputFormat::Csv)
+        {
+            return Err(
+                "--provenance records how the price change report was made, it cannot be used \
+                with --all, --format csv or subcommands"
+                    .to_string(),
+            );
+        }
+
+        if self.percent_places > MAX_PERCENT_PLACES {
+            return Err(format!(
+                "--percent-places must be at most {}",
+                MAX_PERCENT_PLACES
+            ));
+        }
+
+        let histogram = if self.histogram {
+            let edges = if self.histogram_buckets.is_empty() {
+                default_edges()
+            } else {
+                self.histogram_buckets.clone()
+            };
+            Some(Histogram::new(edges)?)
+        } else {
+            None
+        };
+
+        let outliers = match (self.outlier_z, self.outlier_iqr) {
+            (Some(z), _) => Some(OutlierRule::ZScore(z)),
+            (None, Some(k)) => Some(OutlierRule::Iqr(k)),
+            (None, None) => None,
+        };
+        if outliers.is_some_and(|rule| match rule {
+            OutlierRule::ZScore(threshold) | OutlierRule::Iqr(threshold) => {
+                threshold <= Decimal::ZERO
+            }
+        }) {
+            return Err("--outlier-z and --outlier-iqr must be greater than 0".to_string());
+        }
+        if self.outlier_count == 0 {
+            return Err("--outlier-count must be greater than 0".to_string());
+        }
+
+        let cpi = match self.adjust_cpi.as_deref() {
+            Some("builtin") => Some(CpiAdjustment::new("builtin", self.cpi_base_year)?),
+            Some(path) => {
+                let series = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Unable to read CPI file {}: {}", path, e))?;
+                Some(CpiAdjustment::new(&series, self.cpi_base_year)?)
+            }
+            None => None,
+        };
+
+        let ndcs = match &self.ndc_file {
+            Some(path) => Some(parse_ndc_list(&std::fs::read_to_string(path).map_err(
+                |e| format!("Unable to read NDC file {}: {}", path.display(), e),
+            )?)),
+            None => None,
+        };
+
+        let expression = self
+            .where_expression
+            .as_deref()
+            .map(Expression::parse)
+            .transpose()
+            .map_err(|e| format!("Invalid --where expression: {}", e))?;
+
+        let template =
+            match &self.template {
+                Some(path) => Some(
+                    ReportTemplate::parse(&std::fs::read_to_string(path).map_err(|e| {
+                        format!("Unable to read template {}: {}", path.display(), e)
+                    })?)
+                    .map_err(|e| format!("Invalid template {}: {}", path.display(), e))?,
+                ),
+                None => None,
+            };
+
+        let class_map = match &self.class_map {
+            Some(path) => Some(ClassMap::parse(&std::fs::read_to_string(path).map_err(
+                |e| format!("Unable to read class map {}: {}", path.display(), e),
+            )?)?),
+            None => None,
+        };
+
+        let mut latest_year = false;
+        let periods = if self.from.is_some() || self.to.is_some() {
+            let bound = |text: &Option<String>, flag: &str| {
+                text.as_deref()
+                    .map(|text| parse_date(text).map_err(|e| format!("Invalid {}: {}", flag, e)))
+                    .transpose()
+            };
+            let (from, to) = (bound(&self.from, "--from")?, bound(&self.to, "--to")?);
+            if from.zip(to).is_some_and(|(from, to)| from > to) {
+                return Err("--from must not be after --to".to_string());
+            }
+            vec![Period::Range { from, to }]
+        } else {
+            let mut years = match &self.years {
+                Some(years) => parse_years(years)?,
+                None => {
+                    let mut years = Vec::new();
+                    for year in &self.year {
+                        if year.eq_ignore_ascii_case("latest") {
+                            latest_year = true;
+                        } else {
+                            years.push(year.trim().parse().map_err(|_| {
+                                format!("Expected a year or latest for --year but found {}", year)
+                            })?);
+                        }
+                    }
+                    years
+                }
+            };
+            years.sort();
+            years.dedup();
+            years.into_iter().map(Period::Year).collect()
+        };
+
+        Ok(ReportOptions {
+            periods,
+            latest_year,
+            group_by: self.group_by,
+            by_classification: self.by_classification,
+            by_pricing_unit: self.by_pricing_unit,
+            report: self.report,
+            metric: self.metric,
+            direction: self.direction,
+            aggregation: match self.per_ndc {
+                Some(aggregate) => Some((Grouping::Ndc, aggregate)),
+                None if self.group_by_drug => Some((Grouping::Drug, self.drug_aggregate)),
+                None => self.basis.aggregation(),
+            },
+            count: self.count,
+            top_count: self.top_count,
+            bottom_count: self.bottom_count,
+            weekly: self.weekly,
+            show_ndc: self.show_ndc,
+            chart: self.chart,
+            chart_file: self.chart_file.clone(),
+            language: self.lang,
+            output_format: self.format,
+            template,
+            fields: self.fields.clone(),
+            percent_places: self.percent_places,
+            explanation_codes: self.explanation_codes,
+            seasonality: self.seasonality,
+            generic_gap: self.generic_gap,
+            // The flags override each other, so only the last one given is set.
+            exclude_zero: self.exclude_zero || !self.no_exclude_zero,
+            summary: self.summary,
+            run_summary: self.run_summary,
+            provenance: self.provenance,
+            arguments: Vec::new(),
+            histogram,
+            histogram_format: self.histogram_format,
+            outliers,
+            outlier_count: self.outlier_count,
+            cpi,
+            utilization: None,
+            normalization: self.normalize,
+            directory: None,
+            top_manufacturers: self.top_manufacturers,
+            class_map,
+            normalize_descriptions: self.normalize_descriptions,
+            dedup_window: self.dedup.then_some(self.dedup_window),
+            explainer: self
+                .explain
+                .as_deref()
+                .map(|target| Explainer::new(target, Rc::new(RefCell::new(std::io::stderr()))))
+                .transpose()?,
+            filter: RecordFilter {
+                classification: self.classification,
+                otc: self.otc,
+                pricing_unit: self.pricing_unit,
+                explanation_code: self.explanation_code.clone(),
+                min_old_price: self.min_old_price,
+                min_change: self.min_change,
+                min_change_percent: self.min_change_percent,
+                ndcs,
+                expression,
+            },
+        })
+    }
+
+    /// Return the download cache in the directory given on the command line, or the default
+    /// directory.
+    fn download_cache(&self) -> DownloadCache {
+        DownloadCache::new(self.cache_dir.clone().unwrap_or_else(default_cache_dir))
+    }
+}
+
+/// Parse a range of years given on the command line.
+///
+/// # Arguments
+///
+/// * `text` - The range, as `FIRST..LAST` with both years included.
+///
+/// # Returns
+///
+/// On success, returns the years in the range, on error returns a String describing the
+/// problem.
+fn parse_years(text: &str) -> Result<Vec<i32>, String> {
+    let invalid = || {
+        format!(
+            "Expected a range of years like 2021..2023 but found {}",
+            text
+        )
+    };
+    let (first, last) = text.split_once("..").ok_or_else(invalid)?;
+    let first: i32 = first.trim().parse().map_err(|_| invalid())?;
+    let last: i32 = last.trim().parse().map_err(|_| invalid())?;
+    if first > last {
+        return Err(invalid());
+    }
+    Ok((first..=last).collect())
+}
+
+/// Convert a number of seconds from the command line into a timeout, where 0 means no timeout.
+fn seconds(seconds: u64) -> Option<Duration> {
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+/// Generate the report of the largest price changes. Each input is read once, however many
+/// years or other periods the report covers: every record is routed to the `DataStore` of its
+/// section as it streams past. With `--year latest`, the rows that may be in the latest
+/// complete year are held until every input has been read and the year is known.
+///
+/// # Arguments
+///
+/// * `inputs` - The inputs to read.
+/// * `options` - The options used to open the inputs.
+/// * `report_options` - What goes into the report.
+/// * `row_errors` - The skipped records.
+/// * `extremes` - The largest price changes of the report, updated as they are added.
+/// * `out` - Where the workbooks, Parquet and Arrow files and PDFs are written.
+///
+/// # Returns
+///
+/// On success, returns the report, which is empty when it is written to `out`, on error
+/// returns a std::error::Error in a Box.
+async fn generate_nadac_top_price_change_report(
+    inputs: &[Input],
+    options: &SourceOptions,
+    report_options: &ReportOptions,
+    row_errors: &mut RowErrors,
+    extremes: &mut PriceExtremes,
+    out: &mut dyn std::io::Write,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let count = report_options.count;
+    let mut data_stores = SectionDataStores::new(
+        report_options.top_count.unwrap_or(count),
+        report_options.bottom_count.unwrap_or(count),
+        report_options.normalize_descriptions,
+        report_options.metric,
+        report_options.direction,
+        report_options.explainer.clone(),
+        report_options.aggregation,
+    );
+
+    // Repeated rows are looked for across all of the inputs, since snapshots may overlap.
+    let mut dedup = report_options.dedup_window.map(RowDeduplicator::new);
+
+    // A workbook always has a sheet of summary statistics.
+    let mut statistics = (report_options.summary
+        || report_options.output_format == OutputFormat::Xlsx)
+        .then(Statistics::default);
+    let mut histogram = report_options.histogram.clone();
+    let mut outliers = report_options
+        .outliers
+        .map(|rule| Outliers::new(rule, report_options.outlier_count))
+        .transpose()?;
+    let mut activity = (report_options.report != ReportKind::Changes).then(Activity::default);
+    let mut large_increases = report_options.top_manufacturers.map(LargeIncreases::new);
+    let mut generic_gaps = report_options.generic_gap.then(GenericGaps::default);
+    let mut explanation_codes = report_options
+        .explanation_codes
+        .then(ExplanationCodes::default);
+    let mut seasonality = report_options.seasonality.then(Seasonality::default);
+    let mut class_rollup = report_options
+        .class_map
+        .as_ref()
+        .map(|class_map| (ClassRollup::default(), class_map));
+    let mut on_added =
+        |section: Section, row: &ComparisonRow| -> Result<(), Box<dyn std::error::Error>> {
+            let difference = row.new_price - row.old_price;
+            extremes.add(row);
+            if let Some(activity) = &mut activity {
+                activity.add(section, row);
+            }
+            if let Some(statistics) = &mut statistics {
+                statistics.add(difference);
+            }
+            if let Some(histogram) = &mut histogram {
+                histogram.add(difference);
+            }
+            if let Some(outliers) = &mut outliers {
+                outliers.add(row)?;
+            }
+            if let (Some(large_increases), Some(directory)) =
+                (&mut large_increases, &report_options.directory)
+            {
+                large_increases.add(row, directory);
+            }
+            if let Some(explanation_codes) = &mut explanation_codes {
+                explanation_codes.add(row);
+            }
+            if let Some(seasonality) = &mut seasonality {
+                seasonality.add(row);
+            }
+            if let Some(generic_gaps) = &mut generic_gaps {
+                generic_gaps.add(section, row);
+            }
+            if let Some((class_rollup, class_map)) = &mut class_rollup {
+                class_rollup.add(section, row, class_map);
+            }
+            Ok(())
+        };
+
+    // Mirrored inputs may be read from any of their mirrors, so the report notes which ones
+    // were used.
+    let mut mirrors_used = Vec::new();
+
+    // Price changes in weekly files can span files, so the prices from all of them are
+    // collected before the changes are computed.
+    let mut weekly_prices = WeeklyPrices::default();
+
+    // The report's provenance records every input, hashes the rows read from them and notes
+    // when the first was opened.
+    let mut sources = Vec::new();
+    let dataset_hash = DatasetHash::default();
+    let mut fetched = None;
+
+    // With --year latest, the year is only known once every row has been read.
+    let mut latest_year = report_options.latest_year.then(|| {
+        let field = if report_options.weekly {
+            WEEKLY_EFFECTIVE_DATE_FIELD
+        } else {
+            EFFECTIVE_DATE_FIELD
+        };
+        LatestYear::new(field)
+    });
+
+    // The inputs are read one after the other into the same data store, so the report covers
+    // all of them.
+    for input in inputs {
+        fetched.get_or_insert_with(Local::now);
+        let mut opened = input.records(options).await?;
+        if report_options.provenance {
+            sources.push(opened.source.clone());
+            opened.records = dataset_hash.wrap(opened.records);
+        }
+        if report_options.weekly {
+            if let Some(latest_year) = &latest_year {
+                opened.records = latest_year.track(opened.records);
+            }
+            weekly_prices
+                .add_records(&mut opened.records, &opened.source, row_errors)
+                .await?;
+        } else if let Some(latest_year) = &mut latest_year {
+            latest_year
+                .spool(&mut opened.records, &opened.source)
+                .await?;
+        } else {
+            add_records(
+                &mut data_stores,
+                &mut opened.records,
+                &opened.source,
+                report_options,
+                &mut dedup,
+                &mut on_added,
+                row_errors,
+            )
+            .await?;
+        }
+
+        if matches!(input, Input::Mirrored(_)) {
+            mirrors_used.push(opened.source);
+        }
+    }
+
+    // The report covers the latest complete year along with any others asked for.
+    let resolved;
+    let mut spooled = Vec::new();
+    let report_options = match latest_year {
+        Some(latest_year) => {
+            let year = latest_year.year()?;
+            let mut periods = report_options.periods.clone();
+            periods.push(Period::Year(year));
+            periods.sort();
+            periods.dedup();
+            resolved = ReportOptions {
+                periods,
+                ..report_options.clone()
+            };
+            spooled = latest_year.into_records()?;
+            &resolved
+        }
+        None => report_options,
+    };
+
+    // A report on whole periods has their sections even when there are no price changes.
+    if report_options.group_by.is_none() {
+        let classifications = if report_options.by_classification {
+            vec![Some(Classification::Brand), Some(Classification::Generic)]
+        } else {
+            vec![None]
+        };
+        let pricing_units = if report_options.by_pricing_unit {
+            vec![
+                Some(PricingUnit::Each),
+                Some(PricingUnit::Milliliter),
+                Some(PricingUnit::Gram),
+            ]
+        } else {
+            vec![None]
+        };
+        for period in &report_options.periods {
+            for classification in &classifications {
+                for pricing_unit in &pricing_units {
+                    data_stores.get_mut(Section {
+                        period: *period,
+                        classification: *classification,
+                        pricing_unit: *pricing_unit,
+                    })?;
+                }
+            }
+        }
+    }
+
+    for (source, mut records) in spooled {
+        add_records(
+            &mut data_stores,
+            &mut records,
+            &source,
+            report_options,
+            &mut dedup,
+            &mut on_added,
+            row_errors,
+        )
+        .await?;
+    }
+
+    if report_options.weekly {
+        let mut changes = weekly_prices.into_changes();
+        add_records(
+            &mut data_stores,
+            &mut changes,
+            "weekly price changes",
+            report_options,
+            &mut dedup,
+            &mut on_added,
+            row_errors,
+        )
+        .await?;
+    }
+
+    data_stores.finish()?;
+
+    if let Some(dedup) = dedup.filter(|dedup| dedup.duplicates() > 0) {
+        eprintln!("Skipped {} duplicate row(s)", dedup.duplicates());
+    }
+
+    let provenance = report_options.provenance.then(|| {
+        Provenance::new(
+            sources,
+            &dataset_hash,
+            fetched.unwrap_or_else(Local::now),
+            &report_options.arguments,
+        )
+    });
+
+    // JSON, Parquet and Arrow have a field for the provenance, the other formats start their
+    // notes with it.
+    let mut notes = Vec::new();
+    if let Some(provenance) = &provenance {
+        if report_options.output_format != OutputFormat::Json {
+            notes.extend(provenance.lines());
+        }
+    }
+    if let Some(note) = options.sampling.note() {
+        notes.push(note);
+    }
+    for source in mirrors_used {
+        notes.push(format!("Data source: {}", source));
+    }
+    if let Some(cpi) = &report_options.cpi {
+        notes.push(format!(
+            "Prices in {} dollars, adjusted for inflation with the CPI",
+            cpi.base_year
+        ));
+    }
+    if report_options.normalization == Some(Normalization::PerMg) {
+        notes.push(
+            "Prices per milligram of drug, from the strengths in the descriptions".to_string(),
+        );
+    }
+    if report_options.utilization.is_some() {
+        notes.push(
+            "Price changes multiplied by the Medicaid units reimbursed, estimating their spend \
+            impact"
+                .to_string(),
+        );
+    }
+
+    let format = RecordFormat {
+        show_ndc: report_options.show_ndc,
+        directory: report_options.directory.as_ref(),
+        fields: report_options.fields.as_deref(),
+        legacy: report_options.output_format == OutputFormat::Legacy,
+        percent_places: report_options.percent_places,
+        chart: report_options.chart,
+        language: report_options.language,
+    };
+
+    // Workbooks and Parquet and Arrow files are not text, so they are written to `out` and the
+    // report is left empty.
+    let output_format = report_options.output_format;
+    let sections: Vec<(&Section, &DataStore)> = data_stores.iter().collect();
+    if let Some(path) = &report_options.chart_file {
+        write_chart(path, &chart_panels(&sections, &format, histogram.as_ref()))?;
+    }
+    let file = match output_format {
+        OutputFormat::Xlsx => Some(xlsx_report(
+            &sections,
+            &format,
+            &statistics.clone().unwrap_or_default(),
+            &notes,
+        )?),
+        OutputFormat::Parquet | OutputFormat::Arrow => Some(columnar_report(
+            output_format,
+            &sections,
+            report_options.percent_places,
+            provenance.as_ref(),
+        )?),
+        _ => None,
+    };
+    if let Some(file) = file {
+        out.write_all(&file)?;
+        return Ok(String::new());
+    }
+
+    // CSV and JSON hold only the ranked lists.
+    let fields = report_options.fields.as_deref().unwrap_or(&ALL_FIELDS);
+    match output_format {
+        OutputFormat::Csv => {
+            return Ok(csv_report(&sections, fields, report_options.percent_places))
+        }
+        OutputFormat::Json => {
+            return json_report(
+                &sections,
+                fields,
+                report_options.percent_places,
+                &notes,
+                provenance.as_ref(),
+            )
+        }
+        _ => {}
+    }
+
+    // A PDF is laid out from the Markdown report.
+    let output_format = match output_format {
+        OutputFormat::Pdf => OutputFormat::Markdown,
+        output_format => output_format,
+    };
+
+    let mut report = String::new();
+    match output_format {
+        OutputFormat::Markdown => {
+            report.push_str(MARKDOWN_TITLE);
+            for note in &notes {
+                report.push_str(&format!("\n{}\n", note));
+            }
+            report.push('\n');
+        }
+        OutputFormat::Html => {
+            report.push_str(HTML_HEADER);
+            for note in &notes {
+                report.push_str(&format!("<p>{}</p>\n", html::escape(note)));
+            }
+        }
+        _ => {
+            if !notes.is_empty() {
+                report.push_str(&format!("{}\n\n", notes.join("\n")));
+            }
+        }
+    }
+
+    if let Some(activity) = &activity {
+        let sections: Vec<String> = data_stores
+            .iter()
+            .map(|(section, _)| match report_options.report {
+                ReportKind::ChangeCounts => activity.change_count_report(section, count, &format),
+                ReportKind::Changes | ReportKind::Volatility => {
+                    activity.volatility_report(section, count, &format)
+                }
+            })
+            .map(|section| format_section(output_format, section))
+            .collect();
+        report.push_str(&sections.join("\n"));
+    } else if matches!(output_format, OutputFormat::Markdown | OutputFormat::Html) {
+        // Tables are not laid out side by side, so each section has its own.
+        let lists = if output_format == OutputFormat::Html {
+            html_lists
+        } else {
+            markdown_lists
+        };
+        let sections: Vec<String> = data_stores
+            .iter()
+            .map(|(section, data_store)| lists(data_store, section, &format))
+            .collect();
+        report.push_str(&sections.join("\n"));
+    } else if report_options.group_by.is_none() && report_options.periods.len() > 1 {
+        report.push_str(&generate_side_by_side_report(&sections, &format));
+    } else {
+        let sections: Vec<String> = data_stores
+            .iter()
+            .map(|(section, data_store)| generate_report(data_store, section, &format))
+            .collect();
+        report.push_str(&sections.join("\n"));
+    }
+
+    // The sections that follow the ranked lists.
+    let mut extras = Vec::new();
+    if let Some((class_rollup, _)) = class_rollup {
+        for (section, _) in data_stores.iter() {
+            extras.push(class_rollup.report(section, count));
+        }
+    }
+    if let Some(generic_gaps) = generic_gaps {
+        for (section, _) in data_stores.iter() {
+            extras.push(generic_gaps.report(section, count, &format));
+        }
+    }
+    if let Some(explanation_codes) = explanation_codes {
+        extras.push(explanation_codes.report());
+    }
+    if let Some(seasonality) = seasonality {
+        extras.push(seasonality.report());
+    }
+    if let Some(statistics) = statistics {
+        extras.push(statistics.report());
+    }
+    if let Some(histogram) = histogram {
+        extras.push(histogram.report(report_options.histogram_format));
+    }
+    if let Some(outliers) = outliers {
+        extras.push(outliers.report(&format));
+    }
+    if let Some(large_increases) = large_increases {
+        extras.push(large_increases.report(count));
+    }
+    if report_options.run_summary {
+        let mut left_out: Vec<(String, u64)> = row_errors
+            .left_out()
+            .into_iter()
+            .map(|(reason, count)| (reason, count as u64))
+            .collect();
+        let not_sampled = options.counters.rows_not_sampled();
+        if not_sampled > 0 {
+            left_out.push(("not in the --sample".to_string(), not_sampled));
+        }
+        let run_summary = RunSummary {
+            rows_read: options.counters.rows_read(),
+            skipped: row_errors.by_reason(),
+            left_out,
+            inserted: data_stores.inserted(),
+            interned: data_stores.interned(),
+            elapsed: options.counters.elapsed(),
+            bytes_downloaded: options.counters.bytes_downloaded(),
+        };
+        extras.push(run_summary.report());
+    }
+    if let Some(template) = &report_options.template {
+        return Ok(template.render(&sections, &format, &notes, &extras)?);
+    }
+    for extra in extras {
+        report.push('\n');
+        report.push_str(&format_section(output_format, extra));
+    }
+    if output_format == OutputFormat::Html {
+        report.push_str(HTML_FOOTER);
+    }
+    if report_options.output_format == OutputFormat::Pdf {
+        let pdf = pdf_report(&report, Local::now().naive_local());
+        out.write_all(&pdf)?;
+        return Ok(String::new());
+    }
+    Ok(report)
+}
+
+/// Write a section of the plain text report in the report's output format.
+///
+/// # Arguments
+///
+/// * `output_format` - How the report is written.
+/// * `text` - The plain text section.
+///
+/// # Returns
+///
+/// A new String containing the section.
+fn format_section(output_format: OutputFormat, text: String) -> String {
+    match output_format {
+        OutputFormat::Markdown => markdown_section(&text),
+        OutputFormat::Html => html_section(&text),
+        _ => text,
+    }
+}
+
+/// A function called with each record added to the report.
+type OnAdded<'a> =
+    dyn FnMut(Section, &ComparisonRow) -> Result<(), Box<dyn std::error::Error>> + 'a;
+
+/// Add the records for the report's periods from a `RecordStream` to the `DataStore` for their
+/// section of the report. Records with missing or invalid data are skipped and recorded in
+/// `row_errors`.
+///
+/// # Arguments
+///
+/// * `data_stores` - The data stores to add the records to.
+/// * `records` - The records to read.
+/// * `source` - Where the records come from.
+/// * `report_options` - The periods, sections and conditions of the records to add.
+/// * `dedup` - When set, records repeating an earlier record are skipped.
+/// * `on_added` - Called with each record added and its section.
+/// * `row_errors` - The skipped records.
+///
+/// # Returns
+///
+/// On success, returns (), on error returns a std::error::Error in a Box.
+async fn add_records(
+    data_stores: &mut SectionDataStores,
+    records: &mut RecordStream<'_>,
+    source: &str,
+    report_options: &ReportOptions,
+    dedup: &mut Option<RowDeduplicator>,
+    on_added: &mut OnAdded<'_>,
+    row_errors: &mut RowErrors,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let explain = |row: &ComparisonRow, decision: &str| {
+        if let Some(explainer) = &report_options.explainer {
+            explainer.log_row(row, decision);
+        }
+    };
+
+    while let Some(record) = records.next().await {
+        let record = record?;
+
+        let mut row = match ComparisonRow::from_record(&record) {
+            Ok(row) => row,
+            Err(FieldError { field, reason }) => {
+                row_errors.add(source, &record, field, &reason);
+                continue;
+            }
+        };
+
+        // Rows without an effective date cannot be placed in a period, so they are skipped.
+        let effective_date = match row.effective_date {
+            Some(effective_date) => effective_date,
+            None => {
+                row_errors.add(
+                    source,
+                    &record,
+                    EFFECTIVE_DATE_FIELD,
+                    "missing effective date",
+                );
+                explain(&row, "skipped for its missing effective date");
+                continue;
+            }
+        };
+
+        let Some(period) = report_options
+            .periods
+            .iter()
+            .find(|period| period.contains(effective_date))
+        else {
+            row_errors.leave_out("outside the report's periods");
+            explain(
+                &row,
+                &format!(
+                    "effective {} is outside the report's periods",
+                    effective_date
+                ),
+            );
+            continue;
+        };
+
+        // Prices are adjusted before the filters and pools see them, so both work in the
+        // dollars of the base year.
+        if let Some(cpi) = &report_options.cpi {
+            if let Err(reason) = cpi.adjust(&mut row, effective_date) {
+                row_errors.add(source, &record, EFFECTIVE_DATE_FIELD, &reason);
+                explain(&row, &format!("skipped, {}", reason));
+                continue;
+            }
+        }
+
+        if let Some(rejection) = report_options.filter.rejection(&row) {
+            row_errors.leave_out(rejection);
+            explain(&row, &format!("filtered out: {}", rejection));
+            continue;
+        }
+
+        // The prices are normalized and weighed after the filters, which work on per unit
+        // prices.
+        if report_options.normalization == Some(Normalization::PerMg) {
+            let Some(milligrams) = parse_strength(&row.description)
+                .and_then(|strength| strength.milligrams())
+                .filter(|milligrams| !milligrams.is_zero())
+            else {
+                row_errors.leave_out("no strength in mg for --normalize per-mg");
+                explain(&row, "left out by --normalize per-mg: no strength in mg");
+                continue;
+            };
+            row.old_price /= milligrams;
+            row.new_price /= milligrams;
+        }
+        if let Some(utilization) = &report_options.utilization {
+            let Some(units) = utilization.units(&row.ndc) else {
+                row_errors.leave_out("no utilization for its NDC");
+                explain(&row, "left out: no utilization for its NDC");
+                continue;
+            };
+            row.old_price *= units;
+            row.new_price *= units;
+        }
+
+        let classification = if report_options.by_classification {
+            match Classification::from_code(&row.classification) {
+                Some(classification) => Some(classification),
+                // Rows without a known classification have no section to go in.
+                None => {
+                    row_errors.leave_out("no brand or generic classification");
+                    explain(&row, "left out: no brand or generic classification");
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+        let pricing_unit = if report_options.by_pricing_unit {
+            match PricingUnit::from_code(&row.pricing_unit) {
+                Some(pricing_unit) => Some(pricing_unit),
+                // Rows without a known pricing unit have no section to go in.
+                None => {
+                    row_errors.leave_out("no known pricing unit");
+                    explain(&row, "left out: no known pricing unit");
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+
+        if dedup.as_mut().is_some_and(|dedup| dedup.is_duplicate(&row)) {
+            row_errors.leave_out("duplicate of an earlier row");
+            explain(&row, "skipped as a duplicate of an earlier row");
+            continue;
+        }
+
+        let period = match report_options.group_by {
+            Some(group_by) => group_by.period_of(effective_date),
+            None => *period,
+        };
+        let section = Section {
+            period,
+            classification,
+            pricing_unit,
+        };
+        on_added(section, &row)?;
+
+        // Price changes of zero still count towards the other sections, but they are kept out
+        // of the pools.
+        if report_options.exclude_zero && row.new_price == row.old_price {
+            row_errors.leave_out("change of zero");
+            explain(&row, "left out of the pools as a change of zero");
+            continue;
+        }
+        data_stores.insert(section, &row)?;
+    }
+
+    Ok(())
+}
+
+/// Generate a table of every price change of a drug in the report's periods that the filter
+/// matches, in date order.
+///
+/// # Arguments
+///
+/// * `drug` - The description or NDC of the drug.
+/// * `inputs` - The inputs to read.
+/// * `options` - The options used to open the inputs.
+/// * `report_options` - The periods to cover, the filter and the kind of inputs.
+/// * `row_errors` - The skipped records.
+///
+/// # Returns
+///
+/// On success, returns the table, on error returns a std::error::Error in a Box.
+async fn generate_trend_report(
+    drug: &str,
+    inputs: &[Input],
+    options: &SourceOptions,
+    report_options: &ReportOptions,
+    row_errors: &mut RowErrors,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut trend = Trend::new(drug);
+    let mut weekly_prices = WeeklyPrices::default();
+
+    for input in inputs {
+        let mut opened = input.records(options).await?;
+        if report_options.weekly {
+            weekly_prices
+                .add_records(&mut opened.records, &opened.source, row_errors)
+                .await?;
+        } else {
+            add_trend_records(
+                &mut trend,
+                &mut opened.records,
+                &opened.source,
+                report_options,
+                row_errors,
+            )
+            .await?;
+        }
+    }
+
+    if report_options.weekly {
+        let mut changes = weekly_prices.into_changes();
+        add_trend_records(
+            &mut trend,
+            &mut changes,
+            "weekly price changes",
+            report_options,
+            row_errors,
+        )
+        .await?;
+    }
+
+    // Several periods are always whole years.
+    let period = match report_options.periods.as_slice() {
+        [period] => period.to_string(),
+        periods => {
+            let years: Vec<String> = periods
+                .iter()
+                .map(|period| match period {
+                    Period::Year(year) => year.to_string(),
+                    period => period.to_string(),
+                })
+                .collect();
+            format!("of {}", years.join(", "))
+        }
+    };
+    Ok(trend.report(&period))
+}
+
+/// Add the price changes of the drug being followed in the report's periods that the filter
+/// matches from a `RecordStream` to the `Trend`. Records with missing or invalid data are
+/// skipped and recorded in `row_errors`.
+///
+/// # Arguments
+///
+/// * `trend` - The trend to add the price changes to.
+/// * `records` - The records to read.
+/// * `source` - Where the records come from.
+/// * `report_options` - The periods and the filter of the price changes to add.
+/// * `row_errors` - The skipped records.
+///
+/// # Returns
+///
+/// On success, returns (), on error returns a std::error::Error in a Box.
+async fn add_trend_records(
+    trend: &mut Trend,
+    records: &mut RecordStream<'_>,
+    source: &str,
+    report_options: &ReportOptions,
+    row_errors: &mut RowErrors,
+) -> Result<(), Box<dyn std::error::Error>> {
+    while let Some(record) = records.next().await {
+        let record = record?;
+
+        let row = match ComparisonRow::from_record(&record) {
+            Ok(row) => row,
+            Err(FieldError { field, reason }) => {
+                row_errors.add(source, &record, field, &reason);
+                continue;
+            }
+        };
+
+        let Some(effective_date) = row.effective_date else {
+            row_errors.add(
+                source,
+                &record,
+                EFFECTIVE_DATE_FIELD,
+                "missing effective date",
+            );
+            continue;
+        };
+
+        if report_options
+            .periods
+            .iter()
+            .any(|period| period.contains(effective_date))
+            && trend.matches(&row)
+            && report_options.filter.matches(&row)
+        {
+            trend.add(&row, effective_date);
+        }
+    }
+
+    Ok(())
+}
+
+/// Write every price increase above the thresholds in the report's periods as CSV. The
+/// alerts are written as the records are read, except for weekly inputs, whose price changes
+/// are only known once all of them are read.
+///
+/// # Arguments
+///
+/// * `thresholds` - The size of increase that raises an alert.
+/// * `inputs` - The inputs to read.
+/// * `options` - The options used to open the inputs.
+/// * `report_options` - The periods to cover, the filter and the kind of inputs.
+/// * `row_errors` - The skipped records.
+/// * `out` - Where the alerts are written.
+///
+/// # Returns
+///
+/// On success, returns (), on error returns a std::error::Error in a Box.
+async fn write_alerts(
+    thresholds: &AlertThresholds,
+    inputs: &[Input],
+    options: &SourceOptions,
+    report_options: &ReportOptions,
+    row_errors: &mut RowErrors,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    out.write_all(ALERT_HEADER.as_bytes())?;
+    let mut weekly_prices = WeeklyPrices::default();
+
+    for input in inputs {
+        let mut opened = input.records(options).await?;
+        if report_options.weekly {
+            weekly_prices
+                .add_records(&mut opened.records, &opened.source, row_errors)
+                .await?;
+        } else {
+            add_alert_records(
+                thresholds,
+                &mut opened.records,
+                &opened.source,
+                report_options,
+                row_errors,
+                out,
+            )
+            .await?;
+        }
+    }
+
+    if report_options.weekly {
+        let mut changes = weekly_prices.into_changes();
+        add_alert_records(
+            thresholds,
+            &mut changes,
+            "weekly price changes",
+            report_options,
+            row_errors,
+            out,
+        )
+        .await?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+/// Write the price increases above the thresholds in the report's periods from a
+/// `RecordStream`. Records with missing or invalid data are skipped and recorded in
+/// `row_errors`.
+///
+/// # Arguments
+///
+/// * `thresholds` - The size of increase that raises an alert.
+/// * `records` - The records to read.
+/// * `source` - Where the records come from.
+/// * `report_options` - The periods to cover and the filter.
+/// * `row_errors` - The skipped records.
+/// * `out` - Where the alerts are written.
+///
+/// # Returns
+///
+/// On success, returns (), on error returns a std::error::Error in a Box.
+async fn add_alert_records(
+    thresholds: &AlertThresholds,
+    records: &mut RecordStream<'_>,
+    source: &str,
+    report_options: &ReportOptions,
+    row_errors: &mut RowErrors,
+    out: &mut dyn std::io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    while let Some(record) = records.next().await {
+        let record = record?;
+
+        let row = match ComparisonRow::from_record(&record) {
+            Ok(row) => row,
+            Err(FieldError { field, reason }) => {
+                row_errors.add(source, &record, field, &reason);
+                continue;
+            }
+        };
+
+        let Some(effective_date) = row.effective_date else {
+            row_errors.add(
+                source,
+                &record,
+                EFFECTIVE_DATE_FIELD,
+                "missing effective date",
+            );
+            continue;
+        };
+
+        if report_options
+            .periods
+            .iter()
+            .any(|period| period.contains(effective_date))
+            && report_options.filter.matches(&row)
+            && thresholds.matches(&row)
+        {
+            out.write_all(alert_line(&row, effective_date).as_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write every price change in the report's periods, ranked like the report. The changes are
+/// sorted with an `ExternalSort`, so at most `sort_buffer` of them are held in memory.
+///
+/// # Arguments
+///
+/// * `inputs` - The inputs to read.
+/// * `options` - The options used to open the inputs.
+/// * `report_options` - The periods to cover, the filter, the metric and the kind of inputs.
+/// * `sort_buffer` - The number of changes held in memory before they are spilled to a
+///   temporary file.
+/// * `row_errors` - The skipped records.
+/// * `out` - Where the changes are written.
+///
+/// # Returns
+///
+/// On success, returns (), on error returns a std::error::Error in a Box.
+async fn write_all_changes(
+    inputs: &[Input],
+    options: &SourceOptions,
+    report_options: &ReportOptions,
+    sort_buffer: usize,
+    row_errors: &mut RowErrors,
+    out: &mut (dyn std::io::Write + Send),
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut sort = ExternalSort::new(report_options.metric, sort_buffer)?;
+    let mut weekly_prices = WeeklyPrices::default();
+
+    for input in inputs {
+        let mut opened = input.records(options).await?;
+        if report_options.weekly {
+            weekly_prices
+                .add_records(&mut opened.records, &opened.source, row_errors)
+                .await?;
+        } else {
+            add_all_change_records(
+                &mut sort,
+                &mut opened.records,
+                &opened.source,
+                report_options,
+                row_errors,
+            )
+            .await?;
+        }
+    }
+
+    if report_options.weekly {
+        let mut changes = weekly_prices.into_changes();
+        add_all_change_records(
+            &mut sort,
+            &mut changes,
+            "weekly price changes",
+            report_options,
+            row_errors,
+        )
+        .await?;
+    }
+
+    let output_format = report_options.output_format;
+    if matches!(output_format, OutputFormat::Parquet | OutputFormat::Arrow) {
+        let mut writer = ColumnarWriter::new(
+            output_format,
+            false,
+            report_options.percent_places,
+            None,
+            &mut *out,
+        )?;
+        let mut rank = 0;
+        sort.for_each(|change| {
+            rank += 1;
+            writer.push(ColumnarRow {
+                period: None,
+                drugs: None,
+                list: None,
+                rank,
+                change: change.difference,
+                percent: None,
+                old_price: None,
+                new_price: None,
+                effective_date: None,
+                description: change.description.clone(),
+                ndc: change.ndc.clone(),
+            })
+        })?;
+        writer.finish()?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    let heading = match report_options.metric {
+        Metric::Change => "All NADAC per unit price changes, largest increase first:\n",
+        Metric::Magnitude => {
+            "All NADAC per unit price changes, largest in either direction first:\n"
+        }
+    };
+    out.write_all(heading.as_bytes())?;
+    let format = RecordFormat {
+        show_ndc: report_options.show_ndc,
+        directory: report_options.directory.as_ref(),
+        fields: None,
+        legacy: false,
+        percent_places: report_options.percent_places,
+        chart: false,
+        language: Language::En,
+    };
+    sort.write(&format, out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Add the price changes in the report's periods from a `RecordStream` to an
+/// `ExternalSort`. Records with missing or invalid data are skipped and recorded in
+/// `row_errors`.
+///
+/// # Arguments
+///
+/// * `sort` - The sort to add the changes to.
+/// * `records` - The records to read.
+/// * `source` - Where the records come from.
+/// * `report_options` - The periods to cover, the filter and whether changes of zero are left
+///   out.
+/// * `row_errors` - The skipped records.
+///
+/// # Returns
+///
+/// On success, returns (), on error returns a std::error::Error in a Box.
+async fn add_all_change_records(
+    sort: &mut ExternalSort,
+    records: &mut RecordStream<'_>,
+    source: &str,
+    report_options: &ReportOptions,
+    row_errors: &mut RowErrors,
+) -> Result<(), Box<dyn std::error::Error>> {
+    while let Some(record) = records.next().await {
+        let record = record?;
 
-    // Number of top per-unit price increases and decreases
-    #[arg(short, long, default_value_t = 10)]
-    count: usize,
+        let row = match ComparisonRow::from_record(&record) {
+            Ok(row) => row,
+            Err(FieldError { field, reason }) => {
+                row_errors.add(source, &record, field, &reason);
+                continue;
+            }
+        };
 
-    // Drug price change year to report on
-    #[arg(short, long, default_value_t = 2023)]
-    year: i32,
-}
+        let Some(effective_date) = row.effective_date else {
+            row_errors.add(
+                source,
+                &record,
+                EFFECTIVE_DATE_FIELD,
+                "missing effective date",
+            );
+            continue;
+        };
+
+        if report_options
+            .periods
+            .iter()
+            .any(|period| period.contains(effective_date))
+            && report_options.filter.matches(&row)
+            && !(report_options.exclude_zero && row.new_price == row.old_price)
+        {
+            sort.push(Change::new(
+                row.new_price - row.old_price,
+                &row.description,
+                &row.ndc,
+            ))?;
+        }
+    }
 
-const EFFECTIVE_DATE_FIELD: usize = 9;
+    Ok(())
+}
 
-async fn generate_nadac_top_price_change_report(
-    url: &str,
-    year: i32,
-    count: usize,
-) -> Result<String, Box<dyn std::error::Error>> {
-    // The tricky part here is to convert the stream from the reqwest crate into a stream something
-    // that implements the futures::AsyncRead trait needed by csv_async.
+/// Scan the inputs for the most recent year their effective dates run to the end of, for
+/// `--year latest` with the subcommands and `--all`, which read the inputs again afterwards.
+/// The rows scanned are not counted in the run's summary.
+///
+/// # Arguments
+///
+/// * `inputs` - The inputs to read.
+/// * `options` - The options used to open the inputs.
+/// * `weekly` - When true, the inputs are weekly NADAC files.
+///
+/// # Returns
+///
+/// On success, returns the year, on error returns a std::error::Error in a Box.
+async fn find_latest_complete_year(
+    inputs: &[Input],
+    options: &SourceOptions,
+    weekly: bool,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let field = if weekly {
+        WEEKLY_EFFECTIVE_DATE_FIELD
+    } else {
+        EFFECTIVE_DATE_FIELD
+    };
 
-    let stream = reqwest::get(url).await?.bytes_stream();
+    // The data is read twice, which standard input and a random sample cannot be.
+    let once = |source: &DataSource| matches!(source, DataSource::Stdin);
+    if inputs.iter().any(|input| match input {
+        Input::Csv(source) => once(source),
+        Input::Mirrored(sources) => sources.iter().any(once),
+        Input::Api(_) => false,
+    }) {
+        return Err(
+            "--year latest cannot be used with stdin except in the price change \
+            report"
+                .into(),
+        );
+    }
+    if options.sampling.fraction.is_some() {
+        return Err(
+            "--year latest cannot be used with --sample except in the price change \
+            report"
+                .into(),
+        );
+    }
 
-    let async_read_stream = stream
-        .map(|r| r.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e)))
-        .into_async_read();
+    // Rows with missing or invalid dates are recorded when the data is read again.
+    let options = SourceOptions {
+        counters: Default::default(),
+        ..options.clone()
+    };
+    let latest_year = LatestYear::new(field);
+    for input in inputs {
+        let opened = input.records(&options).await?;
+        let mut records = latest_year.track(opened.records);
+        while let Some(record) = records.next().await {
+            record?;
+        }
+    }
 
-    let mut csv_reader = csv_async::AsyncReader::from_reader(async_read_stream);
+    Ok(latest_year.year()?)
+}
 
-    let mut records = csv_reader.records();
+/// Read the two snapshots compared by the `new-drugs` and `discontinued` subcommands.
+///
+/// # Arguments
+///
+/// * `inputs` - The inputs, the earlier snapshot followed by the later one.
+/// * `options` - The options used to open the inputs.
+/// * `report_options` - The kind of inputs.
+/// * `row_errors` - The skipped records.
+///
+/// # Returns
+///
+/// On success, returns the earlier and later snapshots, on error returns a std::error::Error
+/// in a Box.
+async fn read_snapshots(
+    inputs: &[Input],
+    options: &SourceOptions,
+    report_options: &ReportOptions,
+    row_errors: &mut RowErrors,
+) -> Result<(Snapshot, Snapshot), Box<dyn std::error::Error>> {
+    let [earlier, later] = inputs else {
+        return Err("Comparing snapshots needs exactly two inputs, the earlier one first".into());
+    };
 
-    let mut data_store: data_store::DataStore = data_store::DataStore::new(count)?;
+    let earlier = Snapshot::read(earlier, options, report_options.weekly, row_errors).await?;
+    let later = Snapshot::read(later, options, report_options.weekly, row_errors).await?;
+    Ok((earlier, later))
+}
 
-    while let Some(record) = records.next().await {
-        let record = record?;
+/// Carry out one of the `cache` subcommands.
+async fn run_cache_command(
+    action: &CacheCommand,
+    cache: &DownloadCache,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        CacheCommand::List => print!("{}", generate_listing(&cache.list().await?)),
+        CacheCommand::Clear { older_than_days } => {
+            let older_than =
+                older_than_days.map(|days| Duration::from_secs(days.saturating_mul(24 * 60 * 60)));
+            let removed = cache.clear(older_than).await?;
+            println!("Removed {} cached download(s)", removed);
+        }
+        CacheCommand::Path => println!("{}", cache.dir().display()),
+    }
 
-        if let Some(effective_date) = record.get(EFFECTIVE_DATE_FIELD) {
-            if effective_date.is_empty() {
-                continue;
-            }
+    Ok(())
+}
 
-            // We could use a crate like chrono and parse the full date field, but in the interest
-            // of time, I have chosen just to manually extract the year value from the data.
-            let array: Vec<&str> = effective_date.split("/").collect();
-            let record_year = array[2].parse::<i32>()?;
+/// Carry out the `validate` subcommand. Every data source is checked and a summary printed for
+/// each.
+///
+/// # Returns
+///
+/// On success, returns (), on error, including when any source fails validation, returns a
+/// std::error::Error in a Box.
+async fn run_validate_command(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let options = args.source_options()?;
 
-            if record_year == year {
-                data_store.insert(&record)?;
-            }
+    let mut passed = true;
+    for (index, source) in args.data_sources(&options).await?.iter().enumerate() {
+        if index > 0 {
+            println!();
         }
+
+        let validation = validate_source(source, &options).await?;
+        print!("{}", generate_summary(source, &validation));
+        passed &= validation.passed();
+    }
+
+    if !passed {
+        return Err("The price change data failed validation".into());
     }
 
-    Ok(generate_report(&data_store, &count, &year))
+    Ok(())
+}
+
+/// Carry out the `diff` subcommand, writing the comparison of the two reports to the output.
+///
+/// # Arguments
+///
+/// * `old` - The earlier report, saved with `--format json`.
+/// * `new` - The later report, saved with `--format json`.
+/// * `args` - The command line arguments, giving the output.
+///
+/// # Returns
+///
+/// On success, returns (), on error returns a std::error::Error in a Box.
+fn run_diff_command(old: &Path, new: &Path, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let read = |path: &Path| {
+        std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read report {}: {}", path.display(), e))
+    };
+    let comparison = diff_reports(
+        &old.display().to_string(),
+        &read(old)?,
+        &new.display().to_string(),
+        &read(new)?,
+    )?;
+
+    let mut output = ReportOutput::open(args.output.as_deref(), args.append)?;
+    output.write_all(comparison.as_bytes())?;
+    output.finish()
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+async fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    run(std::env::args().collect()).await
+}
+
+/// Run the program with its command line.
+///
+/// # Arguments
+///
+/// * `arguments` - The command line, starting with the name of the program.
+///
+/// # Returns
+///
+/// On success, returns the `ExitCode` the program exits with, on error returns a
+/// std::error::Error in a Box.
+async fn run(arguments: Vec<String>) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let args = Args::parse_from(&arguments);
 
-    let report = generate_nadac_top_price_change_report(&args.url, args.year, args.count).await?;
+    match &args.command {
+        Some(_) if args.all => {
+            return Err("--all only applies to the price change report, not to subcommands".into())
+        }
+        Some(Command::Cache { action }) => {
+            run_cache_command(action, &args.download_cache()).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Validate) => {
+            run_validate_command(&args).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Diff { old, new }) => {
+            run_diff_command(old, new, &args)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Schema) => {
+            let mut output = ReportOutput::open(args.output.as_deref(), args.append)?;
+            output.write_all(json_report_schema()?.as_bytes())?;
+            output.finish()?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Trend { .. })
+        | Some(Command::Alerts { .. })
+        | Some(Command::NewDrugs)
+        | Some(Command::Discontinued)
+        | None => {}
+    }
 
-    print!("{}", report);
+    let options = args.source_options()?;
+    let mut report_options = args.report_options()?;
+    report_options.arguments = arguments.into_iter().skip(1).collect();
+    if let Some(path) = &args.utilization {
+        let source = DataSource::File(path.clone());
+        report_options.utilization = Some(Utilization::load(&source, &options).await?);
+    }
+    if let Some(path) = &args.ndc_directory {
+        let source = DataSource::File(path.clone());
+        report_options.directory = Some(NdcDirectory::load(&source, &options).await?);
+    }
+    let inputs = args.with_fallbacks(args.inputs(&options).await?)?;
 
-    Ok(())
+    // The price change report works out the latest year as it reads the inputs.
+    if report_options.latest_year && (args.command.is_some() || args.all) {
+        let year = find_latest_complete_year(&inputs, &options, report_options.weekly).await?;
+        report_options.periods.push(Period::Year(year));
+        report_options.periods.sort();
+        report_options.periods.dedup();
+        report_options.latest_year = false;
+    }
+
+    let mut output = ReportOutput::open(args.output.as_deref(), args.append)?;
+    let mut row_errors = RowErrors::new(args.errors_file.is_some());
+    let mut extremes = PriceExtremes::default();
+    let report = match &args.command {
+        Some(Command::Trend { drug }) => {
+            generate_trend_report(drug, &inputs, &options, &report_options, &mut row_errors).await?
+        }
+        Some(Command::NewDrugs) => {
+            let (earlier, later) =
+                read_snapshots(&inputs, &options, &report_options, &mut row_errors).await?;
+            new_drugs_report(&earlier, &later)
+        }
+        Some(Command::Discontinued) => {
+            let (earlier, later) =
+                read_snapshots(&inputs, &options, &report_options, &mut row_errors).await?;
+            discontinued_report(&earlier, &later)
+        }
+        Some(Command::Alerts { pct, dollars }) => {
+            let thresholds = AlertThresholds {
+                percent: *pct,
+                dollars: *dollars,
+            };
+            write_alerts(
+                &thresholds,
+                &inputs,
+                &options,
+                &report_options,
+                &mut row_errors,
+                &mut output,
+            )
+            .await?;
+            String::new()
+        }
+        None if args.all => {
+            let mut out = std::io::BufWriter::new(&mut output);
+            write_all_changes(
+                &inputs,
+                &options,
+                &report_options,
+                args.sort_buffer,
+                &mut row_errors,
+                &mut out,
+            )
+            .await?;
+            String::new()
+        }
+        _ => {
+            generate_nadac_top_price_change_report(
+                &inputs,
+                &options,
+                &report_options,
+                &mut row_errors,
+                &mut extremes,
+                &mut output,
+            )
+            .await?
+        }
+    };
+
+    output.write_all(report.as_bytes())?;
+    output.finish()?;
+
+    // The report notes when it is partial, the other output does not have room to.
+    if args.command.is_some() || args.all {
+        if let Some(note) = options.sampling.note() {
+            eprintln!("{}", note);
+        }
+    }
+
+    if row_errors.count() > 0 {
+        eprintln!(
+            "Skipped {} row(s) with missing or invalid data",
+            row_errors.count()
+        );
+    }
+
+    if let Some(path) = &args.errors_file {
+        row_errors.write_csv(path).await?;
+    }
+
+    let crossed = args.exit_thresholds().crossed(&extremes);
+    if !crossed.is_empty() {
+        for threshold in crossed {
+            eprintln!("{}", threshold);
+        }
+        return Ok(ExitCode::from(THRESHOLD_EXIT_CODE));
+    }
+
+    Ok(ExitCode::SUCCESS)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{generate_nadac_top_price_change_report, NADAC_COMPARISON_URL};
+    use crate::alerts::{AlertThresholds, ALERT_HEADER};
+    use crate::columns::ColumnMap;
+    use crate::data_source::{DataSource, Input, SourceOptions};
+    use crate::data_store::Metric;
+    use crate::dates::{GroupBy, Period};
+    use crate::filters::{Classification, RecordFilter};
+    use crate::report::{OutputFormat, ReportField};
+    use crate::row_errors::RowErrors;
+    use crate::sampling::Sampling;
+    use crate::thresholds::{PriceExtremes, THRESHOLD_EXIT_CODE};
+    use crate::{
+        generate_nadac_top_price_change_report, generate_trend_report, run, write_alerts,
+        write_all_changes, Args, ReportOptions, NADAC_COMPARISON_URL,
+    };
+    use chrono::NaiveDate;
+    use clap::Parser;
+    use rust_decimal::Decimal;
+    use std::io::Write;
     use std::path::PathBuf;
-    use tokio::io::AsyncReadExt;
+    use std::process::ExitCode;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Serve a body over HTTP for a single request, so a second read of it fails.
+    ///
+    /// # Returns
+    ///
+    /// The URL the body is served from.
+    async fn serve_once(body: String) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/data.csv", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = socket.read(&mut buffer).await.unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..read]);
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            socket.shutdown().await.unwrap();
+        });
+        url
+    }
 
     // Since this is test code, I have left the use of unwrap() to explicitly allow
     // the test system to catch panics.
@@ -106,11 +3030,1091 @@ mod tests {
 
         let data_report = String::from_utf8_lossy(&contents);
 
-        let generated_report =
-            generate_nadac_top_price_change_report(NADAC_COMPARISON_URL, 2020, 10)
-                .await
-                .unwrap();
+        let inputs = [Input::Csv(DataSource::Url(
+            NADAC_COMPARISON_URL.to_string(),
+        ))];
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &ReportOptions {
+                output_format: OutputFormat::Legacy,
+                periods: vec![Period::Year(2020)],
+                count: 10,
+                ..Default::default()
+            },
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(data_report, generated_report);
     }
+
+    #[tokio::test]
+    async fn test_report_from_file() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &ReportOptions {
+                periods: vec![Period::Year(2023)],
+                count: 3,
+                ..Default::default()
+            },
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        let expected = "Top 3 NADAC per unit price increases of 2023:\n\
+            Rank   Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  $802.39     3.19%  $25172.32  $25974.71  2023-01-11  STELARA 90 MG/ML SYRINGE\n   \
+               2  $320.19     9.99%   $3206.71   $3526.91  2023-01-11  HUMIRA(CF) PEN 40 MG/0.4 ML\n   \
+               3   $75.95     4.99%   $1521.53   $1597.48  2023-01-11  ENBREL 50 MG/ML SURECLICK\n\
+            \n\
+            Top 3 NADAC per unit price decreases of 2023:\n\
+            Rank    Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  -$183.14   -66.67%    $274.70     $91.56  2023-01-04  HUMALOG 100 UNIT/ML VIAL\n   \
+               2   -$13.87   -18.44%     $75.21     $61.34  2023-04-12  EPINEPHRINE 0.3 MG AUTO-INJECT\n   \
+               3    -$2.85   -28.84%      $9.87      $7.02  2023-05-10  DEXMETHYLPHENIDATE ER 40 MG CAP\n";
+
+        assert_eq!(expected, generated_report);
+    }
+
+    #[tokio::test]
+    async fn test_report_table() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &ReportOptions {
+                periods: vec![Period::Year(2023)],
+                count: 2,
+                ..Default::default()
+            },
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        let expected = "Top 2 NADAC per unit price increases of 2023:\n\
+            Rank   Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  $802.39     3.19%  $25172.32  $25974.71  2023-01-11  STELARA 90 MG/ML SYRINGE\n   \
+               2  $320.19     9.99%   $3206.71   $3526.91  2023-01-11  HUMIRA(CF) PEN 40 MG/0.4 ML\n\
+            \n\
+            Top 2 NADAC per unit price decreases of 2023:\n\
+            Rank    Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  -$183.14   -66.67%    $274.70     $91.56  2023-01-04  HUMALOG 100 UNIT/ML VIAL\n   \
+               2   -$13.87   -18.44%     $75.21     $61.34  2023-04-12  EPINEPHRINE 0.3 MG AUTO-INJECT\n";
+
+        assert_eq!(expected, generated_report);
+    }
+
+    #[tokio::test]
+    async fn test_report_chart() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &ReportOptions {
+                periods: vec![Period::Year(2023)],
+                count: 2,
+                fields: Some(vec![ReportField::Change, ReportField::Description]),
+                chart: true,
+                ..Default::default()
+            },
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        let expected = "Top 2 NADAC per unit price increases of 2023:\n\
+            Rank   Change  Drug\n   \
+               1  $802.39  STELARA 90 MG/ML SYRINGE     ########################################\n   \
+               2  $320.19  HUMIRA(CF) PEN 40 MG/0.4 ML  ################\n\
+            \n\
+            Top 2 NADAC per unit price decreases of 2023:\n\
+            Rank    Change  Drug\n   \
+               1  -$183.14  HUMALOG 100 UNIT/ML VIAL        ========================================\n   \
+               2   -$13.87  EPINEPHRINE 0.3 MG AUTO-INJECT  ===\n";
+
+        assert_eq!(expected, generated_report);
+    }
+
+    #[tokio::test]
+    async fn test_run_summary() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        // LISINOPRIL is let go from the pools as larger changes arrive, then kept again.
+        let mut later = tempfile::NamedTempFile::new().unwrap();
+        writeln!(
+            later,
+            "NDC Description,NDC,Old NADAC Per Unit,New NADAC Per Unit,Classification for Rate \
+            Setting,Percent Change,Primary Reason,Start Date,End Date,Effective Date\n\
+            LISINOPRIL 10 MG TABLET,68180051301,0.02265,900.00000,G,0,,,,12/06/2023"
+        )
+        .unwrap();
+
+        let inputs = [
+            Input::Csv(DataSource::File(path)),
+            Input::Csv(DataSource::File(later.path().to_path_buf())),
+        ];
+        let mut row_errors = RowErrors::default();
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &ReportOptions {
+                output_format: OutputFormat::Legacy,
+                periods: vec![Period::Year(2023)],
+                count: 1,
+                run_summary: true,
+                ..Default::default()
+            },
+            &mut row_errors,
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        // The elapsed time varies from run to run.
+        let (report, elapsed) = generated_report.split_once("Elapsed time: ").unwrap();
+        assert!(report.ends_with(
+            "-$183.14: HUMALOG 100 UNIT/ML VIAL\n\
+            \n\
+            Run summary:\n\
+            Rows read: 24\n\
+            Rows skipped: 1\n  \
+              Missing effective_date: 1\n\
+            Rows left out: 3\n  \
+              Change of zero: 1\n  \
+              Outside the report's periods: 2\n\
+            Price changes inserted: 20\n\
+            Descriptions interned: 6\n"
+        ));
+        assert!(elapsed.ends_with("s\nBytes downloaded: 0\n"));
+    }
+
+    #[tokio::test]
+    async fn test_provenance() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path.clone()))];
+        let arguments = [
+            "--bearer-token",
+            "s3cret",
+            "--header",
+            "Authorization:Bearer s3cret",
+            "--bearer-token=s3cret",
+            "--header=X-Api-Key:s3cret",
+            "-y",
+            "2023",
+        ];
+        let report_options = |output_format| ReportOptions {
+            output_format,
+            periods: vec![Period::Year(2023)],
+            count: 1,
+            provenance: true,
+            arguments: arguments
+                .iter()
+                .map(|argument| argument.to_string())
+                .collect(),
+            ..Default::default()
+        };
+        let inputs = &inputs;
+        let generate = |output_format| async move {
+            generate_nadac_top_price_change_report(
+                inputs,
+                &SourceOptions::default(),
+                &report_options(output_format),
+                &mut RowErrors::default(),
+                &mut PriceExtremes::default(),
+                &mut std::io::sink(),
+            )
+            .await
+            .unwrap()
+        };
+        let started = chrono::Local::now();
+        let generated_report = generate(OutputFormat::Legacy).await;
+
+        let mut lines = generated_report.lines();
+        assert_eq!(lines.next().unwrap(), format!("Source: {}", path.display()));
+        let hash = lines.next().unwrap();
+        assert!(hash.starts_with("Data SHA-256: "));
+
+        // The data is fetched during the run.
+        let fetched = lines
+            .next()
+            .unwrap()
+            .strip_prefix("Data fetched: ")
+            .unwrap();
+        let fetched = chrono::DateTime::parse_from_rfc3339(fetched).unwrap();
+        assert!(fetched >= started && fetched <= chrono::Local::now());
+        assert_eq!(
+            lines.next().unwrap(),
+            "Arguments: --bearer-token <redacted> --header Authorization:<redacted> \
+            --bearer-token=<redacted> --header=X-Api-Key:<redacted> -y 2023"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            format!("top10rust version: {}", env!("CARGO_PKG_VERSION"))
+        );
+        assert!(generated_report.contains("\n\nTop 1 NADAC per unit price increases of 2023:\n"));
+
+        // The same data has the same hash.
+        assert!(generate(OutputFormat::Legacy).await.contains(hash));
+
+        // The credentials are kept out of the JSON report too.
+        let json_report = generate(OutputFormat::Json).await;
+        assert!(json_report.contains("<redacted>"));
+        assert!(!json_report.contains("s3cret"));
+    }
+
+    #[tokio::test]
+    async fn test_exclude_zero() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        for exclude_zero in [true, false] {
+            let generated_report = generate_nadac_top_price_change_report(
+                &inputs,
+                &SourceOptions::default(),
+                &ReportOptions {
+                    count: 20,
+                    exclude_zero,
+                    ..Default::default()
+                },
+                &mut RowErrors::default(),
+                &mut PriceExtremes::default(),
+                &mut std::io::sink(),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(
+                generated_report.contains("METFORMIN HCL 500 MG TABLET\n"),
+                !exclude_zero
+            );
+
+            // --all leaves the changes of zero out too.
+            let mut out = Vec::new();
+            write_all_changes(
+                &inputs,
+                &SourceOptions::default(),
+                &ReportOptions {
+                    exclude_zero,
+                    ..Default::default()
+                },
+                2,
+                &mut RowErrors::default(),
+                &mut out,
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                String::from_utf8(out)
+                    .unwrap()
+                    .contains("$0.00: METFORMIN HCL 500 MG TABLET\n"),
+                !exclude_zero
+            );
+        }
+
+        // The last of --exclude-zero and --no-exclude-zero given wins.
+        for (flags, exclude_zero) in [
+            (&[][..], true),
+            (&["--no-exclude-zero"], false),
+            (&["--no-exclude-zero", "--exclude-zero"], true),
+            (&["--exclude-zero", "--no-exclude-zero"], false),
+        ] {
+            let args = Args::try_parse_from(["top10rust"].iter().chain(flags)).unwrap();
+            assert_eq!(args.report_options().unwrap().exclude_zero, exclude_zero);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_report_from_several_files() {
+        let mut first = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        first.push("data");
+        let mut second = first.clone();
+        first.push("sample_comparison.csv");
+        second.push("sample_comparison_2.csv");
+
+        let inputs = [
+            Input::Csv(DataSource::File(first)),
+            Input::Csv(DataSource::File(second)),
+        ];
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &ReportOptions {
+                periods: vec![Period::Year(2023)],
+                count: 2,
+                ..Default::default()
+            },
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        let expected = "Top 2 NADAC per unit price increases of 2023:\n\
+            Rank   Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  $802.39     3.19%  $25172.32  $25974.71  2023-01-11  STELARA 90 MG/ML SYRINGE\n   \
+               2  $415.43   107.87%    $385.12    $800.55  2023-11-29  TRULICITY 1.5 MG/0.5 ML PEN\n\
+            \n\
+            Top 2 NADAC per unit price decreases of 2023:\n\
+            Rank    Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  -$183.14   -66.67%    $274.70     $91.56  2023-01-04  HUMALOG 100 UNIT/ML VIAL\n   \
+               2   -$23.24   -81.95%     $28.36      $5.12  2023-12-13  LANTUS 100 UNIT/ML VIAL\n";
+
+        assert_eq!(expected, generated_report);
+    }
+
+    #[tokio::test]
+    async fn test_report_from_mirror() {
+        let mut missing = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        missing.push("data");
+        let mut mirror = missing.clone();
+        missing.push("missing_comparison.csv");
+        mirror.push("sample_comparison.csv");
+
+        let inputs = [Input::Mirrored(vec![
+            DataSource::File(missing),
+            DataSource::File(mirror.clone()),
+        ])];
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &ReportOptions {
+                periods: vec![Period::Year(2023)],
+                count: 1,
+                ..Default::default()
+            },
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        let expected = format!(
+            "Data source: {}\n\
+            \n\
+            Top 1 NADAC per unit price increases of 2023:\n\
+            Rank   Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  $802.39     3.19%  $25172.32  $25974.71  2023-01-11  STELARA 90 MG/ML SYRINGE\n\
+            \n\
+            Top 1 NADAC per unit price decreases of 2023:\n\
+            Rank    Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  -$183.14   -66.67%    $274.70     $91.56  2023-01-04  HUMALOG 100 UNIT/ML VIAL\n",
+            mirror.display()
+        );
+
+        assert_eq!(expected, generated_report);
+    }
+
+    #[tokio::test]
+    async fn test_report_with_ndc() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let report_options = ReportOptions {
+            periods: vec![Period::Year(2023)],
+            count: 1,
+            show_ndc: true,
+            ..Default::default()
+        };
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &report_options,
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        assert!(generated_report.contains("STELARA 90 MG/ML SYRINGE (NDC 57894006103)\n"));
+    }
+
+    #[tokio::test]
+    async fn test_report_by_classification() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let report_options = ReportOptions {
+            periods: vec![Period::Year(2023)],
+            count: 1,
+            filter: RecordFilter {
+                classification: Some(Classification::Generic),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &report_options,
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        assert!(!generated_report.contains("STELARA"));
+        assert!(generated_report.contains(
+            "1  -$13.87   -18.44%     $75.21     $61.34  2023-04-12  EPINEPHRINE 0.3 MG AUTO-INJECT\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_report_for_date_range() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let report_options = ReportOptions {
+            periods: vec![Period::Range {
+                from: NaiveDate::from_ymd_opt(2023, 2, 1),
+                to: NaiveDate::from_ymd_opt(2023, 6, 30),
+            }],
+            count: 1,
+            ..Default::default()
+        };
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &report_options,
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            generated_report,
+            "Top 1 NADAC per unit price increases from 2023-02-01 to 2023-06-30:\n\
+            Rank  Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  $47.03     4.76%    $988.14   $1035.17  2023-02-08  REVLIMID 25 MG CAPSULE\n\
+            \n\
+            Top 1 NADAC per unit price decreases from 2023-02-01 to 2023-06-30:\n\
+            Rank   Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  -$13.87   -18.44%     $75.21     $61.34  2023-04-12  EPINEPHRINE 0.3 MG AUTO-INJECT\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_report_for_several_years() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let report_options = ReportOptions {
+            periods: vec![Period::Year(2022), Period::Year(2023)],
+            count: 1,
+            ..Default::default()
+        };
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &report_options,
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            generated_report,
+            "Top 1 NADAC per unit price increases by year:\n\
+            2022 | 2023\n     \
+                 | Rank   Change  % Change  Old price  New price  Effective   Drug\n     \
+                 |    1  $802.39     3.19%  $25172.32  $25974.71  2023-01-11  STELARA 90 MG/ML SYRINGE\n\
+            \n\
+            Top 1 NADAC per unit price decreases by year:\n\
+            2022                                                                              | 2023\n\
+            Rank  Change  % Change  Old price  New price  Effective   Drug                    \
+            | Rank    Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  -$0.00    -7.05%      $0.03      $0.03  2022-12-07  LISINOPRIL 10 MG TABLET \
+            |    1  -$183.14   -66.67%    $274.70     $91.56  2023-01-04  HUMALOG 100 UNIT/ML VIAL\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_several_years_in_one_pass() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        // The data is served once, so the report fails if it reads the input for each year.
+        let url = serve_once(std::fs::read_to_string(path).unwrap()).await;
+        let inputs = [Input::Csv(DataSource::Url(url))];
+        let report_options = ReportOptions {
+            periods: vec![Period::Year(2021), Period::Year(2022), Period::Year(2023)],
+            count: 1,
+            ..Default::default()
+        };
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &report_options,
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            generated_report,
+            "Top 1 NADAC per unit price increases by year:\n\
+            2021 | 2022 | 2023\n     \
+            |      | Rank   Change  % Change  Old price  New price  Effective   Drug\n     \
+            |      |    1  $802.39     3.19%  $25172.32  $25974.71  2023-01-11  STELARA 90 MG/ML SYRINGE\n\
+            \n\
+            Top 1 NADAC per unit price decreases by year:\n\
+            2021 | 2022                                                                              \
+            | 2023\n     \
+            | Rank  Change  % Change  Old price  New price  Effective   Drug                    \
+            | Rank    Change  % Change  Old price  New price  Effective   Drug\n     \
+            |    1  -$0.00    -7.05%      $0.03      $0.03  2022-12-07  LISINOPRIL 10 MG TABLET \
+            |    1  -$183.14   -66.67%    $274.70     $91.56  2023-01-04  HUMALOG 100 UNIT/ML VIAL\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_latest_year_in_one_pass() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        // Without the change of 2024 the data stops short of the end of 2023, so the latest
+        // complete year is 2022.
+        let body: String = std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.ends_with("/2024"))
+            .map(|line| format!("{}\n", line))
+            .collect();
+
+        // The data is served once, so a second read of it would fail.
+        let url = serve_once(body).await;
+        let inputs = [Input::Csv(DataSource::Url(url))];
+        let options = SourceOptions::default();
+        let mut row_errors = RowErrors::new(true);
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &options,
+            &ReportOptions {
+                periods: vec![],
+                latest_year: true,
+                count: 1,
+                ..Default::default()
+            },
+            &mut row_errors,
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            generated_report,
+            "Top 1 NADAC per unit price increases of 2022:\n\
+            \n\
+            Top 1 NADAC per unit price decreases of 2022:\n\
+            Rank  Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  -$0.00    -7.05%      $0.03      $0.03  2022-12-07  LISINOPRIL 10 MG TABLET\n"
+        );
+
+        // The rows of other years and the row without an effective date are accounted for.
+        assert_eq!(options.counters.rows_read(), 22);
+        assert_eq!(row_errors.count(), 1);
+        assert_eq!(row_errors.errors()[0].line, Some(21));
+        assert_eq!(
+            row_errors.left_out(),
+            vec![("outside the report's periods".to_string(), 20)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trend_report() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let report_options = ReportOptions {
+            periods: vec![Period::Year(2022), Period::Year(2023)],
+            ..Default::default()
+        };
+        let generated_report = generate_trend_report(
+            "lisinopril 10 mg tablet",
+            &inputs,
+            &SourceOptions::default(),
+            &report_options,
+            &mut RowErrors::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            generated_report,
+            "NADAC per unit price changes for lisinopril 10 mg tablet of 2022, 2023:\n\
+            Effective date  NDC          Old price  New price     Change\n\
+            2022-12-07      68180051301   $0.03120   $0.02900  -$0.00220\n\
+            2023-01-04      68180051301   $0.02011   $0.02265   $0.00254\n"
+        );
+
+        // The row filters apply to the trend too.
+        let report_options = ReportOptions {
+            filter: RecordFilter {
+                min_change_percent: Some(Decimal::new(10, 0)),
+                ..Default::default()
+            },
+            ..report_options
+        };
+        let generated_report = generate_trend_report(
+            "lisinopril 10 mg tablet",
+            &inputs,
+            &SourceOptions::default(),
+            &report_options,
+            &mut RowErrors::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            generated_report,
+            "NADAC per unit price changes for lisinopril 10 mg tablet of 2022, 2023:\n\
+            Effective date  NDC          Old price  New price    Change\n\
+            2023-01-04      68180051301   $0.02011   $0.02265  $0.00254\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_alerts() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let thresholds = AlertThresholds {
+            percent: None,
+            dollars: Some(Decimal::new(100, 0)),
+        };
+        let mut out = Vec::new();
+        write_alerts(
+            &thresholds,
+            &inputs,
+            &SourceOptions::default(),
+            &ReportOptions::default(),
+            &mut RowErrors::default(),
+            &mut out,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "{}\
+                2023-01-11,57894006103,STELARA 90 MG/ML SYRINGE,25172.31540,25974.70600,\
+                802.39060,3.19\n\
+                2023-01-11,00074055402,HUMIRA(CF) PEN 40 MG/0.4 ML,3206.71125,3526.90613,\
+                320.19488,9.99\n",
+                ALERT_HEADER
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_all_changes() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let mut out = Vec::new();
+        let mut row_errors = RowErrors::default();
+        write_all_changes(
+            &inputs,
+            &SourceOptions::default(),
+            &ReportOptions::default(),
+            2,
+            &mut row_errors,
+            &mut out,
+        )
+        .await
+        .unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        // The change of zero is left out.
+        assert_eq!(lines.len(), 20);
+        assert_eq!(
+            lines[..3],
+            [
+                "All NADAC per unit price changes, largest increase first:",
+                "$802.39: STELARA 90 MG/ML SYRINGE",
+                "$320.19: HUMIRA(CF) PEN 40 MG/0.4 ML",
+            ]
+        );
+        assert_eq!(lines[19], "-$183.14: HUMALOG 100 UNIT/ML VIAL");
+        assert_eq!(row_errors.count(), 1);
+
+        let mut parquet = Vec::new();
+        let report_options = ReportOptions {
+            output_format: OutputFormat::Parquet,
+            ..Default::default()
+        };
+        write_all_changes(
+            &inputs,
+            &SourceOptions::default(),
+            &report_options,
+            2,
+            &mut RowErrors::default(),
+            &mut parquet,
+        )
+        .await
+        .unwrap();
+        assert!(parquet.starts_with(b"PAR1") && parquet.ends_with(b"PAR1"));
+    }
+
+    #[tokio::test]
+    async fn test_report_by_quarter() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let report_options = ReportOptions {
+            group_by: Some(GroupBy::Quarter),
+            count: 1,
+            ..Default::default()
+        };
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &report_options,
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        assert!(generated_report.starts_with(
+            "Top 1 NADAC per unit price increases of Q1 2023:\n\
+            Rank   Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  $802.39     3.19%  $25172.32  $25974.71  2023-01-11  STELARA 90 MG/ML SYRINGE\n\
+            \n\
+            Top 1 NADAC per unit price decreases of Q1 2023:\n\
+            Rank    Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  -$183.14   -66.67%    $274.70     $91.56  2023-01-04  HUMALOG 100 UNIT/ML VIAL\n\
+            \n\
+            Top 1 NADAC per unit price increases of Q2 2023:\n\
+            \n"
+        ));
+        assert!(generated_report.contains("Top 1 NADAC per unit price decreases of Q4 2023:\n"));
+        assert!(!generated_report.contains("2022"));
+    }
+
+    #[tokio::test]
+    async fn test_report_by_magnitude() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let report_options = ReportOptions {
+            metric: Metric::Magnitude,
+            count: 3,
+            ..Default::default()
+        };
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &report_options,
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            generated_report,
+            "Top 3 NADAC per unit price swings in either direction of 2023:\n\
+            Rank    Change  % Change  Old price  New price  Effective   Drug\n   \
+               1   $802.39     3.19%  $25172.32  $25974.71  2023-01-11  STELARA 90 MG/ML SYRINGE\n   \
+               2   $320.19     9.99%   $3206.71   $3526.91  2023-01-11  HUMIRA(CF) PEN 40 MG/0.4 ML\n   \
+               3  -$183.14   -66.67%    $274.70     $91.56  2023-01-04  HUMALOG 100 UNIT/ML VIAL\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_report_with_classification_sections() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let report_options = ReportOptions {
+            by_classification: true,
+            count: 1,
+            ..Default::default()
+        };
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &SourceOptions::default(),
+            &report_options,
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            generated_report,
+            "Top 1 brand NADAC per unit price increases of 2023:\n\
+            Rank   Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  $802.39     3.19%  $25172.32  $25974.71  2023-01-11  STELARA 90 MG/ML SYRINGE\n\
+            \n\
+            Top 1 brand NADAC per unit price decreases of 2023:\n\
+            Rank    Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  -$183.14   -66.67%    $274.70     $91.56  2023-01-04  HUMALOG 100 UNIT/ML VIAL\n\
+            \n\
+            Top 1 generic NADAC per unit price increases of 2023:\n\
+            Rank  Change  % Change  Old price  New price  Effective   Drug\n   \
+               1   $0.01    11.49%      $0.06      $0.07  2023-10-11  AMOXICILLIN 500 MG CAPSULE\n\
+            \n\
+            Top 1 generic NADAC per unit price decreases of 2023:\n\
+            Rank   Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  -$13.87   -18.44%     $75.21     $61.34  2023-04-12  EPINEPHRINE 0.3 MG AUTO-INJECT\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_report_without_header() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_no_header.csv");
+
+        let mappings: Vec<String> = [
+            "effective_date=0",
+            "description=1",
+            "old_price=2",
+            "new_price=3",
+        ]
+        .iter()
+        .map(|m| m.to_string())
+        .collect();
+        let options = SourceOptions {
+            columns: Some(ColumnMap::from_mappings(&mappings).unwrap()),
+            no_header: true,
+            ..Default::default()
+        };
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &options,
+            &ReportOptions {
+                periods: vec![Period::Year(2023)],
+                count: 1,
+                ..Default::default()
+            },
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        let expected = "Top 1 NADAC per unit price increases of 2023:\n\
+            Rank   Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  $802.39     3.19%  $25172.32  $25974.71  2023-01-11  STELARA 90 MG/ML SYRINGE\n\
+            \n\
+            Top 1 NADAC per unit price decreases of 2023:\n\
+            Rank    Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  -$183.14   -66.67%    $274.70     $91.56  2023-02-15  HUMALOG 100 UNIT/ML VIAL\n";
+
+        assert_eq!(expected, generated_report);
+    }
+
+    #[tokio::test]
+    async fn test_partial_report() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let options = SourceOptions {
+            sampling: Sampling::new(Some(3), None).unwrap(),
+            ..Default::default()
+        };
+
+        let inputs = [Input::Csv(DataSource::File(path))];
+        let generated_report = generate_nadac_top_price_change_report(
+            &inputs,
+            &options,
+            &ReportOptions {
+                periods: vec![Period::Year(2023)],
+                count: 1,
+                ..Default::default()
+            },
+            &mut RowErrors::default(),
+            &mut PriceExtremes::default(),
+            &mut std::io::sink(),
+        )
+        .await
+        .unwrap();
+
+        // Only the first three rows are read, so HUMIRA and HUMALOG never make it in.
+        let expected = "Partial report: only the first 3 rows of each input were read\n\
+            \n\
+            Top 1 NADAC per unit price increases of 2023:\n\
+            Rank   Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  $802.39     3.19%  $25172.32  $25974.71  2023-01-11  STELARA 90 MG/ML SYRINGE\n\
+            \n\
+            Top 1 NADAC per unit price decreases of 2023:\n\
+            Rank  Change  % Change  Old price  New price  Effective   Drug\n   \
+               1  -$0.00   -10.63%      $0.05      $0.04  2023-01-04  ATORVASTATIN 40 MG TABLET\n";
+
+        assert_eq!(expected, generated_report);
+    }
+
+    #[test]
+    fn test_append_needs_a_text_format() {
+        let report_options = |format: &str| {
+            Args::try_parse_from([
+                "top10rust",
+                "--format",
+                format,
+                "--output",
+                "report",
+                "--append",
+            ])
+            .unwrap()
+            .report_options()
+            .err()
+        };
+
+        assert_eq!(report_options("markdown"), None);
+        assert_eq!(
+            report_options("json"),
+            Some(
+                "--append adds text to the end of a file, a json file cannot be appended to"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            report_options("csv"),
+            Some(
+                "--append adds text to the end of a file, a csv file cannot be appended to"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_api_without_columns() {
+        // The API records have none of the columns these options need.
+        for option in [
+            &["--otc", "only"][..],
+            &["--pricing-unit", "EA"],
+            &["--by-pricing-unit"],
+            &["--explanation-code", "1"],
+            &["--explanation-codes"],
+            &["--generic-gap"],
+        ] {
+            let args = Args::try_parse_from(
+                ["top10rust", "--api-dataset", "latest", "-y", "2023"]
+                    .iter()
+                    .chain(option),
+            )
+            .unwrap();
+            assert!(args
+                .report_options()
+                .unwrap_err()
+                .starts_with("--api-dataset records have no over-the-counter indicator"));
+        }
+
+        let args =
+            Args::try_parse_from(["top10rust", "--api-dataset", "latest", "-y", "2023"]).unwrap();
+        assert!(args.report_options().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_exit_status() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+        let directory = tempfile::tempdir().unwrap();
+        let output = directory.path().join("report.txt");
+
+        // The largest increase of 2023 is $802.39.
+        for (threshold, status) in [
+            ("900", ExitCode::SUCCESS),
+            ("800", ExitCode::from(THRESHOLD_EXIT_CODE)),
+        ] {
+            let arguments = [
+                "top10rust",
+                "-i",
+                path.to_str().unwrap(),
+                "-y",
+                "2023",
+                "-o",
+                output.to_str().unwrap(),
+                "--fail-if-max-increase-above",
+                threshold,
+            ];
+            let arguments = arguments
+                .iter()
+                .map(|argument| argument.to_string())
+                .collect();
+            assert_eq!(run(arguments).await.unwrap(), status);
+
+            // The report is written whether or not the threshold is crossed.
+            let report = std::fs::read_to_string(&output).unwrap();
+            assert!(report.starts_with("Top 10 NADAC per unit price increases of 2023:\n"));
+        }
+    }
 }
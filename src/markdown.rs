@@ -0,0 +1,176 @@
+//! The `markdown` module provides code for writing the report as GitHub-flavored Markdown, with
+//! the ranked lists as tables, so it can be pasted into wikis and pull requests as it is.
+
+use crate::data_store::DataStore;
+use crate::report::{drugs_label, ranked_entries, RecordFormat, ReportField, Section};
+
+/// The title at the top of a Markdown report.
+pub const MARKDOWN_TITLE: &str = "# NADAC per unit price changes\n";
+
+/// Escape the characters of a table cell that Markdown would otherwise read as markup.
+///
+/// # Arguments
+///
+/// * `text` - The text of the cell.
+///
+/// # Returns
+///
+/// A new String containing the escaped text.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(c, '|' | '\\' | '*' | '_' | '`' | '<' | '>' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The columns of the tables of price changes after the rank, in order.
+const TABLE_FIELDS: [ReportField; 6] = [
+    ReportField::Change,
+    ReportField::Percent,
+    ReportField::OldPrice,
+    ReportField::NewPrice,
+    ReportField::EffectiveDate,
+    ReportField::Description,
+];
+
+/// Generate a section of the report as a heading and table for each ranked list.
+///
+/// # Arguments
+///
+/// * `data_store` - The records store.
+/// * `section` - The section of the report.
+/// * `format` - How the records' drugs are written.
+///
+/// # Returns
+///
+/// A new String containing the Markdown for the section.
+pub fn markdown_lists(data_store: &DataStore, section: &Section, format: &RecordFormat) -> String {
+    let period = section.period;
+    let drugs = drugs_label(section);
+
+    let mut headings = String::from("| Rank |");
+    let mut alignments = String::from("| ---: |");
+    for field in TABLE_FIELDS {
+        headings.push_str(&format!(" {} |", field.heading()));
+        alignments.push_str(match field {
+            ReportField::Description | ReportField::Ndc => " --- |",
+            _ => " ---: |",
+        });
+    }
+
+    ranked_entries(data_store, format.percent_places)
+        .into_iter()
+        .map(|(kind, count, entries)| {
+            let mut table =
+                format!("## Top {count} {drugs}NADAC per unit price {kind} {period}\n\n");
+            table.push_str(&format!("{}\n{}\n", headings, alignments));
+            for entry in &entries {
+                table.push_str(&format!("| {} |", entry.rank_label()));
+                for field in TABLE_FIELDS {
+                    let cell = match field {
+                        ReportField::Description => format.describe(&entry.description, &entry.ndc),
+                        field => entry.field(field),
+                    };
+                    table.push_str(&format!(" {} |", escape(&cell)));
+                }
+                table.push('\n');
+            }
+            table
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Turn a section of the plain text report into Markdown. The section may hold several lists
+/// separated by blank lines: the first line of each, without the trailing colon, becomes a
+/// heading and the rest is kept as it is in a code block.
+///
+/// # Arguments
+///
+/// * `text` - The plain text section.
+///
+/// # Returns
+///
+/// A new String containing the Markdown for the section.
+pub fn markdown_section(text: &str) -> String {
+    text.split("\n\n")
+        .filter(|list| !list.trim().is_empty())
+        .map(|list| {
+            let (heading, body) = list.split_once('\n').unwrap_or((list, ""));
+            let mut markdown = format!("## {}\n", heading.trim_end().trim_end_matches(':'));
+            if !body.trim().is_empty() {
+                markdown.push_str(&format!("\n```text\n{}\n```\n", body.trim_end()));
+            }
+            markdown
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_store::{Direction, Metric, PriceDetails};
+    use crate::dates::Period;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_markdown() {
+        let mut data_store = DataStore::new(2, 1, Metric::Change, Direction::Both).unwrap();
+        for (cents, description) in [
+            (80239, "STELARA 90 MG/ML SYRINGE"),
+            (32019, "HUMIRA(CF) PEN | 40 MG"),
+            (7595, "ENBREL 50 MG/ML"),
+            (-18314, "HUMALOG_KWIKPEN"),
+        ] {
+            // Only the first change has the prices behind it.
+            let details = (cents == 80239).then(|| PriceDetails {
+                old_price: Decimal::new(2515830, 2),
+                effective_date: NaiveDate::from_ymd_opt(2023, 1, 11),
+            });
+            data_store
+                .insert_priced_change(Decimal::new(cents, 2), description, "", details)
+                .unwrap();
+        }
+        let section = Section {
+            period: Period::Year(2023),
+            classification: None,
+            pricing_unit: None,
+        };
+
+        let format = RecordFormat {
+            percent_places: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            markdown_lists(&data_store, &section, &format),
+            "## Top 2 NADAC per unit price increases of 2023\n\
+            \n\
+            | Rank | Change | % Change | Old price | New price | Effective | Drug |\n\
+            | ---: | ---: | ---: | ---: | ---: | ---: | --- |\n\
+            | 1 | $802.39 | 3.2% | $25158.30 | $25960.69 | 2023-01-11 | \
+            STELARA 90 MG/ML SYRINGE |\n\
+            | 2 | $320.19 |  |  |  |  | HUMIRA(CF) PEN \\| 40 MG |\n\
+            \n\
+            ## Top 1 NADAC per unit price decreases of 2023\n\
+            \n\
+            | Rank | Change | % Change | Old price | New price | Effective | Drug |\n\
+            | ---: | ---: | ---: | ---: | ---: | ---: | --- |\n\
+            | 1 | -$183.14 |  |  |  |  | HUMALOG\\_KWIKPEN |\n"
+        );
+
+        assert_eq!(
+            markdown_section("Summary of price changes:\nCount: 3\nMean: $1.00\n"),
+            "## Summary of price changes\n\n```text\nCount: 3\nMean: $1.00\n```\n"
+        );
+        assert_eq!(
+            markdown_section("Widening:\n$4: LIPITOR\n\nNarrowing:\n"),
+            "## Widening\n\n```text\n$4: LIPITOR\n```\n\n## Narrowing\n"
+        );
+    }
+}
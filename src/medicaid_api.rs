@@ -0,0 +1,251 @@
+//! The `medicaid_api` module provides code for reading NADAC comparison records out of the
+//! paginated data.medicaid.gov datastore JSON API instead of downloading the whole CSV file.
+
+use crate::data_source::RecordStream;
+use crate::http::{send_with_retries, HttpOptions};
+use csv_async::ByteRecord;
+use futures::{StreamExt, TryStreamExt};
+use reqwest::header::HeaderMap;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// The data.medicaid.gov (DKAN) datastore query endpoint.
+static DATASTORE_QUERY_URL: &str = "https://data.medicaid.gov/api/1/datastore/query";
+
+/// The JSON property names of the NADAC comparison columns, in the order of the columns in the
+/// CSV file. Records from the API are converted to CSV records in this order so the rest of
+/// the program can treat them just like rows of the CSV file. The dataset has none of the
+/// columns that follow them in `COLUMN_NAMES`, so the options that need those are rejected.
+const COMPARISON_PROPERTIES: [&str; 10] = [
+    "ndc_description",
+    "ndc",
+    "old_nadac_per_unit",
+    "new_nadac_per_unit",
+    "classification_for_rate_setting",
+    "percent_change",
+    "primary_reason",
+    "start_date",
+    "end_date",
+    "effective_date",
+];
+
+/// The operators the datastore API accepts in conditions, longest first so that `>=` is not
+/// mistaken for `>`.
+const CONDITION_OPERATORS: [&str; 6] = ["!=", ">=", "<=", "=", ">", "<"];
+
+/// A condition used to filter the records on the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiCondition {
+    /// The JSON property to test, e.g. `classification_for_rate_setting`.
+    pub property: String,
+
+    /// The comparison operator.
+    pub operator: String,
+
+    /// The value to compare with.
+    pub value: String,
+}
+
+impl ApiCondition {
+    /// Parse a condition written as `property<operator>value`, e.g. `effective_date>=2023-01-01`.
+    ///
+    /// # Arguments
+    ///
+    /// * `condition` - The condition text.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `ApiCondition`, on error returns a String describing the problem.
+    pub fn parse(condition: &str) -> Result<ApiCondition, String> {
+        for operator in CONDITION_OPERATORS {
+            if let Some((property, value)) = condition.split_once(operator) {
+                if property.trim().is_empty() {
+                    break;
+                }
+
+                return Ok(ApiCondition {
+                    property: property.trim().to_string(),
+                    operator: operator.to_string(),
+                    value: value.trim().to_string(),
+                });
+            }
+        }
+
+        Err(format!(
+            "Expected a condition like property=value but found {}",
+            condition
+        ))
+    }
+}
+
+/// The query used to page records out of the datastore API.
+#[derive(Debug, Clone)]
+pub struct ApiQuery {
+    /// The identifier of the dataset to read.
+    pub dataset_id: String,
+
+    /// The conditions used to filter the records on the server.
+    pub conditions: Vec<ApiCondition>,
+
+    /// The number of records to request per page.
+    pub page_size: usize,
+}
+
+/// The parts of a datastore query response needed to page through the records.
+#[derive(Debug, Deserialize)]
+struct QueryResponse {
+    #[serde(default)]
+    results: Vec<serde_json::Map<String, Value>>,
+}
+
+/// Convert a JSON value into the text of a CSV field.
+fn field_text(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(text)) => normalize_date(text),
+        Some(Value::Null) | None => String::new(),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// The API returns dates as `YYYY-MM-DD` (sometimes with a time), while the CSV file uses
+/// `MM/DD/YYYY`. Convert API dates to the CSV format and leave any other text unchanged.
+fn normalize_date(text: &str) -> String {
+    let date = text.split('T').next().unwrap_or(text);
+    let parts: Vec<&str> = date.split('-').collect();
+
+    let is_date = parts.len() == 3
+        && parts[0].len() == 4
+        && parts[1].len() == 2
+        && parts[2].len() == 2
+        && parts.iter().all(|p| p.chars().all(|c| c.is_ascii_digit()));
+
+    if is_date {
+        format!("{}/{}/{}", parts[1], parts[2], parts[0])
+    } else {
+        text.to_string()
+    }
+}
+
+/// Convert one JSON record from the API into a CSV record in the NADAC comparison column order.
+fn to_record(result: &serde_json::Map<String, Value>) -> ByteRecord {
+    let mut record = ByteRecord::new();
+    for property in COMPARISON_PROPERTIES {
+        record.push_field(field_text(result.get(property)).as_bytes());
+    }
+    record
+}
+
+/// Build the URL for one page of a query.
+fn page_url(query: &ApiQuery, offset: usize) -> Result<reqwest::Url, Box<dyn std::error::Error>> {
+    let mut url = reqwest::Url::parse(&format!("{}/{}/0", DATASTORE_QUERY_URL, query.dataset_id))?;
+
+    {
+        let mut pairs = url.query_pairs_mut();
+        pairs.append_pair("limit", &query.page_size.to_string());
+        pairs.append_pair("offset", &offset.to_string());
+        for (i, condition) in query.conditions.iter().enumerate() {
+            pairs.append_pair(&format!("conditions[{}][property]", i), &condition.property);
+            pairs.append_pair(&format!("conditions[{}][value]", i), &condition.value);
+            pairs.append_pair(&format!("conditions[{}][operator]", i), &condition.operator);
+        }
+    }
+
+    Ok(url)
+}
+
+/// Page through the records of a datastore query, one request per page, converting each JSON
+/// record into a CSV record.
+///
+/// # Arguments
+///
+/// * `client` - The client used to send the requests.
+/// * `query` - The query to run.
+/// * `options` - The retry configuration.
+///
+/// # Returns
+///
+/// A `RecordStream` producing the records.
+pub fn records(client: Client, query: ApiQuery, options: HttpOptions) -> RecordStream<'static> {
+    let pages = futures::stream::try_unfold(
+        (client, query, options, Some(0)),
+        |(client, query, options, offset)| async move {
+            // An offset of None means the last page has already been read.
+            let offset = match offset {
+                Some(offset) => offset,
+                None => return Ok(None),
+            };
+
+            let url = page_url(&query, offset)?;
+            let response: QueryResponse =
+                send_with_retries(&client, url.as_str(), &options, &HeaderMap::new())
+                    .await?
+                    .json()
+                    .await?;
+
+            let page: Vec<ByteRecord> = response.results.iter().map(to_record).collect();
+            let next = if page.len() < query.page_size {
+                None
+            } else {
+                Some(offset + page.len())
+            };
+
+            Ok::<_, Box<dyn std::error::Error>>(Some((page, (client, query, options, next))))
+        },
+    );
+
+    pages
+        .map_ok(|page| futures::stream::iter(page.into_iter().map(Ok)))
+        .try_flatten()
+        .boxed_local()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_condition() {
+        assert_eq!(
+            ApiCondition::parse("effective_date>=2023-01-01").unwrap(),
+            ApiCondition {
+                property: "effective_date".to_string(),
+                operator: ">=".to_string(),
+                value: "2023-01-01".to_string(),
+            }
+        );
+        assert_eq!(
+            ApiCondition::parse("classification_for_rate_setting=G")
+                .unwrap()
+                .operator,
+            "="
+        );
+        assert!(ApiCondition::parse("no operator").is_err());
+        assert!(ApiCondition::parse("=G").is_err());
+    }
+
+    #[test]
+    fn test_to_record() {
+        let json = r#"{
+            "ndc_description": "LISINOPRIL 10 MG TABLET",
+            "ndc": "68180051301",
+            "old_nadac_per_unit": "0.02011",
+            "new_nadac_per_unit": 0.02265,
+            "classification_for_rate_setting": "G",
+            "percent_change": "12.63",
+            "primary_reason": "Survey Rate",
+            "start_date": "2022-12-28",
+            "end_date": null,
+            "effective_date": "2023-01-04T00:00:00"
+        }"#;
+
+        let result: serde_json::Map<String, Value> = serde_json::from_str(json).unwrap();
+        let record = to_record(&result);
+
+        assert_eq!(record.get(0), Some(&b"LISINOPRIL 10 MG TABLET"[..]));
+        assert_eq!(record.get(3), Some(&b"0.02265"[..]));
+        assert_eq!(record.get(7), Some(&b"12/28/2022"[..]));
+        assert_eq!(record.get(8), Some(&b""[..]));
+        assert_eq!(record.get(9), Some(&b"01/04/2023"[..]));
+    }
+}
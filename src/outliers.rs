@@ -0,0 +1,227 @@
+//! The `outliers` module provides code for finding the price changes that are far from the rest
+//! of the year's changes. The distribution is summarized while the data streams past, and only
+//! the most extreme changes are kept as candidates, so memory stays bounded.
+
+use crate::comparison::ComparisonRow;
+use crate::data_store::{DataStore, Direction, Metric};
+use crate::record_pool::RecordPool;
+use crate::report::{dollars, record_string, RecordFormat};
+use crate::statistics::Statistics;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+
+/// Enum describing how far from the distribution a price change must be to be an outlier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutlierRule {
+    /// More than this many standard deviations from the mean.
+    ZScore(Decimal),
+
+    /// More than this many interquartile ranges below the first quartile or above the third.
+    Iqr(Decimal),
+}
+
+/// The `Outliers` struct tracks the distribution of the price changes and the most extreme of
+/// them, so the outliers can be listed once all of the data has been read.
+#[derive(Debug)]
+pub struct Outliers {
+    /// How outliers are recognized.
+    rule: OutlierRule,
+
+    /// The running summary of all of the price changes.
+    statistics: Statistics,
+
+    /// The largest increases and decreases, the only changes that can be outliers.
+    candidates: DataStore,
+
+    /// The number of outliers listed in each direction.
+    limit: usize,
+}
+
+impl Outliers {
+    /// Create a new, empty `Outliers`.
+    ///
+    /// # Arguments
+    ///
+    /// * `rule` - How outliers are recognized.
+    /// * `limit` - The number of outliers listed in each direction.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `Outliers`, on error returns a std::error::Error in a Box.
+    pub fn new(rule: OutlierRule, limit: usize) -> Result<Outliers, Box<dyn std::error::Error>> {
+        Ok(Outliers {
+            rule,
+            statistics: Statistics::default(),
+            candidates: DataStore::new(limit, limit, Metric::Change, Direction::Both)?,
+            limit,
+        })
+    }
+
+    /// Add a price change.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a std::error::Error in a Box.
+    pub fn add(&mut self, row: &ComparisonRow) -> Result<(), Box<dyn std::error::Error>> {
+        self.statistics.add(row.new_price - row.old_price);
+        self.candidates.insert(row)
+    }
+
+    /// Work out the range of price changes that are not outliers.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the lowest and highest changes that are not outliers, or
+    /// None if no price changes have been added.
+    fn bounds(&self) -> Option<(Decimal, Decimal)> {
+        let (low, high) = match self.rule {
+            OutlierRule::ZScore(z) => {
+                let mean = self.statistics.mean()?;
+                let spread = z.to_f64()? * self.statistics.standard_deviation()?;
+                (mean - spread, mean + spread)
+            }
+            OutlierRule::Iqr(k) => {
+                let (first, third) = self.statistics.quartiles()?;
+                let spread = k.to_f64()? * (third - first);
+                (first - spread, third + spread)
+            }
+        };
+        Some((Decimal::from_f64(low)?, Decimal::from_f64(high)?))
+    }
+
+    /// Generate the outliers section of the report. The increases are listed largest first,
+    /// followed by the decreases.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - How the records' drugs are written.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the section.
+    pub fn report(&self, format: &RecordFormat) -> String {
+        let mut report = match self.rule {
+            OutlierRule::ZScore(z) => format!(
+                "Statistical outliers (more than {} standard deviations from the mean):\n",
+                z
+            ),
+            OutlierRule::Iqr(k) => format!(
+                "Statistical outliers (more than {} interquartile ranges outside the quartiles):\n",
+                k
+            ),
+        };
+
+        let mut records = Vec::new();
+        if let Some((low, high)) = self.bounds() {
+            report.push_str(&format!(
+                "Expected range: {} to {}\n",
+                dollars(&low),
+                dollars(&high)
+            ));
+
+            // Until the top pool is full it also holds decreases, so both pools are searched
+            // for outliers in either direction.
+            let mut candidates: Vec<(&Decimal, &usize)> = self
+                .candidates
+                .get_top()
+                .into_iter()
+                .chain(self.candidates.get_bottom())
+                .flat_map(RecordPool::iter)
+                .filter(|(difference, _)| **difference > high || **difference < low)
+                .collect();
+            candidates
+                .sort_by_key(|(difference, _)| (**difference < Decimal::ZERO, -difference.abs()));
+
+            // Only the largest changes are kept, so when every one kept in a direction is an
+            // outlier there may be more.
+            let decreases = candidates
+                .iter()
+                .filter(|(difference, _)| **difference < Decimal::ZERO)
+                .count();
+            if decreases >= self.limit || candidates.len() - decreases >= self.limit {
+                report.push_str(&format!(
+                    "Only the {} largest outliers in each direction are listed, see \
+                    --outlier-count\n",
+                    self.limit
+                ));
+            }
+            records.extend(candidates.into_iter().filter_map(|(difference, code)| {
+                record_string(difference, code, &self.candidates, format)
+            }));
+        }
+
+        if records.is_empty() {
+            report.push_str("No outliers found\n");
+        }
+        for record in records {
+            report.push_str(&record);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+
+    #[test]
+    fn test_outliers() {
+        let mut prices = vec![("HUMIRA", "100"), ("EPINEPHRINE", "-80")];
+        for _ in 0..20 {
+            prices.push(("ASPIRIN", "1"));
+            prices.push(("LISINOPRIL", "-1"));
+        }
+
+        for (rule, header) in [
+            (
+                OutlierRule::ZScore(Decimal::new(3, 0)),
+                "Statistical outliers (more than 3 standard deviations from the mean):\n",
+            ),
+            (
+                OutlierRule::Iqr(Decimal::new(15, 1)),
+                "Statistical outliers (more than 1.5 interquartile ranges outside the \
+                quartiles):\n",
+            ),
+        ] {
+            let mut outliers = Outliers::new(rule, 5).unwrap();
+            assert!(outliers
+                .report(&RecordFormat::default())
+                .ends_with("No outliers found\n"));
+
+            for (description, change) in &prices {
+                let record = ByteRecord::from(vec![description, "", "0", change]);
+                outliers
+                    .add(&ComparisonRow::from_record(&record).unwrap())
+                    .unwrap();
+            }
+
+            let report = outliers.report(&RecordFormat::default());
+            assert!(report.starts_with(header));
+            assert!(
+                report.ends_with("$100: HUMIRA\n-$80: EPINEPHRINE\n"),
+                "{}",
+                report
+            );
+            assert!(!report.contains("--outlier-count"));
+        }
+
+        // With room for one outlier in each direction, the list may be cut short.
+        let mut outliers = Outliers::new(OutlierRule::ZScore(Decimal::new(3, 0)), 1).unwrap();
+        for (description, change) in &prices {
+            let record = ByteRecord::from(vec![description, "", "0", change]);
+            outliers
+                .add(&ComparisonRow::from_record(&record).unwrap())
+                .unwrap();
+        }
+        assert!(outliers.report(&RecordFormat::default()).ends_with(
+            "Only the 1 largest outliers in each direction are listed, see --outlier-count\n\
+            $100: HUMIRA\n\
+            -$80: EPINEPHRINE\n"
+        ));
+    }
+}
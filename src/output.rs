@@ -0,0 +1,162 @@
+//! The `output` module provides code for writing the report to a file chosen with `--output`,
+//! or to standard output. A file is replaced atomically: the report is written to a temporary
+//! file beside it, which is renamed over the file once the report is complete, so a scheduled
+//! run that fails part way never leaves a truncated report behind.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Stdout, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+/// Where the report is written.
+enum Destination {
+    /// Standard output.
+    Stdout(Stdout),
+
+    /// A temporary file, renamed to `path` when the report is finished.
+    Replace { file: NamedTempFile, path: PathBuf },
+
+    /// The end of an existing file, or a new one.
+    Append(File),
+}
+
+/// The `ReportOutput` struct writes the report to a file or to standard output.
+pub struct ReportOutput {
+    /// Where the report is written.
+    destination: Destination,
+}
+
+impl ReportOutput {
+    /// Open the destination of the report.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to write the report to, or `-` or None for standard output.
+    /// * `append` - When true, the report is added to the end of the file, which is created if
+    ///   it does not exist, instead of replacing it.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the new `ReportOutput`, on error returns a std::error::Error in a
+    /// Box.
+    pub fn open(
+        path: Option<&Path>,
+        append: bool,
+    ) -> Result<ReportOutput, Box<dyn std::error::Error>> {
+        let destination = match path {
+            None => Destination::Stdout(std::io::stdout()),
+            Some(path) if path == Path::new("-") => Destination::Stdout(std::io::stdout()),
+            Some(path) if append => Destination::Append(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("Cannot open {}: {}", path.display(), e))?,
+            ),
+            Some(path) => {
+                // The temporary file is in the same directory so that it can be renamed.
+                let directory = match path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent,
+                    _ => Path::new("."),
+                };
+                let file = NamedTempFile::new_in(directory)
+                    .map_err(|e| format!("Cannot write to {}: {}", directory.display(), e))?;
+                Destination::Replace {
+                    file,
+                    path: path.to_path_buf(),
+                }
+            }
+        };
+        Ok(ReportOutput { destination })
+    }
+
+    /// Finish writing the report, moving it into place when it replaces a file. When a
+    /// `ReportOutput` is dropped without being finished, the file it replaces is left as it
+    /// was.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a std::error::Error in a Box.
+    pub fn finish(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.flush()?;
+        match self.destination {
+            Destination::Stdout(_) => {}
+            Destination::Replace { file, path } => {
+                // The temporary file is only readable by its owner, so the report is given the
+                // permissions of the file it replaces, or those of a new file.
+                match std::fs::metadata(&path) {
+                    Ok(metadata) => file.as_file().set_permissions(metadata.permissions())?,
+                    Err(_) => {
+                        #[cfg(unix)]
+                        {
+                            use std::os::unix::fs::PermissionsExt;
+                            file.as_file()
+                                .set_permissions(std::fs::Permissions::from_mode(0o644))?;
+                        }
+                    }
+                }
+                file.as_file().sync_all()?;
+                file.persist(&path)
+                    .map_err(|e| format!("Cannot write {}: {}", path.display(), e.error))?;
+            }
+            Destination::Append(file) => file.sync_all()?,
+        }
+        Ok(())
+    }
+}
+
+impl Write for ReportOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.destination {
+            Destination::Stdout(stdout) => stdout.write(buf),
+            Destination::Replace { file, .. } => file.write(buf),
+            Destination::Append(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.destination {
+            Destination::Stdout(stdout) => stdout.flush(),
+            Destination::Replace { file, .. } => file.flush(),
+            Destination::Append(file) => file.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_output() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("report.txt");
+        std::fs::write(&path, "old report\n").unwrap();
+
+        // The file is only replaced once the report is finished.
+        let mut output = ReportOutput::open(Some(&path), false).unwrap();
+        output.write_all(b"new report\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old report\n");
+        output.finish().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new report\n");
+
+        // A report that is not finished leaves the file and no temporary file behind.
+        let mut output = ReportOutput::open(Some(&path), false).unwrap();
+        output.write_all(b"partial").unwrap();
+        drop(output);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new report\n");
+        assert_eq!(std::fs::read_dir(directory.path()).unwrap().count(), 1);
+
+        let mut output = ReportOutput::open(Some(&path), true).unwrap();
+        output.write_all(b"next report\n").unwrap();
+        output.finish().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "new report\nnext report\n"
+        );
+
+        assert!(
+            ReportOutput::open(Some(&directory.path().join("missing/report.txt")), false).is_err()
+        );
+    }
+}
@@ -0,0 +1,361 @@
+//! The `pdf` module provides code for laying out the report as a PDF, for archiving. The report
+//! is first written as Markdown, which this module reads back: its title, headings and notes
+//! are set in Helvetica and its tables and code blocks in Courier, so their columns line up.
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use pdf_writer::{Content, Date, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+
+/// The width of a US letter page, in points.
+const PAGE_WIDTH: f32 = 612.0;
+
+/// The height of a US letter page, in points.
+const PAGE_HEIGHT: f32 = 792.0;
+
+/// The margin around the text, in points.
+const MARGIN: f32 = 54.0;
+
+/// The size of the Courier text of the tables and code blocks, in points.
+const MONO_SIZE: f32 = 9.0;
+
+/// The number of Courier characters that fit across the page. Each is 0.6 of the text size.
+const MONO_COLUMNS: usize = ((PAGE_WIDTH - 2.0 * MARGIN) / (MONO_SIZE * 0.6)) as usize;
+
+/// The fonts of the report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Style {
+    /// The title, in Helvetica Bold.
+    Title,
+
+    /// A heading, in Helvetica Bold.
+    Heading,
+
+    /// A note, in Helvetica.
+    Body,
+
+    /// A line of a table or code block, in Courier.
+    Mono,
+}
+
+impl Style {
+    /// The name of the style's font in the page resources.
+    fn font(&self) -> Name<'static> {
+        match self {
+            Style::Title | Style::Heading => Name(b"F2"),
+            Style::Body => Name(b"F1"),
+            Style::Mono => Name(b"F3"),
+        }
+    }
+
+    /// The size of the style's text, in points.
+    fn size(&self) -> f32 {
+        match self {
+            Style::Title => 16.0,
+            Style::Heading => 12.0,
+            Style::Body => 10.0,
+            Style::Mono => MONO_SIZE,
+        }
+    }
+
+    /// The space the style's lines take up, in points, with the space above headings.
+    fn height(&self) -> f32 {
+        match self {
+            Style::Title => 26.0,
+            Style::Heading => 22.0,
+            Style::Body => 14.0,
+            Style::Mono => 11.0,
+        }
+    }
+}
+
+/// Undo the escaping of the characters of a Markdown table cell.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Lay out the rows of a Markdown table as lines of Courier text, with each column as wide as
+/// its widest cell and the columns marked `---:` aligned to the right.
+fn table_lines(rows: &[&str]) -> Vec<String> {
+    let cells = |row: &str| -> Vec<String> {
+        let row = row.trim().trim_start_matches('|');
+        let row = row.strip_suffix('|').unwrap_or(row);
+        let mut cells = Vec::new();
+        let mut cell = String::new();
+        let mut escaped = false;
+        for c in row.chars() {
+            if c == '|' && !escaped {
+                cells.push(unescape(cell.trim()));
+                cell.clear();
+            } else {
+                cell.push(c);
+            }
+            escaped = c == '\\' && !escaped;
+        }
+        cells.push(unescape(cell.trim()));
+        cells
+    };
+
+    let mut right = Vec::new();
+    let mut table: Vec<Vec<String>> = Vec::new();
+    for row in rows {
+        let row = cells(row);
+        if row
+            .iter()
+            .all(|cell| cell.trim_end_matches(':').ends_with("---"))
+        {
+            right = row.iter().map(|cell| cell.ends_with(':')).collect();
+        } else {
+            table.push(row);
+        }
+    }
+
+    let columns = table.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            table
+                .iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    table
+        .iter()
+        .map(|row| {
+            let line: Vec<String> = row
+                .iter()
+                .enumerate()
+                .map(|(column, cell)| {
+                    let width = widths[column];
+                    if right.get(column).copied().unwrap_or(false) {
+                        format!("{:>width$}", cell)
+                    } else {
+                        format!("{:<width$}", cell)
+                    }
+                })
+                .collect();
+            line.join("  ").trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Read the lines of a Markdown report and the style to set each in.
+fn styled_lines(markdown: &str) -> Vec<(Style, String)> {
+    let mut lines = Vec::new();
+    let mut table = Vec::new();
+    let mut code = false;
+    for line in markdown.lines() {
+        if !code && line.starts_with('|') {
+            table.push(line);
+            continue;
+        }
+        if !table.is_empty() {
+            lines.extend(
+                table_lines(&table)
+                    .into_iter()
+                    .map(|line| (Style::Mono, line)),
+            );
+            table.clear();
+        }
+
+        if line.starts_with("```") {
+            code = !code;
+        } else if code {
+            lines.push((Style::Mono, line.to_string()));
+        } else if let Some(title) = line.strip_prefix("# ") {
+            lines.push((Style::Title, title.to_string()));
+        } else if let Some(heading) = line.strip_prefix("## ") {
+            lines.push((Style::Heading, heading.to_string()));
+        } else if !line.trim().is_empty() {
+            lines.push((Style::Body, line.to_string()));
+        }
+    }
+    if !table.is_empty() {
+        lines.extend(
+            table_lines(&table)
+                .into_iter()
+                .map(|line| (Style::Mono, line)),
+        );
+    }
+    lines
+}
+
+/// Encode text for the standard fonts, replacing the characters they cannot show with `?`,
+/// and cut off lines of Courier text too long for the page.
+fn encode(style: Style, text: &str) -> Vec<u8> {
+    let mut text: String = text
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() {
+                c
+            } else {
+                '?'
+            }
+        })
+        .collect();
+    if style == Style::Mono && text.len() > MONO_COLUMNS {
+        text.truncate(MONO_COLUMNS - 3);
+        text.push_str("...");
+    }
+    text.into_bytes()
+}
+
+/// Lay out a Markdown report as a PDF.
+///
+/// # Arguments
+///
+/// * `markdown` - The report, written as Markdown.
+/// * `generated` - When the report was generated, which is noted under its title.
+///
+/// # Returns
+///
+/// The contents of the PDF file.
+pub fn pdf_report(markdown: &str, generated: NaiveDateTime) -> Vec<u8> {
+    let mut lines = styled_lines(markdown);
+    let title = match lines.first() {
+        Some((Style::Title, title)) => title.clone(),
+        _ => "NADAC per unit price changes".to_string(),
+    };
+    let position = lines.iter().position(|(style, _)| *style == Style::Title);
+    lines.insert(
+        position.map_or(0, |position| position + 1),
+        (
+            Style::Body,
+            format!("Generated {}", generated.format("%Y-%m-%d %H:%M")),
+        ),
+    );
+
+    // Break the lines into pages.
+    let mut pages: Vec<Vec<(f32, Style, &str)>> = vec![Vec::new()];
+    let mut y = PAGE_HEIGHT - MARGIN;
+    for (style, text) in &lines {
+        if y - style.height() < MARGIN {
+            pages.push(Vec::new());
+            y = PAGE_HEIGHT - MARGIN;
+        }
+        y -= style.height();
+        if let Some(page) = pages.last_mut() {
+            page.push((y, *style, text));
+        }
+    }
+
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let info_id = Ref::new(3);
+    let fonts = [
+        (Ref::new(4), Name(b"F1"), Name(b"Helvetica")),
+        (Ref::new(5), Name(b"F2"), Name(b"Helvetica-Bold")),
+        (Ref::new(6), Name(b"F3"), Name(b"Courier")),
+    ];
+    let page_ids: Vec<(Ref, Ref)> = (0..pages.len() as i32)
+        .map(|index| (Ref::new(7 + 2 * index), Ref::new(8 + 2 * index)))
+        .collect();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id)
+        .kids(page_ids.iter().map(|(page_id, _)| *page_id))
+        .count(pages.len() as i32);
+    let date = Date::new(generated.year() as u16)
+        .month(generated.month() as u8)
+        .day(generated.day() as u8)
+        .hour(generated.hour() as u8)
+        .minute(generated.minute() as u8);
+    pdf.document_info(info_id)
+        .title(TextStr(&title))
+        .creator(TextStr("top10rust"))
+        .creation_date(date);
+    for (font_id, _, base_font) in fonts {
+        pdf.type1_font(font_id)
+            .base_font(base_font)
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+    }
+
+    for (page_lines, (page_id, content_id)) in pages.iter().zip(&page_ids) {
+        let mut page = pdf.page(*page_id);
+        page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT));
+        page.parent(page_tree_id);
+        page.contents(*content_id);
+        let mut resources = page.resources();
+        let mut page_fonts = resources.fonts();
+        for (font_id, name, _) in fonts {
+            page_fonts.pair(name, font_id);
+        }
+        page_fonts.finish();
+        resources.finish();
+        page.finish();
+
+        let mut content = Content::new();
+        for (y, style, text) in page_lines {
+            content.begin_text();
+            content.set_font(style.font(), style.size());
+            content.next_line(MARGIN, *y);
+            content.show(Str(&encode(*style, text)));
+            content.end_text();
+        }
+        pdf.stream(*content_id, &content.finish());
+    }
+
+    pdf.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_pdf_report() {
+        let markdown = "# NADAC per unit price changes\n\
+            \n\
+            Partial report\n\
+            \n\
+            ## Top 2 NADAC per unit price increases of 2023\n\
+            \n\
+            | Rank | Change | Drug |\n\
+            | ---: | ---: | --- |\n\
+            | 1 | $802.39 | STELARA |\n\
+            | 2 | $20.19 | HUMIRA \\| PEN |\n\
+            \n\
+            ## Summary statistics\n\
+            \n\
+            ```text\n\
+            Price changes: 2\n\
+            ```\n";
+
+        assert_eq!(
+            styled_lines(markdown),
+            [
+                (Style::Title, "NADAC per unit price changes".to_string()),
+                (Style::Body, "Partial report".to_string()),
+                (
+                    Style::Heading,
+                    "Top 2 NADAC per unit price increases of 2023".to_string()
+                ),
+                (Style::Mono, "Rank   Change  Drug".to_string()),
+                (Style::Mono, "   1  $802.39  STELARA".to_string()),
+                (Style::Mono, "   2   $20.19  HUMIRA | PEN".to_string()),
+                (Style::Heading, "Summary statistics".to_string()),
+                (Style::Mono, "Price changes: 2".to_string()),
+            ]
+        );
+
+        let generated = NaiveDate::from_ymd_opt(2024, 5, 1)
+            .and_then(|date| date.and_hms_opt(9, 30, 0))
+            .unwrap();
+        let pdf = pdf_report(markdown, generated);
+        assert!(pdf.starts_with(b"%PDF-"));
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("(Generated 2024-05-01 09:30) Tj"));
+        assert!(text.contains("(   1  $802.39  STELARA) Tj"));
+    }
+}
@@ -0,0 +1,242 @@
+//! The `provenance` module provides code for the block at the top of the report that records
+//! how it was made, added with `--provenance`: where the data came from, a hash of the rows
+//! read, when they were read, the arguments of the run and the version of the program, so an
+//! archived report can be checked and made again.
+
+use crate::data_source::RecordStream;
+use chrono::{DateTime, Local};
+use futures::StreamExt;
+use schemars::JsonSchema;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The `DatasetHash` struct hashes the rows read from the inputs with SHA-256. Its clones share
+/// the hash, so it can be handed to every input.
+#[derive(Debug, Clone, Default)]
+pub struct DatasetHash {
+    /// The hash of the rows read so far.
+    hasher: Rc<RefCell<Sha256>>,
+}
+
+impl DatasetHash {
+    /// Hash the rows of a `RecordStream` as they are read.
+    ///
+    /// # Arguments
+    ///
+    /// * `records` - The records to hash.
+    ///
+    /// # Returns
+    ///
+    /// A new `RecordStream` giving the same records.
+    pub fn wrap(&self, records: RecordStream<'static>) -> RecordStream<'static> {
+        let hasher = self.hasher.clone();
+        records
+            .inspect(move |record| {
+                if let Ok(record) = record {
+                    let mut hasher = hasher.borrow_mut();
+                    // Each field is ended, so moving a byte between fields changes the hash.
+                    for field in record.iter() {
+                        hasher.update(field);
+                        hasher.update([0x1f]);
+                    }
+                    hasher.update([b'\n']);
+                }
+            })
+            .boxed_local()
+    }
+
+    /// Get the hash of the rows read so far, in hexadecimal.
+    pub fn hex(&self) -> String {
+        self.hasher
+            .borrow()
+            .clone()
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// The options whose values hold credentials, which are not recorded.
+const SECRET_OPTIONS: [&str; 2] = ["--bearer-token", "--header"];
+
+/// What is recorded in place of a credential.
+const REDACTED: &str = "<redacted>";
+
+/// Hide the value of an option holding credentials. A header keeps its name, so the record
+/// still shows which header was sent.
+fn redact_value(option: &str, value: &str) -> String {
+    match (option, value.split_once(':')) {
+        ("--header", Some((key, _))) => format!("{}:{}", key, REDACTED),
+        _ => REDACTED.to_string(),
+    }
+}
+
+/// Hide the credentials in the arguments of the run, whether an option's value follows it or is
+/// joined to it with `=`.
+fn redact(arguments: &[String]) -> Vec<String> {
+    let mut redacted = Vec::with_capacity(arguments.len());
+    let mut secret_option = None;
+    for argument in arguments {
+        if let Some(option) = secret_option.take() {
+            redacted.push(redact_value(option, argument));
+            continue;
+        }
+        match argument.split_once('=') {
+            Some((option, value)) if SECRET_OPTIONS.contains(&option) => {
+                redacted.push(format!("{}={}", option, redact_value(option, value)));
+            }
+            _ => {
+                if SECRET_OPTIONS.contains(&argument.as_str()) {
+                    secret_option = Some(argument.as_str());
+                }
+                redacted.push(argument.clone());
+            }
+        }
+    }
+    redacted
+}
+
+/// Write the arguments of the run as they would be typed, quoting those with spaces.
+fn command_line(arguments: &[String]) -> String {
+    arguments
+        .iter()
+        .map(|argument| {
+            if argument.is_empty() || argument.contains(char::is_whitespace) {
+                format!("{:?}", argument)
+            } else {
+                argument.clone()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// The `Provenance` struct holds what is recorded about how a report was made.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Provenance {
+    /// Where the data was read from, e.g. a URL or file, in the order it was read.
+    pub sources: Vec<String>,
+
+    /// The SHA-256 hash of the rows read, in hexadecimal.
+    pub sha256: String,
+
+    /// When the data was read, in RFC 3339 format. This is when the first input was opened,
+    /// not when the last row was read.
+    pub fetched: String,
+
+    /// The arguments of the run, which hold its filters, without their credentials.
+    pub arguments: Vec<String>,
+
+    /// The version of the program.
+    pub version: String,
+}
+
+impl Provenance {
+    /// Create a new `Provenance` for the current run.
+    ///
+    /// # Arguments
+    ///
+    /// * `sources` - Where the data was read from.
+    /// * `hash` - The hash of the rows read.
+    /// * `fetched` - When the first input was opened, rather than when the reading finished.
+    /// * `arguments` - The arguments of the run, without the program's name. The values of
+    ///   `--bearer-token` and `--header` are not recorded.
+    ///
+    /// # Returns
+    ///
+    /// The new `Provenance`.
+    pub fn new(
+        sources: Vec<String>,
+        hash: &DatasetHash,
+        fetched: DateTime<Local>,
+        arguments: &[String],
+    ) -> Provenance {
+        Provenance {
+            sources,
+            sha256: hash.hex(),
+            fetched: fetched.to_rfc3339(),
+            arguments: redact(arguments),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// Write the block at the top of the report.
+    ///
+    /// # Returns
+    ///
+    /// A Vec containing the lines of the block, without the line breaks.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for source in &self.sources {
+            lines.push(format!("Source: {}", source));
+        }
+        lines.push(format!("Data SHA-256: {}", self.sha256));
+        lines.push(format!("Data fetched: {}", self.fetched));
+        lines.push(format!("Arguments: {}", command_line(&self.arguments)));
+        lines.push(format!("top10rust version: {}", self.version));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+    use futures::stream;
+    use std::slice;
+
+    /// Hash rows read from one input after another.
+    async fn hash_inputs(inputs: &[&[Vec<&str>]]) -> String {
+        let hash = DatasetHash::default();
+        for rows in inputs {
+            let rows: Vec<Result<ByteRecord, Box<dyn std::error::Error>>> = rows
+                .iter()
+                .map(|row| Ok(ByteRecord::from(row.clone())))
+                .collect();
+            let mut records = hash.wrap(stream::iter(rows).boxed_local());
+            while records.next().await.is_some() {}
+        }
+        hash.hex()
+    }
+
+    #[tokio::test]
+    async fn test_provenance() {
+        let stelara = vec!["STELARA", "00001", "25.15", "27.10"];
+        let humalog = vec!["HUMALOG", "00002", "274.70", "91.56"];
+        let both = hash_inputs(&[&[stelara.clone(), humalog.clone()]]).await;
+        assert_eq!(both.len(), 64);
+        assert_ne!(both, hash_inputs(&[]).await);
+
+        // The hash covers every row read, whatever input it came from, and moving a byte
+        // between fields changes it.
+        assert_eq!(
+            hash_inputs(&[slice::from_ref(&stelara), slice::from_ref(&humalog)]).await,
+            both
+        );
+        assert_ne!(
+            hash_inputs(&[&[vec!["STELARA0", "0001", "25.15", "27.10"], humalog]]).await,
+            both
+        );
+
+        let provenance = Provenance {
+            sources: vec!["data/sample_comparison.csv".to_string()],
+            sha256: both.clone(),
+            fetched: "2024-01-02T03:04:05+00:00".to_string(),
+            arguments: vec!["-y".to_string(), "2023".to_string(), "New York".to_string()],
+            version: "1.0.0".to_string(),
+        };
+        assert_eq!(
+            provenance.lines(),
+            [
+                "Source: data/sample_comparison.csv".to_string(),
+                format!("Data SHA-256: {}", both),
+                "Data fetched: 2024-01-02T03:04:05+00:00".to_string(),
+                "Arguments: -y 2023 \"New York\"".to_string(),
+                "top10rust version: 1.0.0".to_string(),
+            ]
+        );
+    }
+}
@@ -2,7 +2,8 @@
 //! range of records.
 
 use rust_decimal::Decimal;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::HashSet;
 
 /// Enum that controls the accounting of the ordering of the elements
 /// in a `RecordPool`.
@@ -13,20 +14,29 @@ pub enum PoolType {
 
     /// The `RecordPool` contains the bottom smallest values.
     Least,
+
+    /// The `RecordPool` contains the values furthest from zero, in either direction.
+    Magnitude,
 }
 
 /// The `RecordPool` has a container for the difference/description codes and
 /// the other elements needed to efficiently insert and track the pool records.
 /// The `RecordPool` is designed to work closely with the `DataStore`.
+///
+/// Records with the same difference are all kept. When records tied at the cutoff do not all
+/// fit, the ones with the lowest description codes, which are the drugs the `DataStore` saw
+/// first, stay in the pool.
 #[derive(Debug)]
 pub struct RecordPool {
-    /// The map of the difference values and their corresponding description code.
-    pub records: HashMap<Decimal, usize>,
+    /// The difference values and their corresponding description codes.
+    pub records: HashSet<(Decimal, usize)>,
 
-    /// The largest difference stored in the pool.
+    /// The largest difference stored in the pool. For a `PoolType::Magnitude` pool, this is the
+    /// largest magnitude.
     pub largest: Decimal,
 
-    /// The smallest difference stored in the pool.
+    /// The smallest difference stored in the pool. For a `PoolType::Magnitude` pool, this is the
+    /// smallest magnitude.
     pub smallest: Decimal,
 
     /// The number of records allowed in the pool.
@@ -49,7 +59,7 @@ impl RecordPool {
         }
 
         Ok(RecordPool {
-            records: HashMap::new(),
+            records: HashSet::new(),
             largest: Decimal::new(0, 0),
             smallest: Decimal::new(0, 0),
             bounds,
@@ -57,6 +67,28 @@ impl RecordPool {
         })
     }
 
+    /// The value a difference is ordered by in the pool: its magnitude for a
+    /// `PoolType::Magnitude` pool, otherwise the difference itself.
+    fn rank(&self, difference: &Decimal) -> Decimal {
+        match self.pool_type {
+            PoolType::Magnitude => difference.abs(),
+            PoolType::Most | PoolType::Least => *difference,
+        }
+    }
+
+    /// Order two records of the pool from the first to be removed to the last for a
+    /// `PoolType::Most` or `PoolType::Magnitude` pool, or from the last to be removed to the
+    /// first for a `PoolType::Least` pool. Records are ordered by their rank, then their
+    /// difference, then so that the record with the higher code is removed first.
+    fn compare(&self, a: &(Decimal, usize), b: &(Decimal, usize)) -> Ordering {
+        (self.rank(&a.0), a.0)
+            .cmp(&(self.rank(&b.0), b.0))
+            .then_with(|| match self.pool_type {
+                PoolType::Most | PoolType::Magnitude => b.1.cmp(&a.1),
+                PoolType::Least => a.1.cmp(&b.1),
+            })
+    }
+
     /// Determine if the argument difference value should be a member of the pool.
     ///
     /// # Argument
@@ -73,25 +105,26 @@ impl RecordPool {
             return true;
         }
 
+        let rank = self.rank(difference);
         match self.pool_type {
-            PoolType::Most => {
+            PoolType::Most | PoolType::Magnitude => {
                 // In the pool where we track the most, if the difference is bigger than the largest
                 // element it fits.
-                if *difference > self.largest {
+                if rank > self.largest {
                     return true;
                 }
             }
             PoolType::Least => {
                 // In the pool where we track the least, if the difference is smaller than the smallest
                 // difference, it fits.
-                if *difference < self.smallest {
+                if rank < self.smallest {
                     return true;
                 }
             }
         }
 
         // Now test to see if the difference is in the range [smallest, largest]
-        if *difference >= self.smallest && *difference <= self.largest {
+        if rank >= self.smallest && rank <= self.largest {
             return true;
         }
 
@@ -115,58 +148,39 @@ impl RecordPool {
         difference: Decimal,
         description_code: usize,
     ) -> Option<(Decimal, usize)> {
-        // Check to see if the difference fits and that we do not already have this difference
-        // in the pool.
+        // Check to see if the difference fits in the pool.
         if self.fits(&difference) {
             // See if we already have this difference/code in the pool. If so, then just jump
-            // out of this function so we do not insert duplicate records.
-            if let Some(code) = self.records.get(&difference) {
-                if description_code == *code {
-                    return None;
-                }
+            // out of this function so we do not insert duplicate records. The same difference
+            // with another code is a tie and is kept.
+            if !self.records.insert((difference, description_code)) {
+                return None;
             }
 
-            self.records.insert(difference, description_code);
-
             // Check to see if we have exceeded the allowed number of records in the pool.
             if self.records.len() > self.bounds {
                 // We have inserted a new difference value which means our cached smallest/largest
-                // values are invalid. Get the keys of the differences and use the keys array to
-                // calculate what we need to remove.
-                let mut keys: Vec<Decimal> = self.records.keys().map(|k| k.clone()).collect();
-
-                // Sort the keys so that smallest is in keys.first and largest is in keys.last.
-                keys.sort();
-                let result = match self.pool_type {
-                    PoolType::Most => {
-                        // We already know that we have more than one key because the number
-                        // of records in the map exceed our bounds. Even if bounds is 0 that
-                        // means we have at least one key. Similarly, that key has a value.
-                        // Thus, we can safely unwrap the results of a get operation.
-                        let key = keys.remove(0);
-                        let value = self.records.get(&key).unwrap();
-                        let result = Some((key.clone(), *value));
-                        self.records.remove(&key);
-                        result
-                    }
-                    PoolType::Least => {
-                        let key = keys.pop().unwrap();
-                        // Similarly, this unwrap is safe.
-                        let value = self.records.get(&key).unwrap();
-                        let result = Some((key.clone(), *value));
-                        drop(keys);
-                        self.records.remove(&key);
-                        result
-                    }
+                // values are invalid. Get the records and sort them to calculate what we need to
+                // remove.
+                let mut keys: Vec<(Decimal, usize)> = self.records.iter().copied().collect();
+
+                // Sort the records so that smallest is in keys.first and largest is in keys.last.
+                keys.sort_by(|a, b| self.compare(a, b));
+
+                // We already know that we have more than one record because the number of
+                // records in the set exceeds our bounds. Thus, we can safely unwrap the result of
+                // the pop.
+                let removed = match self.pool_type {
+                    PoolType::Most | PoolType::Magnitude => keys.remove(0),
+                    PoolType::Least => keys.pop().unwrap(),
                 };
-                // We have now removed the excess item, so recalculate the keys with a sort
-                // to get the smallest and largest.
-                let mut keys: Vec<&Decimal> = self.records.keys().collect();
-                keys.sort();
-                // Since we are
-                self.smallest = (*keys.first().unwrap()).clone();
-                self.largest = (*keys.last().unwrap()).clone();
-                result
+                self.records.remove(&removed);
+
+                // We have now removed the excess item, so the remaining sorted records give the
+                // smallest and largest.
+                self.smallest = self.rank(&keys.first().unwrap().0);
+                self.largest = self.rank(&keys.last().unwrap().0);
+                Some(removed)
             } else {
                 None
             }
@@ -189,11 +203,11 @@ pub struct RecordPoolIterator<'a> {
     /// The pool reference.
     pool: &'a RecordPool,
 
-    /// The keys for the elements in the pool. Caching them here
+    /// The elements in the pool. Caching them here
     /// only in the iterator helps to do the correct in-order
     /// traversal of the elements without keeping them as a copy
     /// in the pool itself.
-    keys: Vec<&'a Decimal>,
+    keys: Vec<&'a (Decimal, usize)>,
 
     /// For forward iteration, use the index
     index: usize,
@@ -220,8 +234,8 @@ impl<'a> RecordPoolIterator<'a> {
             (pool.records.len() - 1, false)
         };
 
-        let mut keys: Vec<&Decimal> = pool.records.keys().collect();
-        keys.sort();
+        let mut keys: Vec<&(Decimal, usize)> = pool.records.iter().collect();
+        keys.sort_by(|a, b| pool.compare(a, b));
 
         RecordPoolIterator {
             pool,
@@ -238,10 +252,7 @@ impl<'a> Iterator for RecordPoolIterator<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.index < self.pool.records.len() {
-            let key = &self.keys[self.index];
-            // This get call is valid as long as the keys are borrowed
-            // from the pool.
-            let value = self.pool.records.get(*key).unwrap();
+            let (key, value) = self.keys[self.index];
             self.index += 1;
             Some((key, value))
         } else {
@@ -257,16 +268,13 @@ impl<'a> DoubleEndedIterator for RecordPoolIterator<'a> {
         if self.rindex.1 {
             None
         } else {
-            let key = &self.keys[self.rindex.0];
-            // This get call is valid as long as the keys are borrowed
-            // from the pool.
-            let value = self.pool.records.get(key).unwrap();
+            let (key, value) = self.keys[self.rindex.0];
             if self.rindex.0 == 0 {
                 self.rindex.1 = true;
             } else {
                 self.rindex.0 -= 1;
             }
-            Some((*key, value))
+            Some((key, value))
         }
     }
 }
@@ -342,4 +350,46 @@ mod tests {
             counter += 1;
         }
     }
+
+    #[test]
+    fn test_insert_with_magnitude_pool() {
+        let mut pool = RecordPool::new(3, PoolType::Magnitude).unwrap();
+
+        for (difference, code) in [(1, 1), (-5, 2), (2, 3), (4, 4), (-3, 5), (-1, 6)] {
+            pool.insert(Decimal::new(difference, 0), code);
+        }
+
+        assert_eq!(pool.records.len(), 3);
+
+        let differences: Vec<Decimal> = pool.iter().rev().map(|record| *record.0).collect();
+        assert_eq!(
+            differences,
+            vec![Decimal::new(-5, 0), Decimal::new(4, 0), Decimal::new(-3, 0)]
+        );
+    }
+
+    #[test]
+    fn test_insert_with_ties() {
+        let d1 = Decimal::new(1, 0);
+        let d5 = Decimal::new(5, 0);
+
+        // Ties are kept until the pool is full, then the records with the lowest codes stay.
+        let mut pool = RecordPool::new(2, PoolType::Most).unwrap();
+        assert_eq!(pool.insert(d1, 1), None);
+        assert_eq!(pool.insert(d5, 3), None);
+        assert_eq!(pool.insert(d5, 2), Some((d1, 1)));
+        assert_eq!(pool.insert(d5, 4), Some((d5, 4)));
+        assert_eq!(pool.insert(d5, 2), None);
+
+        let records: Vec<(Decimal, usize)> = pool.iter().rev().map(|(d, c)| (*d, *c)).collect();
+        assert_eq!(records, vec![(d5, 2), (d5, 3)]);
+
+        let mut pool = RecordPool::new(2, PoolType::Least).unwrap();
+        for code in [3, 1, 2] {
+            pool.insert(-d5, code);
+        }
+
+        let records: Vec<(Decimal, usize)> = pool.iter().map(|(d, c)| (*d, *c)).collect();
+        assert_eq!(records, vec![(-d5, 1), (-d5, 2)]);
+    }
 }
@@ -1,7 +1,465 @@
 //! The `report` module provides code for working with the elements in the `DataStore` to generate
 //! the report.
-use crate::data_store::DataStore;
+use crate::data_store::{DataStore, Metric};
+use crate::dates::Period;
+use crate::directory::NdcDirectory;
+use crate::filters::{Classification, PricingUnit};
+use crate::locale::Language;
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use comfy_table::{presets, CellAlignment, Table};
+use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Enum describing what the report ranks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReportKind {
+    /// The largest price changes.
+    #[default]
+    Changes,
+
+    /// The drugs with the most price changes, and the most varied among drugs with as many.
+    Volatility,
+
+    /// The drugs with price changes on the most distinct effective dates.
+    ChangeCounts,
+}
+
+/// Enum describing how the report is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Plain text, with the ranked lists as aligned tables.
+    #[default]
+    Text,
+
+    /// Plain text, with each ranked price change as its change and drug, e.g.
+    /// `$1.25: ASPIRIN`, as earlier versions wrote it.
+    Legacy,
+
+    /// GitHub-flavored Markdown, with the ranked lists as tables.
+    Markdown,
+
+    /// A single HTML file, with the ranked lists as styled tables and bar charts.
+    Html,
+
+    /// An Excel workbook, with a sheet for each kind of ranked list and one of summary
+    /// statistics.
+    Xlsx,
+
+    /// A Parquet file of the ranked price changes.
+    Parquet,
+
+    /// An Arrow IPC file of the ranked price changes.
+    Arrow,
+
+    /// A PDF laid out from the Markdown report, for archiving.
+    Pdf,
+
+    /// A CSV file with a row for each ranked price change.
+    Csv,
+
+    /// A JSON document of the ranked lists and their price changes.
+    Json,
+}
+
+impl OutputFormat {
+    /// The name of the format, as it is given to `--format`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Legacy => "legacy",
+            OutputFormat::Markdown => "markdown",
+            OutputFormat::Html => "html",
+            OutputFormat::Xlsx => "xlsx",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Arrow => "arrow",
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+/// Enum describing the fields of a ranked price change the report can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportField {
+    /// The change in the per unit price.
+    Change,
+
+    /// The change as a percent of the old price.
+    Percent,
+
+    /// The per unit price before the change.
+    #[value(name = "old_price")]
+    OldPrice,
+
+    /// The per unit price after the change.
+    #[value(name = "new_price")]
+    NewPrice,
+
+    /// The NDC of the drug.
+    Ndc,
+
+    /// The description of the drug.
+    Description,
+
+    /// The date the new price took effect.
+    #[value(name = "effective_date")]
+    EffectiveDate,
+}
+
+impl ReportField {
+    /// The name of the field, as it is given to `--fields` and used for CSV columns and JSON
+    /// keys.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReportField::Change => "change",
+            ReportField::Percent => "percent",
+            ReportField::OldPrice => "old_price",
+            ReportField::NewPrice => "new_price",
+            ReportField::Ndc => "ndc",
+            ReportField::Description => "description",
+            ReportField::EffectiveDate => "effective_date",
+        }
+    }
+
+    /// The heading of the field's column in the text report.
+    pub fn heading(&self) -> &'static str {
+        match self {
+            ReportField::Change => "Change",
+            ReportField::Percent => "% Change",
+            ReportField::OldPrice => "Old price",
+            ReportField::NewPrice => "New price",
+            ReportField::Ndc => "NDC",
+            ReportField::Description => "Drug",
+            ReportField::EffectiveDate => "Effective",
+        }
+    }
+}
+
+/// The number of decimal places of the percent changes when no other number is chosen.
+pub const DEFAULT_PERCENT_PLACES: u32 = 2;
+
+/// The most decimal places of the percent changes that can be chosen.
+pub const MAX_PERCENT_PLACES: u32 = 10;
+
+/// The width of the bar of the largest change of a list drawn with `--chart`, in characters.
+const CHART_WIDTH: usize = 40;
+
+/// The fields of the text report when no others are chosen.
+pub const TEXT_FIELDS: [ReportField; 6] = [
+    ReportField::Change,
+    ReportField::Percent,
+    ReportField::OldPrice,
+    ReportField::NewPrice,
+    ReportField::EffectiveDate,
+    ReportField::Description,
+];
+
+/// The fields of the CSV and JSON output when no others are chosen.
+pub const ALL_FIELDS: [ReportField; 7] = [
+    ReportField::Change,
+    ReportField::Percent,
+    ReportField::OldPrice,
+    ReportField::NewPrice,
+    ReportField::Ndc,
+    ReportField::Description,
+    ReportField::EffectiveDate,
+];
+
+/// The `ReportEntry` struct holds a ranked price change with everything the report can show
+/// about it. The output formats all write their lines, rows and objects from it.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ReportEntry {
+    /// The rank of the change in its list, starting from 1. Changes tied with the ones above
+    /// them share their rank, e.g. 1, 2, 2, 4.
+    pub rank: usize,
+
+    /// When true, the change is tied with another change of its list.
+    pub tied: bool,
+
+    /// The change in the per unit price.
+    pub change: Decimal,
+
+    /// The change as a percent of the old price, rounded to the number of places requested,
+    /// when the old price is known and not zero.
+    pub percent: Option<Decimal>,
+
+    /// The per unit price before the change, when it is known. It is not known for changes
+    /// that combine several price changes of a drug.
+    pub old_price: Option<Decimal>,
+
+    /// The per unit price after the change, when it is known.
+    pub new_price: Option<Decimal>,
+
+    /// The NDC of the drug, which may be empty.
+    pub ndc: String,
+
+    /// The description of the drug.
+    pub description: String,
+
+    /// The date the new price took effect, when it is known.
+    pub effective_date: Option<NaiveDate>,
+}
+
+impl ReportEntry {
+    /// Write the rank of the entry, e.g. `3`, or `3=` when it is tied.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the rank.
+    pub fn rank_label(&self) -> String {
+        if self.tied {
+            format!("{}=", self.rank)
+        } else {
+            self.rank.to_string()
+        }
+    }
+
+    /// Write a field of the entry as text.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the field, which is empty when the field is not known.
+    pub fn field(&self, field: ReportField) -> String {
+        match field {
+            ReportField::Change => dollars(&self.change),
+            ReportField::Percent => self
+                .percent
+                .map(|percent| format!("{}%", percent))
+                .unwrap_or_default(),
+            ReportField::OldPrice => self.old_price.as_ref().map(dollars).unwrap_or_default(),
+            ReportField::NewPrice => self.new_price.as_ref().map(dollars).unwrap_or_default(),
+            ReportField::Ndc => self.ndc.clone(),
+            ReportField::Description => self.description.clone(),
+            ReportField::EffectiveDate => self
+                .effective_date
+                .map(|date| date.to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Write a field of the entry as a plain value for machine-readable output, e.g. `-1.25`
+    /// for a change of `-$1.25`.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the value, which is empty when the field is not known.
+    pub fn value(&self, field: ReportField) -> String {
+        match field {
+            ReportField::Change => self.change.normalize().to_string(),
+            ReportField::Percent => self
+                .percent
+                .map(|percent| percent.to_string())
+                .unwrap_or_default(),
+            ReportField::OldPrice => self
+                .old_price
+                .map(|price| price.normalize().to_string())
+                .unwrap_or_default(),
+            ReportField::NewPrice => self
+                .new_price
+                .map(|price| price.normalize().to_string())
+                .unwrap_or_default(),
+            field => self.field(field),
+        }
+    }
+}
+
+/// The `Section` struct identifies a section of the report, which has its own top and bottom
+/// price changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Section {
+    /// The span of effective dates of the section.
+    pub period: Period,
+
+    /// When set, the section only covers drugs with this classification for rate setting.
+    pub classification: Option<Classification>,
+
+    /// When set, the section only covers drugs priced per this unit.
+    pub pricing_unit: Option<PricingUnit>,
+}
+
+/// The `RecordFormat` struct describes how the drugs are written in the report.
+#[derive(Debug, Clone, Copy)]
+pub struct RecordFormat<'a> {
+    /// When true, each drug's NDC follows its description.
+    pub show_ndc: bool,
+
+    /// When set, each drug's manufacturer from this directory follows its description.
+    pub directory: Option<&'a NdcDirectory>,
+
+    /// When set, the columns of the text report after the rank, in order, in place of
+    /// `TEXT_FIELDS`.
+    pub fields: Option<&'a [ReportField]>,
+
+    /// When true, each price change is written as its change and drug, e.g. `$1.25: ASPIRIN`,
+    /// as earlier versions did, instead of as a row of a table.
+    pub legacy: bool,
+
+    /// The number of decimal places of the percent changes.
+    pub percent_places: u32,
+
+    /// When true, each row of the text report ends with a bar as long as its change is large.
+    pub chart: bool,
+
+    /// The language of the headings of the text report.
+    pub language: Language,
+}
+
+impl Default for RecordFormat<'_> {
+    fn default() -> Self {
+        RecordFormat {
+            show_ndc: false,
+            directory: None,
+            fields: None,
+            legacy: false,
+            percent_places: DEFAULT_PERCENT_PLACES,
+            chart: false,
+            language: Language::En,
+        }
+    }
+}
+
+impl RecordFormat<'_> {
+    /// Describe a drug for the report.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - The drug's description.
+    /// * `ndc` - The drug's NDC, which may be empty.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the description, followed by the NDC and manufacturer when
+    /// they are requested and known.
+    pub fn describe(&self, description: &str, ndc: &str) -> String {
+        let mut text = description.to_string();
+        if self.show_ndc && !ndc.is_empty() {
+            text.push_str(&format!(" (NDC {})", ndc));
+        }
+        if let Some(manufacturer) = self
+            .directory
+            .and_then(|directory| directory.manufacturer(ndc))
+        {
+            text.push_str(&format!(" [{}]", manufacturer));
+        }
+        text
+    }
+
+    /// Write the price changes of a ranked list as lines of the text report, without the line
+    /// breaks. The lines are an aligned table of the rank and fields of each change, with a
+    /// row of headings and, with `chart`, a bar for each change, or the change and drug of
+    /// each with `legacy`.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The price changes, in rank order.
+    ///
+    /// # Returns
+    ///
+    /// A Vec containing the lines, which is empty when there are no price changes.
+    pub fn lines(&self, entries: &[ReportEntry]) -> Vec<String> {
+        if self.legacy {
+            return entries
+                .iter()
+                .map(|entry| {
+                    format!(
+                        "{}: {}",
+                        dollars(&entry.change),
+                        self.describe(&entry.description, &entry.ndc)
+                    )
+                })
+                .collect();
+        }
+        if entries.is_empty() {
+            return Vec::new();
+        }
+
+        let fields = self.fields.unwrap_or(&TEXT_FIELDS);
+        let mut table = Table::new();
+        table.load_preset(presets::NOTHING);
+        let mut headings = vec![self.language.rank()];
+        headings.extend(fields.iter().map(|field| self.language.heading(*field)));
+        if self.chart {
+            headings.push("");
+        }
+        table.set_header(headings);
+        let largest = entries
+            .iter()
+            .map(|entry| entry.change.abs())
+            .max()
+            .unwrap_or_default();
+        for entry in entries {
+            let mut row = vec![entry.rank_label()];
+            row.extend(fields.iter().map(|field| match field {
+                ReportField::Description => self.describe(&entry.description, &entry.ndc),
+                field => entry.field(*field),
+            }));
+            if self.chart {
+                row.push(chart_bar(&entry.change, &largest));
+            }
+            table.add_row(row);
+        }
+
+        // The numbers are aligned to the right and the columns are separated by two spaces.
+        let right = [
+            ReportField::Change,
+            ReportField::Percent,
+            ReportField::OldPrice,
+            ReportField::NewPrice,
+        ];
+        for (index, column) in table.column_iter_mut().enumerate() {
+            if index == 0
+                || fields
+                    .get(index - 1)
+                    .is_some_and(|field| right.contains(field))
+            {
+                column.set_cell_alignment(CellAlignment::Right);
+            }
+            column.set_padding((0, 2));
+        }
+        table
+            .lines()
+            .map(|line| line.trim_end().to_string())
+            .collect()
+    }
+}
+
+/// Draw the bar of a price change for the text report, as long as the change is large
+/// compared to the largest change of its list, which is `CHART_WIDTH` characters long.
+/// Increases are drawn with `#` and decreases with `=`. Every change that is not zero has at
+/// least one character.
+///
+/// # Arguments
+///
+/// * `change` - The price change.
+/// * `largest` - The largest size of a change in the list.
+///
+/// # Returns
+///
+/// A new String containing the bar.
+fn chart_bar(change: &Decimal, largest: &Decimal) -> String {
+    if change.is_zero() || largest.is_zero() {
+        return String::new();
+    }
+    let length = (change.abs() / largest * Decimal::from(CHART_WIDTH))
+        .round()
+        .to_usize()
+        .unwrap_or_default()
+        .max(1);
+    let mark = if change.is_sign_negative() { "=" } else { "#" };
+    mark.repeat(length)
+}
 
 /// Create a formatted string representing the record from the `DataStore`.
 ///
@@ -10,57 +468,316 @@ use rust_decimal::Decimal;
 /// * `difference` - The record's difference value.
 /// * `code` - The code representing the record's description.
 /// * `data_store` - The `DataStore` instance used to convert the code to the description.
+/// * `format` - How the record's drug is written.
 ///
 /// # Returns
 ///
 /// An Option which will contain the formatted record for the report if the record code
 /// could be converted to a description.
-fn record_string(difference: &Decimal, code: &usize, data_store: &DataStore) -> Option<String> {
-    if let Some(description) = data_store.get_description_for_code(*code) {
-        if difference.is_zero() || difference.is_sign_positive() {
-            Some(format!("${}: {}\n", difference.round_dp(2), description))
-        } else {
-            Some(format!(
-                "-${}: {}\n",
-                difference.abs().round_dp(2),
-                description
-            ))
-        }
+pub fn record_string(
+    difference: &Decimal,
+    code: &usize,
+    data_store: &DataStore,
+    format: &RecordFormat,
+) -> Option<String> {
+    if let Some(drug) = data_store.get_drug_for_code(*code) {
+        let description = format.describe(&drug.description, &drug.ndc);
+
+        Some(format!("{}: {}\n", dollars(difference), description))
     } else {
         None
     }
 }
 
-/// Generate the report for the exercise.
+/// Format an amount of money for the report, rounded to cents, e.g. `$1.25` or `-$0.50`.
+///
+/// # Arguments
+///
+/// * `amount` - The amount.
+///
+/// # Returns
+///
+/// A new String containing the formatted amount.
+pub fn dollars(amount: &Decimal) -> String {
+    if amount.is_zero() || amount.is_sign_positive() {
+        format!("${}", amount.round_dp(2))
+    } else {
+        format!("-${}", amount.abs().round_dp(2))
+    }
+}
+
+/// Generate the report for the exercise. With `Metric::Magnitude`, the report has a single
+/// section of the largest changes in either direction. Each section is headed with the number
+/// of records its pool holds.
 ///
 /// # Arguments
 ///
 /// * `data_store` - The records store.
-/// * `count` - The number of records requested for the report.
-/// * `year` - The requested year for the report.
+/// * `section` - The section of the report, giving the span of effective dates it covers and
+///   the classification of its drugs.
+/// * `format` - How the records' drugs are written.
+///
+/// # Returns
+///
+/// A new String containing the report.
+pub fn generate_report(data_store: &DataStore, section: &Section, format: &RecordFormat) -> String {
+    ranked_entries(data_store, format.percent_places)
+        .into_iter()
+        .map(|(kind, count, entries)| {
+            let heading = format
+                .language
+                .list_heading(count, section, kind, Some(&section.period));
+            let mut report = format!("{}:\n", heading);
+            for line in format.lines(&entries) {
+                report.push_str(&line);
+                report.push('\n');
+            }
+            report
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Generate the report for several years with their sections side by side, so the largest
+/// changes of each year can be compared line by line.
+///
+/// # Arguments
+///
+/// * `sections` - The sections of the report and their records stores, in date order.
+/// * `format` - How the records' drugs are written.
 ///
 /// # Returns
 ///
 /// A new String containing the report.
-pub fn generate_report(data_store: &DataStore, count: &usize, year: &i32) -> String {
-    let mut report = format!("Top {count} NADAC per unit price increases of {year}:\n");
-    for record in data_store.get_top().iter().rev() {
-        if let Some(record_str) = record_string(record.0, record.1, data_store) {
-            report.push_str(&record_str);
+pub fn generate_side_by_side_report(
+    sections: &[(&Section, &DataStore)],
+    format: &RecordFormat,
+) -> String {
+    // Only the sections for the same drugs are put side by side.
+    let mut segments: Vec<(Option<Classification>, Option<PricingUnit>)> = sections
+        .iter()
+        .map(|(section, _)| (section.classification, section.pricing_unit))
+        .collect();
+    segments.sort();
+    segments.dedup();
+
+    let mut blocks = Vec::new();
+    for segment in segments {
+        let columns: Vec<(&Section, Vec<RankedEntries>)> = sections
+            .iter()
+            .filter(|(section, _)| (section.classification, section.pricing_unit) == segment)
+            .map(|(section, data_store)| {
+                (*section, ranked_entries(data_store, format.percent_places))
+            })
+            .collect();
+
+        let kinds: Vec<(&str, usize)> = columns[0]
+            .1
+            .iter()
+            .map(|(kind, count, _)| (*kind, *count))
+            .collect();
+        for (index, (kind, count)) in kinds.iter().enumerate() {
+            let cells: Vec<Vec<String>> = columns
+                .iter()
+                .map(|(section, lists)| {
+                    let label = match section.period {
+                        Period::Year(year) => year.to_string(),
+                        period => format.language.period(&period),
+                    };
+                    std::iter::once(label)
+                        .chain(format.lines(&lists[index].2))
+                        .collect()
+                })
+                .collect();
+
+            let heading = format
+                .language
+                .list_heading(*count, columns[0].0, kind, None);
+            let mut block = format!("{}:\n", heading);
+            block.push_str(&side_by_side(&cells));
+            blocks.push(block);
         }
     }
 
-    report.push_str("\n");
+    blocks.join("\n")
+}
+
+/// The label of a period used for the columns and rows of the report, e.g. `2023` for a year
+/// or `of Q1 2023` for a quarter.
+pub fn period_label(period: &Period) -> String {
+    match period {
+        Period::Year(year) => year.to_string(),
+        period => period.to_string(),
+    }
+}
+
+/// The words for the drugs of a section used in the report headers, e.g. `brand EA-priced `,
+/// with a trailing space, or nothing when the section covers every classification and pricing
+/// unit.
+pub fn drugs_label(section: &Section) -> String {
+    Language::En.drugs(section)
+}
 
-    report.push_str(&format!(
-        "Top {count} NADAC per unit price decreases of {year}:\n"
-    ));
+/// A ranked list of a records store with everything the report can show about its records:
+/// the kind of change it holds, the number of records requested for it and its entries.
+pub type RankedEntries = (&'static str, usize, Vec<ReportEntry>);
 
-    for record in data_store.get_bottom().iter() {
-        if let Some(record_str) = record_string(record.0, record.1, data_store) {
-            report.push_str(&record_str);
+/// Get the ranked lists of a records store, with the kind of change each list holds and the
+/// bounds of its pool. With `Metric::Magnitude` there is a single list of the changes in
+/// either direction, otherwise there are lists of the increases and the decreases, for the
+/// directions the store tracks.
+///
+/// # Arguments
+///
+/// * `data_store` - The records store.
+/// * `percent_places` - The number of decimal places of the percent changes.
+///
+/// # Returns
+///
+/// The kind of change, the number of records requested and the entries of each list.
+pub fn ranked_entries(data_store: &DataStore, percent_places: u32) -> Vec<RankedEntries> {
+    let record = |(difference, code): (&Decimal, &usize)| {
+        let details = data_store.get_details(*difference, *code);
+        data_store.get_drug_for_code(*code).map(|drug| ReportEntry {
+            rank: 0,
+            tied: false,
+            change: *difference,
+            percent: details
+                .filter(|details| !details.old_price.is_zero())
+                .and_then(|details| difference.checked_div(details.old_price))
+                .and_then(|ratio| ratio.checked_mul(Decimal::ONE_HUNDRED))
+                .map(|percent| {
+                    // Every percent has the same number of places, e.g. `10.00` and `3.19`.
+                    let mut percent = percent.round_dp(percent_places);
+                    percent.rescale(percent_places);
+                    percent
+                }),
+            old_price: details.map(|details| details.old_price),
+            new_price: details.map(|details| details.old_price + difference),
+            ndc: drug.ndc.clone(),
+            description: drug.description.clone(),
+            effective_date: details.and_then(|details| details.effective_date),
+        })
+    };
+
+    let mut lists: Vec<RankedEntries> = Vec::new();
+    match data_store.metric {
+        Metric::Magnitude => {
+            if let Some(top) = data_store.get_top() {
+                lists.push((
+                    "swings in either direction",
+                    top.bounds,
+                    top.iter().rev().filter_map(record).collect(),
+                ));
+            }
+        }
+        Metric::Change => {
+            // Until the pools are full, decreases are held in the top pool and increases can be
+            // moved to the bottom pool, so each list takes the changes of its direction from
+            // both. The changes of the pools, largest first, are in the top pool's order and then
+            // the bottom pool's, since the bottom pool only holds changes no larger than those of
+            // the top pool.
+            let mut largest_first = Vec::new();
+            if let Some(top) = data_store.get_top() {
+                largest_first.extend(top.iter().rev());
+            }
+            if let Some(bottom) = data_store.get_bottom() {
+                largest_first.extend(bottom.iter().rev());
+            }
+
+            if let Some(top) = data_store.get_top() {
+                lists.push((
+                    "increases",
+                    top.bounds,
+                    largest_first
+                        .iter()
+                        .filter(|(difference, _)| **difference >= Decimal::ZERO)
+                        .take(top.bounds)
+                        .filter_map(|pooled| record(*pooled))
+                        .collect(),
+                ));
+            }
+            if let Some(bottom) = data_store.get_bottom() {
+                lists.push((
+                    "decreases",
+                    bottom.bounds,
+                    largest_first
+                        .iter()
+                        .rev()
+                        .filter(|(difference, _)| **difference < Decimal::ZERO)
+                        .take(bottom.bounds)
+                        .filter_map(|pooled| record(*pooled))
+                        .collect(),
+                ));
+            }
         }
     }
+    for (_, _, entries) in &mut lists {
+        rank_entries(entries, data_store.metric);
+    }
+    lists
+}
 
-    report
+/// Number the entries of a ranked list, giving the entries with the same change, or the same
+/// size of change with `Metric::Magnitude`, the same rank.
+///
+/// # Arguments
+///
+/// * `entries` - The entries, in rank order.
+/// * `metric` - How the entries are ranked.
+fn rank_entries(entries: &mut [ReportEntry], metric: Metric) {
+    let key = |entry: &ReportEntry| match metric {
+        Metric::Change => entry.change,
+        Metric::Magnitude => entry.change.abs(),
+    };
+    for index in 0..entries.len() {
+        let previous = index.checked_sub(1).map(|previous| &entries[previous]);
+        let next = entries.get(index + 1);
+        let key_of = key(&entries[index]);
+        let rank = match previous {
+            Some(previous) if key(previous) == key_of => previous.rank,
+            _ => index + 1,
+        };
+        let tied = previous.is_some_and(|previous| key(previous) == key_of)
+            || next.is_some_and(|next| key(next) == key_of);
+        entries[index].rank = rank;
+        entries[index].tied = tied;
+    }
+}
+
+/// Lay out columns of text side by side, separated by `|`.
+///
+/// # Arguments
+///
+/// * `columns` - The lines of each column.
+///
+/// # Returns
+///
+/// A new String containing the lines of the columns.
+fn side_by_side(columns: &[Vec<String>]) -> String {
+    let widths: Vec<usize> = columns
+        .iter()
+        .map(|column| {
+            column
+                .iter()
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let rows = columns.iter().map(Vec::len).max().unwrap_or(0);
+
+    let mut text = String::new();
+    for row in 0..rows {
+        let cells: Vec<String> = columns
+            .iter()
+            .zip(&widths)
+            .map(|(column, width)| {
+                format!("{:<width$}", column.get(row).map_or("", String::as_str))
+            })
+            .collect();
+        text.push_str(cells.join(" | ").trim_end());
+        text.push('\n');
+    }
+    text
 }
@@ -0,0 +1,228 @@
+//! The `row_errors` module provides code for keeping track of the rows of the price change data
+//! that were skipped because they were missing data or had invalid values, so data quality
+//! problems can be reported to whoever publishes the data, and of the rows the report leaves out
+//! for other reasons, e.g. because they are outside the report's periods.
+
+use crate::columns::{decode_field, COLUMN_NAMES};
+use csv_async::ByteRecord;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio_util::compat::TokioAsyncWriteCompatExt;
+
+/// A row that was skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowError {
+    /// Where the row came from.
+    pub source: String,
+
+    /// The line of the row in the CSV data, when the row came from CSV data.
+    pub line: Option<u64>,
+
+    /// The name of the offending column.
+    pub field: String,
+
+    /// The offending value.
+    pub value: String,
+
+    /// Why the row was skipped.
+    pub reason: String,
+}
+
+/// The `RowErrors` struct collects the rows skipped while building a report.
+#[derive(Debug, Default)]
+pub struct RowErrors {
+    /// When true, the details of each skipped row are kept so they can be written out.
+    keep: bool,
+
+    /// The number of skipped rows.
+    count: usize,
+
+    /// The number of skipped rows for each offending column, split into the rows missing the
+    /// column's value and the rows with an invalid value.
+    fields: BTreeMap<(String, bool), usize>,
+
+    /// The number of rows left out of the report for each reason.
+    left_out: BTreeMap<String, usize>,
+
+    /// The details of the skipped rows, when they are kept.
+    errors: Vec<RowError>,
+}
+
+impl RowErrors {
+    /// Create a new `RowErrors`.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep` - When true, the details of each skipped row are kept. Otherwise the rows are
+    ///   only counted.
+    pub fn new(keep: bool) -> RowErrors {
+        RowErrors {
+            keep,
+            ..Default::default()
+        }
+    }
+
+    /// Record a skipped row.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Where the row came from.
+    /// * `record` - The skipped row.
+    /// * `field` - The index of the offending column in the NADAC comparison columns.
+    /// * `reason` - Why the row was skipped.
+    pub fn add(&mut self, source: &str, record: &ByteRecord, field: usize, reason: &str) {
+        self.add_named(source, record, field, COLUMN_NAMES[field], reason);
+    }
+
+    /// Record a skipped row that is not in the NADAC comparison column order.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Where the row came from.
+    /// * `record` - The skipped row.
+    /// * `field` - The index of the offending column in the row.
+    /// * `name` - The name of the offending column.
+    /// * `reason` - Why the row was skipped.
+    pub fn add_named(
+        &mut self,
+        source: &str,
+        record: &ByteRecord,
+        field: usize,
+        name: &str,
+        reason: &str,
+    ) {
+        let value = decode_field(record, field).unwrap_or_default();
+        let missing = value.trim().is_empty();
+        self.count += 1;
+        *self.fields.entry((name.to_string(), missing)).or_default() += 1;
+
+        if self.keep {
+            self.errors.push(RowError {
+                source: source.to_string(),
+                line: record.position().map(|position| position.line()),
+                field: name.to_string(),
+                value: value.into_owned(),
+                reason: reason.to_string(),
+            });
+        }
+    }
+
+    /// Count a row that has usable data but is left out of the report, e.g. by a filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the row was left out, e.g. `outside the report's periods`.
+    pub fn leave_out(&mut self, reason: &str) {
+        *self.left_out.entry(reason.to_string()).or_default() += 1;
+    }
+
+    /// Get the number of skipped rows.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Get the number of skipped rows for each reason, e.g. `missing effective_date`, in column
+    /// name order with the missing values of a column before its invalid ones.
+    pub fn by_reason(&self) -> Vec<(String, usize)> {
+        let mut fields: Vec<_> = self.fields.iter().collect();
+        fields.sort_by(|((a, a_missing), _), ((b, b_missing), _)| {
+            a.cmp(b).then(b_missing.cmp(a_missing))
+        });
+        fields
+            .into_iter()
+            .map(|((field, missing), count)| {
+                let problem = if *missing { "missing" } else { "invalid" };
+                (format!("{} {}", problem, field), *count)
+            })
+            .collect()
+    }
+
+    /// Get the number of rows left out of the report for each reason, in reason order.
+    pub fn left_out(&self) -> Vec<(String, usize)> {
+        self.left_out
+            .iter()
+            .map(|(reason, count)| (reason.clone(), *count))
+            .collect()
+    }
+
+    /// Get the details of the skipped rows. This is empty unless the details are kept.
+    #[cfg(test)]
+    pub fn errors(&self) -> &[RowError] {
+        &self.errors
+    }
+
+    /// Write the details of the skipped rows to a CSV file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file to write.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a std::error::Error in a Box.
+    pub async fn write_csv(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let file = tokio::fs::File::create(path).await?;
+        let mut writer = csv_async::AsyncWriter::from_writer(file.compat_write());
+
+        writer
+            .write_record(["source", "line", "field", "value", "reason"])
+            .await?;
+        for error in &self.errors {
+            let line = error.line.map(|line| line.to_string()).unwrap_or_default();
+            writer
+                .write_record([
+                    error.source.as_str(),
+                    line.as_str(),
+                    error.field.as_str(),
+                    error.value.as_str(),
+                    error.reason.as_str(),
+                ])
+                .await?;
+        }
+        writer.flush().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_errors() {
+        let record = ByteRecord::from(vec!["ASPIRIN 81 MG", "", "1.25", "N/A"]);
+
+        let mut counted = RowErrors::new(false);
+        counted.add("a.csv", &record, 3, "invalid price");
+        counted.add("a.csv", &record, 3, "invalid price");
+        counted.add("a.csv", &record, 1, "missing NDC");
+        counted.leave_out("outside the report's periods");
+        assert_eq!(counted.count(), 3);
+        assert_eq!(
+            counted.by_reason(),
+            [
+                ("missing ndc".to_string(), 1),
+                ("invalid new_price".to_string(), 2)
+            ]
+        );
+        assert_eq!(
+            counted.left_out(),
+            [("outside the report's periods".to_string(), 1)]
+        );
+        assert!(counted.errors().is_empty());
+
+        let mut kept = RowErrors::new(true);
+        kept.add("a.csv", &record, 3, "invalid price");
+        assert_eq!(
+            kept.errors(),
+            &[RowError {
+                source: "a.csv".to_string(),
+                line: None,
+                field: "new_price".to_string(),
+                value: "N/A".to_string(),
+                reason: "invalid price".to_string(),
+            }]
+        );
+    }
+}
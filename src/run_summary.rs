@@ -0,0 +1,186 @@
+//! The `run_summary` module provides code for the footer of the report that describes the run
+//! that produced it, added with `--run-summary`: how many rows were read, skipped, left out and
+//! inserted, how many descriptions were stored, how long the run took and how much data was downloaded,
+//! so a scheduled run can be checked at a glance.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The `RunCounters` struct counts the rows read and bytes downloaded while the inputs are
+/// read. Its clones share the counts, so it can be handed to every input.
+#[derive(Debug, Clone)]
+pub struct RunCounters {
+    /// When the run started.
+    started: Instant,
+
+    /// The number of rows read from the inputs.
+    rows_read: Arc<AtomicU64>,
+
+    /// The number of rows read that were kept by the sampling of the inputs.
+    rows_sampled: Arc<AtomicU64>,
+
+    /// The number of bytes downloaded from remote sources.
+    bytes_downloaded: Arc<AtomicU64>,
+}
+
+impl Default for RunCounters {
+    fn default() -> Self {
+        RunCounters {
+            started: Instant::now(),
+            rows_read: Arc::new(AtomicU64::new(0)),
+            rows_sampled: Arc::new(AtomicU64::new(0)),
+            bytes_downloaded: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl RunCounters {
+    /// Count a row read from an input.
+    pub fn add_row(&self) {
+        self.rows_read.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a row kept by the sampling of an input.
+    pub fn add_sampled_row(&self) {
+        self.rows_sampled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count bytes downloaded from a remote source.
+    pub fn add_downloaded(&self, bytes: u64) {
+        self.bytes_downloaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Get the number of rows read from the inputs.
+    pub fn rows_read(&self) -> u64 {
+        self.rows_read.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of rows read that were not kept by the sampling of the inputs.
+    pub fn rows_not_sampled(&self) -> u64 {
+        self.rows_read() - self.rows_sampled.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of bytes downloaded from remote sources.
+    pub fn bytes_downloaded(&self) -> u64 {
+        self.bytes_downloaded.load(Ordering::Relaxed)
+    }
+
+    /// Get the time since the run started.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// The `RunSummary` struct holds the counts written in the footer of the report.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunSummary {
+    /// The number of rows read from the inputs.
+    pub rows_read: u64,
+
+    /// The number of rows skipped for missing or invalid data, for each reason.
+    pub skipped: Vec<(String, usize)>,
+
+    /// The number of rows with usable data left out of the report, for each reason.
+    pub left_out: Vec<(String, u64)>,
+
+    /// The number of price changes inserted into the data stores.
+    pub inserted: usize,
+
+    /// The number of descriptions the data stores have given a code.
+    pub interned: usize,
+
+    /// How long the run took.
+    pub elapsed: Duration,
+
+    /// The number of bytes downloaded from remote sources.
+    pub bytes_downloaded: u64,
+}
+
+impl RunSummary {
+    /// Generate the footer of the report.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the footer.
+    pub fn report(&self) -> String {
+        let mut report = String::from("Run summary:\n");
+        report.push_str(&format!("Rows read: {}\n", self.rows_read));
+        let skipped: usize = self.skipped.iter().map(|(_, count)| count).sum();
+        report.push_str(&format!("Rows skipped: {}\n", skipped));
+        for (reason, count) in &self.skipped {
+            report.push_str(&format!("  {}: {}\n", capitalize(reason), count));
+        }
+        let left_out: u64 = self.left_out.iter().map(|(_, count)| count).sum();
+        report.push_str(&format!("Rows left out: {}\n", left_out));
+        for (reason, count) in &self.left_out {
+            report.push_str(&format!("  {}: {}\n", capitalize(reason), count));
+        }
+        report.push_str(&format!("Price changes inserted: {}\n", self.inserted));
+        report.push_str(&format!("Descriptions interned: {}\n", self.interned));
+        report.push_str(&format!(
+            "Elapsed time: {:.2}s\n",
+            self.elapsed.as_secs_f64()
+        ));
+        report.push_str(&format!("Bytes downloaded: {}\n", self.bytes_downloaded));
+        report
+    }
+}
+
+/// Capitalize the first letter of a reason, for a line of the footer.
+fn capitalize(reason: &str) -> String {
+    let mut chars = reason.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_summary() {
+        let counters = RunCounters::default();
+        let shared = counters.clone();
+        shared.add_row();
+        shared.add_row();
+        shared.add_sampled_row();
+        shared.add_downloaded(512);
+        assert_eq!(counters.rows_read(), 2);
+        assert_eq!(counters.rows_not_sampled(), 1);
+        assert_eq!(counters.bytes_downloaded(), 512);
+
+        let summary = RunSummary {
+            rows_read: 12,
+            skipped: vec![
+                ("missing effective_date".to_string(), 2),
+                ("invalid new_price".to_string(), 1),
+            ],
+            left_out: vec![
+                ("not in the --sample".to_string(), 4),
+                ("outside the report's periods".to_string(), 1),
+            ],
+            inserted: 9,
+            interned: 7,
+            elapsed: Duration::from_millis(1250),
+            bytes_downloaded: 4096,
+        };
+        assert_eq!(
+            summary.report(),
+            "Run summary:\n\
+            Rows read: 12\n\
+            Rows skipped: 3\n  \
+              Missing effective_date: 2\n  \
+              Invalid new_price: 1\n\
+            Rows left out: 5\n  \
+              Not in the --sample: 4\n  \
+              Outside the report's periods: 1\n\
+            Price changes inserted: 9\n\
+            Descriptions interned: 7\n\
+            Elapsed time: 1.25s\n\
+            Bytes downloaded: 4096\n"
+        );
+    }
+}
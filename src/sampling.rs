@@ -0,0 +1,121 @@
+//! The `sampling` module provides code for reading only part of the data, the first rows of
+//! each input or a random sample of its rows, so a run can be checked quickly before the full
+//! data is read.
+
+use crate::data_source::RecordStream;
+use futures::StreamExt;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+
+/// The `Sampling` struct describes how much of each input is read. Without a limit or a
+/// sample, all of it is read.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sampling {
+    /// When set, at most this many rows of each input are read.
+    pub max_rows: Option<usize>,
+
+    /// When set, each row read is kept with this probability, between 0 and 1.
+    pub fraction: Option<Decimal>,
+}
+
+impl Sampling {
+    /// Create a new `Sampling`.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_rows` - The most rows of each input to read.
+    /// * `fraction` - The fraction of the rows to keep, more than 0 and at most 1.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `Sampling`, on error returns a String describing the problem.
+    pub fn new(max_rows: Option<usize>, fraction: Option<Decimal>) -> Result<Sampling, String> {
+        if fraction.is_some_and(|fraction| fraction <= Decimal::ZERO || fraction > Decimal::ONE) {
+            return Err("--sample must be more than 0 and at most 1, e.g. 0.01".to_string());
+        }
+
+        Ok(Sampling { max_rows, fraction })
+    }
+
+    /// Limit and sample the records of an input. Errors reading the input are always kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `records` - The records of the input.
+    ///
+    /// # Returns
+    ///
+    /// The `RecordStream` of the records read.
+    pub fn apply<'a>(&self, records: RecordStream<'a>) -> RecordStream<'a> {
+        let mut records = records;
+        if let Some(max_rows) = self.max_rows {
+            records = records.take(max_rows).boxed_local();
+        }
+        if let Some(fraction) = self.fraction.and_then(|fraction| fraction.to_f64()) {
+            records = records
+                .filter(move |record| {
+                    futures::future::ready(record.is_err() || fastrand::f64() < fraction)
+                })
+                .boxed_local();
+        }
+        records
+    }
+
+    /// Describe how the data was cut down, for the heading of a report made from it.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the description, or None if all of the data is read.
+    pub fn note(&self) -> Option<String> {
+        let percent = self
+            .fraction
+            .map(|fraction| (fraction * Decimal::ONE_HUNDRED).normalize());
+        let read = match (self.max_rows, percent) {
+            (None, None) => return None,
+            (Some(max_rows), None) => format!("only the first {} rows of each input", max_rows),
+            (None, Some(percent)) => format!("a random {}% of the rows", percent),
+            (Some(max_rows), Some(percent)) => format!(
+                "a random {}% of the first {} rows of each input",
+                percent, max_rows
+            ),
+        };
+        Some(format!("Partial report: {} were read", read))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+
+    async fn count(sampling: Sampling) -> usize {
+        let records = (0..10).map(|index| Ok(ByteRecord::from(vec![index.to_string()])));
+        sampling
+            .apply(futures::stream::iter(records).boxed_local())
+            .count()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_sampling() {
+        assert_eq!(count(Sampling::default()).await, 10);
+        assert_eq!(count(Sampling::new(Some(4), None).unwrap()).await, 4);
+        let everything = Sampling::new(Some(4), Some(Decimal::ONE)).unwrap();
+        assert_eq!(count(everything).await, 4);
+
+        assert_eq!(Sampling::default().note(), None);
+        assert_eq!(
+            Sampling::new(Some(1000), None).unwrap().note().unwrap(),
+            "Partial report: only the first 1000 rows of each input were read"
+        );
+        assert_eq!(
+            Sampling::new(Some(1000), Some(Decimal::new(10, 3)))
+                .unwrap()
+                .note()
+                .unwrap(),
+            "Partial report: a random 1% of the first 1000 rows of each input were read"
+        );
+        assert!(Sampling::new(None, Some(Decimal::ZERO)).is_err());
+        assert!(Sampling::new(None, Some(Decimal::new(15, 1))).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! The `seasonality` module provides code for summarizing the price changes by the calendar
+//! month they took effect in, whatever the year, which shows patterns like the waves of
+//! repricing each January.
+
+use crate::comparison::ComparisonRow;
+use crate::report::dollars;
+use chrono::{Datelike, Month};
+use rust_decimal::Decimal;
+
+/// The price increases and decreases that took effect in one calendar month.
+#[derive(Debug, Clone, Copy, Default)]
+struct MonthTotals {
+    /// The number of price increases.
+    increases: u64,
+
+    /// The sum of the price increases.
+    increase_total: Decimal,
+
+    /// The number of price decreases.
+    decreases: u64,
+
+    /// The sum of the price decreases, which is negative.
+    decrease_total: Decimal,
+}
+
+/// The `Seasonality` struct keeps a running count and sum of the price increases and decreases
+/// for each calendar month.
+#[derive(Debug, Clone, Default)]
+pub struct Seasonality {
+    /// The totals for each month, January first.
+    months: [MonthTotals; 12],
+}
+
+impl Seasonality {
+    /// Add a price change to the totals for the month it took effect in. Changes without an
+    /// effective date, or of zero, are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    pub fn add(&mut self, row: &ComparisonRow) {
+        let Some(effective_date) = row.effective_date else {
+            return;
+        };
+
+        let difference = row.new_price - row.old_price;
+        let totals = &mut self.months[effective_date.month0() as usize];
+        if difference > Decimal::ZERO {
+            totals.increases += 1;
+            totals.increase_total += difference;
+        } else if difference < Decimal::ZERO {
+            totals.decreases += 1;
+            totals.decrease_total += difference;
+        }
+    }
+
+    /// Generate the section of the report with the number and sum of the price increases and
+    /// decreases in each calendar month.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the section.
+    pub fn report(&self) -> String {
+        let mut report = String::from("Price changes by effective month:\n");
+        for (index, totals) in self.months.iter().enumerate() {
+            let name = Month::try_from(index as u8 + 1).map_or("", |month| month.name());
+            report.push_str(&format!(
+                "{}: {} increases totaling {}, {} decreases totaling {}\n",
+                name,
+                totals.increases,
+                dollars(&totals.increase_total),
+                totals.decreases,
+                dollars(&totals.decrease_total)
+            ));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+
+    #[test]
+    fn test_seasonality() {
+        let mut seasonality = Seasonality::default();
+        for (old_price, new_price, date) in [
+            ("1", "3", "01/04/2023"),
+            ("2", "2.5", "01/18/2024"),
+            ("4", "1", "01/25/2023"),
+            ("1", "1", "02/01/2023"),
+            ("5", "4.25", "12/06/2023"),
+            ("5", "6", ""),
+        ] {
+            let mut fields = vec!["ASPIRIN", "", old_price, new_price];
+            fields.extend(["", "", "", "", "", date]);
+            let record = ByteRecord::from(fields);
+            seasonality.add(&ComparisonRow::from_record(&record).unwrap());
+        }
+
+        let report = seasonality.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 13);
+        assert_eq!(lines[0], "Price changes by effective month:");
+        assert_eq!(
+            lines[1],
+            "January: 2 increases totaling $2.5, 1 decreases totaling -$3"
+        );
+        assert_eq!(
+            lines[2],
+            "February: 0 increases totaling $0, 0 decreases totaling $0"
+        );
+        assert_eq!(
+            lines[12],
+            "December: 0 increases totaling $0, 1 decreases totaling -$0.75"
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! The `sftp` module provides code for streaming the CSV data from an SFTP server. The
+//! connection is made with the system `ssh` program, so the user's SSH configuration, keys and
+//! agent are used just as they would be by the `sftp` command.
+
+use crate::data_source::DataReader;
+use futures::io::AsyncRead;
+use openssh::{KnownHosts, Session};
+use openssh_sftp_client::file::TokioCompatFile;
+use openssh_sftp_client::{Sftp, SftpOptions};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+/// A remote file being read over SFTP. The SFTP session is kept alongside the file so the
+/// connection stays open until the reader is dropped.
+struct SftpReader {
+    /// The remote file.
+    file: Pin<Box<Compat<TokioCompatFile>>>,
+
+    /// The SFTP session the file was opened with.
+    _sftp: Sftp,
+}
+
+impl AsyncRead for SftpReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.file.as_mut().poll_read(cx, buf)
+    }
+}
+
+/// Split the part of an `sftp://[user@]host[:port]/path` URL after the scheme into the SSH
+/// destination and the remote path. The path is absolute unless it starts with `~/`, in which
+/// case it is relative to the home directory of the user on the server.
+///
+/// # Arguments
+///
+/// * `location` - The URL without the `sftp://` prefix.
+///
+/// # Returns
+///
+/// An Option which will contain the destination and path, or None if either is missing.
+pub fn split_sftp_location(location: &str) -> Option<(String, String)> {
+    let (destination, path) = location.split_once('/')?;
+    if destination.is_empty() || path.is_empty() {
+        return None;
+    }
+
+    let path = match path.strip_prefix("~/") {
+        Some(relative) => relative.to_string(),
+        None => format!("/{}", path),
+    };
+
+    Some((destination.to_string(), path))
+}
+
+/// Open a file on an SFTP server as a stream of bytes.
+///
+/// # Arguments
+///
+/// * `destination` - The server as `[user@]host[:port]`.
+/// * `path` - The path of the file on the server.
+///
+/// # Returns
+///
+/// On success, returns the `DataReader` for the file, on error returns a std::error::Error in a
+/// Box.
+pub async fn open_sftp_file(
+    destination: &str,
+    path: &str,
+) -> Result<DataReader, Box<dyn std::error::Error>> {
+    // Hosts must already be in known_hosts, the same as for a scheduled sftp or scp job.
+    let session =
+        Session::connect_mux(format!("ssh://{}", destination), KnownHosts::Strict).await?;
+    let sftp = Sftp::from_session(session, SftpOptions::default()).await?;
+    let file = sftp.open(path).await?;
+
+    Ok(Box::pin(SftpReader {
+        file: Box::pin(TokioCompatFile::from(file).compat()),
+        _sftp: sftp,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_sftp_location() {
+        assert_eq!(
+            split_sftp_location("nadac@drop.example.gov:2222/outgoing/comparison.csv"),
+            Some((
+                "nadac@drop.example.gov:2222".to_string(),
+                "/outgoing/comparison.csv".to_string()
+            ))
+        );
+        assert_eq!(
+            split_sftp_location("drop.example.gov/~/comparison.csv.gz"),
+            Some((
+                "drop.example.gov".to_string(),
+                "comparison.csv.gz".to_string()
+            ))
+        );
+        assert_eq!(split_sftp_location("drop.example.gov"), None);
+        assert_eq!(split_sftp_location("drop.example.gov/"), None);
+    }
+}
@@ -0,0 +1,279 @@
+//! The `snapshots` module provides code for comparing two snapshots of the NADAC data, either
+//! comparison files or weekly files, by the NDCs they list. NDCs that only the later snapshot
+//! lists are newly priced products, and NDCs that only the earlier snapshot lists have been
+//! discontinued.
+
+use crate::comparison::{ComparisonRow, FieldError};
+use crate::data_source::{Input, SourceOptions};
+use crate::filters::normalize_ndc;
+use crate::row_errors::RowErrors;
+use crate::trend::price;
+use crate::weekly::WeeklyPrices;
+use chrono::NaiveDate;
+use futures::StreamExt;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// The index of the NDC in the NADAC comparison columns.
+const NDC_FIELD: usize = 1;
+
+/// The latest price of an NDC in a snapshot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotEntry {
+    /// The NDC as written in the snapshot.
+    pub ndc: String,
+
+    /// The description of the drug.
+    pub description: String,
+
+    /// The latest per unit price.
+    pub price: Decimal,
+
+    /// The date the latest price took effect, which comparison rows may not have.
+    pub effective_date: Option<NaiveDate>,
+}
+
+/// The `Snapshot` struct holds the latest price of each NDC in a snapshot of the NADAC data.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    /// A description of where the snapshot was read from.
+    pub source: String,
+
+    /// The entries, keyed by the 11 digit NDC.
+    entries: HashMap<String, SnapshotEntry>,
+}
+
+impl Snapshot {
+    /// Read a snapshot. Rows with missing or invalid data are skipped and recorded in
+    /// `row_errors`.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input to read.
+    /// * `options` - The options used to open the input.
+    /// * `weekly` - When true, the input is a weekly NADAC file, otherwise a comparison file,
+    ///   whose new prices are taken as the latest prices.
+    /// * `row_errors` - The skipped rows.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `Snapshot`, on error returns a std::error::Error in a Box.
+    pub async fn read(
+        input: &Input,
+        options: &SourceOptions,
+        weekly: bool,
+        row_errors: &mut RowErrors,
+    ) -> Result<Snapshot, Box<dyn std::error::Error>> {
+        let mut opened = input.records(options).await?;
+        let mut snapshot = Snapshot {
+            source: opened.source.clone(),
+            ..Default::default()
+        };
+
+        if weekly {
+            let mut weekly_prices = WeeklyPrices::default();
+            weekly_prices
+                .add_records(&mut opened.records, &opened.source, row_errors)
+                .await?;
+            for (ndc, description, price, effective_date) in weekly_prices.latest_prices() {
+                snapshot.add(ndc, description, price, Some(effective_date));
+            }
+            return Ok(snapshot);
+        }
+
+        while let Some(record) = opened.records.next().await {
+            let record = record?;
+            let row = match ComparisonRow::from_record(&record) {
+                Ok(row) => row,
+                Err(FieldError { field, reason }) => {
+                    row_errors.add(&opened.source, &record, field, &reason);
+                    continue;
+                }
+            };
+            if row.ndc.trim().is_empty() {
+                row_errors.add(&opened.source, &record, NDC_FIELD, "missing NDC");
+                continue;
+            }
+
+            snapshot.add(
+                &row.ndc,
+                &row.description,
+                row.new_price,
+                row.effective_date,
+            );
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Add a price to the snapshot, unless the snapshot has a later price for the NDC. Of
+    /// prices for the same date, the one added last is kept.
+    fn add(
+        &mut self,
+        ndc: &str,
+        description: &str,
+        price: Decimal,
+        effective_date: Option<NaiveDate>,
+    ) {
+        let entry = SnapshotEntry {
+            ndc: ndc.trim().to_string(),
+            description: description.trim().to_string(),
+            price,
+            effective_date,
+        };
+        self.entries
+            .entry(normalize_ndc(ndc))
+            .and_modify(|existing| {
+                if existing.effective_date <= effective_date {
+                    *existing = entry.clone();
+                }
+            })
+            .or_insert(entry);
+    }
+
+    /// Get the entries of the NDCs this snapshot lists and another does not.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The other snapshot.
+    ///
+    /// # Returns
+    ///
+    /// The entries, ordered by description and NDC.
+    pub fn missing_from(&self, other: &Snapshot) -> Vec<&SnapshotEntry> {
+        let mut entries: Vec<&SnapshotEntry> = self
+            .entries
+            .iter()
+            .filter(|(ndc, _)| !other.entries.contains_key(*ndc))
+            .map(|(_, entry)| entry)
+            .collect();
+        entries.sort_by(|a, b| (&a.description, &a.ndc).cmp(&(&b.description, &b.ndc)));
+        entries
+    }
+}
+
+/// Generate the report of the NDCs a later snapshot lists and an earlier one does not.
+///
+/// # Arguments
+///
+/// * `earlier` - The earlier snapshot.
+/// * `later` - The later snapshot.
+///
+/// # Returns
+///
+/// A new String containing the report.
+pub fn new_drugs_report(earlier: &Snapshot, later: &Snapshot) -> String {
+    let mut report = format!(
+        "NDCs in {} that are not in {}:\n",
+        later.source, earlier.source
+    );
+
+    let entries = later.missing_from(earlier);
+    if entries.is_empty() {
+        report.push_str("No new NDCs found\n");
+    }
+    for entry in entries {
+        report.push_str(&format!(
+            "{} (NDC {}) at {}\n",
+            entry.description,
+            entry.ndc,
+            price(&entry.price)
+        ));
+    }
+    report
+}
+
+/// Generate the report of the NDCs an earlier snapshot lists and a later one does not, with
+/// their last known prices.
+///
+/// # Arguments
+///
+/// * `earlier` - The earlier snapshot.
+/// * `later` - The later snapshot.
+///
+/// # Returns
+///
+/// A new String containing the report.
+pub fn discontinued_report(earlier: &Snapshot, later: &Snapshot) -> String {
+    let mut report = format!(
+        "NDCs in {} that are no longer in {}:\n",
+        earlier.source, later.source
+    );
+
+    let entries = earlier.missing_from(later);
+    if entries.is_empty() {
+        report.push_str("No discontinued NDCs found\n");
+    }
+    for entry in entries {
+        let since = entry
+            .effective_date
+            .map_or(String::new(), |date| format!(" since {}", date));
+        report.push_str(&format!(
+            "{} (NDC {}) last priced at {}{}\n",
+            entry.description,
+            entry.ndc,
+            price(&entry.price),
+            since
+        ));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_source::DataSource;
+    use std::io::Write;
+
+    async fn read(file: &tempfile::NamedTempFile, row_errors: &mut RowErrors) -> Snapshot {
+        let input = Input::Csv(DataSource::File(file.path().to_path_buf()));
+        Snapshot::read(&input, &SourceOptions::default(), false, row_errors)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_reports() {
+        let header = "NDC Description,NDC,Old NADAC Per Unit,New NADAC Per Unit,\
+            Classification for Rate Setting,Percent Change,Primary Reason,Start Date,End Date,\
+            Effective Date\n";
+        let mut earlier = tempfile::NamedTempFile::new().unwrap();
+        writeln!(
+            earlier,
+            "{}ASPIRIN 81 MG,00000000001,0.01,0.02,G,100,,,,01/04/2023",
+            header
+        )
+        .unwrap();
+        let mut later = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            later,
+            "{}\
+            ASPIRIN 81 MG,00000-0000-01,0.02,0.03,G,50,,,,02/01/2023\n\
+            ZEPBOUND 5 MG/0.5 ML PEN,00002-2495-80,2000,2100.5,B,5,,,,02/01/2023\n\
+            ZEPBOUND 5 MG/0.5 ML PEN,00002-2495-80,1900,2000,B,5,,,,01/04/2023\n\
+            NO NDC,,1,2,G,100,,,,02/01/2023\n",
+            header
+        )
+        .unwrap();
+
+        let mut row_errors = RowErrors::new(true);
+        let earlier = read(&earlier, &mut row_errors).await;
+        let later = read(&later, &mut row_errors).await;
+        assert_eq!(row_errors.count(), 1);
+
+        assert!(new_drugs_report(&earlier, &later)
+            .ends_with("ZEPBOUND 5 MG/0.5 ML PEN (NDC 00002-2495-80) at $2100.5\n"));
+        assert!(new_drugs_report(&later, &earlier).ends_with("No new NDCs found\n"));
+
+        assert_eq!(
+            discontinued_report(&later, &earlier),
+            format!(
+                "NDCs in {} that are no longer in {}:\n\
+                ZEPBOUND 5 MG/0.5 ML PEN (NDC 00002-2495-80) last priced at $2100.5 since \
+                2023-02-01\n",
+                later.source, earlier.source
+            )
+        );
+        assert!(discontinued_report(&earlier, &later).ends_with("No discontinued NDCs found\n"));
+    }
+}
@@ -0,0 +1,319 @@
+//! The `statistics` module provides code for summarizing all of the price changes that go into
+//! the report, which gives context to the extremes the report lists. The summary is computed
+//! while the data streams past, so it does not keep the price changes.
+
+use crate::report::dollars;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+
+/// The number of markers used by the P² median estimate.
+const MARKERS: usize = 5;
+
+/// The `MedianEstimate` struct estimates the median of a stream of values with the P²
+/// algorithm of Jain and Chlamtac, which keeps five markers instead of the values.
+#[derive(Debug, Clone, Default)]
+struct MedianEstimate {
+    /// The heights of the markers. Until there are five values, these are the values seen.
+    heights: Vec<f64>,
+
+    /// The positions of the markers, counting from 1.
+    positions: [f64; MARKERS],
+
+    /// The positions the markers should be at.
+    desired: [f64; MARKERS],
+}
+
+impl MedianEstimate {
+    /// The amount each desired position moves for each value.
+    const INCREMENTS: [f64; MARKERS] = [0.0, 0.25, 0.5, 0.75, 1.0];
+
+    /// Add a value to the estimate.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value.
+    fn add(&mut self, value: f64) {
+        if self.heights.len() < MARKERS {
+            self.heights.push(value);
+            self.heights.sort_by(f64::total_cmp);
+            if self.heights.len() == MARKERS {
+                self.positions = [1.0, 2.0, 3.0, 4.0, 5.0];
+                self.desired = [1.0, 2.0, 3.0, 4.0, 5.0];
+            }
+            return;
+        }
+
+        // Find the cell the value falls in, stretching the outer markers to hold it.
+        let h = &mut self.heights;
+        let cell = if value < h[0] {
+            h[0] = value;
+            0
+        } else if value >= h[MARKERS - 1] {
+            h[MARKERS - 1] = value;
+            MARKERS - 2
+        } else {
+            (1..MARKERS).find(|i| value < h[*i]).unwrap_or(MARKERS - 1) - 1
+        };
+
+        for position in &mut self.positions[cell + 1..] {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(Self::INCREMENTS) {
+            *desired += increment;
+        }
+
+        // Move the middle markers toward their desired positions.
+        for i in 1..MARKERS - 1 {
+            let offset = self.desired[i] - self.positions[i];
+            let room_above = self.positions[i + 1] - self.positions[i];
+            let room_below = self.positions[i - 1] - self.positions[i];
+            if (offset >= 1.0 && room_above > 1.0) || (offset <= -1.0 && room_below < -1.0) {
+                let step = offset.signum();
+                let height = self.parabolic(i, step);
+                self.heights[i] = if self.heights[i - 1] < height && height < self.heights[i + 1] {
+                    height
+                } else {
+                    self.linear(i, step)
+                };
+                self.positions[i] += step;
+            }
+        }
+    }
+
+    /// The piecewise parabolic prediction of a marker's height after moving it one step.
+    fn parabolic(&self, i: usize, step: f64) -> f64 {
+        let (n, q) = (&self.positions, &self.heights);
+        q[i] + step / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + step) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - step) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    /// The linear prediction of a marker's height after moving it one step.
+    fn linear(&self, i: usize, step: f64) -> f64 {
+        let (n, q) = (&self.positions, &self.heights);
+        let j = if step > 0.0 { i + 1 } else { i - 1 };
+        q[i] + step * (q[j] - q[i]) / (n[j] - n[i])
+    }
+
+    /// Get the estimate of the median.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the median, or None if no values have been added. The
+    /// median of fewer than five values is exact.
+    fn median(&self) -> Option<f64> {
+        let h = &self.heights;
+        match h.len() {
+            0 => None,
+            MARKERS => Some(h[2]),
+            len if len % 2 == 0 => Some((h[len / 2 - 1] + h[len / 2]) / 2.0),
+            len => Some(h[len / 2]),
+        }
+    }
+
+    /// Get the estimate of the first and third quartiles. The markers on either side of the
+    /// median track the quartiles.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the quartiles, or None if no values have been added. The
+    /// quartiles of fewer than five values are interpolated between the values.
+    fn quartiles(&self) -> Option<(f64, f64)> {
+        let h = &self.heights;
+        let quantile = |p: f64| {
+            let position = p * (h.len() - 1) as f64;
+            let below = position.floor() as usize;
+            let above = position.ceil() as usize;
+            h[below] + (h[above] - h[below]) * (position - below as f64)
+        };
+        match h.len() {
+            0 => None,
+            MARKERS => Some((h[1], h[3])),
+            _ => Some((quantile(0.25), quantile(0.75))),
+        }
+    }
+}
+
+/// The `Statistics` struct keeps the running summary of the price changes.
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+    /// The number of price changes.
+    count: u64,
+
+    /// The mean of the price changes, updated with Welford's method.
+    mean: f64,
+
+    /// The sum of the squared distances from the mean, updated with Welford's method.
+    squares: f64,
+
+    /// The smallest price change.
+    min: Option<Decimal>,
+
+    /// The largest price change.
+    max: Option<Decimal>,
+
+    /// The estimate of the median price change.
+    median: MedianEstimate,
+}
+
+impl Statistics {
+    /// Add a price change to the summary.
+    ///
+    /// # Arguments
+    ///
+    /// * `difference` - The change in the per unit price.
+    pub fn add(&mut self, difference: Decimal) {
+        let value = difference.to_f64().unwrap_or_default();
+
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.squares += delta * (value - self.mean);
+
+        self.min = Some(self.min.map_or(difference, |min| min.min(difference)));
+        self.max = Some(self.max.map_or(difference, |max| max.max(difference)));
+        self.median.add(value);
+    }
+
+    /// Get the mean of the price changes.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the mean, or None if no price changes have been added.
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// Get the sample standard deviation of the price changes, which is 0 for a single change.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the standard deviation, or None if no price changes have
+    /// been added.
+    pub fn standard_deviation(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            1 => Some(0.0),
+            count => Some((self.squares / (count - 1) as f64).sqrt()),
+        }
+    }
+
+    /// Get the number of price changes.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Get the amounts of the summary, named as they are in the summary section.
+    ///
+    /// # Returns
+    ///
+    /// The name and value of each amount, or nothing if no price changes have been added.
+    pub fn amounts(&self) -> Vec<(&'static str, f64)> {
+        let (Some(min), Some(max)) = (self.min, self.max) else {
+            return Vec::new();
+        };
+
+        let mut amounts = vec![("Mean", self.mean)];
+        if let Some(median) = self.median.median() {
+            amounts.push(("Median (estimated)", median));
+        }
+        amounts.push((
+            "Standard deviation",
+            self.standard_deviation().unwrap_or_default(),
+        ));
+        amounts.push(("Minimum", min.to_f64().unwrap_or_default()));
+        amounts.push(("Maximum", max.to_f64().unwrap_or_default()));
+        amounts
+    }
+
+    /// Get the estimate of the first and third quartiles of the price changes.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the quartiles, or None if no price changes have been added.
+    pub fn quartiles(&self) -> Option<(f64, f64)> {
+        self.median.quartiles()
+    }
+
+    /// Generate the summary section of the report.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the section.
+    pub fn report(&self) -> String {
+        let mut report = String::from("Summary statistics:\n");
+        report.push_str(&format!("Price changes: {}\n", self.count));
+
+        let (Some(min), Some(max)) = (self.min, self.max) else {
+            return report;
+        };
+
+        let deviation = self.standard_deviation().unwrap_or_default();
+        let amount = |value: f64| cents(Decimal::from_f64(value).unwrap_or_default());
+
+        report.push_str(&format!("Mean: {}\n", amount(self.mean)));
+        if let Some(median) = self.median.median() {
+            report.push_str(&format!("Median (estimated): {}\n", amount(median)));
+        }
+        report.push_str(&format!("Standard deviation: {}\n", amount(deviation)));
+        report.push_str(&format!("Minimum: {}\n", cents(min)));
+        report.push_str(&format!("Maximum: {}\n", cents(max)));
+        report
+    }
+}
+
+/// Format an amount of money with exactly two decimal places, so the amounts in the summary
+/// line up.
+pub fn cents(amount: Decimal) -> String {
+    let mut amount = amount.round_dp(2);
+    amount.rescale(2);
+    dollars(&amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_median_estimate() {
+        let mut estimate = MedianEstimate::default();
+        assert_eq!(estimate.median(), None);
+
+        for value in [5.0, 1.0, 4.0, 2.0] {
+            estimate.add(value);
+        }
+        assert_eq!(estimate.median(), Some(3.0));
+        assert_eq!(estimate.quartiles(), Some((1.75, 4.25)));
+
+        // Add 1 to 1001 in a scattered order.
+        let mut estimate = MedianEstimate::default();
+        for i in 0..1001 {
+            estimate.add(((i * 389) % 1001 + 1) as f64);
+        }
+        let median = estimate.median().unwrap();
+        assert!((median - 501.0).abs() < 10.0, "median was {}", median);
+    }
+
+    #[test]
+    fn test_statistics() {
+        let mut statistics = Statistics::default();
+        assert_eq!(
+            statistics.report(),
+            "Summary statistics:\nPrice changes: 0\n"
+        );
+
+        for cents in [-200, 100, 100, 400] {
+            statistics.add(Decimal::new(cents, 2));
+        }
+        assert_eq!(
+            statistics.report(),
+            "Summary statistics:\n\
+            Price changes: 4\n\
+            Mean: $1.00\n\
+            Median (estimated): $1.00\n\
+            Standard deviation: $2.45\n\
+            Minimum: -$2.00\n\
+            Maximum: $4.00\n"
+        );
+    }
+}
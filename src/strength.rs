@@ -0,0 +1,237 @@
+//! The `strength` module provides code for reading the strength of a drug out of its NADAC
+//! description, e.g. `500 MG` in `METFORMIN HCL 500 MG TABLET` or `10 MG/ML` in
+//! `ENBREL 10 MG/ML VIAL`, so that the prices of different strengths of a drug can be compared.
+
+use clap::ValueEnum;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+/// Enum describing how the prices are normalized before they are ranked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Normalization {
+    /// Divide the per unit prices by the milligrams of drug in a unit.
+    PerMg,
+}
+
+/// Enum describing the unit of a drug's strength.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrengthUnit {
+    /// Grams.
+    Gram,
+
+    /// Milligrams.
+    Milligram,
+
+    /// Micrograms.
+    Microgram,
+
+    /// International units, which measure activity rather than mass.
+    Unit,
+
+    /// Milliequivalents, which measure ions rather than mass.
+    Milliequivalent,
+}
+
+impl StrengthUnit {
+    /// Get the unit for its abbreviation in a NADAC description.
+    fn from_abbreviation(text: &str) -> Option<StrengthUnit> {
+        match text {
+            "G" | "GM" | "GRAM" => Some(StrengthUnit::Gram),
+            "MG" => Some(StrengthUnit::Milligram),
+            "MCG" => Some(StrengthUnit::Microgram),
+            "UNIT" | "UNITS" | "UN" => Some(StrengthUnit::Unit),
+            "MEQ" => Some(StrengthUnit::Milliequivalent),
+            _ => None,
+        }
+    }
+
+    /// The milligrams in one of the unit, or None if the unit is not a mass.
+    fn milligrams(&self) -> Option<Decimal> {
+        match self {
+            StrengthUnit::Gram => Some(Decimal::ONE_THOUSAND),
+            StrengthUnit::Milligram => Some(Decimal::ONE),
+            StrengthUnit::Microgram => Some(Decimal::new(1, 3)),
+            StrengthUnit::Unit | StrengthUnit::Milliequivalent => None,
+        }
+    }
+}
+
+/// The `Strength` struct holds the strength of a drug, e.g. 500 MG, or for a concentration
+/// such as 40 MG/0.4 ML, the amount of drug in an amount of liquid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strength {
+    /// The amount of drug.
+    pub amount: Decimal,
+
+    /// The unit of the amount of drug.
+    pub unit: StrengthUnit,
+
+    /// For a concentration, the amount and unit it is per, e.g. 0.4 and `ML`.
+    pub per: Option<(Decimal, String)>,
+}
+
+impl Strength {
+    /// Get the milligrams of drug in a pricing unit. NADAC prices tablets and capsules by the
+    /// each and liquids by the milliliter, so a strength in milligrams is the amount in a
+    /// tablet and a concentration per milliliters gives the amount in a milliliter.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the milligrams, or None if the strength is not a mass or
+    /// is per something other than milliliters, such as a day.
+    pub fn milligrams(&self) -> Option<Decimal> {
+        let milligrams = self.amount * self.unit.milligrams()?;
+        match &self.per {
+            None => Some(milligrams),
+            Some((amount, unit)) if unit == "ML" && !amount.is_zero() => Some(milligrams / amount),
+            Some(_) => None,
+        }
+    }
+}
+
+/// A piece of a description.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A number, e.g. `0.4`.
+    Number(Decimal),
+
+    /// A word in upper case, e.g. `MG`.
+    Word(String),
+
+    /// A `/`, which separates a concentration's amounts.
+    Slash,
+
+    /// A `-`, which separates the strengths of a combination drug.
+    Dash,
+}
+
+/// Split a description into numbers, words, slashes and dashes. A number and a word written
+/// together, as in `500MG`, are split apart.
+fn tokenize(description: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = description.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || (c == '.' && chars.peek().is_some_and(char::is_ascii_digit)) {
+            let mut number = String::from(c);
+            while let Some(next) = chars.next_if(|c| c.is_ascii_digit() || *c == '.' || *c == ',') {
+                if next != ',' {
+                    number.push(next);
+                }
+            }
+            match Decimal::from_str(number.trim_end_matches('.')) {
+                Ok(number) => tokens.push(Token::Number(number)),
+                Err(_) => tokens.push(Token::Word(number)),
+            }
+        } else if c.is_alphabetic() {
+            let mut word = String::from(c);
+            while let Some(next) = chars.next_if(|c| c.is_alphabetic()) {
+                word.push(next);
+            }
+            tokens.push(Token::Word(word.to_uppercase()));
+        } else if c == '/' {
+            tokens.push(Token::Slash);
+        } else if c == '-' {
+            tokens.push(Token::Dash);
+        }
+    }
+    tokens
+}
+
+/// Read the strength of a drug out of its description. The first amount followed by a unit
+/// of strength is taken, along with what it is per when it is a concentration. Combination
+/// drugs, whose strengths are written like `5-325 MG`, have no single strength.
+///
+/// # Arguments
+///
+/// * `description` - The description of the drug.
+///
+/// # Returns
+///
+/// An Option which will contain the strength, or None if the description has no single
+/// strength.
+pub fn parse_strength(description: &str) -> Option<Strength> {
+    let tokens = tokenize(description);
+    for (index, token) in tokens.iter().enumerate() {
+        let Token::Number(amount) = token else {
+            continue;
+        };
+        let Some(Token::Word(word)) = tokens.get(index + 1) else {
+            continue;
+        };
+        let Some(unit) = StrengthUnit::from_abbreviation(word) else {
+            continue;
+        };
+
+        let combination = index >= 2
+            && tokens[index - 1] == Token::Dash
+            && matches!(tokens[index - 2], Token::Number(_));
+        if combination {
+            return None;
+        }
+
+        let per = match (
+            tokens.get(index + 2),
+            tokens.get(index + 3),
+            tokens.get(index + 4),
+        ) {
+            (Some(Token::Slash), Some(Token::Number(per)), Some(Token::Word(per_unit))) => {
+                Some((*per, per_unit.clone()))
+            }
+            (Some(Token::Slash), Some(Token::Word(per_unit)), _) => {
+                Some((Decimal::ONE, per_unit.clone()))
+            }
+            _ => None,
+        };
+
+        return Some(Strength {
+            amount: *amount,
+            unit,
+            per,
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_strength() {
+        for (description, milligrams) in [
+            ("METFORMIN HCL 500 MG TABLET", Some(Decimal::new(500, 0))),
+            ("ACETAMINOPHEN 325MG TABLET", Some(Decimal::new(325, 0))),
+            ("ALBUTEROL HFA 90 MCG INHALER", Some(Decimal::new(9, 2))),
+            ("CEFTRIAXONE 1 GM VIAL", Some(Decimal::new(1000, 0))),
+            ("LEVOTHYROXINE 0.025 MG TAB", Some(Decimal::new(25, 3))),
+            ("ENBREL 50 MG/ML SURECLICK", Some(Decimal::new(50, 0))),
+            ("HUMIRA(CF) PEN 40 MG/0.4 ML", Some(Decimal::new(100, 0))),
+            ("VICTOZA 3-PAK 18 MG/3 ML PEN", Some(Decimal::new(6, 0))),
+            ("AMOXICILLIN 250 MG/5 ML SUSP", Some(Decimal::new(50, 0))),
+            ("OXYCODONE HCL 10MG/ML SOLN", Some(Decimal::new(10, 0))),
+            ("HUMALOG 100 UNIT/ML VIAL", None),
+            ("POTASSIUM CL ER 20 MEQ TABLET", None),
+            ("ESTRADIOL 0.1 MG/DAY PATCH", None),
+            ("HYDROCODONE-APAP 5-325 MG TAB", None),
+            ("LIDOCAINE 5% PATCH", None),
+        ] {
+            let strength = parse_strength(description);
+            assert_eq!(
+                strength.as_ref().and_then(Strength::milligrams),
+                milligrams,
+                "{}",
+                description
+            );
+        }
+
+        assert_eq!(
+            parse_strength("HUMALOG 100 UNIT/ML VIAL"),
+            Some(Strength {
+                amount: Decimal::new(100, 0),
+                unit: StrengthUnit::Unit,
+                per: Some((Decimal::ONE, "ML".to_string())),
+            })
+        );
+        assert_eq!(parse_strength("HYDROCODONE-APAP 5-325 MG TAB"), None);
+    }
+}
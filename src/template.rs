@@ -0,0 +1,236 @@
+//! The `template` module provides code for writing the report with a user-supplied Tera
+//! template, so the wording, ordering and fields of the report can be changed without changing
+//! the program. The template is given:
+//!
+//! * `title` - The title of the report.
+//! * `notes` - The notes on how the data was read and adjusted.
+//! * `sections` - The sections of the report, each with its `period`, e.g. `of 2023`, its
+//!   `drugs`, e.g. `brand`, or empty for all of them, and its `lists`. Each list has its `kind`,
+//!   e.g. `increases`, the `count` requested, the `heading` of the plain text report and its
+//!   `records`, which have their `rank`, `tied`, true when the record shares its rank with
+//!   another, `change`, e.g. `-$1.25`, `amount`, the change as a number, `percent`, e.g.
+//!   `-2.5%`, `old_price` and `new_price`, e.g. `$48.75`, `description`, `ndc`,
+//!   `effective_date` and `drug`, the drug as the plain text report writes it. The percent,
+//!   prices and effective date are empty when they are not known.
+//! * `extras` - The sections that follow the lists, e.g. the summary statistics, as plain text.
+
+use crate::data_store::DataStore;
+use crate::report::{dollars, drugs_label, ranked_entries, RecordFormat, ReportField, Section};
+use rust_decimal::prelude::ToPrimitive;
+use serde::Serialize;
+use tera::{Context, Tera};
+
+/// The name the template is registered under.
+const TEMPLATE_NAME: &str = "report";
+
+/// The title of the report given to the template.
+const TITLE: &str = "NADAC per unit price changes";
+
+/// A ranked record given to the template.
+#[derive(Debug, Serialize)]
+struct TemplateRecord {
+    rank: usize,
+    tied: bool,
+    change: String,
+    amount: f64,
+    percent: String,
+    old_price: String,
+    new_price: String,
+    description: String,
+    ndc: String,
+    effective_date: String,
+    drug: String,
+}
+
+/// A ranked list given to the template.
+#[derive(Debug, Serialize)]
+struct TemplateList {
+    kind: &'static str,
+    count: usize,
+    heading: String,
+    records: Vec<TemplateRecord>,
+}
+
+/// A section of the report given to the template.
+#[derive(Debug, Serialize)]
+struct TemplateSection {
+    period: String,
+    drugs: String,
+    lists: Vec<TemplateList>,
+}
+
+/// Describe a Tera error with the errors that caused it, which hold the details of what is
+/// wrong with the template.
+fn describe(error: &tera::Error) -> String {
+    let mut description = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        description.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    description
+}
+
+/// The `ReportTemplate` struct holds a user-supplied template for the report.
+#[derive(Debug, Clone)]
+pub struct ReportTemplate {
+    /// The template engine, with the template registered as `TEMPLATE_NAME`.
+    tera: Tera,
+}
+
+impl ReportTemplate {
+    /// Parse a report template.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text of the template, in Tera's syntax.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the new `ReportTemplate`, on error returns a String describing what
+    /// is wrong with the template.
+    pub fn parse(text: &str) -> Result<ReportTemplate, String> {
+        let mut tera = Tera::default();
+        tera.add_raw_template(TEMPLATE_NAME, text)
+            .map_err(|e| describe(&e))?;
+        Ok(ReportTemplate { tera })
+    }
+
+    /// Write the report with the template.
+    ///
+    /// # Arguments
+    ///
+    /// * `sections` - The sections of the report and their records stores, in date order.
+    /// * `format` - How the records' drugs are written.
+    /// * `notes` - The notes on how the data was read and adjusted.
+    /// * `extras` - The sections that follow the lists, as plain text.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns a new String containing the report, on error returns a String
+    /// describing why the template could not be rendered.
+    pub fn render(
+        &self,
+        sections: &[(&Section, &DataStore)],
+        format: &RecordFormat,
+        notes: &[String],
+        extras: &[String],
+    ) -> Result<String, String> {
+        let sections: Vec<TemplateSection> = sections
+            .iter()
+            .map(|(section, data_store)| {
+                let drugs = drugs_label(section);
+                let lists = ranked_entries(data_store, format.percent_places)
+                    .into_iter()
+                    .map(|(kind, count, entries)| TemplateList {
+                        kind,
+                        count,
+                        heading: format!(
+                            "Top {count} {drugs}NADAC per unit price {kind} {}",
+                            section.period
+                        ),
+                        records: entries
+                            .into_iter()
+                            .map(|entry| TemplateRecord {
+                                rank: entry.rank,
+                                tied: entry.tied,
+                                change: dollars(&entry.change),
+                                amount: entry.change.to_f64().unwrap_or_default(),
+                                percent: entry.field(ReportField::Percent),
+                                old_price: entry.field(ReportField::OldPrice),
+                                new_price: entry.field(ReportField::NewPrice),
+                                drug: format.describe(&entry.description, &entry.ndc),
+                                effective_date: entry.field(ReportField::EffectiveDate),
+                                description: entry.description,
+                                ndc: entry.ndc,
+                            })
+                            .collect(),
+                    })
+                    .collect();
+                TemplateSection {
+                    period: section.period.to_string(),
+                    drugs: drugs.trim().to_string(),
+                    lists,
+                }
+            })
+            .collect();
+
+        let mut context = Context::new();
+        context.insert("title", TITLE);
+        context.insert("notes", notes);
+        context.insert("sections", &sections);
+        context.insert("extras", extras);
+        self.tera
+            .render(TEMPLATE_NAME, &context)
+            .map_err(|e| describe(&e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_store::{Direction, Metric};
+    use crate::dates::Period;
+    use crate::report::generate_report;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_report_template() {
+        let mut data_store = DataStore::new(2, 1, Metric::Change, Direction::Both).unwrap();
+        for (cents, description, ndc) in [
+            (80239, "STELARA", "57894006103"),
+            (32019, "HUMIRA", ""),
+            (-18314, "HUMALOG", "00002751001"),
+        ] {
+            data_store
+                .insert_change(Decimal::new(cents, 2), description, ndc)
+                .unwrap();
+        }
+        let section = Section {
+            period: Period::Year(2023),
+            classification: None,
+            pricing_unit: None,
+        };
+        let sections = [(&section, &data_store)];
+        let format = RecordFormat {
+            legacy: true,
+            ..Default::default()
+        };
+
+        // The example template writes the same report as the program does with --format legacy.
+        let example = ReportTemplate::parse(include_str!("../data/report.tmpl")).unwrap();
+        let notes = ["Skipped 1 row(s) with missing or invalid data".to_string()];
+        let extras = ["Summary of price changes:\nCount: 3\n".to_string()];
+        assert_eq!(
+            example.render(&sections, &format, &notes, &extras).unwrap(),
+            format!(
+                "{}\n\n{}\n{}",
+                notes[0],
+                generate_report(&data_store, &section, &format),
+                extras[0]
+            )
+        );
+
+        let template = ReportTemplate::parse(
+            "{% for list in sections[0].lists %}{{ list.kind | upper }}\n\
+            {% for record in list.records %}{{ record.rank }}. {{ record.description }} \
+            [{{ record.ndc }}] {{ record.amount }}\n{% endfor %}{% endfor %}",
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(&sections, &format, &[], &[]).unwrap(),
+            "INCREASES\n\
+            1. STELARA [57894006103] 802.39\n\
+            2. HUMIRA [] 320.19\n\
+            DECREASES\n\
+            1. HUMALOG [00002751001] -183.14\n"
+        );
+
+        assert!(ReportTemplate::parse("{% for list in sections %}").is_err());
+        let missing = ReportTemplate::parse("{{ sections[0].missing }}").unwrap();
+        assert!(missing
+            .render(&sections, &format, &[], &[])
+            .unwrap_err()
+            .contains("missing"));
+    }
+}
@@ -0,0 +1,221 @@
+//! The `thresholds` module provides code for failing a run whose data crosses a threshold, set
+//! with `--fail-if-max-increase-above` and `--fail-if-max-decrease-above`, so a scheduled run
+//! can raise an alarm through its exit status without its output being read.
+
+use crate::comparison::ComparisonRow;
+use crate::report::dollars;
+use rust_decimal::Decimal;
+use std::fmt;
+use std::str::FromStr;
+
+/// The exit status of a run whose data crosses a threshold.
+pub const THRESHOLD_EXIT_CODE: u8 = 3;
+
+/// Enum describing the size of a price change that crosses a threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Threshold {
+    /// A percent of the old price, written e.g. `50%`.
+    Percent(Decimal),
+
+    /// Dollars per unit, written e.g. `25` or `$25`.
+    Dollars(Decimal),
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let invalid = || {
+            format!(
+                "Invalid threshold {}, expected a percent, e.g. 50%, or dollars, e.g. 25",
+                text
+            )
+        };
+        let (number, percent) = match text.strip_suffix('%') {
+            Some(number) => (number, true),
+            None => (text.strip_prefix('$').unwrap_or(text), false),
+        };
+        let number = Decimal::from_str(number.trim()).map_err(|_| invalid())?;
+        if number.is_sign_negative() {
+            return Err(invalid());
+        }
+        Ok(if percent {
+            Threshold::Percent(number)
+        } else {
+            Threshold::Dollars(number)
+        })
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Threshold::Percent(percent) => write!(f, "{}%", percent),
+            Threshold::Dollars(amount) => write!(f, "{}", dollars(amount)),
+        }
+    }
+}
+
+/// The `PriceExtremes` struct tracks the largest price increase and decrease of the price
+/// changes in the report, in dollars and in percent of the old price. Changes from an old price
+/// of zero have no percent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PriceExtremes {
+    /// The largest increase in dollars.
+    increase: Decimal,
+
+    /// The largest increase in percent of the old price.
+    increase_percent: Decimal,
+
+    /// The size of the largest decrease in dollars.
+    decrease: Decimal,
+
+    /// The size of the largest decrease in percent of the old price.
+    decrease_percent: Decimal,
+}
+
+impl PriceExtremes {
+    /// Track a price change.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    pub fn add(&mut self, row: &ComparisonRow) {
+        let change = row.new_price - row.old_price;
+        let percent = if row.old_price.is_zero() {
+            Decimal::ZERO
+        } else {
+            change * Decimal::ONE_HUNDRED / row.old_price.abs()
+        };
+        if change > Decimal::ZERO {
+            self.increase = self.increase.max(change);
+            self.increase_percent = self.increase_percent.max(percent);
+        } else {
+            self.decrease = self.decrease.max(-change);
+            self.decrease_percent = self.decrease_percent.max(-percent);
+        }
+    }
+}
+
+/// Determine if the largest change of a direction is above a threshold.
+///
+/// # Arguments
+///
+/// * `threshold` - The threshold.
+/// * `amount` - The size of the largest change in dollars.
+/// * `percent` - The size of the largest change in percent of the old price.
+///
+/// # Returns
+///
+/// The size of the largest change, as the threshold measures it, when it is above the
+/// threshold, otherwise None.
+fn above(threshold: Threshold, amount: Decimal, percent: Decimal) -> Option<String> {
+    match threshold {
+        Threshold::Percent(limit) => (percent > limit).then(|| {
+            let mut percent = percent.round_dp(2);
+            percent.rescale(2);
+            format!("{}%", percent)
+        }),
+        Threshold::Dollars(limit) => (amount > limit).then(|| dollars(&amount)),
+    }
+}
+
+/// The `ExitThresholds` struct holds the sizes of the largest price increase and decrease
+/// that fail the run.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExitThresholds {
+    /// The size the largest increase must not be above.
+    pub max_increase: Option<Threshold>,
+
+    /// The size the largest decrease must not be above.
+    pub max_decrease: Option<Threshold>,
+}
+
+impl ExitThresholds {
+    /// Find the thresholds the price changes of the report cross.
+    ///
+    /// # Arguments
+    ///
+    /// * `extremes` - The largest changes of the report.
+    ///
+    /// # Returns
+    ///
+    /// A Vec containing a description of each threshold crossed, which is empty when the run
+    /// passes.
+    pub fn crossed(&self, extremes: &PriceExtremes) -> Vec<String> {
+        let mut crossed = Vec::new();
+        if let Some(threshold) = self.max_increase {
+            if let Some(largest) = above(threshold, extremes.increase, extremes.increase_percent) {
+                crossed.push(format!(
+                    "The largest price increase, {}, is above {}",
+                    largest, threshold
+                ));
+            }
+        }
+        if let Some(threshold) = self.max_decrease {
+            if let Some(largest) = above(threshold, extremes.decrease, extremes.decrease_percent) {
+                crossed.push(format!(
+                    "The largest price decrease, {}, is above {}",
+                    largest, threshold
+                ));
+            }
+        }
+        crossed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+
+    #[test]
+    fn test_thresholds() {
+        assert_eq!(
+            "50%".parse::<Threshold>(),
+            Ok(Threshold::Percent(Decimal::new(50, 0)))
+        );
+        assert_eq!(
+            "$25.50".parse::<Threshold>(),
+            Ok(Threshold::Dollars(Decimal::new(2550, 2)))
+        );
+        assert_eq!(
+            "25".parse::<Threshold>(),
+            Ok(Threshold::Dollars(Decimal::new(25, 0)))
+        );
+        assert!("-5".parse::<Threshold>().is_err());
+        assert!("lots".parse::<Threshold>().is_err());
+
+        let mut extremes = PriceExtremes::default();
+        for record in [
+            ByteRecord::from(vec!["ASPIRIN", "00001", "10.00", "15.00"]),
+            ByteRecord::from(vec!["HUMIRA", "00002", "200.00", "220.00"]),
+            ByteRecord::from(vec!["HUMALOG", "00003", "274.70", "91.56"]),
+            ByteRecord::from(vec!["NEW DRUG", "00004", "0", "1.00"]),
+        ] {
+            extremes.add(&ComparisonRow::from_record(&record).unwrap());
+        }
+
+        let thresholds = ExitThresholds {
+            max_increase: Some(Threshold::Percent(Decimal::new(40, 0))),
+            max_decrease: Some(Threshold::Dollars(Decimal::new(200, 0))),
+        };
+        assert_eq!(
+            thresholds.crossed(&extremes),
+            ["The largest price increase, 50.00%, is above 40%"]
+        );
+
+        let thresholds = ExitThresholds {
+            max_increase: Some(Threshold::Dollars(Decimal::new(15, 0))),
+            max_decrease: Some(Threshold::Percent(Decimal::new(60, 0))),
+        };
+        assert_eq!(
+            thresholds.crossed(&extremes),
+            [
+                "The largest price increase, $20.00, is above $15",
+                "The largest price decrease, 66.67%, is above 60%"
+            ]
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! The `trend` module provides code for following the price of a single drug through the price
+//! change data, which shows how a drug in the report got there.
+
+use crate::comparison::ComparisonRow;
+use crate::data_store::normalize_description;
+use crate::filters::normalize_ndc;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+/// A price change of the drug being followed.
+#[derive(Debug, Clone, PartialEq)]
+struct TrendChange {
+    /// The date the new price took effect.
+    effective_date: NaiveDate,
+
+    /// The National Drug Code of the drug, which may be empty.
+    ndc: String,
+
+    /// The per unit price before the change.
+    old_price: Decimal,
+
+    /// The per unit price after the change.
+    new_price: Decimal,
+}
+
+/// The `Trend` struct collects the price changes of a drug, identified by its description or
+/// its NDC.
+#[derive(Debug, Clone)]
+pub struct Trend {
+    /// The description or NDC as given.
+    drug: String,
+
+    /// The normalized description to match, when the drug is identified by its description.
+    description: Option<String>,
+
+    /// The 11 digit NDC to match, when the drug is identified by its NDC.
+    ndc: Option<String>,
+
+    /// The price changes found.
+    changes: Vec<TrendChange>,
+}
+
+impl Trend {
+    /// Create a new, empty `Trend`.
+    ///
+    /// # Arguments
+    ///
+    /// * `drug` - The description or NDC of the drug. Text made up of digits and dashes is
+    ///   taken to be an NDC, anything else a description, which is matched ignoring case and
+    ///   whitespace.
+    pub fn new(drug: &str) -> Trend {
+        let is_ndc = drug.trim().chars().all(|c| c.is_ascii_digit() || c == '-')
+            && drug.chars().any(|c| c.is_ascii_digit());
+
+        Trend {
+            drug: drug.trim().to_string(),
+            description: (!is_ndc).then(|| normalize_description(drug)),
+            ndc: is_ndc.then(|| normalize_ndc(drug)),
+            changes: Vec::new(),
+        }
+    }
+
+    /// Check if a row is about the drug being followed.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    ///
+    /// # Returns
+    ///
+    /// True if the row's description or NDC matches.
+    pub fn matches(&self, row: &ComparisonRow) -> bool {
+        match (&self.ndc, &self.description) {
+            (Some(ndc), _) => normalize_ndc(&row.ndc) == *ndc,
+            (None, Some(description)) => normalize_description(&row.description) == *description,
+            (None, None) => false,
+        }
+    }
+
+    /// Add a price change of the drug.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row of comparison data.
+    /// * `effective_date` - The date the new price took effect.
+    pub fn add(&mut self, row: &ComparisonRow, effective_date: NaiveDate) {
+        self.changes.push(TrendChange {
+            effective_date,
+            ndc: row.ndc.to_string(),
+            old_price: row.old_price,
+            new_price: row.new_price,
+        });
+    }
+
+    /// Generate the table of the price changes in date order.
+    ///
+    /// # Arguments
+    ///
+    /// * `period` - The description of the dates covered, e.g. `of 2023`.
+    ///
+    /// # Returns
+    ///
+    /// A new String containing the table.
+    pub fn report(&self, period: &str) -> String {
+        let mut report = format!(
+            "NADAC per unit price changes for {} {}:\n",
+            self.drug, period
+        );
+        if self.changes.is_empty() {
+            report.push_str("No price changes found\n");
+            return report;
+        }
+
+        let mut changes: Vec<&TrendChange> = self.changes.iter().collect();
+        changes.sort_by(|a, b| (a.effective_date, &a.ndc).cmp(&(b.effective_date, &b.ndc)));
+
+        let mut rows = vec![[
+            "Effective date".to_string(),
+            "NDC".to_string(),
+            "Old price".to_string(),
+            "New price".to_string(),
+            "Change".to_string(),
+        ]];
+        for change in changes {
+            rows.push([
+                change.effective_date.to_string(),
+                change.ndc.clone(),
+                price(&change.old_price),
+                price(&change.new_price),
+                price(&(change.new_price - change.old_price)),
+            ]);
+        }
+
+        let mut widths = [0; 5];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        for row in rows {
+            // The dates and NDCs are aligned left and the prices right.
+            let line = format!(
+                "{:<w0$}  {:<w1$}  {:>w2$}  {:>w3$}  {:>w4$}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                row[4],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+                w4 = widths[4],
+            );
+            report.push_str(line.trim_end());
+            report.push('\n');
+        }
+        report
+    }
+}
+
+/// Format a per unit price with all of its decimal places, since the prices of many drugs are
+/// fractions of a cent.
+pub fn price(amount: &Decimal) -> String {
+    if amount.is_sign_negative() && !amount.is_zero() {
+        format!("-${}", amount.abs())
+    } else {
+        format!("${}", amount.abs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_async::ByteRecord;
+
+    #[test]
+    fn test_trend() {
+        let records = [
+            ByteRecord::from(vec![
+                "LISINOPRIL 10 MG TABLET",
+                "68180051301",
+                "0.02011",
+                "0.02265",
+            ]),
+            ByteRecord::from(vec![
+                "lisinopril 10 mg  tablet",
+                "68180051301",
+                "0.03120",
+                "0.02900",
+            ]),
+            ByteRecord::from(vec!["ASPIRIN 81 MG", "00000000001", "1.00", "2.00"]),
+        ];
+        let dates = [
+            NaiveDate::from_ymd_opt(2023, 1, 4).unwrap(),
+            NaiveDate::from_ymd_opt(2022, 12, 7).unwrap(),
+            NaiveDate::from_ymd_opt(2023, 1, 4).unwrap(),
+        ];
+
+        for drug in ["Lisinopril 10 MG Tablet", "68180-0513-01"] {
+            let mut trend = Trend::new(drug);
+            for (record, date) in records.iter().zip(dates) {
+                let row = ComparisonRow::from_record(record).unwrap();
+                if trend.matches(&row) {
+                    trend.add(&row, date);
+                }
+            }
+
+            assert_eq!(
+                trend.report("of all dates"),
+                format!(
+                    "NADAC per unit price changes for {} of all dates:\n\
+                    Effective date  NDC          Old price  New price     Change\n\
+                    2022-12-07      68180051301   $0.03120   $0.02900  -$0.00220\n\
+                    2023-01-04      68180051301   $0.02011   $0.02265   $0.00254\n",
+                    drug
+                )
+            );
+        }
+
+        assert_eq!(
+            Trend::new("ASPIRIN 325 MG").report("of 2023"),
+            "NADAC per unit price changes for ASPIRIN 325 MG of 2023:\nNo price changes found\n"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! The `utilization` module provides code for reading the Medicaid State Drug Utilization Data
+//! (SDUD), so that price changes can be weighed by how much of each drug Medicaid pays for. A
+//! small price change to a widely used drug can cost more than a large change to a rare one.
+
+use crate::columns::header_key;
+use crate::data_source::{DataSource, SourceOptions};
+use crate::data_store::parse_price;
+use crate::encoding::decode;
+use crate::filters::normalize_ndc;
+use futures::StreamExt;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// The state code the SDUD uses for the national totals of each NDC.
+const NATIONAL: &str = "XX";
+
+/// The units reimbursed for an NDC.
+#[derive(Debug, Clone, Copy, Default)]
+struct Units {
+    /// The sum of the national totals.
+    national: Decimal,
+
+    /// The sum of the units reimbursed in each state.
+    states: Decimal,
+}
+
+/// The `Utilization` struct holds the number of units Medicaid reimbursed for each NDC.
+#[derive(Debug, Clone, Default)]
+pub struct Utilization {
+    /// The units reimbursed for each NDC, keyed by its 11 digit form.
+    units: HashMap<String, Units>,
+}
+
+impl Utilization {
+    /// Read the State Drug Utilization Data. The units reimbursed are summed over the quarters,
+    /// and over the states when the data has no national totals. Rows with suppressed units are
+    /// left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Where the data is.
+    /// * `options` - The options used to open the source. Only the download and encoding
+    ///   options are used, since the data has its own layout.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns the `Utilization`, on error returns a std::error::Error in a Box.
+    pub async fn load(
+        source: &DataSource,
+        options: &SourceOptions,
+    ) -> Result<Utilization, Box<dyn std::error::Error>> {
+        let reader = decode(source.open(options).await?, options.encoding).await?;
+        let mut csv_reader = csv_async::AsyncReaderBuilder::new()
+            .flexible(true)
+            .create_reader(reader);
+
+        let headers: Vec<String> = csv_reader.headers().await?.iter().map(header_key).collect();
+        let column = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header == name)
+                .ok_or_else(|| {
+                    format!(
+                        "The utilization data from {} has no {} column",
+                        source, name
+                    )
+                })
+        };
+        let ndc_column = column("ndc")?;
+        let units_column = column("unitsreimbursed")?;
+        let state_column = column("state").ok();
+
+        let mut utilization = Utilization::default();
+        let mut records = csv_reader.byte_records();
+        while let Some(record) = records.next().await {
+            let record = record?;
+            let field = |index: usize| {
+                record
+                    .get(index)
+                    .map(String::from_utf8_lossy)
+                    .unwrap_or_default()
+            };
+
+            let Ok(units) = parse_price(field(units_column).trim()) else {
+                continue;
+            };
+            let entry = utilization
+                .units
+                .entry(normalize_ndc(&field(ndc_column)))
+                .or_default();
+            match state_column.map(field) {
+                Some(state) if state.trim() == NATIONAL => entry.national += units,
+                _ => entry.states += units,
+            }
+        }
+
+        Ok(utilization)
+    }
+
+    /// Get the units reimbursed for an NDC.
+    ///
+    /// # Arguments
+    ///
+    /// * `ndc` - The NDC.
+    ///
+    /// # Returns
+    ///
+    /// An Option which will contain the units, or None if the data has none for the NDC.
+    pub fn units(&self, ndc: &str) -> Option<Decimal> {
+        let units = self.units.get(&normalize_ndc(ndc))?;
+        // The national totals already include the states, so they are not added together.
+        if units.national > Decimal::ZERO {
+            Some(units.national)
+        } else {
+            Some(units.states)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn test_utilization() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            "Utilization Type,State,NDC,Year,Quarter,Suppression Used,Units Reimbursed\n\
+            FFSU,OH,68180051301,2023,1,false,100\n\
+            FFSU,OH,68180051301,2023,2,false,50.5\n\
+            FFSU,XX,00074055402,2023,1,false,30\n\
+            FFSU,NY,00074055402,2023,1,false,20\n\
+            FFSU,NY,00002751001,2023,1,true,\n"
+        )
+        .unwrap();
+
+        let utilization = Utilization::load(
+            &DataSource::File(file.path().to_path_buf()),
+            &SourceOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            utilization.units("68180-0513-01"),
+            Some(Decimal::new(1505, 1))
+        );
+        assert_eq!(utilization.units("00074055402"), Some(Decimal::new(30, 0)));
+        assert_eq!(utilization.units("00002751001"), None);
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "NDC,Quarter\n68180051301,1\n").unwrap();
+        assert!(Utilization::load(
+            &DataSource::File(file.path().to_path_buf()),
+            &SourceOptions::default(),
+        )
+        .await
+        .is_err());
+    }
+}
@@ -0,0 +1,385 @@
+//! The `validate` module provides code for checking that price change CSV data has the layout
+//! and values the report expects, without building a report. It is meant as a pre-flight check
+//! before scheduled report runs.
+
+use crate::columns::{decode_field, COLUMN_NAMES};
+use crate::data_source::{DataSource, SourceOptions};
+use crate::data_store::parse_price;
+use crate::dates::parse_date;
+use crate::encoding::decode;
+use chrono::NaiveDate;
+use futures::StreamExt;
+use rust_decimal::Decimal;
+
+/// The column headers of the NADAC comparison file.
+const EXPECTED_HEADERS: [&str; 10] = [
+    "NDC Description",
+    "NDC",
+    "Old NADAC Per Unit",
+    "New NADAC Per Unit",
+    "Classification for Rate Setting",
+    "Percent Change",
+    "Primary Reason",
+    "Start Date",
+    "End Date",
+    "Effective Date",
+];
+
+/// The kinds of values in the NADAC comparison columns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnKind {
+    /// Free text.
+    Text,
+
+    /// An 11 digit National Drug Code.
+    Ndc,
+
+    /// A decimal number.
+    Number,
+
+    /// A date.
+    Date,
+}
+
+/// The kind of value in each of the NADAC comparison columns.
+const COLUMN_KINDS: [ColumnKind; 10] = [
+    ColumnKind::Text,
+    ColumnKind::Ndc,
+    ColumnKind::Number,
+    ColumnKind::Number,
+    ColumnKind::Text,
+    ColumnKind::Number,
+    ColumnKind::Text,
+    ColumnKind::Date,
+    ColumnKind::Date,
+    ColumnKind::Date,
+];
+
+/// A parsed value, kept to track the smallest and largest values in a column.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Value {
+    Number(Decimal),
+    Date(NaiveDate),
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Date(date) => write!(f, "{}", date.format("%m/%d/%Y")),
+        }
+    }
+}
+
+/// Statistics about the values in one column.
+#[derive(Debug, Default, Clone)]
+struct ColumnStats {
+    /// The number of rows with a value in the column.
+    filled: u64,
+
+    /// The number of rows with an empty column.
+    empty: u64,
+
+    /// The number of values that could not be parsed.
+    invalid: u64,
+
+    /// The line of the first value that could not be parsed.
+    first_invalid_line: Option<u64>,
+
+    /// The smallest value.
+    min: Option<Value>,
+
+    /// The largest value.
+    max: Option<Value>,
+}
+
+impl ColumnStats {
+    /// Add a value from the column to the statistics.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of value the column holds.
+    /// * `text` - The value.
+    /// * `line` - The line the value is on.
+    fn add(&mut self, kind: ColumnKind, text: &str, line: u64) {
+        let text = text.trim();
+        if text.is_empty() {
+            self.empty += 1;
+            return;
+        }
+        self.filled += 1;
+
+        let value = match kind {
+            ColumnKind::Text => return,
+            ColumnKind::Ndc => {
+                if text.len() == 11 && text.chars().all(|c| c.is_ascii_digit()) {
+                    return;
+                }
+                None
+            }
+            ColumnKind::Number => parse_price(text).ok().map(Value::Number),
+            ColumnKind::Date => parse_date(text).ok().map(Value::Date),
+        };
+
+        match value {
+            Some(value) => {
+                if self.min.is_none_or(|min| value < min) {
+                    self.min = Some(value);
+                }
+                if self.max.is_none_or(|max| value > max) {
+                    self.max = Some(value);
+                }
+            }
+            None => {
+                self.invalid += 1;
+                self.first_invalid_line.get_or_insert(line);
+            }
+        }
+    }
+}
+
+/// The result of validating one data source.
+#[derive(Debug, Default)]
+pub struct Validation {
+    /// The problems with the column headers.
+    header_problems: Vec<String>,
+
+    /// The number of data rows.
+    rows: u64,
+
+    /// The number of rows without the expected number of columns.
+    bad_rows: u64,
+
+    /// The line of the first row without the expected number of columns.
+    first_bad_row: Option<u64>,
+
+    /// The statistics for each column.
+    columns: Vec<ColumnStats>,
+}
+
+impl Validation {
+    /// Determine if the data passed validation.
+    pub fn passed(&self) -> bool {
+        self.header_problems.is_empty()
+            && self.bad_rows == 0
+            && self.columns.iter().all(|column| column.invalid == 0)
+    }
+}
+
+/// Check the column headers against the NADAC comparison headers.
+///
+/// # Arguments
+///
+/// * `headers` - The headers of the data.
+///
+/// # Returns
+///
+/// The problems found, which is empty if the headers are as expected.
+fn check_headers(headers: &csv_async::StringRecord) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if headers.len() != EXPECTED_HEADERS.len() {
+        problems.push(format!(
+            "Expected {} columns but the header has {}",
+            EXPECTED_HEADERS.len(),
+            headers.len()
+        ));
+    }
+
+    for (index, expected) in EXPECTED_HEADERS.iter().enumerate() {
+        // Some exports put a byte order mark in front of the first header.
+        let found = headers
+            .get(index)
+            .map(|h| h.trim().trim_start_matches('\u{feff}'));
+        match found {
+            Some(found) if found.eq_ignore_ascii_case(expected) => {}
+            Some(found) => problems.push(format!(
+                "Column {} should be \"{}\" but is \"{}\"",
+                index + 1,
+                expected,
+                found
+            )),
+            None => problems.push(format!("Column {} \"{}\" is missing", index + 1, expected)),
+        }
+    }
+
+    problems
+}
+
+/// Stream a data source and check its headers, column counts and values.
+///
+/// # Arguments
+///
+/// * `source` - The data source to check.
+/// * `options` - The options that control how the source is opened. When a column mapping is
+///   given, the headers are not checked and the values are checked after mapping. When the
+///   data has no header row, the headers are not checked either.
+///
+/// # Returns
+///
+/// On success, returns the `Validation`, on error returns a std::error::Error in a Box.
+pub async fn validate_source(
+    source: &DataSource,
+    options: &SourceOptions,
+) -> Result<Validation, Box<dyn std::error::Error>> {
+    let reader = decode(source.open(options).await?, options.encoding).await?;
+    let (reader, delimiter) = options.delimiter.resolve(reader).await?;
+    let mut csv_reader = csv_async::AsyncReaderBuilder::new()
+        .flexible(true)
+        .delimiter(delimiter)
+        .has_headers(!options.no_header)
+        .create_reader(reader);
+
+    let mut validation = Validation {
+        columns: vec![ColumnStats::default(); COLUMN_KINDS.len()],
+        ..Default::default()
+    };
+
+    let expected_len = match &options.columns {
+        Some(_) => None,
+        None => {
+            if !options.no_header {
+                validation.header_problems = check_headers(csv_reader.headers().await?);
+            }
+            Some(EXPECTED_HEADERS.len())
+        }
+    };
+
+    let mut records = csv_reader.byte_records();
+    while let Some(record) = records.next().await {
+        let record = record?;
+        let line = record.position().map_or(0, |position| position.line());
+        validation.rows += 1;
+
+        if expected_len.is_some_and(|len| record.len() != len) {
+            validation.bad_rows += 1;
+            validation.first_bad_row.get_or_insert(line);
+            continue;
+        }
+
+        let record = match &options.columns {
+            Some(columns) => columns.apply(&record),
+            None => record,
+        };
+
+        for (index, stats) in validation.columns.iter_mut().enumerate() {
+            let text = decode_field(&record, index).unwrap_or_default();
+            stats.add(COLUMN_KINDS[index], &text, line);
+        }
+    }
+
+    Ok(validation)
+}
+
+/// Generate the text summary of a validation.
+///
+/// # Arguments
+///
+/// * `source` - The data source that was checked.
+/// * `validation` - The result of the check.
+///
+/// # Returns
+///
+/// The summary.
+pub fn generate_summary(source: &DataSource, validation: &Validation) -> String {
+    let mut summary = format!("Validating {}\n", source);
+
+    if validation.header_problems.is_empty() {
+        summary.push_str("Headers: ok\n");
+    } else {
+        for problem in &validation.header_problems {
+            summary.push_str(&format!("Headers: {}\n", problem));
+        }
+    }
+
+    summary.push_str(&format!("Rows: {}\n", validation.rows));
+    if let Some(line) = validation.first_bad_row {
+        summary.push_str(&format!(
+            "Rows with the wrong number of columns: {} (first on line {})\n",
+            validation.bad_rows, line
+        ));
+    }
+
+    summary.push_str(&format!(
+        "\n{:<16} {:>9} {:>9} {:>9}  {:<12} Max\n",
+        "Column", "Filled", "Empty", "Invalid", "Min"
+    ));
+    for (name, stats) in COLUMN_NAMES.iter().zip(&validation.columns) {
+        let value = |value: Option<Value>| value.map(|v| v.to_string()).unwrap_or_default();
+        let mut line = format!(
+            "{:<16} {:>9} {:>9} {:>9}  {:<12} {:<12}",
+            name,
+            stats.filled,
+            stats.empty,
+            stats.invalid,
+            value(stats.min),
+            value(stats.max)
+        );
+        if let Some(invalid_line) = stats.first_invalid_line {
+            line.push_str(&format!(" first invalid value on line {}", invalid_line));
+        }
+        summary.push_str(line.trim_end());
+        summary.push('\n');
+    }
+
+    summary.push_str(if validation.passed() {
+        "\nResult: PASS\n"
+    } else {
+        "\nResult: FAIL\n"
+    });
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    #[tokio::test]
+    async fn test_validate_source() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR").to_string());
+        path.push("data");
+        path.push("sample_comparison.csv");
+
+        let validation = validate_source(&DataSource::File(path), &SourceOptions::default())
+            .await
+            .unwrap();
+
+        assert!(validation.passed());
+        assert_eq!(validation.rows, 23);
+        assert!(validation.columns[9].empty > 0);
+        assert!(matches!(validation.columns[2].min, Some(Value::Number(_))));
+    }
+
+    #[test]
+    fn test_check_headers() {
+        let headers = csv_async::StringRecord::from(EXPECTED_HEADERS.to_vec());
+        assert!(check_headers(&headers).is_empty());
+
+        let headers = csv_async::StringRecord::from(vec!["Description", "NDC"]);
+        let problems = check_headers(&headers);
+        assert_eq!(problems[0], "Expected 10 columns but the header has 2");
+        assert_eq!(
+            problems[1],
+            "Column 1 should be \"NDC Description\" but is \"Description\""
+        );
+    }
+
+    #[test]
+    fn test_column_stats() {
+        let mut stats = ColumnStats::default();
+        stats.add(ColumnKind::Number, "1.5", 2);
+        stats.add(ColumnKind::Number, "", 3);
+        stats.add(ColumnKind::Number, "N/A", 4);
+        stats.add(ColumnKind::Number, "-2", 5);
+
+        assert_eq!((stats.filled, stats.empty, stats.invalid), (3, 1, 1));
+        assert_eq!(stats.first_invalid_line, Some(4));
+        assert_eq!(stats.min, Some(Value::Number(Decimal::from(-2))));
+        assert_eq!(
+            stats.max,
+            Some(Value::Number(Decimal::from_str("1.5").unwrap()))
+        );
+    }
+}
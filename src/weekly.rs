@@ -0,0 +1,351 @@
+//! The `weekly` module provides code for computing price changes from the weekly NADAC files,
+//! which list the price of every NDC for each effective date, instead of relying on the
+//! pre-computed NADAC comparison file. The changes are produced as records in the NADAC
+//! comparison column order so they go through the same report machinery.
+
+use crate::columns::decode_field;
+use crate::data_source::RecordStream;
+use crate::data_store::parse_price;
+use crate::dates::parse_date;
+use crate::row_errors::RowErrors;
+use chrono::{Duration, NaiveDate};
+use csv_async::ByteRecord;
+use futures::StreamExt;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// The index of the description in the weekly NADAC columns.
+const DESCRIPTION_FIELD: usize = 0;
+
+/// The index of the NDC in the weekly NADAC columns.
+const NDC_FIELD: usize = 1;
+
+/// The index of the price in the weekly NADAC columns.
+const PRICE_FIELD: usize = 2;
+
+/// The index of the effective date in the weekly NADAC columns.
+pub const EFFECTIVE_DATE_FIELD: usize = 3;
+
+/// The index of the pricing unit in the weekly NADAC columns.
+const PRICING_UNIT_FIELD: usize = 4;
+
+/// The index of the over-the-counter indicator in the weekly NADAC columns.
+const OTC_FIELD: usize = 6;
+
+/// The index of the explanation codes in the weekly NADAC columns.
+const EXPLANATION_CODE_FIELD: usize = 7;
+
+/// The index of the classification in the weekly NADAC columns.
+const CLASSIFICATION_FIELD: usize = 8;
+
+/// The index of the price of the corresponding generic drug in the weekly NADAC columns.
+const GENERIC_PRICE_FIELD: usize = 9;
+
+/// The names of the weekly NADAC columns used, for the row error report.
+const FIELD_NAMES: [(usize, &str); 4] = [
+    (DESCRIPTION_FIELD, "description"),
+    (NDC_FIELD, "ndc"),
+    (PRICE_FIELD, "price"),
+    (EFFECTIVE_DATE_FIELD, "effective_date"),
+];
+
+/// The reason given for the price changes computed from the weekly files.
+const CHANGE_REASON: &str = "Weekly NADAC change";
+
+/// The price of an NDC from one effective date.
+#[derive(Debug, Clone)]
+struct PricePoint {
+    /// The effective date of the price.
+    effective_date: NaiveDate,
+
+    /// The price per unit.
+    price: Decimal,
+
+    /// The classification for rate setting.
+    classification: String,
+
+    /// The explanation codes of the price.
+    explanation_code: String,
+
+    /// The price of the corresponding generic drug, which may be empty.
+    generic_price: String,
+}
+
+/// The prices of one NDC over time.
+#[derive(Debug, Default)]
+struct NdcPrices {
+    /// The most recent description of the NDC.
+    description: String,
+
+    /// The most recent over-the-counter indicator of the NDC.
+    otc: String,
+
+    /// The most recent pricing unit of the NDC.
+    pricing_unit: String,
+
+    /// The effective date of the description, indicator and pricing unit, so the latest ones are
+    /// kept.
+    description_date: Option<NaiveDate>,
+
+    /// The prices, in the order they were read.
+    points: Vec<PricePoint>,
+}
+
+/// The `WeeklyPrices` struct collects the prices from the weekly NADAC files so the changes
+/// between consecutive effective dates can be computed.
+#[derive(Debug, Default)]
+pub struct WeeklyPrices {
+    /// The prices of each NDC.
+    prices: HashMap<String, NdcPrices>,
+}
+
+impl WeeklyPrices {
+    /// Read the rows of a weekly NADAC file. Rows with missing or invalid data are skipped and
+    /// recorded in `row_errors`.
+    ///
+    /// # Arguments
+    ///
+    /// * `records` - The rows of the weekly file.
+    /// * `source` - Where the rows come from.
+    /// * `row_errors` - The skipped rows.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns a std::error::Error in a Box.
+    pub async fn add_records(
+        &mut self,
+        records: &mut RecordStream<'_>,
+        source: &str,
+        row_errors: &mut RowErrors,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        while let Some(record) = records.next().await {
+            let record = record?;
+            if let Err((field, reason)) = self.add_record(&record) {
+                let name = FIELD_NAMES
+                    .iter()
+                    .find(|(index, _)| *index == field)
+                    .map_or("", |(_, name)| name);
+                row_errors.add_named(source, &record, field, name, &reason);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add one row of a weekly NADAC file.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The row.
+    ///
+    /// # Returns
+    ///
+    /// On success, returns (), on error returns the index of the offending column and the
+    /// reason the row cannot be used.
+    fn add_record(&mut self, record: &ByteRecord) -> Result<(), (usize, String)> {
+        let field = |index: usize| decode_field(record, index).unwrap_or_default();
+
+        let ndc = field(NDC_FIELD);
+        let ndc = ndc.trim();
+        if ndc.is_empty() {
+            return Err((NDC_FIELD, "missing NDC".to_string()));
+        }
+
+        let effective_date = match field(EFFECTIVE_DATE_FIELD).trim() {
+            "" => return Err((EFFECTIVE_DATE_FIELD, "missing effective date".to_string())),
+            date => parse_date(date).map_err(|e| (EFFECTIVE_DATE_FIELD, e))?,
+        };
+
+        let price = parse_price(&field(PRICE_FIELD))
+            .map_err(|e| (PRICE_FIELD, format!("invalid price: {}", e)))?;
+
+        let prices = self.prices.entry(ndc.to_string()).or_default();
+        if prices
+            .description_date
+            .is_none_or(|date| date <= effective_date)
+        {
+            prices.description = field(DESCRIPTION_FIELD).trim().to_string();
+            prices.otc = field(OTC_FIELD).trim().to_string();
+            prices.pricing_unit = field(PRICING_UNIT_FIELD).trim().to_string();
+            prices.description_date = Some(effective_date);
+        }
+
+        prices.points.push(PricePoint {
+            effective_date,
+            price,
+            classification: field(CLASSIFICATION_FIELD).trim().to_string(),
+            explanation_code: field(EXPLANATION_CODE_FIELD).trim().to_string(),
+            generic_price: field(GENERIC_PRICE_FIELD).trim().to_string(),
+        });
+
+        Ok(())
+    }
+
+    /// Get the latest price of each NDC. When the latest date appears more than once, the row
+    /// read last wins.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the NDC, description, price and effective date of each latest price.
+    pub fn latest_prices(&self) -> impl Iterator<Item = (&str, &str, Decimal, NaiveDate)> {
+        self.prices.iter().filter_map(|(ndc, prices)| {
+            let latest = prices
+                .points
+                .iter()
+                .max_by_key(|point| point.effective_date)?;
+            Some((
+                ndc.as_str(),
+                prices.description.as_str(),
+                latest.price,
+                latest.effective_date,
+            ))
+        })
+    }
+
+    /// Compute the price changes between consecutive effective dates of each NDC. Dates on
+    /// which the price did not change do not produce a record.
+    ///
+    /// # Returns
+    ///
+    /// A `RecordStream` of the changes in the NADAC comparison column order.
+    pub fn into_changes(self) -> RecordStream<'static> {
+        let mut changes = Vec::new();
+
+        for (ndc, mut prices) in self.prices {
+            prices.points.sort_by_key(|point| point.effective_date);
+            // When a date appears more than once, the row read last wins.
+            prices.points.reverse();
+            prices.points.dedup_by_key(|point| point.effective_date);
+            prices.points.reverse();
+
+            for pair in prices.points.windows(2) {
+                let (old, new) = (&pair[0], &pair[1]);
+                if old.price == new.price {
+                    continue;
+                }
+
+                changes.push(change_record(&prices, &ndc, old, new));
+            }
+        }
+
+        futures::stream::iter(changes.into_iter().map(Ok)).boxed_local()
+    }
+}
+
+/// Build a record in the NADAC comparison column order for a price change.
+///
+/// # Arguments
+///
+/// * `prices` - The prices of the NDC, for its description and over-the-counter indicator.
+/// * `ndc` - The NDC.
+/// * `old` - The price before the change.
+/// * `new` - The price after the change.
+///
+/// # Returns
+///
+/// The record.
+fn change_record(prices: &NdcPrices, ndc: &str, old: &PricePoint, new: &PricePoint) -> ByteRecord {
+    let percent_change = if old.price.is_zero() {
+        String::new()
+    } else {
+        ((new.price - old.price) / old.price * Decimal::from(100))
+            .round_dp(2)
+            .to_string()
+    };
+
+    let date = |date: NaiveDate| date.format("%m/%d/%Y").to_string();
+    let end_date = new.effective_date - Duration::days(1);
+
+    ByteRecord::from(vec![
+        prices.description.clone(),
+        ndc.to_string(),
+        old.price.to_string(),
+        new.price.to_string(),
+        new.classification.clone(),
+        percent_change,
+        CHANGE_REASON.to_string(),
+        date(old.effective_date),
+        date(end_date),
+        date(new.effective_date),
+        prices.otc.clone(),
+        prices.pricing_unit.clone(),
+        new.explanation_code.clone(),
+        old.generic_price.clone(),
+        new.generic_price.clone(),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+
+    fn weekly_row(description: &str, ndc: &str, price: &str, date: &str) -> ByteRecord {
+        ByteRecord::from(vec![
+            description,
+            ndc,
+            price,
+            date,
+            "EA",
+            "C/I",
+            "N",
+            "1, 5",
+            "G",
+            "",
+            "",
+            "",
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_into_changes() {
+        let rows = vec![
+            weekly_row("ASPIRIN 81 MG", "00001", "0.02000", "01/18/2023"),
+            weekly_row("ASPIRIN 81 MG", "00001", "0.02000", "01/04/2023"),
+            weekly_row("ASPIRIN 81 MG", "00001", "0.02000", "01/11/2023"),
+            weekly_row("ASPIRIN 81 MG EC", "00001", "0.03000", "01/25/2023"),
+            weekly_row("METFORMIN 500 MG", "00002", "0.05000", "01/04/2023"),
+            weekly_row("METFORMIN 500 MG", "00002", "0.04000", "01/11/2023"),
+            weekly_row("METFORMIN 500 MG", "00002", "bad", "01/18/2023"),
+        ];
+
+        let mut records: RecordStream =
+            futures::stream::iter(rows.into_iter().map(Ok)).boxed_local();
+        let mut weekly = WeeklyPrices::default();
+        let mut row_errors = RowErrors::new(true);
+        weekly
+            .add_records(&mut records, "weekly.csv", &mut row_errors)
+            .await
+            .unwrap();
+
+        assert_eq!(row_errors.count(), 1);
+        assert_eq!(row_errors.errors()[0].field, "price");
+
+        let mut changes: Vec<ByteRecord> = weekly.into_changes().try_collect().await.unwrap();
+        changes.sort_by(|a, b| a.get(1).cmp(&b.get(1)));
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(
+            changes[0],
+            ByteRecord::from(vec![
+                "ASPIRIN 81 MG EC",
+                "00001",
+                "0.02000",
+                "0.03000",
+                "G",
+                "50.00",
+                CHANGE_REASON,
+                "01/18/2023",
+                "01/24/2023",
+                "01/25/2023",
+                "N",
+                "EA",
+                "1, 5",
+                "",
+                "",
+            ])
+        );
+        assert_eq!(changes[1].get(5), Some(&b"-20.00"[..]));
+        assert_eq!(changes[1].get(9), Some(&b"01/11/2023"[..]));
+    }
+}
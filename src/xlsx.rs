@@ -0,0 +1,247 @@
+//! The `xlsx` module provides code for writing the report as an Excel workbook, with a sheet
+//! for each kind of ranked list, e.g. the increases and the decreases, and a sheet of summary
+//! statistics.
+
+use crate::data_store::DataStore;
+use crate::report::{
+    drugs_label, period_label, ranked_entries, RecordFormat, ReportEntry, ReportField, Section,
+};
+use crate::statistics::Statistics;
+use rust_decimal::prelude::ToPrimitive;
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+
+/// The headings of the columns of the sheets of ranked lists.
+const LIST_HEADINGS: [&str; 9] = [
+    "Period",
+    "Drugs",
+    "Rank",
+    "Change",
+    "% Change",
+    "Old price",
+    "New price",
+    "Effective",
+    "Drug",
+];
+
+/// The Excel number format of the prices and price changes.
+const DOLLARS_FORMAT: &str = "$#,##0.00;-$#,##0.00";
+
+/// Make the Excel number format of the percent changes, e.g. `0.00"%"` for two places.
+fn percent_format(places: u32) -> String {
+    let mut format = String::from("0");
+    if places > 0 {
+        format.push('.');
+        format.push_str(&"0".repeat(places as usize));
+    }
+    format.push_str("\"%\"");
+    format
+}
+
+/// The rows of a sheet of ranked lists: the section of each entry and the entry.
+type ListRows<'a> = Vec<(&'a Section, ReportEntry)>;
+
+/// Make the name of a sheet from the kind of change its list holds, e.g. `Increases`.
+///
+/// # Arguments
+///
+/// * `kind` - The kind of change.
+///
+/// # Returns
+///
+/// A new String containing the name, at most the 31 characters Excel allows.
+fn sheet_name(kind: &str) -> String {
+    let mut name: String = kind.chars().take(31).collect();
+    if let Some(first) = name.get(..1) {
+        name = format!("{}{}", first.to_uppercase(), &name[1..]);
+    }
+    name
+}
+
+/// Write the headings of a sheet in bold, kept in view when the sheet is scrolled.
+fn write_headings(
+    worksheet: &mut Worksheet,
+    headings: &[&str],
+    bold: &Format,
+) -> Result<(), XlsxError> {
+    for (column, heading) in headings.iter().enumerate() {
+        worksheet.write_string_with_format(0, column as u16, *heading, bold)?;
+    }
+    worksheet.set_freeze_panes(1, 0)?;
+    Ok(())
+}
+
+/// Generate the report as an Excel workbook.
+///
+/// # Arguments
+///
+/// * `sections` - The sections of the report and their records stores, in date order.
+/// * `format` - How the records' drugs are written.
+/// * `statistics` - The summary statistics of all of the price changes in the report.
+/// * `notes` - The notes on how the data was read and adjusted, written above the statistics.
+///
+/// # Returns
+///
+/// On success, returns the contents of the workbook file, on error returns a std::error::Error
+/// in a Box.
+pub fn xlsx_report(
+    sections: &[(&Section, &DataStore)],
+    format: &RecordFormat,
+    statistics: &Statistics,
+    notes: &[String],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    // The records of each kind of list, from all of the sections, go on one sheet.
+    let mut sheets: Vec<(&str, ListRows)> = Vec::new();
+    for (section, data_store) in sections {
+        for (kind, _, entries) in ranked_entries(data_store, format.percent_places) {
+            let index = match sheets.iter().position(|(sheet, _)| *sheet == kind) {
+                Some(index) => index,
+                None => {
+                    sheets.push((kind, Vec::new()));
+                    sheets.len() - 1
+                }
+            };
+            let rows = &mut sheets[index].1;
+            rows.extend(entries.into_iter().map(|entry| (*section, entry)));
+        }
+    }
+
+    let bold = Format::new().set_bold();
+    let money = Format::new().set_num_format(DOLLARS_FORMAT);
+    let percent = Format::new().set_num_format(percent_format(format.percent_places));
+    let mut workbook = Workbook::new();
+
+    for (kind, rows) in sheets {
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name(sheet_name(kind))?;
+        write_headings(worksheet, &LIST_HEADINGS, &bold)?;
+        for (index, (section, entry)) in rows.iter().enumerate() {
+            let row = index as u32 + 1;
+            worksheet.write_string(row, 0, period_label(&section.period))?;
+            worksheet.write_string(row, 1, drugs_label(section).trim())?;
+            // Tied ranks are written as text, e.g. `3=`.
+            if entry.tied {
+                worksheet.write_string(row, 2, entry.rank_label())?;
+            } else {
+                worksheet.write_number(row, 2, entry.rank as f64)?;
+            }
+            // The percent and prices are left blank when they are not known.
+            let numbers = [
+                (Some(entry.change), &money),
+                (entry.percent, &percent),
+                (entry.old_price, &money),
+                (entry.new_price, &money),
+            ];
+            for (column, (number, number_format)) in (3..).zip(numbers) {
+                if let Some(number) = number {
+                    worksheet.write_number_with_format(
+                        row,
+                        column,
+                        number.to_f64().unwrap_or_default(),
+                        number_format,
+                    )?;
+                }
+            }
+            worksheet.write_string(row, 7, entry.field(ReportField::EffectiveDate))?;
+            worksheet.write_string(row, 8, format.describe(&entry.description, &entry.ndc))?;
+        }
+        worksheet.autofit();
+    }
+
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name("Summary")?;
+    write_headings(worksheet, &["Statistic", "Value"], &bold)?;
+    let mut row = 1;
+    for note in notes {
+        worksheet.write_string(row, 0, note)?;
+        row += 1;
+    }
+    worksheet.write_string(row, 0, "Price changes")?;
+    worksheet.write_number(row, 1, statistics.count() as f64)?;
+    for (name, amount) in statistics.amounts() {
+        row += 1;
+        worksheet.write_string(row, 0, name)?;
+        worksheet.write_number_with_format(row, 1, amount, &money)?;
+    }
+    worksheet.autofit();
+
+    Ok(workbook.save_to_buffer()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_store::{Direction, Metric};
+    use crate::dates::Period;
+    use calamine::{open_workbook_from_rs, Data, Reader, Xlsx};
+    use rust_decimal::Decimal;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_xlsx_report() {
+        assert_eq!(sheet_name("increases"), "Increases");
+        assert_eq!(percent_format(2), "0.00\"%\"");
+        assert_eq!(percent_format(0), "0\"%\"");
+        assert_eq!(
+            sheet_name("swings in either direction"),
+            "Swings in either direction"
+        );
+
+        let mut data_store = DataStore::new(2, 2, Metric::Change, Direction::Both).unwrap();
+        let mut statistics = Statistics::default();
+        for cents in [80239, 32019, -18314] {
+            let difference = Decimal::new(cents, 2);
+            data_store.insert_change(difference, "DRUG", "").unwrap();
+            statistics.add(difference);
+        }
+        let section = Section {
+            period: Period::Year(2023),
+            classification: None,
+            pricing_unit: None,
+        };
+
+        let workbook = xlsx_report(
+            &[(&section, &data_store)],
+            &RecordFormat::default(),
+            &statistics,
+            &[],
+        )
+        .unwrap();
+
+        let mut workbook: Xlsx<_> = open_workbook_from_rs(Cursor::new(workbook)).unwrap();
+        assert_eq!(
+            workbook.sheet_names(),
+            ["Increases", "Decreases", "Summary"]
+        );
+        let increases = workbook.worksheet_range("Increases").unwrap();
+        assert_eq!(
+            increases.rows().next().unwrap(),
+            LIST_HEADINGS.map(|heading| Data::String(heading.to_string()))
+        );
+        assert_eq!(increases.height(), 3);
+        assert_eq!(
+            increases.rows().nth(1).unwrap(),
+            [
+                Data::String("2023".to_string()),
+                Data::Empty,
+                Data::Float(1.0),
+                Data::Float(802.39),
+                Data::Empty,
+                Data::Empty,
+                Data::Empty,
+                Data::Empty,
+                Data::String("DRUG".to_string()),
+            ]
+        );
+        let decreases = workbook.worksheet_range("Decreases").unwrap();
+        assert_eq!(decreases.get_value((1, 3)), Some(&Data::Float(-183.14)));
+        assert_eq!(
+            decreases.get_value((1, 8)),
+            Some(&Data::String("DRUG".to_string()))
+        );
+        let summary = workbook.worksheet_range("Summary").unwrap();
+        assert_eq!(
+            summary.rows().nth(1).unwrap(),
+            [Data::String("Price changes".to_string()), Data::Float(3.0)]
+        );
+    }
+}